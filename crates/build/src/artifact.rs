@@ -0,0 +1,160 @@
+use std::{fs, time::Duration};
+
+use anyhow::Result;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::BuildError;
+
+/// The result of building a program with [`crate::build_program_with_output`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildOutput {
+    /// The ELFs copied to the output directory.
+    pub artifacts: Vec<ElfArtifact>,
+    /// The target directory cargo compiled the program into.
+    pub target_directory: Utf8PathBuf,
+    /// Whether the program was built in a Docker container.
+    pub docker: bool,
+    /// The exit code of the cargo command, or `None` if it was not run.
+    pub cargo_exit_code: Option<i32>,
+    /// The time spent in each phase of the build.
+    pub timings: BuildTimings,
+    /// Whether building was skipped with `SP1_SKIP_PROGRAM_BUILD` or for the profile of the crate
+    /// building it, see [`crate::BuildArgs::build_when`], in which case the artifacts are those
+    /// of a previous build. See [`BuildError::Skipped`].
+    #[serde(default)]
+    pub skipped: bool,
+    /// Whether cargo was not run because nothing changed since the artifacts were built, see
+    /// [`crate::BuildArgs::force`].
+    #[serde(default)]
+    pub fresh: bool,
+    /// Whether the program failed to build and the artifacts are those of a previous build, which
+    /// do not match its sources, see [`crate::FailPolicy::WarnAndUseStale`].
+    #[serde(default)]
+    pub stale: bool,
+    /// The SP1 crates of the program replaced by a `[patch]` section, which change its
+    /// verification key, see [`crate::BuildArgs::deny_patched_sp1`].
+    #[serde(default)]
+    pub patched_sp1_crates: Vec<crate::PatchedCrate>,
+    /// The software bills of materials written next to the ELFs, see [`crate::BuildArgs::sbom`].
+    #[serde(default)]
+    pub sboms: Vec<Utf8PathBuf>,
+    /// The build manifests written next to the ELFs, see [`crate::BuildArgs::manifest`].
+    #[serde(default)]
+    pub manifests: Vec<Utf8PathBuf>,
+    /// The environment cargo was run with, see [`crate::BuildArgs::capture_env`].
+    #[serde(default)]
+    pub env_snapshot: Option<crate::EnvSnapshot>,
+    /// The warnings of this crate emitted during the build, such as a fallback to nightly, with
+    /// their kind, see [`crate::BuildArgs::deny_warnings`]. Warnings of cargo and rustc are not
+    /// included.
+    #[serde(default)]
+    pub warnings: Vec<crate::BuildWarning>,
+}
+
+impl BuildOutput {
+    /// The path to the first (and usually only) ELF.
+    ///
+    /// Panics if the build wrote no ELF, as with [`crate::BuildArgs::check_only`], see
+    /// [`BuildOutput::try_elf_path`].
+    pub fn elf_path(&self) -> &Utf8Path {
+        &self.artifacts[0].path
+    }
+
+    /// The path to the first ELF, or [`crate::BuildError::NoElf`] if the build wrote none.
+    pub fn try_elf_path(&self) -> Result<&Utf8Path, crate::BuildError> {
+        self.artifacts
+            .first()
+            .map(|artifact| artifact.path.as_path())
+            .ok_or(crate::BuildError::NoElf)
+    }
+
+    /// The paths to all the ELFs.
+    pub fn elf_paths(&self) -> Vec<&Utf8Path> {
+        self.artifacts.iter().map(|artifact| artifact.path.as_path()).collect()
+    }
+}
+
+/// An ELF produced by a build.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElfArtifact {
+    /// The path the ELF was copied to. In a dry run, the path it would be copied to.
+    pub path: Utf8PathBuf,
+    /// The size of the ELF in bytes, or `None` in a dry run.
+    pub size: Option<u64>,
+    /// The hex encoded SHA-256 digest of the ELF, or `None` in a dry run.
+    pub sha256: Option<String>,
+    /// The cycles the ELF executed in, if executed as a smoke test, see
+    /// [`crate::BuildArgs::smoke_test`].
+    #[serde(default)]
+    pub cycles: Option<u64>,
+}
+
+impl ElfArtifact {
+    /// Describe the ELF at `path`, reading its size and digest.
+    pub(crate) fn read(path: Utf8PathBuf) -> Result<Self> {
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(source) => return Err(BuildError::Artifact { path, source }.into()),
+        };
+        let sha256 = elf_sha256(&bytes);
+        Ok(Self { path, size: Some(bytes.len() as u64), sha256: Some(sha256), cycles: None })
+    }
+
+    /// Describe an ELF which was not built, in a dry run.
+    pub(crate) fn not_built(path: Utf8PathBuf) -> Self {
+        Self { path, size: None, sha256: None, cycles: None }
+    }
+}
+
+/// The hex encoded SHA-256 digest of `elf`, as in [`ElfArtifact::sha256`].
+pub fn elf_sha256(elf: &[u8]) -> String {
+    hex::encode(Sha256::digest(elf))
+}
+
+/// Whether `elf` has the hex encoded SHA-256 digest `expected`, such as the
+/// `SP1_ELF_<NAME>_SHA256` a build script sets for the ELF it built, in any case.
+pub fn verify_elf_sha256(elf: &[u8], expected: &str) -> bool {
+    elf_sha256(elf).eq_ignore_ascii_case(expected.trim())
+}
+
+/// The wall-clock time spent in each phase of a build.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildTimings {
+    /// Reading the program's metadata and resolving the build arguments.
+    pub metadata: Duration,
+    /// Running the cargo command.
+    pub cargo: Duration,
+    /// Copying the ELF to the output directory.
+    pub copy: Duration,
+}
+
+impl BuildTimings {
+    /// The total time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.metadata + self.cargo + self.copy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("elf")).unwrap();
+        fs::write(&path, b"abc").unwrap();
+
+        let artifact = ElfArtifact::read(path.clone()).unwrap();
+        assert_eq!(artifact.size, Some(3));
+        assert_eq!(
+            artifact.sha256.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert!(ElfArtifact::read(path.with_file_name("missing")).is_err());
+        assert!(verify_elf_sha256(b"abc", &artifact.sha256.as_deref().unwrap().to_uppercase()));
+        assert!(!verify_elf_sha256(b"abd", artifact.sha256.as_deref().unwrap()));
+    }
+}
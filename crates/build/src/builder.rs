@@ -0,0 +1,242 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::BuildArgs;
+
+impl BuildArgs {
+    /// Create a [`BuildArgsBuilder`] starting from the default arguments.
+    ///
+    /// ```
+    /// use sp1_build::BuildArgs;
+    ///
+    /// let args = BuildArgs::builder().docker(true).feature("bls").binary("fib").build().unwrap();
+    /// assert_eq!(args.features, vec!["bls".to_string()]);
+    /// ```
+    pub fn builder() -> BuildArgsBuilder {
+        BuildArgsBuilder::default()
+    }
+}
+
+/// A builder for [`BuildArgs`] which validates the arguments when calling
+/// [`BuildArgsBuilder::build`].
+///
+/// Names are trimmed of surrounding whitespace, and setting a name to an empty string is an error
+/// rather than meaning "unset" as it does on [`BuildArgs`].
+#[derive(Clone, Debug, Default)]
+pub struct BuildArgsBuilder {
+    args: BuildArgs,
+    tag: Option<String>,
+    features: Vec<String>,
+    binary: Option<String>,
+    elf_name: Option<String>,
+    output_directory: Vec<String>,
+}
+
+impl BuildArgsBuilder {
+    /// Run compilation using a Docker container for reproducible builds.
+    pub fn docker(mut self, docker: bool) -> Self {
+        self.args.docker = docker;
+        self.explicit("docker")
+    }
+
+    /// The ghcr.io/succinctlabs/sp1 image tag to use when building with Docker.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self.explicit("tag")
+    }
+
+    /// Activate a feature. May be a comma or space separated list of features.
+    pub fn feature(mut self, feature: impl Into<String>) -> Self {
+        self.features.push(feature.into());
+        self
+    }
+
+    /// Activate several features.
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Activate all available features.
+    pub fn all_features(mut self, all_features: bool) -> Self {
+        self.args.all_features = all_features;
+        self.explicit("all_features")
+    }
+
+    /// Do not activate the `default` feature.
+    pub fn no_default_features(mut self, no_default_features: bool) -> Self {
+        self.args.no_default_features = no_default_features;
+        self.explicit("no_default_features")
+    }
+
+    /// Ignore `rust-version` specification in packages.
+    pub fn ignore_rust_version(mut self, ignore_rust_version: bool) -> Self {
+        self.args.ignore_rust_version = ignore_rust_version;
+        self.explicit("ignore_rust_version")
+    }
+
+    /// Assert that `Cargo.lock` will remain unchanged.
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.args.locked = locked;
+        self.explicit("locked")
+    }
+
+    /// Assert that `Cargo.lock` will remain unchanged and that the network is not accessed.
+    pub fn frozen(mut self, frozen: bool) -> Self {
+        self.args.frozen = frozen;
+        self.explicit("frozen")
+    }
+
+    /// Generate the missing `Cargo.lock` of the program in a `locked` build.
+    pub fn generate_lockfile(mut self, generate_lockfile: bool) -> Self {
+        self.args.generate_lockfile = generate_lockfile;
+        self.explicit("generate_lockfile")
+    }
+
+    /// Build only the specified binary.
+    pub fn binary(mut self, binary: impl Into<String>) -> Self {
+        self.binary = Some(binary.into());
+        self
+    }
+
+    /// The name of the ELF file written to the output directory.
+    pub fn elf_name(mut self, elf_name: impl Into<String>) -> Self {
+        self.elf_name = Some(elf_name.into());
+        self
+    }
+
+    /// Copy the compiled ELF to this directory. Call it again to copy the ELF to several
+    /// directories.
+    pub fn output_directory(mut self, output_directory: impl AsRef<Path>) -> Self {
+        self.output_directory.push(output_directory.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Count `field` as passed explicitly, so that setting it to its default value still overrides
+    /// the environment variables and configuration files.
+    fn explicit(mut self, field: &str) -> Self {
+        self.args = self.args.explicitly_set(field);
+        self
+    }
+
+    /// Validate the arguments and build the [`BuildArgs`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name was set to an empty or whitespace-only string, or if mutually
+    /// exclusive options were combined.
+    pub fn build(self) -> Result<BuildArgs> {
+        let mut args = self.args;
+
+        if let Some(tag) = self.tag {
+            args.tag = non_empty("tag", &tag)?;
+        }
+        if let Some(binary) = self.binary {
+            args.binary = non_empty("binary", &binary)?;
+        }
+        if let Some(elf_name) = self.elf_name {
+            args.elf_name = non_empty("elf_name", &elf_name)?;
+        }
+        for output_directory in self.output_directory {
+            let output_directory = non_empty("output_directory", &output_directory)?;
+            let output_directory = normalize_path(Path::new(&output_directory))
+                .to_str()
+                .ok_or_else(|| anyhow!("`output_directory` is not valid UTF-8"))?
+                .to_string();
+            if !args.output_directory.contains(&output_directory) {
+                args.output_directory.push(output_directory);
+            }
+        }
+
+        for feature in &self.features {
+            let split = feature
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            if split.is_empty() {
+                bail!("`features` contains an empty feature name");
+            }
+            args.features.extend(split);
+        }
+
+        if args.all_features && !args.features.is_empty() {
+            bail!("`all_features` cannot be combined with `features`");
+        }
+
+        Ok(args)
+    }
+}
+
+/// Trim `value`, returning an error naming `field` if nothing is left.
+fn non_empty(field: &str, value: &str) -> Result<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        bail!("`{}` was set to an empty name", field);
+    }
+    Ok(trimmed.to_string())
+}
+
+/// Remove `.` components and trailing separators from `path`, without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let normalized: PathBuf = path.components().filter(|c| *c != Component::CurDir).collect();
+    if normalized.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults() {
+        let args = BuildArgs::builder().build().unwrap();
+        let default = BuildArgs::default();
+        assert_eq!(args.tag, default.tag);
+        assert_eq!(args.output_directory, default.output_directory);
+        assert!(args.binary.is_empty() && args.elf_name.is_empty() && args.features.is_empty());
+    }
+
+    #[test]
+    fn test_builder_trims_and_splits() {
+        let args = BuildArgs::builder()
+            .binary("my-bin ")
+            .elf_name(" my-elf")
+            .feature("a, b")
+            .features(["c"])
+            .output_directory("./elf/./programs/")
+            .output_directory("out")
+            .output_directory("elf/programs")
+            .build()
+            .unwrap();
+        assert_eq!(args.binary, "my-bin");
+        assert_eq!(args.elf_name, "my-elf");
+        assert_eq!(args.features, vec!["a", "b", "c"]);
+        assert_eq!(args.output_directory, vec!["elf/programs", "out"]);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid() {
+        assert!(BuildArgs::builder().binary("  ").build().is_err());
+        assert!(BuildArgs::builder().elf_name("").build().is_err());
+        assert!(BuildArgs::builder().tag(" ").build().is_err());
+        assert!(BuildArgs::builder().feature(" , ").build().is_err());
+        assert!(BuildArgs::builder().all_features(true).feature("bls").build().is_err());
+        assert!(BuildArgs::builder().all_features(true).build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_marks_explicit_fields() {
+        let args = BuildArgs::builder().docker(false).locked(false).docker(true).build().unwrap();
+        assert_eq!(args.explicit_fields, vec!["docker", "locked"]);
+        assert!(BuildArgs::builder().binary("fib").build().unwrap().explicit_fields.is_empty());
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cargo_metadata::{camino::Utf8Path, DependencyKind, Metadata};
+use dirs::home_dir;
+
+use crate::{
+    host_path,
+    lock::BuildLock,
+    output,
+    pipe::{self, Stream},
+    BuildArgs, CancellationToken,
+};
+
+/// The variable cc-rs reads the C compiler for the zkVM target from.
+pub(crate) const CC_ENV: &str = "CC_riscv32im_succinct_zkvm_elf";
+/// The variable cc-rs reads the flags of the C compiler for the zkVM target from.
+const CFLAGS_ENV: &str = "CFLAGS_riscv32im_succinct_zkvm_elf";
+/// The variable cc-rs reads the flags of the C++ compiler for the zkVM target from.
+const CXXFLAGS_ENV: &str = "CXXFLAGS_riscv32im_succinct_zkvm_elf";
+/// The variable cc-rs reads the archiver for the zkVM target from.
+const AR_ENV: &str = "AR_riscv32im_succinct_zkvm_elf";
+/// The variable cc-rs reads the ranlib for the zkVM target from.
+const RANLIB_ENV: &str = "RANLIB_riscv32im_succinct_zkvm_elf";
+/// The variable build scripts read the objcopy for the zkVM target from.
+const OBJCOPY_ENV: &str = "OBJCOPY_riscv32im_succinct_zkvm_elf";
+
+/// The variables of the C toolchain for the zkVM target, and the tools of the toolchain installed
+/// by `sp1up --c-toolchain` which they default to.
+const TOOLS: &[(&str, &str)] = &[
+    (CC_ENV, "riscv32-unknown-elf-gcc"),
+    (AR_ENV, "riscv32-unknown-elf-ar"),
+    (RANLIB_ENV, "riscv32-unknown-elf-ranlib"),
+    (OBJCOPY_ENV, "riscv32-unknown-elf-objcopy"),
+];
+
+/// How to install a C toolchain, following a description of the problem.
+pub(crate) const INSTALL_HELP: &str = "install it with `sp1up --c-toolchain` (or `brew install \
+                                       riscv64-elf-gcc` on macOS), or set \
+                                       `CC_riscv32im_succinct_zkvm_elf` to a RISC-V C compiler";
+
+/// The build dependencies which indicate that a crate compiles C code in its build script.
+const C_BUILD_DEPENDENCIES: &[&str] = &["cc", "cmake", "bindgen"];
+
+/// The directory `sp1up` installs into.
+fn sp1_dir() -> Option<PathBuf> {
+    Some(home_dir()?.join(".sp1"))
+}
+
+/// The names of the RISC-V C compilers which compile for the zkVM target, in order of preference:
+/// that of `sp1up --c-toolchain`, and those of other toolchains, such as `riscv64-elf-gcc` of
+/// Homebrew, which compile for it with the flags of [`RV32_FLAGS`].
+const COMPILER_NAMES: &[&str] =
+    &["riscv32-unknown-elf-gcc", "riscv64-unknown-elf-gcc", "riscv64-elf-gcc"];
+
+/// The flags a 64-bit RISC-V C compiler compiles for the zkVM target with.
+const RV32_FLAGS: &str = "-march=rv32im -mabi=ilp32";
+
+/// The directories Homebrew installs into, when `brew --prefix` cannot tell.
+const BREW_PREFIXES: &[&str] = &["/opt/homebrew", "/usr/local"];
+
+/// Where the C compilers for the zkVM target are looked for when none is configured.
+#[derive(Debug, Default)]
+struct Layout {
+    /// The directory `sp1up` installs into.
+    sp1_dir: Option<PathBuf>,
+    /// The prefixes of Homebrew, on macOS.
+    brew_prefixes: Vec<PathBuf>,
+    /// The directories of `PATH`.
+    path: Vec<PathBuf>,
+}
+
+impl Layout {
+    /// The layout of the host.
+    fn host() -> Self {
+        let brew_prefixes = if cfg!(target_os = "macos") { brew_prefixes() } else { vec![] };
+        let path = env::var_os("PATH").map(|path| env::split_paths(&path).collect());
+        Layout { sp1_dir: sp1_dir(), brew_prefixes, path: path.unwrap_or_default() }
+    }
+
+    /// The compilers to probe, in order, with where each is installed, for messages: those of
+    /// `sp1up --c-toolchain`, in `~/.sp1/bin` or `~/.sp1/riscv/bin`, those of Homebrew, and those
+    /// in `PATH`.
+    fn candidates(&self) -> Vec<(PathBuf, &'static str)> {
+        let mut dirs = vec![];
+        if let Some(sp1_dir) = &self.sp1_dir {
+            dirs.push((sp1_dir.join("bin"), "sp1up --c-toolchain"));
+            dirs.push((sp1_dir.join("riscv").join("bin"), "sp1up --c-toolchain"));
+        }
+        dirs.extend(self.brew_prefixes.iter().map(|prefix| (prefix.join("bin"), "Homebrew")));
+        dirs.extend(self.path.iter().map(|dir| (dir.clone(), "PATH")));
+        dirs.iter()
+            .flat_map(|(dir, source)| COMPILER_NAMES.iter().map(|name| (dir.join(name), *source)))
+            .collect()
+    }
+
+    /// The first of the candidates which exists.
+    fn probe(&self) -> Option<Compiler> {
+        self.candidates().into_iter().find_map(|(path, source)| {
+            Some(Compiler { path: host_path::executable(path)?, source })
+        })
+    }
+}
+
+/// The prefixes of Homebrew: the one `brew --prefix` prints, or else the default ones.
+fn brew_prefixes() -> Vec<PathBuf> {
+    static PREFIXES: OnceLock<Vec<PathBuf>> = OnceLock::new();
+    PREFIXES
+        .get_or_init(|| {
+            let output = Command::new("brew").arg("--prefix").stderr(Stdio::null()).output();
+            match output {
+                Ok(output) if output.status.success() => {
+                    vec![PathBuf::from(String::from_utf8_lossy(&output.stdout).trim())]
+                }
+                _ => BREW_PREFIXES.iter().map(PathBuf::from).collect(),
+            }
+        })
+        .clone()
+}
+
+/// The C compiler for the zkVM target installed on the host, if any, see [`Layout::candidates`].
+fn default_compiler() -> Option<Compiler> {
+    let compiler = Layout::host().probe()?;
+    tracing::debug!(
+        target: output::TRACING_TARGET,
+        compiler = %compiler.path.display(),
+        source = compiler.source,
+        "selected the C compiler"
+    );
+    Some(compiler)
+}
+
+/// The variables of the C toolchain for the zkVM target which are not set in the environment, set
+/// to the compiler installed on the host and the tools next to it which exist, such as
+/// `riscv32-unknown-elf-ar` next to `riscv32-unknown-elf-gcc`. A 64-bit compiler is given the
+/// flags of the zkVM target, unless they are set as well.
+pub(crate) fn default_env_vars() -> Vec<(&'static str, OsString)> {
+    let Some(compiler) = default_compiler() else {
+        return vec![];
+    };
+    let mut vars: Vec<_> =
+        tool_paths(&compiler.path).into_iter().map(|(key, path)| (key, path.into())).collect();
+    if compiler.path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("riscv64")) {
+        vars.push((CFLAGS_ENV, RV32_FLAGS.into()));
+    }
+    vars.retain(|(key, _)| env::var_os(key).is_none());
+    vars
+}
+
+/// The tools of the toolchain of the C compiler at `compiler` which exist, named with its prefix.
+fn tool_paths(compiler: &Path) -> Vec<(&'static str, PathBuf)> {
+    let name = compiler.file_stem().unwrap_or_default().to_string_lossy();
+    let prefix = name.strip_suffix("gcc").unwrap_or(&name);
+    let default_prefix = TOOLS[0].1.strip_suffix("gcc").unwrap();
+    TOOLS
+        .iter()
+        .filter_map(|(key, tool)| {
+            let tool = format!("{}{}", prefix, tool.strip_prefix(default_prefix).unwrap());
+            Some((*key, host_path::executable(compiler.with_file_name(tool))?))
+        })
+        .collect()
+}
+
+/// The variables configuring the C toolchain for the zkVM target set by [`BuildArgs::c_compiler`],
+/// [`BuildArgs::c_flags`], [`BuildArgs::cxx_flags`] and the binutils of [`BuildArgs`], which
+/// replace those of the environment. cc-rs splits the flags on whitespace.
+pub(crate) fn env_vars(args: &BuildArgs) -> Vec<(&'static str, String)> {
+    let mut vars = vec![];
+    let tools = [
+        (CC_ENV, &args.c_compiler),
+        (AR_ENV, &args.ar),
+        (RANLIB_ENV, &args.ranlib),
+        (OBJCOPY_ENV, &args.objcopy),
+    ];
+    for (key, tool) in tools {
+        if let Some(tool) = tool {
+            vars.push((key, tool.to_string_lossy().into_owned()));
+        }
+    }
+    if !args.c_flags.is_empty() {
+        vars.push((CFLAGS_ENV, args.c_flags.join(" ")));
+    }
+    if !args.cxx_flags.is_empty() {
+        vars.push((CXXFLAGS_ENV, args.cxx_flags.join(" ")));
+    }
+    vars
+}
+
+/// The C compiler a build uses, and where it was configured.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Compiler {
+    /// The path to the compiler.
+    pub(crate) path: PathBuf,
+    /// Where the compiler was configured, for messages.
+    pub(crate) source: &'static str,
+}
+
+/// The C compiler a local build with `args` uses: [`BuildArgs::c_compiler`], the one set as
+/// [`CC_ENV`], or the one installed on the host, see [`Layout::candidates`]. Returns the configured
+/// value as an error if it does not resolve to an existing compiler, or `None` if no compiler is
+/// configured or installed.
+pub(crate) fn compiler(args: &BuildArgs) -> Result<Compiler, Option<String>> {
+    if let Some(configured) = &args.c_compiler {
+        return match find_executable(configured) {
+            Some(path) => Ok(Compiler { path, source: "c-compiler" }),
+            None => Err(Some(configured.to_string_lossy().into_owned())),
+        };
+    }
+    if let Some(configured) = env::var_os(CC_ENV).filter(|cc| !cc.is_empty()) {
+        return match find_executable(Path::new(&configured)) {
+            Some(path) => Ok(Compiler { path, source: CC_ENV }),
+            None => Err(Some(configured.to_string_lossy().into_owned())),
+        };
+    }
+    default_compiler().ok_or(None)
+}
+
+/// Find `program` as a path, or in `PATH` if it is a bare name.
+pub(crate) fn find_executable(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The names of the crates compiled for the zkVM target which likely compile C code in their build
+/// script: those declaring `links`, or with a build dependency on `cc`, `cmake` or `bindgen`. Only
+/// the normal dependencies of the program are considered, since build dependencies and procedural
+/// macros are compiled for the host.
+pub(crate) fn crates_with_c_code(program_metadata: &Metadata) -> Vec<String> {
+    let Some(resolve) = &program_metadata.resolve else {
+        return vec![];
+    };
+    let Some(root) = &resolve.root else {
+        return vec![];
+    };
+    let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let packages: HashMap<_, _> =
+        program_metadata.packages.iter().map(|package| (&package.id, package)).collect();
+
+    let mut visited = HashSet::from([root]);
+    let mut stack = vec![root];
+    let mut crates = vec![];
+    while let Some(id) = stack.pop() {
+        let Some(package) = packages.get(id) else {
+            continue;
+        };
+        if package.targets.iter().any(|target| target.kind.iter().any(|kind| kind == "proc-macro"))
+        {
+            continue;
+        }
+        let compiles_c = package.links.is_some() ||
+            package.dependencies.iter().any(|dep| {
+                dep.kind == DependencyKind::Build &&
+                    C_BUILD_DEPENDENCIES.contains(&dep.name.as_str())
+            });
+        if compiles_c {
+            crates.push(package.name.clone());
+        }
+        for dep in nodes.get(id).map(|node| node.deps.as_slice()).unwrap_or_default() {
+            let normal = dep.dep_kinds.iter().any(|kind| kind.kind == DependencyKind::Normal);
+            if normal && visited.insert(&dep.pkg) {
+                stack.push(&dep.pkg);
+            }
+        }
+    }
+    crates.sort();
+    crates
+}
+
+/// Install the C toolchain with `sp1up --c-toolchain`, found in `~/.sp1/bin` or in `PATH`. Its
+/// output is printed prefixed with `[sp1]`. Concurrent builds wait for the installation rather
+/// than installing it again.
+pub(crate) fn install(cancel: Option<&CancellationToken>) -> Result<()> {
+    let sp1_dir = sp1_dir().ok_or_else(|| anyhow!("failed to find the home directory"))?;
+    let sp1up = Some(sp1_dir.join("bin").join("sp1up"))
+        .filter(|sp1up| sp1up.is_file())
+        .or_else(|| find_executable(Path::new("sp1up")))
+        .ok_or_else(|| anyhow!("sp1up is not installed"))?;
+    let sp1_dir = Utf8Path::from_path(&sp1_dir)
+        .ok_or_else(|| anyhow!("{} is not valid UTF-8", sp1_dir.display()))?;
+    install_with(&sp1up, sp1_dir, cancel)
+}
+
+/// Install the C toolchain into `sp1_dir` by running `sp1up --c-toolchain`.
+fn install_with(
+    sp1up: &Path,
+    sp1_dir: &Utf8Path,
+    cancel: Option<&CancellationToken>,
+) -> Result<()> {
+    let _lock = BuildLock::acquire(sp1_dir, cancel)?;
+    if sp1_dir.join("bin").join(TOOLS[0].1).exists() {
+        return Ok(());
+    }
+
+    output::info(&format!(
+        "[sp1] installing the C toolchain with `{} --c-toolchain`",
+        sp1up.display()
+    ));
+    let mut child = Command::new(sp1up)
+        .arg("--c-toolchain")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run {}", sp1up.display()))?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let prefix = output::prefix(None, false);
+    pipe::read_lines(stdout, stderr, |stream, line| match stream {
+        Stream::Stdout => output::cargo_stdout(&prefix, line, None),
+        Stream::Stderr => output::cargo_stderr(&prefix, line, None),
+    });
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("`{} --c-toolchain` exited with {}", sp1up.display(), status);
+    }
+    output::info("[sp1] installed the C toolchain");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{program_metadata, resolve_program_dir};
+
+    fn fixture_metadata(name: &str) -> Metadata {
+        let program_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        program_metadata(&resolve_program_dir(&program_dir).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_crates_with_c_code() {
+        // The build dependency declaring `links` is compiled for the host, and is not reported.
+        assert_eq!(crates_with_c_code(&fixture_metadata("c-dependency")), vec!["fixture-sys"]);
+        assert!(crates_with_c_code(&fixture_metadata("metadata")).is_empty());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        assert!(env_vars(&BuildArgs::default()).is_empty());
+        let args = BuildArgs {
+            c_compiler: Some(PathBuf::from("/opt/riscv/bin/clang")),
+            c_flags: vec!["-march=rv32im".to_string(), "-I/opt/include".to_string()],
+            ar: Some(PathBuf::from("/opt/riscv/bin/llvm-ar")),
+            ..Default::default()
+        };
+        assert_eq!(
+            env_vars(&args),
+            vec![
+                (CC_ENV, "/opt/riscv/bin/clang".to_string()),
+                (AR_ENV, "/opt/riscv/bin/llvm-ar".to_string()),
+                (CFLAGS_ENV, "-march=rv32im -I/opt/include".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_probe_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = Layout {
+            sp1_dir: Some(dir.path().join(".sp1")),
+            brew_prefixes: vec![dir.path().join("homebrew")],
+            path: vec![dir.path().join("usr/bin")],
+        };
+        let install = |path: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            path
+        };
+        let probed = || layout.probe().map(|compiler| (compiler.path, compiler.source));
+        assert_eq!(probed(), None);
+
+        let in_path = install("usr/bin/riscv32-unknown-elf-gcc");
+        assert_eq!(probed(), Some((in_path, "PATH")));
+        let brew = install("homebrew/bin/riscv64-elf-gcc");
+        assert_eq!(probed(), Some((brew.clone(), "Homebrew")));
+        let sp1up = install(".sp1/riscv/bin/riscv64-unknown-elf-gcc");
+        assert_eq!(probed(), Some((sp1up, "sp1up --c-toolchain")));
+        let sp1up = install(".sp1/bin/riscv32-unknown-elf-gcc");
+        assert_eq!(probed(), Some((sp1up.clone(), "sp1up --c-toolchain")));
+
+        let candidates = layout.candidates();
+        assert_eq!(candidates.len(), 4 * COMPILER_NAMES.len());
+        assert_eq!(candidates[0].0, sp1up);
+
+        // The tools of the toolchain are found next to the compiler, named with its prefix.
+        let ar = install("homebrew/bin/riscv64-elf-ar");
+        let objcopy = install("homebrew/bin/riscv64-elf-objcopy");
+        assert_eq!(tool_paths(&brew), vec![(CC_ENV, brew), (AR_ENV, ar), (OBJCOPY_ENV, objcopy)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_with_stub_sp1up() {
+        use std::{fs, os::unix::fs::PermissionsExt, thread};
+
+        let dir = tempfile::tempdir().unwrap();
+        let sp1_dir = Utf8Path::from_path(dir.path()).unwrap().join(".sp1");
+        let sp1up = dir.path().join("sp1up");
+        let script = format!(
+            "#!/bin/sh\n[ \"$1\" = --c-toolchain ] || exit 1\necho installing\n\
+             echo run >> {log}\nmkdir -p {bin} && touch {bin}/riscv32-unknown-elf-gcc\n",
+            log = dir.path().join("runs").display(),
+            bin = sp1_dir.join("bin"),
+        );
+        fs::write(&sp1up, script).unwrap();
+        fs::set_permissions(&sp1up, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Concurrent installations run sp1up once.
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| install_with(&sp1up, &sp1_dir, None).unwrap());
+            }
+        });
+        assert!(sp1_dir.join("bin/riscv32-unknown-elf-gcc").exists());
+        assert_eq!(fs::read_to_string(dir.path().join("runs")).unwrap(), "run\n");
+
+        fs::write(&sp1up, "#!/bin/sh\nexit 3\n").unwrap();
+        let err = install_with(&sp1up, &sp1_dir.with_file_name("other"), None);
+        assert!(err.unwrap_err().to_string().contains("exited with exit status: 3"));
+    }
+
+    #[test]
+    fn test_find_executable() {
+        assert!(find_executable(Path::new("sh")).is_some());
+        assert!(find_executable(Path::new("/nonexistent/riscv32-unknown-elf-gcc")).is_none());
+        assert!(find_executable(Path::new("riscv32-nonexistent-gcc")).is_none());
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    io,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// A handle to cancel a build from any thread, see [`crate::BuildOptions::cancellation_token`].
+///
+/// Clones share the same state, so cancelling any clone cancels the build. Cancelling a build
+/// kills cargo and everything it spawned, stops the Docker container if built with Docker, releases
+/// the lock on the target directory, and makes the build fail with
+/// [`crate::BuildError::Cancelled`]. Cancelling a build which already finished has no effect.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the builds using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether [`CancellationToken::cancel`] was called on this token or any of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A unique name for the Docker container of a cancellable build, so that it can be stopped.
+pub(crate) fn container_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!("sp1-build-{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst))
+}
+
+/// Spawn `command` so that it can be killed with everything it spawns by [`kill`]. On Unix, it runs
+/// in its own process group, which means that it no longer receives the signals sent to the
+/// terminal's process group, such as the interrupt of Ctrl-C.
+pub(crate) fn spawn(command: &mut Command) -> io::Result<Child> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    command.spawn()
+}
+
+/// Kill `child`, spawned with [`spawn`], and its descendants, and stop the Docker `container` it
+/// runs if any. Errors are ignored, as the processes may have exited already.
+pub(crate) fn kill(child: &mut Child, container: Option<&str>) {
+    if let Some(container) = container {
+        let _ = Command::new("docker")
+            .args(["kill", container])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+    #[cfg(unix)]
+    // SAFETY: Sending a signal has no memory safety requirements. The process group has the id of
+    // its leader, which is not reused while the child is not waited on.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        thread::spawn(move || clone.cancel()).join().unwrap();
+        assert!(token.is_cancelled());
+        assert_ne!(container_name(), container_name());
+    }
+}
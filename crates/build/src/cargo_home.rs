@@ -0,0 +1,90 @@
+//! The cargo home of a build, see [`BuildArgs::cargo_home`].
+//!
+//! By default, the nested cargo uses the cargo home of the developer, with its `config.toml`, its
+//! credentials and its registry cache. With [`BuildArgs::cargo_home`], it uses a directory of its
+//! own instead, which is created if missing and kept between builds, so that the crates downloaded
+//! by one build are reused by the next, while the configuration of the developer, such as source
+//! replacements, does not apply to the program.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{BuildArgs, ValidationError};
+
+/// The cargo home of the Docker image.
+pub(crate) const CONTAINER_CARGO_HOME: &str = "/root/.cargo";
+
+/// Where a Docker build mounts the cargo home of [`BuildArgs::cargo_home`]. The cargo home of the
+/// image is left in place, as it has the binaries of cargo and rustup.
+pub(crate) const CONTAINER_ISOLATED_CARGO_HOME: &str = "/root/.sp1-cargo-home";
+
+/// The cargo home set by `args` for the program in `program_dir`, relative to which a relative
+/// path is resolved.
+pub(crate) fn isolated(args: &BuildArgs, program_dir: &Path) -> Option<PathBuf> {
+    args.cargo_home.as_ref().map(|home| program_dir.join(home))
+}
+
+/// Creates the cargo home set by `args` for the program in `program_dir`, if missing. It is only
+/// created by a build, not by a dry run or a check of the arguments.
+pub(crate) fn create(args: &BuildArgs, program_dir: &Path) -> Result<(), ValidationError> {
+    match isolated(args, program_dir) {
+        Some(path) => fs::create_dir_all(&path)
+            .map_err(|error| ValidationError::CargoHome { path, error: error.to_string() }),
+        None => Ok(()),
+    }
+}
+
+/// The cargo home of a local build with `args`: that of `args`, `CARGO_HOME`, or `~/.cargo`.
+pub(crate) fn local(args: &BuildArgs, program_dir: &Path) -> Option<PathBuf> {
+    isolated(args, program_dir).or_else(|| {
+        env::var_os("CARGO_HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+    })
+}
+
+/// The cargo home of a Docker build with `args`, in the container.
+pub(crate) fn container(args: &BuildArgs) -> &'static str {
+    if args.cargo_home.is_some() {
+        CONTAINER_ISOLATED_CARGO_HOME
+    } else {
+        CONTAINER_CARGO_HOME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cargo_home() {
+        let args = BuildArgs { cargo_home: Some("../cargo-home".into()), ..Default::default() };
+        let program_dir = Path::new("/work/program");
+        assert_eq!(
+            isolated(&args, program_dir),
+            Some(PathBuf::from("/work/program/../cargo-home"))
+        );
+        assert_eq!(local(&args, program_dir), isolated(&args, program_dir));
+        assert_eq!(container(&args), CONTAINER_ISOLATED_CARGO_HOME);
+
+        let absolute = BuildArgs { cargo_home: Some("/cache/cargo".into()), ..args };
+        assert_eq!(isolated(&absolute, program_dir), Some(PathBuf::from("/cache/cargo")));
+        assert_eq!(isolated(&BuildArgs::default(), program_dir), None);
+        assert_eq!(container(&BuildArgs::default()), CONTAINER_CARGO_HOME);
+    }
+
+    #[test]
+    fn test_create() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = BuildArgs { cargo_home: Some("cargo-home".into()), ..Default::default() };
+        create(&args, dir.path()).unwrap();
+        assert!(dir.path().join("cargo-home").is_dir());
+
+        std::fs::write(dir.path().join("file"), "").unwrap();
+        let file = BuildArgs { cargo_home: Some("file/cargo-home".into()), ..args };
+        assert!(matches!(create(&file, dir.path()), Err(ValidationError::CargoHome { .. })));
+    }
+}
@@ -0,0 +1,153 @@
+//! Removing everything a build of a program wrote, see [`clean_program`].
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check_program_dir, elf_paths, helper_target_dir, metadata_cache, output, prune, resolve_args,
+    resolve_program_dir, shared_target, summary::human_size, toolchain::Toolchain, BuildArgs,
+    HELPER_TARGET_SUBDIR,
+};
+
+/// The files written next to an ELF, named after it with these suffixes: its fingerprint, its
+/// checksum, its SBOMs and its build manifest.
+const SIDECAR_SUFFIXES: [&str; 7] = [
+    ".fingerprint",
+    ".sha256",
+    ".cdx.json",
+    ".spdx.json",
+    crate::manifest::MANIFEST_SUFFIX,
+    crate::env_snapshot::ENV_SNAPSHOT_SUFFIX,
+    crate::debuginfo::DEBUG_SUFFIX,
+];
+
+/// What [`clean_program`] removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanReport {
+    /// The files and directories removed, or which would be removed in a dry run.
+    pub removed: Vec<Utf8PathBuf>,
+    /// The bytes freed by removing them.
+    pub bytes_freed: u64,
+    /// The directories which were kept, and why, such as a shared target directory.
+    pub kept: Vec<(Utf8PathBuf, String)>,
+}
+
+/// Remove what building the program in `program_dir` with `args` wrote, as `cargo clean` does for
+/// a crate: the target directories it compiles into, both for local and Docker builds, the ELFs
+/// copied to the output directory, and the fingerprint, checksum and SBOM files next to them.
+///
+/// Only paths named as this crate names them are removed: a target directory is removed if it is
+/// named `elf-compilation`, or is its `docker` subdirectory, and the shared target directory of
+/// [`BuildArgs::shared_target`], which other programs compile into, is kept, as is a target
+/// directory locked by a build in progress. The build locks of the target directories removed are
+/// kept, see [`crate::prune_build_caches`]. With [`BuildArgs::dry_run`], nothing is removed, and
+/// the report lists what would be.
+pub fn clean_program(args: &BuildArgs, program_dir: impl AsRef<Path>) -> Result<CleanReport> {
+    let program_dir = program_dir.as_ref();
+    check_program_dir(program_dir)?;
+    let program_dir = resolve_program_dir(program_dir)?;
+    let program_metadata = metadata_cache::metadata(args, &program_dir)?;
+    let args = resolve_args(args, &program_dir, &program_metadata)?;
+    let _mode = output::set_mode(output::OutputMode::of(&args));
+
+    let mut report = CleanReport::default();
+    let shared = shared_target::shared_target_dir(&args);
+    for docker in [false, true] {
+        let target_dir =
+            helper_target_dir(&BuildArgs { docker, ..args.clone() }, &program_metadata);
+        if report.removed.iter().any(|removed| target_dir.starts_with(removed)) ||
+            !target_dir.is_dir()
+        {
+            continue;
+        }
+        if shared.as_ref() == Some(&target_dir) {
+            report.kept.push((target_dir, "shared with other programs".to_string()));
+        } else if !is_target_dir(&target_dir) {
+            report.kept.push((target_dir, "not a target directory of sp1-build".to_string()));
+        } else {
+            let (size, _) = prune::usage(target_dir.as_std_path())
+                .with_context(|| format!("failed to measure {}", target_dir))?;
+            match prune::delete(&target_dir, args.dry_run)? {
+                prune::CacheStatus::InUse => {
+                    report.kept.push((target_dir, "a build is using it".to_string()))
+                }
+                _ => {
+                    report.removed.push(target_dir);
+                    report.bytes_freed += size;
+                }
+            }
+        }
+    }
+
+    let mut elfs = vec![];
+    for toolchain in [Toolchain::Succinct, Toolchain::NightlyFallback] {
+        for (_, elf_path) in elf_paths(&args, &program_metadata, toolchain)? {
+            if !elfs.contains(&elf_path) {
+                elfs.push(elf_path);
+            }
+        }
+    }
+    for elf_path in elfs {
+        let sidecars =
+            SIDECAR_SUFFIXES.iter().map(|suffix| format!("{}{}", elf_path, suffix).into());
+        for path in std::iter::once(elf_path.clone()).chain(sidecars) {
+            remove_file(&path, args.dry_run, &mut report)?;
+        }
+    }
+
+    let verb = if args.dry_run { "would remove" } else { "removed" };
+    for path in &report.removed {
+        output::info(&format!("[sp1] {} {}", verb, path));
+    }
+    for (path, reason) in &report.kept {
+        output::info(&format!("[sp1] kept {}, {}", path, reason));
+    }
+    output::info(&format!(
+        "[sp1] {} {} files and directories, {} in total",
+        verb,
+        report.removed.len(),
+        human_size(report.bytes_freed)
+    ));
+    Ok(report)
+}
+
+/// Whether `dir` is named as the target directories programs compile into.
+fn is_target_dir(dir: &Utf8Path) -> bool {
+    match dir.file_name() {
+        Some(HELPER_TARGET_SUBDIR) => true,
+        Some("docker") => dir.parent().and_then(Utf8Path::file_name) == Some(HELPER_TARGET_SUBDIR),
+        _ => false,
+    }
+}
+
+fn remove_file(path: &Utf8Path, dry_run: bool, report: &mut CleanReport) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.is_file() {
+        return Ok(());
+    }
+    if !dry_run {
+        fs::remove_file(path).with_context(|| format!("failed to remove {}", path))?;
+    }
+    report.removed.push(path.to_path_buf());
+    report.bytes_freed += metadata.len();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_target_dir() {
+        assert!(is_target_dir(Utf8Path::new("/program/target/elf-compilation")));
+        assert!(is_target_dir(Utf8Path::new("/program/target/elf-compilation/docker")));
+        assert!(!is_target_dir(Utf8Path::new("/program/target/docker")));
+        assert!(!is_target_dir(Utf8Path::new("/home/user/.sp1/build-cache/succinct-0123")));
+        assert!(!is_target_dir(Utf8Path::new("/")));
+    }
+}
@@ -0,0 +1,106 @@
+use cargo_metadata::semver::{Version, VersionReq};
+
+use crate::ValidationError;
+
+/// The releases of the succinct toolchain which each release of the SP1 crates is compatible with,
+/// as Rust versions in `major.minor` form. Add a row for each release of SP1 which requires a new
+/// toolchain; versions of the crates which no row covers are not checked.
+const COMPATIBILITY: &[(&str, &[&str])] = &[
+    // (SP1 crate versions, toolchain versions)
+    (">=1.0.0, <2.0.0", &["1.79"]),
+];
+
+/// The crates of a program whose versions must match the toolchain.
+const CHECKED_CRATES: &[&str] = &["sp1-zkvm", "sp1-lib"];
+
+/// The recommended pairings of SP1 crates and toolchains, as a human readable list.
+pub(crate) fn pairings() -> String {
+    COMPATIBILITY
+        .iter()
+        .map(|(sp1, toolchains)| format!("SP1 {} with toolchain {}", sp1, toolchains.join("/")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Check the SP1 crates the program described by `program_metadata` resolved to against the
+/// toolchain `release`, such as `1.79.0-dev`, returning a
+/// [`ValidationError::IncompatibleToolchain`] for each crate which is not compatible with it.
+pub(crate) fn check(
+    program_metadata: &cargo_metadata::Metadata,
+    release: &str,
+) -> Vec<ValidationError> {
+    let crates = program_metadata
+        .packages
+        .iter()
+        .filter(|package| CHECKED_CRATES.contains(&package.name.as_str()))
+        .map(|package| (package.name.as_str(), &package.version));
+    check_versions(crates, release)
+}
+
+fn check_versions<'a>(
+    crates: impl IntoIterator<Item = (&'a str, &'a Version)>,
+    release: &str,
+) -> Vec<ValidationError> {
+    let Ok(toolchain) = Version::parse(release) else {
+        return vec![];
+    };
+    let toolchain_minor = format!("{}.{}", toolchain.major, toolchain.minor);
+
+    let mut incompatibilities = vec![];
+    for (name, version) in crates {
+        let row = COMPATIBILITY.iter().find(|(sp1, _)| {
+            VersionReq::parse(sp1).is_ok_and(|req| {
+                // Pre-releases of the crates follow the toolchain of their release.
+                let release = Version::new(version.major, version.minor, version.patch);
+                req.matches(&release)
+            })
+        });
+        let Some((_, toolchains)) = row else {
+            continue;
+        };
+        if !toolchains.contains(&toolchain_minor.as_str()) {
+            incompatibilities.push(ValidationError::IncompatibleToolchain {
+                crate_name: name.to_string(),
+                crate_version: version.to_string(),
+                toolchain: release.to_string(),
+                compatible: toolchains.iter().map(|t| t.to_string()).collect(),
+            });
+        }
+    }
+    incompatibilities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_matrix_parses() {
+        for (sp1, toolchains) in COMPATIBILITY {
+            VersionReq::parse(sp1).unwrap();
+            assert!(!toolchains.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_check_versions() {
+        let v1 = Version::parse("1.1.1").unwrap();
+        let v1_rc = Version::parse("1.2.0-rc.1").unwrap();
+        let unknown = Version::parse("0.9.0").unwrap();
+        let crates = [("sp1-zkvm", &v1), ("sp1-lib", &v1_rc), ("sp1-zkvm", &unknown)];
+
+        assert!(check_versions(crates, "1.79.0-dev").is_empty());
+        let incompatibilities = check_versions(crates, "1.81.0-dev");
+        assert_eq!(incompatibilities.len(), 2);
+        assert_eq!(
+            incompatibilities[0],
+            ValidationError::IncompatibleToolchain {
+                crate_name: "sp1-zkvm".to_string(),
+                crate_version: "1.1.1".to_string(),
+                toolchain: "1.81.0-dev".to_string(),
+                compatible: vec!["1.79".to_string()],
+            }
+        );
+        assert!(incompatibilities[1].to_string().starts_with("sp1-lib 1.2.0-rc.1 requires"));
+    }
+}
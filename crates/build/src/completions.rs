@@ -0,0 +1,60 @@
+//! Shell completions of the build arguments, for CLIs wrapping [`BuildArgs`].
+//!
+//! A CLI taking the build arguments can generate its completions from its own `completions`
+//! subcommand rather than maintaining them, so that they stay up to date with [`BuildArgs`]:
+//!
+//! ```no_run
+//! use sp1_build::completions::{generate_completions, Shell};
+//!
+//! generate_completions(Shell::Bash, "my-prove", &mut std::io::stdout());
+//! ```
+//!
+//! Arguments taking paths, such as `--output-directory`, complete paths.
+
+use std::io::Write;
+
+pub use clap_complete::Shell;
+
+use crate::BuildArgs;
+
+/// Write the completions of `shell` for the command `cmd_name`, whose arguments are the
+/// [`BuildArgs`], to `out`.
+pub fn generate_completions(shell: Shell, cmd_name: &str, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut command(), cmd_name, out);
+}
+
+/// The command whose arguments are the [`BuildArgs`], to add the subcommands or arguments of a
+/// wrapper CLI to before generating its completions with [`clap_complete::generate`].
+pub fn command() -> clap::Command {
+    BuildArgs::command()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completions(shell: Shell) -> String {
+        let mut out = vec![];
+        generate_completions(shell, "my-prove", &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_generate_completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let completions = completions(shell);
+            assert!(completions.contains("my-prove"), "{}", shell);
+            assert!(completions.contains("output-directory"), "{}", shell);
+            assert!(completions.contains("rustc-wrapper"), "{}", shell);
+        }
+
+        // Paths are completed, with directories only for the output directory.
+        let zsh = completions(Shell::Zsh);
+        let line = |arg: &str| zsh.lines().find(|line| line.contains(arg)).unwrap().to_string();
+        assert!(line("--output-directory=").contains("_files -/"), "{}", zsh);
+        assert!(line("'--log-file=").contains("_files"), "{}", zsh);
+        assert!(line("'--c-compiler=").contains("_absolute_command_paths"), "{}", zsh);
+        let fish = completions(Shell::Fish);
+        assert!(fish.contains("__fish_complete_directories"), "{}", fish);
+    }
+}
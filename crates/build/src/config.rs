@@ -0,0 +1,539 @@
+//! Resolution of the [`BuildArgs`] to build with from the layers of configuration, see
+//! [`ResolvedBuildArgs`].
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::camino::Utf8Path;
+use clap::parser::{ArgMatches, ValueSource};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::{output, overrides, BuildArgs, BuildWarning, WarningKind};
+
+/// The name of the configuration file in the program directory.
+pub(crate) const CONFIG_FILE: &str = "sp1.toml";
+
+/// The tables grouping fields, such as `[package.metadata.sp1.post-build]`, with the field each
+/// of their keys sets.
+const SECTIONS: [(&str, &[(&str, &str)]); 2] = [
+    ("pre-build", &[("hooks", "pre_build_hooks")]),
+    (
+        "post-build",
+        &[
+            ("hooks", "post_build_hooks"),
+            ("allow-failure", "allow_hook_failure"),
+            ("run-on-skip", "run_hooks_on_skip"),
+        ],
+    ),
+];
+
+/// A layer of configuration: the values it sets, keyed by [`BuildArgs`] field name.
+pub(crate) type Layer = Map<String, Value>;
+
+/// The layer of configuration which supplied a value of the [`ResolvedBuildArgs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The default value of the field.
+    Default,
+    /// The `[package.metadata.sp1]` table of the program's `Cargo.toml`.
+    PackageMetadata,
+    /// A configuration file.
+    ConfigFile(PathBuf),
+    /// An `SP1_BUILD_*` environment variable.
+    Env,
+    /// The arguments passed explicitly in code or on the command line.
+    Explicit,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::PackageMetadata => write!(f, "[package.metadata.sp1]"),
+            ConfigSource::ConfigFile(path) => write!(f, "{}", path.display()),
+            ConfigSource::Env => write!(f, "environment"),
+            ConfigSource::Explicit => write!(f, "explicit"),
+        }
+    }
+}
+
+/// The [`BuildArgs`] resolved from all layers of configuration, along with the layers which
+/// supplied each value. From the highest to the lowest precedence, the layers are:
+///
+/// 1. The arguments passed explicitly in code or on the command line.
+/// 2. The `SP1_BUILD_*` environment variables, see [`crate::build_env_vars`].
+/// 3. The `sp1.toml` (or `.sp1/build.toml`) file in the program directory.
+/// 4. The `[package.metadata.sp1]` table in the program's `Cargo.toml`.
+/// 5. The defaults of [`BuildArgs`].
+///
+/// An argument counts as passed explicitly if it differs from its default value, or if it was
+/// marked with [`BuildArgs::explicitly_set`], which the [`crate::BuildArgsBuilder`] setters and
+/// [`BuildArgs::explicitly_set_from`] do, so that e.g. `docker: false` overrides
+/// `SP1_BUILD_DOCKER=true`. Lists, such as `features` and `rustflags`, are not replaced by higher
+/// layers but concatenated, from the lowest to the highest precedence, keeping repeated values
+/// since tokens such as `-C` are meaningful more than once. `output_directory` and `build_when`,
+/// which choose where and when to build, are replaced like the other fields.
+///
+/// The configuration file and the metadata table use the field names of [`BuildArgs`] in
+/// kebab-case, at the top level of the file and within the table respectively:
+///
+/// ```toml
+/// [package.metadata.sp1]
+/// features = ["bls"]
+/// elf-name = "fibonacci-elf"
+/// docker = true
+/// rustflags = ["-Copt-level=3"]
+/// ```
+#[derive(Clone, Debug)]
+pub struct ResolvedBuildArgs {
+    args: BuildArgs,
+    sources: BTreeMap<String, Vec<ConfigSource>>,
+}
+
+impl ResolvedBuildArgs {
+    /// The resolved arguments.
+    pub fn args(&self) -> &BuildArgs {
+        &self.args
+    }
+
+    /// Consume `self`, returning the resolved arguments.
+    pub fn into_args(self) -> BuildArgs {
+        self.args
+    }
+
+    /// The layers which supplied the value of `field`. Lists may have several.
+    pub fn sources(&self, field: &str) -> &[ConfigSource] {
+        self.sources.get(field).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Describe each resolved value and the layers which supplied it, one per line.
+    pub fn explain(&self) -> String {
+        let values = to_layer(&self.args);
+        let mut lines = vec!["[sp1] resolved build configuration:".to_string()];
+        for (field, sources) in &self.sources {
+            let sources = sources
+                .iter()
+                .map(|source| match source {
+                    ConfigSource::Env => overrides::env_var_name(field),
+                    source => source.to_string(),
+                })
+                .collect::<Vec<_>>();
+            lines.push(format!("[sp1]   {} = {} ({})", field, values[field], sources.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+impl BuildArgs {
+    /// Resolve the arguments to build the program in `program_dir` with, from all layers of
+    /// configuration. See [`ResolvedBuildArgs`] for their precedence.
+    pub fn resolve(&self, program_dir: impl AsRef<Path>) -> Result<ResolvedBuildArgs> {
+        let program_dir = crate::resolve_program_dir(program_dir.as_ref())?;
+        resolve(self, &program_dir, &crate::program_metadata(&program_dir)?)
+    }
+
+    /// Read [`BuildArgs`] from a TOML configuration file, with the same keys as the
+    /// `[package.metadata.sp1]` table. Fields missing from the file take their default values.
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let layer = file_layer(path)?;
+        Ok(resolve_layers(vec![(ConfigSource::ConfigFile(path.to_path_buf()), layer)])?.args)
+    }
+}
+
+/// Resolve `args` for the program in `program_dir` from all layers of configuration.
+pub(crate) fn resolve(
+    args: &BuildArgs,
+    program_dir: &Utf8Path,
+    program_metadata: &cargo_metadata::Metadata,
+) -> Result<ResolvedBuildArgs> {
+    let mut layers = vec![];
+    if let Some(layer) = metadata_layer(program_metadata)? {
+        layers.push((ConfigSource::PackageMetadata, layer));
+    }
+    if let Some(path) = find_config_file(program_dir.as_std_path()) {
+        layers.push((ConfigSource::ConfigFile(path.clone()), file_layer(&path)?));
+    }
+    if !args.ignore_env_overrides {
+        layers.push((ConfigSource::Env, overrides::env_layer(|name| std::env::var(name).ok())?));
+    }
+    layers.push((ConfigSource::Explicit, explicit_layer(args)));
+
+    let mut resolved = resolve_layers(layers)?;
+    resolved.args.ignore_env_overrides = args.ignore_env_overrides;
+    resolved.args.linked_features.clone_from(&args.linked_features);
+    Ok(resolved)
+}
+
+/// The lists which choose where or when to build rather than adding to the build, and so are
+/// replaced by a higher layer instead of concatenated.
+const REPLACED_LISTS: [&str; 2] = ["output_directory", "build_when"];
+
+/// Merge `layers`, given from the lowest to the highest precedence, over the defaults.
+pub(crate) fn resolve_layers(layers: Vec<(ConfigSource, Layer)>) -> Result<ResolvedBuildArgs> {
+    let mut merged = default_layer();
+    let mut sources: BTreeMap<String, Vec<ConfigSource>> =
+        merged.keys().map(|field| (field.clone(), vec![ConfigSource::Default])).collect();
+
+    for (source, layer) in layers {
+        for (field, value) in layer {
+            let field_sources = sources.entry(field.clone()).or_default();
+            match (merged.get_mut(&field), value) {
+                (Some(Value::Array(list)), Value::Array(values)) => {
+                    if values.is_empty() {
+                        continue;
+                    }
+                    if REPLACED_LISTS.contains(&field.as_str()) {
+                        list.clear();
+                        field_sources.clear();
+                    }
+                    list.extend(values);
+                    field_sources.retain(|s| *s != ConfigSource::Default);
+                    field_sources.push(source.clone());
+                }
+                (_, value) => {
+                    merged.insert(field, value);
+                    *field_sources = vec![source.clone()];
+                }
+            }
+        }
+    }
+
+    let args = serde_json::from_value(Value::Object(merged))
+        .context("failed to resolve the build configuration")?;
+    Ok(ResolvedBuildArgs { args, sources })
+}
+
+/// The default value of every field, keyed by field name.
+pub(crate) fn default_layer() -> Layer {
+    to_layer(&BuildArgs::default())
+}
+
+pub(crate) fn to_layer(args: &BuildArgs) -> Layer {
+    match serde_json::to_value(args).expect("failed to serialize BuildArgs") {
+        Value::Object(map) => map,
+        _ => unreachable!("BuildArgs serializes to a map"),
+    }
+}
+
+/// The fields of `args` which differ from their defaults or were marked as explicitly set.
+pub(crate) fn explicit_layer(args: &BuildArgs) -> Layer {
+    let defaults = default_layer();
+    to_layer(args)
+        .into_iter()
+        .filter(|(field, value)| {
+            defaults.get(field) != Some(value) || args.explicit_fields.contains(field)
+        })
+        .collect()
+}
+
+impl BuildArgs {
+    /// Count `field` as passed explicitly even if it equals its default value, so that it
+    /// overrides the environment variables and configuration files, see [`ResolvedBuildArgs`].
+    ///
+    /// ```
+    /// use sp1_build::BuildArgs;
+    ///
+    /// let args = BuildArgs { docker: false, ..Default::default() }.explicitly_set("docker");
+    /// assert_eq!(args.explicit_fields, vec!["docker".to_string()]);
+    /// ```
+    pub fn explicitly_set(mut self, field: &str) -> Self {
+        if !self.explicit_fields.iter().any(|f| f == field) {
+            self.explicit_fields.push(field.to_string());
+        }
+        self
+    }
+
+    /// Count the arguments given on the command line in `matches` as passed explicitly, see
+    /// [`BuildArgs::explicitly_set`].
+    pub fn explicitly_set_from(mut self, matches: &ArgMatches) -> Self {
+        let fields = default_layer();
+        for id in matches.ids().map(|id| id.as_str()) {
+            if fields.contains_key(id) && matches.value_source(id) == Some(ValueSource::CommandLine)
+            {
+                self = self.explicitly_set(id);
+            }
+        }
+        self
+    }
+}
+
+/// Check that `value` is valid for `field` by deserializing it over the defaults.
+pub(crate) fn is_valid_value(field: &str, value: &Value) -> bool {
+    let mut layer = default_layer();
+    layer.insert(field.to_string(), value.clone());
+    serde_json::from_value::<BuildArgs>(Value::Object(layer)).is_ok()
+}
+
+/// Deserialize a list which may also be a single value, as `output_directory` was before it
+/// became a list.
+pub(crate) fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Convert a table of configuration with kebab-case keys into a layer. Unknown keys are skipped
+/// with a warning, and invalid values are an error mentioning `origin`.
+fn table_layer(table: &Map<String, Value>, origin: &str) -> Result<Layer> {
+    let defaults = default_layer();
+    let mut layer = Layer::new();
+    for (key, field, value) in entries(table) {
+        if !defaults.contains_key(&field) {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::UnknownConfigKey,
+                    format!("unknown key `{}` in {}", key, origin),
+                )
+                .field("key", key)
+                .field("origin", origin),
+            );
+            continue;
+        }
+        if !is_valid_value(&field, value) {
+            return Err(anyhow!("invalid value {} for `{}` in {}", value, key, origin));
+        }
+        // A single value of a list is merged with the other layers as a list of one.
+        let value = match (&defaults[&field], value) {
+            (Value::Array(_), Value::String(_)) => Value::Array(vec![value.clone()]),
+            _ => value.clone(),
+        };
+        layer.insert(field, value);
+    }
+    Ok(layer)
+}
+
+/// The entries of `table`, with those of its [`SECTIONS`] spelled out: the key, such as
+/// `post-build.hooks`, the field it sets, empty if none, and the value.
+fn entries(table: &Map<String, Value>) -> Vec<(String, String, &Value)> {
+    let mut entries = vec![];
+    for (key, value) in table {
+        let section = SECTIONS.iter().find(|(name, _)| name == key);
+        match (section, value.as_object()) {
+            (Some((_, keys)), Some(section)) => {
+                for (inner, value) in section {
+                    let field = keys.iter().find(|(name, _)| name == inner);
+                    let field = field.map(|(_, field)| field.to_string()).unwrap_or_default();
+                    entries.push((format!("{}.{}", key, inner), field, value));
+                }
+            }
+            _ => entries.push((key.clone(), key.replace('-', "_"), value)),
+        }
+    }
+    entries
+}
+
+/// Read the layer from the `[package.metadata.sp1]` table of the root package, if any.
+fn metadata_layer(program_metadata: &cargo_metadata::Metadata) -> Result<Option<Layer>> {
+    let Some(package) = program_metadata.root_package() else {
+        return Ok(None);
+    };
+    let Some(table) = package.metadata.get("sp1") else {
+        return Ok(None);
+    };
+    let origin = format!("[package.metadata.sp1] of {}", package.manifest_path);
+    let table = table.as_object().ok_or_else(|| anyhow!("{} is not a table", origin))?;
+    table_layer(table, &origin).map(Some)
+}
+
+/// Read the layer from a TOML configuration file.
+fn file_layer(path: &Path) -> Result<Layer> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let table: Map<String, Value> = toml::from_str(&contents)
+        .map_err(|e| anyhow!("invalid config file {}: {}", path.display(), e))?;
+    table_layer(&table, &path.display().to_string())
+}
+
+/// Find the configuration file in `program_dir`: `sp1.toml`, or else `.sp1/build.toml`.
+fn find_config_file(program_dir: &Path) -> Option<PathBuf> {
+    let candidates = [program_dir.join(CONFIG_FILE), program_dir.join(".sp1").join("build.toml")];
+    let mut found = candidates.into_iter().filter(|path| path.is_file());
+    let file = found.next()?;
+    if let Some(ignored) = found.next() {
+        output::warning(
+            BuildWarning::new(
+                WarningKind::DuplicateConfigFile,
+                format!(
+                    "both {} and {} exist, ignoring the latter",
+                    file.display(),
+                    ignored.display()
+                ),
+            )
+            .field("path", file.display())
+            .field("ignored", ignored.display()),
+        );
+    }
+    Some(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn layer(value: Value) -> Layer {
+        table_layer(value.as_object().unwrap(), "test").unwrap()
+    }
+
+    #[test]
+    fn test_table_layer() {
+        let layer = layer(json!({"elf-name": "fib", "docker": true, "foo": 1}));
+        assert_eq!(layer.get("elf_name"), Some(&json!("fib")));
+        assert_eq!(layer.get("docker"), Some(&json!(true)));
+        assert!(!layer.contains_key("foo"));
+
+        let err = table_layer(json!({"docker": "yes"}).as_object().unwrap(), "test").unwrap_err();
+        assert!(err.to_string().contains("`docker`"));
+
+        let post_build =
+            json!({"post-build": {"hooks": ["./upload.sh"], "run-on-skip": true, "x": 1}});
+        let layer = table_layer(post_build.as_object().unwrap(), "test").unwrap();
+        assert_eq!(layer.get("post_build_hooks"), Some(&json!(["./upload.sh"])));
+        assert_eq!(layer.get("run_hooks_on_skip"), Some(&json!(true)));
+        assert_eq!(layer.len(), 2);
+        let err = table_layer(json!({"post-build": {"hooks": 1}}).as_object().unwrap(), "test");
+        assert!(err.unwrap_err().to_string().contains("`post-build.hooks`"));
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let file = PathBuf::from("sp1.toml");
+        let resolved = resolve_layers(vec![
+            (ConfigSource::PackageMetadata, layer(json!({"tag": "v1", "elf-name": "metadata"}))),
+            (ConfigSource::ConfigFile(file), layer(json!({"elf-name": "file"}))),
+            (ConfigSource::Env, layer(json!({"elf-name": "env", "docker": true}))),
+            (ConfigSource::Explicit, explicit_layer(&BuildArgs::default())),
+        ])
+        .unwrap();
+        assert_eq!(resolved.args.tag, "v1");
+        assert_eq!(resolved.args.elf_name, "env");
+        assert!(resolved.args.docker);
+        assert_eq!(resolved.sources("tag"), [ConfigSource::PackageMetadata]);
+        assert_eq!(resolved.sources("elf_name"), [ConfigSource::Env]);
+        assert_eq!(resolved.sources("binary"), [ConfigSource::Default]);
+
+        let explicit = BuildArgs { elf_name: "explicit".to_string(), ..Default::default() };
+        let resolved = resolve_layers(vec![
+            (ConfigSource::Env, layer(json!({"elf-name": "env"}))),
+            (ConfigSource::Explicit, explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert_eq!(resolved.args.elf_name, "explicit");
+        assert_eq!(resolved.sources("elf_name"), [ConfigSource::Explicit]);
+    }
+
+    #[test]
+    fn test_lists_are_concatenated() {
+        let explicit = BuildArgs { features: vec!["b".into(), "c".into()], ..Default::default() };
+        let resolved = resolve_layers(vec![
+            (ConfigSource::PackageMetadata, layer(json!({"features": ["a", "b"]}))),
+            (ConfigSource::Env, layer(json!({"features": []}))),
+            (ConfigSource::Explicit, explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert_eq!(resolved.args.features, vec!["a", "b", "b", "c"]);
+        assert_eq!(
+            resolved.sources("features"),
+            [ConfigSource::PackageMetadata, ConfigSource::Explicit]
+        );
+        assert_eq!(resolved.sources("rustflags"), [ConfigSource::Default]);
+        assert!(resolved.explain().contains("features = [\"a\",\"b\",\"b\",\"c\"]"));
+
+        // Repeated flags, such as the `-C` of each codegen option, are kept.
+        let explicit =
+            BuildArgs { rustflags: vec!["-C".into(), "debuginfo=1".into()], ..Default::default() };
+        let resolved = resolve_layers(vec![
+            (ConfigSource::Env, layer(json!({"rustflags": ["-C", "opt-level=3"]}))),
+            (ConfigSource::Explicit, explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert_eq!(resolved.args.rustflags, vec!["-C", "opt-level=3", "-C", "debuginfo=1"]);
+
+        // A single output directory, as before it became a list, is a list of one, and the output
+        // directories of a higher layer replace those of a lower one.
+        let explicit = BuildArgs { output_directory: vec!["out".into()], ..Default::default() };
+        let resolved = resolve_layers(vec![
+            (
+                ConfigSource::ConfigFile("sp1.toml".into()),
+                layer(json!({"output-directory": "elf"})),
+            ),
+            (ConfigSource::Explicit, explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert_eq!(resolved.args.output_directory, vec!["out"]);
+        assert_eq!(resolved.sources("output_directory"), [ConfigSource::Explicit]);
+        let stored: BuildArgs = serde_json::from_str(r#"{"output_directory":"out"}"#).unwrap();
+        assert_eq!(stored.output_directory, vec!["out"]);
+    }
+
+    #[test]
+    fn test_explicit_defaults_override() {
+        let explicit = BuildArgs::builder().docker(false).locked(false).build().unwrap();
+        let resolved = resolve_layers(vec![
+            (ConfigSource::ConfigFile("sp1.toml".into()), layer(json!({"locked": true}))),
+            (ConfigSource::Env, layer(json!({"docker": true}))),
+            (ConfigSource::Explicit, explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert!(!resolved.args.docker && !resolved.args.locked);
+        assert_eq!(resolved.sources("docker"), [ConfigSource::Explicit]);
+
+        let resolved = resolve_layers(vec![
+            (ConfigSource::Env, layer(json!({"docker": true}))),
+            (ConfigSource::Explicit, explicit_layer(&BuildArgs::default())),
+        ])
+        .unwrap();
+        assert!(resolved.args.docker);
+
+        let cli = clap::Command::new("build").arg(clap::arg!(--docker)).arg(clap::arg!(--locked));
+        let matches = cli.get_matches_from(["build", "--docker"]);
+        let args = BuildArgs::default().explicitly_set_from(&matches);
+        assert_eq!(args.explicit_fields, vec!["docker"]);
+    }
+
+    #[test]
+    fn test_from_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+
+        fs::write(&path, "docker = true\nfeatures = [\"a\"]\n").unwrap();
+        let args = BuildArgs::from_config_file(&path).unwrap();
+        assert_eq!(
+            args,
+            BuildArgs { docker: true, features: vec!["a".into()], ..Default::default() }
+        );
+
+        fs::write(&path, "docker = true\nfeatures = [\"a\"\n").unwrap();
+        let err = BuildArgs::from_config_file(&path).unwrap_err().to_string();
+        assert!(err.contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(find_config_file(dir.path()), None);
+
+        fs::create_dir(dir.path().join(".sp1")).unwrap();
+        fs::write(dir.path().join(".sp1/build.toml"), "").unwrap();
+        assert_eq!(find_config_file(dir.path()), Some(dir.path().join(".sp1/build.toml")));
+
+        fs::write(dir.path().join(CONFIG_FILE), "").unwrap();
+        assert_eq!(find_config_file(dir.path()), Some(dir.path().join(CONFIG_FILE)));
+    }
+}
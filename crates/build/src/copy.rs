@@ -0,0 +1,108 @@
+//! Copying the compiled ELFs to their output directories, see [`copy_all`].
+
+use std::{fs, io, process};
+
+use anyhow::Result;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{BuildError, CopyOutcome};
+
+/// Copy each of `sources` to the destination at the same index.
+///
+/// Each source is first copied next to its destination, and only moved into place once all of
+/// them were copied, so that a destination is never half-written and, unless moving a copy into
+/// place fails, none is updated when another could not be written. Fails with
+/// [`BuildError::Artifact`] for a single destination, and with [`BuildError::Copy`] reporting each
+/// destination otherwise.
+pub(crate) fn copy_all(sources: &[Utf8PathBuf], destinations: &[Utf8PathBuf]) -> Result<()> {
+    let staged: Vec<_> =
+        sources.iter().zip(destinations).map(|(source, dest)| stage(source, dest)).collect();
+    if staged.iter().any(Result::is_err) {
+        let outcomes = staged.into_iter().map(|staged| match staged {
+            Ok(staging) => {
+                let _ = fs::remove_file(staging);
+                CopyOutcome::NotUpdated
+            }
+            Err(err) => CopyOutcome::Failed(err),
+        });
+        return Err(error(destinations, outcomes.collect()));
+    }
+
+    let staged: Vec<_> = staged.into_iter().flatten().collect();
+    for (index, (staging, destination)) in staged.iter().zip(destinations).enumerate() {
+        if let Err(err) = fs::rename(staging, destination) {
+            let mut outcomes: Vec<_> = (0..index).map(|_| CopyOutcome::Copied).collect();
+            let _ = fs::remove_file(staging);
+            outcomes.push(CopyOutcome::Failed(err));
+            for staging in &staged[index + 1..] {
+                let _ = fs::remove_file(staging);
+                outcomes.push(CopyOutcome::NotUpdated);
+            }
+            return Err(error(destinations, outcomes));
+        }
+    }
+    Ok(())
+}
+
+/// Copy `source` to a temporary file next to `destination`, creating its directory.
+fn stage(source: &Utf8Path, destination: &Utf8Path) -> io::Result<Utf8PathBuf> {
+    fs::create_dir_all(destination.parent().unwrap())?;
+    let name = destination.file_name().unwrap_or_default();
+    let staging = destination.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+    fs::copy(source, &staging)?;
+    Ok(staging)
+}
+
+fn error(destinations: &[Utf8PathBuf], mut outcomes: Vec<CopyOutcome>) -> anyhow::Error {
+    if let [path] = destinations {
+        if let Some(CopyOutcome::Failed(source)) = outcomes.pop() {
+            return BuildError::Artifact { path: path.clone(), source }.into();
+        }
+    }
+    BuildError::Copy { destinations: destinations.iter().cloned().zip(outcomes).collect() }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_all() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let source = dir.join("compiled");
+        fs::write(&source, "new").unwrap();
+        let sources = [source.clone(), source];
+        let entries = |dir: &Utf8Path| fs::read_dir(dir).unwrap().count();
+
+        let destinations = [dir.join("elf/program"), dir.join("out/nested/program")];
+        copy_all(&sources, &destinations).unwrap();
+        for destination in &destinations {
+            assert_eq!(fs::read_to_string(destination).unwrap(), "new");
+            assert_eq!(entries(destination.parent().unwrap()), 1);
+        }
+
+        // A destination which cannot be written leaves the others as they were.
+        fs::write(&sources[0], "newer").unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        let blocked = [destinations[0].clone(), dir.join("file/program")];
+        let err = copy_all(&sources, &blocked).unwrap_err();
+        match err.downcast_ref::<BuildError>() {
+            Some(BuildError::Copy { destinations }) => {
+                assert!(
+                    matches!(destinations[0], (ref path, CopyOutcome::NotUpdated) if *path == blocked[0])
+                );
+                assert!(
+                    matches!(destinations[1], (ref path, CopyOutcome::Failed(_)) if *path == blocked[1])
+                );
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().contains("not updated"));
+        assert_eq!(fs::read_to_string(&destinations[0]).unwrap(), "new");
+        assert_eq!(entries(destinations[0].parent().unwrap()), 1);
+
+        let err = copy_all(&sources[..1], &blocked[1..]).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BuildError::Artifact { .. })));
+    }
+}
@@ -0,0 +1,334 @@
+//! Splitting the symbols and debug information off the ELF, see [`BuildArgs::split_debuginfo`].
+//!
+//! The ELF compiled by cargo is copied as `<elf>.debug`, with its symbol table and DWARF sections,
+//! and the ELF copied as `<elf>` is stripped of them: its program headers and the contents of its
+//! segments are unchanged, only the sections the zkVM does not load are left out. The location and
+//! number of the section headers in the ELF header change, which the zkVM loads when a segment
+//! maps it, so the verification key of the stripped ELF may differ from that of the unstripped
+//! one; the stripped ELF is the one to prove and deploy. The stripped ELF names its companion in a
+//! `.gnu_debuglink` section, with its CRC-32, as `objcopy --add-gnu-debuglink` does, which
+//! [`locate_debug_file`] and debuggers follow.
+//!
+//! The release profile does not include DWARF by default, set `debug = true` in it to symbolize
+//! source lines and not only functions.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+
+/// The suffix of the debug file, appended to the path of the ELF.
+pub(crate) const DEBUG_SUFFIX: &str = ".debug";
+
+/// The name of the section naming the debug file.
+const DEBUGLINK: &str = ".gnu_debuglink";
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHF_ALLOC: u32 = 2;
+
+/// The size of the header of a 32-bit ELF.
+const EHDR_SIZE: usize = 52;
+/// The size of a section header of a 32-bit ELF.
+const SHDR_SIZE: usize = 40;
+
+/// Where the debug file of the ELF at `elf_path` is: the file its `.gnu_debuglink` section names,
+/// next to it or in a `.debug` directory next to it, if its CRC-32 matches, or else `<elf>.debug`.
+/// `None` if there is no such file.
+pub fn locate_debug_file(elf_path: impl AsRef<Path>) -> Option<PathBuf> {
+    let elf_path = elf_path.as_ref();
+    let dir = elf_path.parent().unwrap_or(Path::new(""));
+    if let Some((name, crc)) = fs::read(elf_path).ok().as_deref().and_then(debuglink) {
+        let linked = [dir.join(&name), dir.join(".debug").join(&name)].into_iter().find(|path| {
+            path != elf_path && fs::read(path).is_ok_and(|debug| crc32(&debug) == crc)
+        });
+        if linked.is_some() {
+            return linked;
+        }
+    }
+    let mut path = elf_path.as_os_str().to_owned();
+    path.push(DEBUG_SUFFIX);
+    Some(PathBuf::from(path)).filter(|path| path.is_file())
+}
+
+/// Write the stripped copy of each of the ELFs `compiled`, to be copied to the destination at the
+/// same index, next to it, returning the paths of the copies.
+pub(crate) fn strip_all(
+    compiled: &[Utf8PathBuf],
+    destinations: &[Utf8PathBuf],
+) -> Result<Vec<Utf8PathBuf>> {
+    compiled
+        .iter()
+        .zip(destinations)
+        .map(|(compiled, destination)| {
+            let elf = fs::read(compiled)
+                .with_context(|| format!("failed to read the ELF compiled at {}", compiled))?;
+            let debug_name = format!("{}{}", destination.file_name().unwrap(), DEBUG_SUFFIX);
+            let stripped = strip(&elf, &debug_name)
+                .ok_or_else(|| anyhow!("failed to strip {}: it is not a 32-bit ELF", compiled))?;
+            let path =
+                compiled.with_file_name(format!("{}.stripped", destination.file_name().unwrap()));
+            fs::write(&path, stripped)
+                .with_context(|| format!("failed to write the stripped ELF to {}", path))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// The path of the debug file of the ELF at `elf_path`, see [`BuildArgs::split_debuginfo`].
+pub(crate) fn debug_path(elf_path: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{}{}", elf_path, DEBUG_SUFFIX))
+}
+
+/// The section headers of the 32-bit little endian ELF `elf`, with their names.
+fn sections(elf: &[u8]) -> Option<Vec<(String, [u8; SHDR_SIZE])>> {
+    if elf.get(..6)? != b"\x7fELF\x01\x01" {
+        return None;
+    }
+    let (shoff, shnum, shstrndx) = (u32_at(elf, 32)?, u16_at(elf, 48)?, u16_at(elf, 50)?);
+    if u16_at(elf, 46)? != SHDR_SIZE {
+        return None;
+    }
+    let headers = (0..shnum)
+        .map(|index| elf.get(shoff + index * SHDR_SIZE..)?.get(..SHDR_SIZE)?.try_into().ok())
+        .collect::<Option<Vec<[u8; SHDR_SIZE]>>>()?;
+    let names = u32_at(&headers.get(shstrndx)?[..], 16)?;
+    headers
+        .into_iter()
+        .map(|header| {
+            let name = elf.get(names + u32_at(&header, 0)?..)?;
+            let end = name.iter().position(|&byte| byte == 0)?;
+            Some((String::from_utf8_lossy(&name[..end]).into_owned(), header))
+        })
+        .collect()
+}
+
+/// The name of the debug file in the `.gnu_debuglink` section of `elf`, with its CRC-32.
+fn debuglink(elf: &[u8]) -> Option<(String, u32)> {
+    let (_, header) = sections(elf)?.into_iter().find(|(name, _)| name == DEBUGLINK)?;
+    let (offset, size) = (u32_at(&header, 16)?, u32_at(&header, 20)?);
+    let link = elf.get(offset..offset + size)?;
+    let end = link.iter().position(|&byte| byte == 0)?;
+    let crc = u32_at(link, size.checked_sub(4)?)? as u32;
+    Some((String::from_utf8_lossy(&link[..end]).into_owned(), crc))
+}
+
+/// The ELF `elf` without its symbol table and debug sections, naming `debug_name` in a
+/// `.gnu_debuglink` section, with the CRC-32 of `elf`. The bytes loaded by the program headers are
+/// left in place; the sections which are not loaded follow them, then the section headers.
+fn strip(elf: &[u8], debug_name: &str) -> Option<Vec<u8>> {
+    let sections = sections(elf)?;
+    let shstrndx = u16_at(elf, 50)?;
+    let field = |header: &[u8], offset: usize| u32_at(header, offset).map(|value| value as u32);
+    let kept: Vec<usize> = (1..sections.len())
+        .filter(|&index| {
+            let (name, header) = &sections[index];
+            let alloc = field(header, 8).is_some_and(|flags| flags & SHF_ALLOC != 0);
+            let dropped = name.starts_with(".debug") ||
+                name == ".strtab" ||
+                name == DEBUGLINK ||
+                field(header, 4) == Some(SHT_SYMTAB);
+            index != shstrndx && (alloc || !dropped)
+        })
+        .collect();
+
+    // The headers and the segments stay where they are.
+    let (phoff, phentsize, phnum) = (u32_at(elf, 28)?, u16_at(elf, 42)?, u16_at(elf, 44)?);
+    let mut end = EHDR_SIZE.max(phoff + phentsize * phnum);
+    for index in 0..phnum {
+        let header = elf.get(phoff + index * phentsize..)?;
+        end = end.max(u32_at(header, 4)? + u32_at(header, 16)?);
+    }
+    let mut out = elf.get(..end)?.to_vec();
+
+    let mut names = vec![0u8];
+    let name = |names: &mut Vec<u8>, name: &str| {
+        let offset = names.len() as u32;
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+        offset
+    };
+    let mut headers = vec![[0u8; SHDR_SIZE]];
+    for &index in &kept {
+        let (section_name, mut header) = sections[index].clone();
+        header[..4].copy_from_slice(&name(&mut names, &section_name).to_le_bytes());
+        let (offset, size) = (u32_at(&header, 16)?, u32_at(&header, 20)?);
+        if field(&header, 4) != Some(SHT_NOBITS) && offset + size > end {
+            align(&mut out, u32_at(&header, 32)?);
+            header[16..20].copy_from_slice(&(out.len() as u32).to_le_bytes());
+            out.extend_from_slice(elf.get(offset..offset + size)?);
+        }
+        // Links to removed sections, such as from `.symtab`, are cleared.
+        let new_index = |old: usize| kept.iter().position(|&kept| kept == old).map_or(0, |i| i + 1);
+        let (link, info) = (new_index(u32_at(&header, 24)?), new_index(u32_at(&header, 28)?));
+        header[24..28].copy_from_slice(&(link as u32).to_le_bytes());
+        if matches!(field(&header, 4), Some(SHT_REL | SHT_RELA)) {
+            header[28..32].copy_from_slice(&(info as u32).to_le_bytes());
+        }
+        headers.push(header);
+    }
+
+    // The debug link is the name of the file, padded to 4 bytes, then its CRC-32.
+    let mut link = debug_name.as_bytes().to_vec();
+    link.push(0);
+    align(&mut link, 4);
+    link.extend_from_slice(&crc32(elf).to_le_bytes());
+    align(&mut out, 4);
+    headers.push(section_header(name(&mut names, DEBUGLINK), 1, out.len(), link.len(), 4));
+    out.extend_from_slice(&link);
+
+    let shstrtab_name = name(&mut names, ".shstrtab");
+    headers.push(section_header(shstrtab_name, 3, out.len(), names.len(), 1));
+    out.extend_from_slice(&names);
+
+    align(&mut out, 4);
+    let shoff = out.len() as u32;
+    out[32..36].copy_from_slice(&shoff.to_le_bytes());
+    out[48..50].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+    out[50..52].copy_from_slice(&(headers.len() as u16 - 1).to_le_bytes());
+    out.extend(headers.concat());
+    Some(out)
+}
+
+/// The header of a section of `kind` named at `name`, of `size` bytes at `offset`.
+fn section_header(name: u32, kind: u32, offset: usize, size: usize, align: u32) -> [u8; SHDR_SIZE] {
+    let mut header = [0u8; SHDR_SIZE];
+    header[..4].copy_from_slice(&name.to_le_bytes());
+    header[4..8].copy_from_slice(&kind.to_le_bytes());
+    header[16..20].copy_from_slice(&(offset as u32).to_le_bytes());
+    header[20..24].copy_from_slice(&(size as u32).to_le_bytes());
+    header[32..36].copy_from_slice(&align.to_le_bytes());
+    header
+}
+
+/// Pad `bytes` with zeros to a multiple of `alignment`.
+fn align(bytes: &mut Vec<u8>, alignment: usize) {
+    let alignment = alignment.max(1);
+    bytes.resize(bytes.len().div_ceil(alignment) * alignment, 0);
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+/// The CRC-32 of `bytes`, as used by `.gnu_debuglink`.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8)
+            .fold(crc ^ byte as u32, |crc, _| (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ELF of the fibonacci example, which has a symbol table.
+    fn fibonacci() -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        fs::read(path).unwrap()
+    }
+
+    /// The functions of the symbol table of `elf`, with their addresses and sizes.
+    fn functions(elf: &[u8]) -> Option<Vec<(String, usize, usize)>> {
+        let sections = sections(elf)?;
+        let symtab = SHT_SYMTAB as usize;
+        let (_, symtab) = sections.iter().find(|(_, header)| u32_at(header, 4) == Some(symtab))?;
+        let (offset, size) = (u32_at(symtab, 16)?, u32_at(symtab, 20)?);
+        let strtab = u32_at(&sections[u32_at(symtab, 24)?].1, 16)?;
+        (offset..offset + size)
+            .step_by(16)
+            .filter(|&symbol| elf[symbol + 12] & 0xf == 2)
+            .map(|symbol| {
+                let name = &elf[strtab + u32_at(elf, symbol)?..];
+                let name = &name[..name.iter().position(|&b| b == 0)?];
+                let (value, size) = (u32_at(elf, symbol + 4)?, u32_at(elf, symbol + 8)?);
+                Some((String::from_utf8_lossy(name).into_owned(), value, size))
+            })
+            .collect()
+    }
+
+    /// The name of the function of the symbol table of `elf` containing `address`.
+    fn symbolize(elf: &[u8], address: usize) -> Option<String> {
+        functions(elf)?
+            .into_iter()
+            .find(|(_, value, size)| (*value..value + size).contains(&address))
+            .map(|(name, _, _)| name)
+    }
+
+    /// The address of the function `main` of `elf`, wherever the linker placed it.
+    fn main_address(elf: &[u8]) -> usize {
+        functions(elf).unwrap().into_iter().find(|(name, _, _)| name == "main").unwrap().1
+    }
+
+    #[test]
+    fn test_strip() {
+        let elf = fibonacci();
+        let stripped = strip(&elf, "fibonacci.debug").unwrap();
+        assert!(stripped.len() < elf.len());
+
+        // The program headers and the segments they load are unchanged, but for the location
+        // and number of the section headers in the ELF header.
+        let (phoff, phnum) = (u32_at(&elf, 28).unwrap(), u16_at(&elf, 44).unwrap());
+        assert_eq!((&stripped[..32], &stripped[36..48]), (&elf[..32], &elf[36..48]));
+        assert_eq!(stripped[phoff..phoff + phnum * 32], elf[phoff..phoff + phnum * 32]);
+        for index in 0..phnum {
+            let header = &elf[phoff + index * 32..];
+            let (offset, size) = (u32_at(header, 4).unwrap(), u32_at(header, 16).unwrap());
+            let offset = offset.max(EHDR_SIZE);
+            assert_eq!(stripped[offset..offset + size], elf[offset..offset + size]);
+        }
+
+        // The sections are those of the ELF in the same order, without the symbols and the debug
+        // information, followed by the debug link and the section names.
+        let names = |elf: &[u8]| -> Vec<String> {
+            sections(elf).unwrap().into_iter().map(|(name, _)| name).collect()
+        };
+        assert!(names(&elf).iter().any(|name| name == ".symtab"));
+        let expected: Vec<String> = names(&elf)
+            .into_iter()
+            .filter(|name| {
+                !name.starts_with(".debug") &&
+                    !matches!(name.as_str(), ".symtab" | ".strtab" | ".shstrtab")
+            })
+            .chain([DEBUGLINK.to_string(), ".shstrtab".to_string()])
+            .collect();
+        assert_eq!(names(&stripped), expected);
+        assert_eq!(debuglink(&stripped), Some(("fibonacci.debug".to_string(), crc32(&elf))));
+        let main = main_address(&elf);
+        assert_eq!(symbolize(&stripped, main), None);
+        assert_eq!(symbolize(&elf, main).as_deref(), Some("main"));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_locate_debug_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf = fibonacci();
+        let elf_path = dir.path().join("fibonacci");
+        fs::write(&elf_path, strip(&elf, "renamed.debug").unwrap()).unwrap();
+        assert_eq!(locate_debug_file(&elf_path), None);
+
+        // By naming convention, then by link, which must match the CRC-32 of the ELF.
+        fs::write(dir.path().join("fibonacci.debug"), &elf).unwrap();
+        assert_eq!(locate_debug_file(&elf_path), Some(dir.path().join("fibonacci.debug")));
+        fs::create_dir(dir.path().join(".debug")).unwrap();
+        fs::write(dir.path().join(".debug/renamed.debug"), &elf).unwrap();
+        assert_eq!(locate_debug_file(&elf_path), Some(dir.path().join(".debug/renamed.debug")));
+        fs::write(dir.path().join(".debug/renamed.debug"), b"other").unwrap();
+        assert_eq!(locate_debug_file(&elf_path), Some(dir.path().join("fibonacci.debug")));
+
+        let debug = fs::read(locate_debug_file(&elf_path).unwrap()).unwrap();
+        assert_eq!(symbolize(&debug, main_address(&elf)).as_deref(), Some("main"));
+    }
+}
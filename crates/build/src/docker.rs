@@ -1,61 +1,64 @@
-use std::process::{exit, Command, Stdio};
+use std::process::{Command, Stdio};
 
-use anyhow::{Context, Result};
-use cargo_metadata::camino::Utf8PathBuf;
+use anyhow::Result;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
 
-use crate::{get_program_build_args, get_rust_compiler_flags, BuildArgs};
+use crate::{
+    c_toolchain, cargo_home, get_program_build_args, host_path, remap, toolchain, BuildArgs,
+    BuildError, ZkvmRustFlags,
+};
+
+/// The variable which replaces the Docker image, including its tag.
+pub(crate) const IMAGE_ENV: &str = "SP1_DOCKER_IMAGE";
 
 /// Uses SP1_DOCKER_IMAGE environment variable if set, otherwise constructs the image to use based
 /// on the provided tag.
-fn get_docker_image(tag: &str) -> String {
-    std::env::var("SP1_DOCKER_IMAGE").unwrap_or_else(|_| {
+pub(crate) fn get_docker_image(tag: &str) -> String {
+    std::env::var(IMAGE_ENV).unwrap_or_else(|_| {
         let image_base = "ghcr.io/succinctlabs/sp1";
         format!("{}:{}", image_base, tag)
     })
 }
 
-/// Creates a Docker command to build the program.
+/// Checks that docker is installed and running.
+pub fn check_docker_available() -> Result<()> {
+    let docker_check =
+        Command::new("docker").args(["info"]).stdout(Stdio::null()).stderr(Stdio::null()).status();
+    if !docker_check.is_ok_and(|status| status.success()) {
+        return Err(BuildError::DockerUnavailable.into());
+    }
+    Ok(())
+}
+
+/// Creates a Docker command to build the program, in a container named `container` if set.
 pub fn create_docker_command(
     args: &BuildArgs,
     program_dir: &Utf8PathBuf,
     program_metadata: &cargo_metadata::Metadata,
+    container: Option<&str>,
 ) -> Result<Command> {
     let image = get_docker_image(&args.tag);
-    let canonicalized_program_dir: Utf8PathBuf = program_dir
-        .canonicalize()
+    let canonicalized_program_dir: Utf8PathBuf = host_path::canonicalize(program_dir.as_std_path())
         .expect("Failed to canonicalize program directory")
         .try_into()
         .unwrap();
     let workspace_root = &program_metadata.workspace_root;
 
-    // Check if docker is installed and running.
-    let docker_check = Command::new("docker")
-        .args(["info"])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .context("failed to run docker command")?;
-    if !docker_check.success() {
-        eprintln!("docker is not installed or not running: https://docs.docker.com/get-docker/");
-        exit(1);
-    }
-
     // Mount the entire workspace, and set the working directory to the program dir. Note: If the
     // program dir has local dependencies outside of the workspace, building with Docker will fail.
-    let workspace_root_path = format!("{}:/root/program", workspace_root);
+    let workspace_mount = host_path::bind_mount(workspace_root, "/root/program")?;
     let program_dir_path = format!(
         "/root/program/{}",
-        canonicalized_program_dir.strip_prefix(workspace_root).unwrap()
+        host_path::container_path(canonicalized_program_dir.strip_prefix(workspace_root).unwrap())
     );
 
     // Get the target directory for the ELF in the context of the Docker container.
     let relative_target_dir =
         (program_metadata.target_directory).strip_prefix(workspace_root).unwrap();
     let target_dir = format!(
-        "/root/program/{}/{}/{}",
-        relative_target_dir,
-        crate::HELPER_TARGET_SUBDIR,
-        "docker"
+        "/root/program/{}/{}",
+        host_path::container_path(relative_target_dir),
+        crate::helper_target_subdir(args)
     );
 
     // When executing the Docker command:
@@ -71,8 +74,8 @@ pub fn create_docker_command(
         "--rm".to_string(),
         "--platform".to_string(),
         "linux/amd64".to_string(),
-        "-v".to_string(),
-        workspace_root_path,
+        "--mount".to_string(),
+        workspace_mount,
         "-w".to_string(),
         program_dir_path,
         "-e".to_string(),
@@ -80,13 +83,40 @@ pub fn create_docker_command(
         "-e".to_string(),
         "RUSTUP_TOOLCHAIN=succinct".to_string(),
         "-e".to_string(),
-        format!("CARGO_ENCODED_RUSTFLAGS={}", get_rust_compiler_flags()),
+        format!("CARGO_ENCODED_RUSTFLAGS={}", docker_rust_flags(args).render_encoded()),
         "--entrypoint".to_string(),
         "".to_string(),
         image,
         "cargo".to_string(),
     ];
 
+    // Configure the C compiler of the container, which only has the variables passed explicitly.
+    let c_env =
+        c_toolchain::env_vars(args).into_iter().map(|(key, value)| format!("{}={}", key, value));
+    let entrypoint = docker_args.iter().position(|arg| arg == "--entrypoint").unwrap();
+    docker_args.splice(entrypoint..entrypoint, c_env.flat_map(|var| ["-e".to_string(), var]));
+
+    // The cargo home of `args` replaces that of the image, whose binaries are still used. It is
+    // missing in a dry run, which does not create it.
+    if let Some(home) = cargo_home::isolated(args, canonicalized_program_dir.as_std_path()) {
+        let home = if home.exists() { host_path::canonicalize(&home)? } else { home };
+        let home = Utf8PathBuf::try_from(home)?;
+        let mount = host_path::bind_mount(&home, cargo_home::CONTAINER_ISOLATED_CARGO_HOME)?;
+        let env = format!("CARGO_HOME={}", cargo_home::CONTAINER_ISOLATED_CARGO_HOME);
+        let entrypoint = docker_args.iter().position(|arg| arg == "--entrypoint").unwrap();
+        docker_args
+            .splice(entrypoint..entrypoint, ["--mount".to_string(), mount, "-e".to_string(), env]);
+    }
+
+    if let Some(container) = container {
+        docker_args.splice(2..2, ["--name".to_string(), container.to_string()]);
+    }
+
+    // As for a local build, a toolchain file of the program must not take precedence.
+    if toolchain::toolchain_file(program_dir, workspace_root).is_some() {
+        docker_args.push(format!("+{}", toolchain::TOOLCHAIN_NAME));
+    }
+
     // Add the SP1 program build arguments.
     docker_args.extend_from_slice(&get_program_build_args(args));
 
@@ -94,3 +124,48 @@ pub fn create_docker_command(
     command.current_dir(canonicalized_program_dir.clone()).args(&docker_args);
     Ok(command)
 }
+
+/// The flags of rustc of a Docker build with `args`.
+fn docker_rust_flags(args: &BuildArgs) -> ZkvmRustFlags {
+    let mut flags = ZkvmRustFlags::default().flags(args.rustflags.iter().cloned());
+    if args.remap_paths {
+        for (from, to) in remap::docker_prefixes(cargo_home::container(args)) {
+            flags = flags.remap_path_prefix(from, to);
+        }
+    }
+    flags
+}
+
+/// Creates a Docker command generating the lock file of the workspace in `workspace_root` with the
+/// succinct toolchain of the image, for the program in `program_dir`, see
+/// [`BuildArgs::generate_lockfile`].
+pub(crate) fn generate_lockfile_command(
+    args: &BuildArgs,
+    program_dir: &Utf8PathBuf,
+    workspace_root: &Utf8PathBuf,
+) -> Result<Command> {
+    let canonicalized_program_dir: Utf8PathBuf =
+        host_path::canonicalize(program_dir.as_std_path())?.try_into()?;
+    let relative_program_dir =
+        canonicalized_program_dir.strip_prefix(workspace_root).unwrap_or(Utf8Path::new(""));
+    let docker_args = [
+        "run".to_string(),
+        "--rm".to_string(),
+        "--platform".to_string(),
+        "linux/amd64".to_string(),
+        "--mount".to_string(),
+        host_path::bind_mount(workspace_root, "/root/program")?,
+        "-w".to_string(),
+        format!("/root/program/{}", host_path::container_path(relative_program_dir)),
+        "-e".to_string(),
+        "RUSTUP_TOOLCHAIN=succinct".to_string(),
+        "--entrypoint".to_string(),
+        "".to_string(),
+        get_docker_image(&args.tag),
+        "cargo".to_string(),
+        "generate-lockfile".to_string(),
+    ];
+    let mut command = Command::new("docker");
+    command.current_dir(canonicalized_program_dir).args(docker_args);
+    Ok(command)
+}
@@ -0,0 +1,127 @@
+use std::process::Command;
+
+use crate::output;
+
+/// Print `command` as a shell command which can be copied and run as is: the working directory,
+/// the environment variables set or removed by this crate, the program and its arguments.
+/// `CARGO_ENCODED_RUSTFLAGS` is also printed in its decoded form, as a comment.
+pub fn print_command(command: &Command) {
+    output::info("[sp1] dry run, the build would run the following command:");
+    output::info(&format_command(command));
+}
+
+/// Format `command` as a shell command, see [`print_command`].
+pub fn format_command(command: &Command) -> String {
+    let mut lines = vec![];
+
+    // Docker passes the environment through `-e` arguments instead of setting it on the command.
+    let encoded_rustflags = command
+        .get_envs()
+        .find(|(key, _)| *key == "CARGO_ENCODED_RUSTFLAGS")
+        .and_then(|(_, value)| value.map(|v| v.to_string_lossy().into_owned()))
+        .or_else(|| {
+            command.get_args().find_map(|arg| {
+                arg.to_string_lossy().strip_prefix("CARGO_ENCODED_RUSTFLAGS=").map(str::to_string)
+            })
+        });
+    if let Some(encoded) = encoded_rustflags {
+        lines.push(format!("# CARGO_ENCODED_RUSTFLAGS: {}", encoded.replace('\x1f', " ")));
+    }
+
+    let mut parts = vec![];
+    if let Some(dir) = command.get_current_dir() {
+        parts.push(format!("cd {} &&", quote(&dir.as_os_str().to_string_lossy())));
+    }
+
+    // `env` requires the variables to remove to come before the ones to set.
+    let mut env = vec![];
+    for (key, value) in command.get_envs() {
+        if value.is_none() {
+            env.push(format!("-u {}", key.to_string_lossy()));
+        }
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            env.push(format!("{}={}", key.to_string_lossy(), quote(&value.to_string_lossy())));
+        }
+    }
+    if !env.is_empty() {
+        parts.push(format!("env {}", env.join(" ")));
+    }
+
+    let program = command.get_program().to_string_lossy();
+    let args = command.get_args().map(|arg| quote(&arg.to_string_lossy())).collect::<Vec<_>>();
+    parts.push(format!("{} {}", quote(&program), args.join(" ")).trim_end().to_string());
+
+    lines.push(parts.join(" \\\n  "));
+    lines.join("\n")
+}
+
+/// Quote `arg` for a POSIX shell. Arguments containing control characters use the `$'...'` form
+/// supported by bash and zsh, so that they survive copying from a terminal.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c))
+    {
+        return arg.to_string();
+    }
+    if arg.chars().any(char::is_control) {
+        let mut quoted = String::from("$'");
+        for c in arg.chars() {
+            match c {
+                '\'' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("cargo"), "cargo");
+        assert_eq!(
+            quote("--target=riscv32im-succinct-zkvm-elf"),
+            "--target=riscv32im-succinct-zkvm-elf"
+        );
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("my dir"), "'my dir'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote("-C\x1fpanic=abort"), "$'-C\\x1fpanic=abort'");
+    }
+
+    #[test]
+    fn test_format_command() {
+        let mut command = Command::new("cargo");
+        command
+            .current_dir("/tmp/my program")
+            .env("CARGO_ENCODED_RUSTFLAGS", "-C\x1fpanic=abort")
+            .env_remove("RUSTC")
+            .args(["build", "--release"]);
+        let formatted = format_command(&command);
+        assert!(formatted.starts_with("# CARGO_ENCODED_RUSTFLAGS: -C panic=abort\n"));
+        assert!(formatted.contains("cd '/tmp/my program' &&"));
+        assert!(formatted.contains("CARGO_ENCODED_RUSTFLAGS=$'-C\\x1fpanic=abort'"));
+        assert!(formatted.contains("env -u RUSTC CARGO_ENCODED_RUSTFLAGS="));
+        assert!(formatted.ends_with("cargo build --release"));
+    }
+
+    #[test]
+    fn test_format_docker_command() {
+        let mut command = Command::new("docker");
+        command.args(["run", "-e", "CARGO_ENCODED_RUSTFLAGS=-C\x1fpanic=abort", "cargo"]);
+        let formatted = format_command(&command);
+        assert!(formatted.starts_with("# CARGO_ENCODED_RUSTFLAGS: -C panic=abort\n"));
+        assert!(formatted
+            .ends_with("docker run -e $'CARGO_ENCODED_RUSTFLAGS=-C\\x1fpanic=abort' cargo"));
+    }
+}
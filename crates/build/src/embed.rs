@@ -0,0 +1,100 @@
+//! Embedding the build arguments in other CLIs.
+//!
+//! [`BuildArgs`] has no short flags, and its arguments default to the values of
+//! [`BuildArgs::default`], so that a CLI can take them along with its own with `#[clap(flatten)]`:
+//!
+//! ```
+//! use clap::Parser;
+//! use sp1_build::BuildArgs;
+//!
+//! #[derive(Parser)]
+//! struct Prove {
+//!     #[clap(long)]
+//!     output: Option<String>,
+//!     #[clap(flatten)]
+//!     build_args: BuildArgs,
+//! }
+//!
+//! let prove = Prove::parse_from(["prove", "--output", "proof.bin", "--docker"]);
+//! assert!(prove.build_args.docker);
+//! ```
+
+use std::ffi::OsString;
+
+use clap::{CommandFactory, Parser};
+
+use crate::BuildArgs;
+
+impl BuildArgs {
+    /// Parse the arguments from `args`, the flags only, without the name of the binary which
+    /// `clap::Parser::try_parse_from` expects first.
+    ///
+    /// ```
+    /// use sp1_build::BuildArgs;
+    ///
+    /// let args = BuildArgs::try_parse_from_args(["--docker", "--features", "bls"]).unwrap();
+    /// assert!(args.docker);
+    /// assert_eq!(args.features, vec!["bls".to_string()]);
+    /// ```
+    pub fn try_parse_from_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let bin_name = OsString::from("sp1-build");
+        Self::try_parse_from(std::iter::once(bin_name).chain(args.into_iter().map(Into::into)))
+    }
+
+    /// The [`clap::Command`] of the arguments, such as to compose the help of a CLI taking them.
+    pub fn command() -> clap::Command {
+        <Self as CommandFactory>::command()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    #[clap(name = "parent", version)]
+    struct Parent {
+        #[clap(long, short)]
+        output: Option<String>,
+        #[clap(long, short)]
+        verbose: bool,
+        #[clap(flatten)]
+        build_args: BuildArgs,
+    }
+
+    #[test]
+    fn test_flatten() {
+        // Panics if any argument conflicts with those of the parent or another one.
+        Parent::command().debug_assert();
+        BuildArgs::command().debug_assert();
+        assert!(BuildArgs::command().get_arguments().all(|arg| arg.get_short().is_none()));
+
+        let parent = Parent::parse_from(["parent", "-o", "proof.bin", "--tag", "v2.0.0"]);
+        assert_eq!(parent.output.as_deref(), Some("proof.bin"));
+        assert_eq!(
+            parent.build_args,
+            BuildArgs { tag: "v2.0.0".to_string(), ..Default::default() }
+        );
+        let parent = Parent::parse_from(["parent"]);
+        assert_eq!(parent.build_args, BuildArgs::default());
+    }
+
+    #[test]
+    fn test_try_parse_from_args() {
+        assert_eq!(
+            BuildArgs::try_parse_from_args(Vec::<String>::new()).unwrap(),
+            BuildArgs::default()
+        );
+        let args =
+            BuildArgs::try_parse_from_args(["--elf-name", "fib", "--c-flag", "-O2"]).unwrap();
+        assert_eq!((args.elf_name.as_str(), args.c_flags), ("fib", vec!["-O2".to_string()]));
+        let err = BuildArgs::try_parse_from_args(["--unknown"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::UnknownArgument);
+    }
+}
@@ -0,0 +1,210 @@
+//! Snapshots of the environment a program is compiled in, see [`BuildArgs::capture_env`].
+//!
+//! A snapshot records the variables the cargo command compiling the program sees, after this crate
+//! set and removed its own, see the `scrub` module: those of SP1, cargo and rustc, of the C
+//! toolchain of the zkVM target, and `PATH`, along with the toolchain. For a Docker build, only the
+//! variables passed to the container are known. The values of variables which look like secrets,
+//! such as `GITHUB_TOKEN`, are redacted. Snapshots are written next to the ELF, as
+//! `<elf>.env.json`, added to its manifest, and compared with [`compare_env_snapshots`].
+
+use std::{collections::BTreeMap, ffi::OsStr, fs, process::Command};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    docker,
+    toolchain::{self, Toolchain},
+    BuildArgs, ElfArtifact, BUILD_TARGET,
+};
+
+/// The suffix of the snapshot, appended to the path of the ELF.
+pub(crate) const ENV_SNAPSHOT_SUFFIX: &str = ".env.json";
+
+/// The value of a redacted variable.
+const REDACTED: &str = "<redacted>";
+
+/// The variables captured by name.
+const CAPTURED: &[&str] = &["PATH", "CC", "CXX", "CFLAGS", "CXXFLAGS", "AR"];
+
+/// The prefixes of the variables captured.
+const CAPTURED_PREFIXES: &[&str] = &["SP1_", "CARGO", "RUST"];
+
+/// The parts of the names of variables whose values are redacted, compared case-insensitively.
+const SECRETS: &[&str] = &["TOKEN", "KEY", "SECRET"];
+
+/// The environment a program was compiled in, see [`BuildArgs::capture_env`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvSnapshot {
+    /// The toolchain, such as `succinct: rustc 1.79.0-dev (a0b1c2d3e 2024-06-01)`, or the Docker
+    /// image of a Docker build.
+    pub toolchain: String,
+    /// The variables of the cargo command by name, with the values of those which look like
+    /// secrets replaced with `<redacted>`.
+    pub vars: BTreeMap<String, String>,
+}
+
+impl EnvSnapshot {
+    /// Read the snapshot at `path`, such as `elf/riscv32im-succinct-zkvm-elf.env.json`.
+    pub fn read(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read(path).with_context(|| {
+            format!("failed to read the environment snapshot {}", path.display())
+        })?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("invalid environment snapshot {}", path.display()))
+    }
+}
+
+/// The names of the variables which are set in only one of the snapshots `a` and `b`, or to
+/// different values, sorted, followed by `toolchain` if the toolchains differ.
+pub fn compare_env_snapshots(a: &EnvSnapshot, b: &EnvSnapshot) -> Vec<String> {
+    let names = a.vars.keys().chain(b.vars.keys().filter(|name| !a.vars.contains_key(*name)));
+    let mut differing: Vec<_> =
+        names.filter(|name| a.vars.get(*name) != b.vars.get(*name)).cloned().collect();
+    differing.sort();
+    if a.toolchain != b.toolchain {
+        differing.push("toolchain".to_string());
+    }
+    differing
+}
+
+/// The snapshot of the environment of `command`, which compiles the program with `args` and
+/// `toolchain`.
+pub(crate) fn capture(args: &BuildArgs, command: &Command, toolchain: Toolchain) -> EnvSnapshot {
+    if args.docker {
+        return EnvSnapshot {
+            toolchain: docker::get_docker_image(&args.tag),
+            vars: container_vars(command),
+        };
+    }
+    let version = toolchain::installed_version(toolchain.name()).map(|version| version.line);
+    EnvSnapshot {
+        toolchain: format!("{}: {}", toolchain.name(), version.unwrap_or_default()),
+        vars: command_vars(command, std::env::vars_os()),
+    }
+}
+
+/// The captured variables of a local `command`, which inherits `inherited`.
+fn command_vars(
+    command: &Command,
+    inherited: impl IntoIterator<Item = (impl AsRef<OsStr>, impl AsRef<OsStr>)>,
+) -> BTreeMap<String, String> {
+    let lossy = |value: &OsStr| value.to_string_lossy().into_owned();
+    let mut vars: BTreeMap<_, _> = inherited
+        .into_iter()
+        .map(|(name, value)| (lossy(name.as_ref()), lossy(value.as_ref())))
+        .collect();
+    for (name, value) in command.get_envs() {
+        match value {
+            Some(value) => vars.insert(lossy(name), lossy(value)),
+            None => vars.remove(&lossy(name)),
+        };
+    }
+    redact(vars)
+}
+
+/// The captured variables a Docker `command` passes to the container with `-e`.
+fn container_vars(command: &Command) -> BTreeMap<String, String> {
+    let args: Vec<_> = command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    let vars = args
+        .windows(2)
+        .filter(|pair| pair[0] == "-e")
+        .filter_map(|pair| pair[1].split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    redact(vars.collect())
+}
+
+/// Keep the captured variables of `vars`, redacting the values of secrets.
+fn redact(vars: BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let target_suffix = format!("_{}", BUILD_TARGET.replace('-', "_"));
+    vars.into_iter()
+        .filter(|(name, _)| {
+            CAPTURED.contains(&name.as_str()) ||
+                CAPTURED_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) ||
+                name.ends_with(&target_suffix)
+        })
+        .map(|(name, value)| {
+            let upper = name.to_uppercase();
+            if SECRETS.iter().any(|secret| upper.contains(secret)) {
+                (name, REDACTED.to_string())
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+/// Write `snapshot` next to each of the `artifacts` which was built, returning the paths it is
+/// written to.
+pub(crate) fn write(snapshot: &EnvSnapshot, artifacts: &[ElfArtifact]) -> Result<Vec<Utf8PathBuf>> {
+    let mut paths = vec![];
+    for artifact in artifacts.iter().filter(|artifact| artifact.sha256.is_some()) {
+        let path = Utf8PathBuf::from(format!("{}{}", artifact.path, ENV_SNAPSHOT_SUFFIX));
+        fs::write(&path, serde_json::to_string_pretty(snapshot)?)
+            .with_context(|| format!("failed to write the environment snapshot to {}", path))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_vars() {
+        let inherited = [
+            ("PATH", "/usr/bin"),
+            ("HOME", "/root"),
+            ("RUSTFLAGS", "-Copt-level=1"),
+            ("CARGO_REGISTRY_TOKEN", "hunter2"),
+            ("SP1_PRIVATE_KEY", "0x1"),
+            ("CC_riscv32im_succinct_zkvm_elf", "gcc"),
+        ];
+        let mut command = Command::new("cargo");
+        command.env("RUSTUP_TOOLCHAIN", "succinct").env_remove("RUSTFLAGS").env("OTHER", "1");
+        let vars = command_vars(&command, inherited);
+        let expected: BTreeMap<_, _> = [
+            ("CARGO_REGISTRY_TOKEN", REDACTED),
+            ("CC_riscv32im_succinct_zkvm_elf", "gcc"),
+            ("PATH", "/usr/bin"),
+            ("RUSTUP_TOOLCHAIN", "succinct"),
+            ("SP1_PRIVATE_KEY", REDACTED),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(vars, expected);
+
+        let mut docker = Command::new("docker");
+        docker.args([
+            "run",
+            "-e",
+            "RUSTUP_TOOLCHAIN=succinct",
+            "-e",
+            "CARGO_REGISTRY_TOKEN=x",
+            "-w",
+            "/root",
+        ]);
+        let vars = container_vars(&docker);
+        assert_eq!(vars["RUSTUP_TOOLCHAIN"], "succinct");
+        assert_eq!(vars["CARGO_REGISTRY_TOKEN"], REDACTED);
+        assert_eq!(vars.len(), 2);
+    }
+
+    #[test]
+    fn test_compare_env_snapshots() {
+        let snapshot = |toolchain: &str, vars: &[(&str, &str)]| EnvSnapshot {
+            toolchain: toolchain.to_string(),
+            vars: vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        };
+        let a =
+            snapshot("succinct", &[("PATH", "/a"), ("RUSTUP_TOOLCHAIN", "succinct"), ("X", "1")]);
+        let b =
+            snapshot("nightly", &[("PATH", "/b"), ("RUSTUP_TOOLCHAIN", "succinct"), ("Y", "1")]);
+        assert_eq!(compare_env_snapshots(&a, &b), vec!["PATH", "X", "Y", "toolchain"]);
+        assert!(compare_env_snapshots(&a, &a).is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+use std::{io, process::ExitStatus};
+
+use cargo_metadata::camino::Utf8PathBuf;
+use thiserror::Error;
+
+use crate::{BuildWarning, ValidationError};
+
+/// The categories of errors a build can fail with.
+///
+/// The functions of this crate return [`anyhow::Error`]s, which wrap a [`BuildError`] when the
+/// failure falls into one of these categories. Use [`BuildError::exit_code_of`] to map any such
+/// error to the exit code of a process which terminates because of it:
+///
+/// | Code | Error                             |
+/// |------|-----------------------------------|
+/// | 1    | any other error                   |
+/// | 2    | [`BuildError::Config`]            |
+/// | 2    | [`BuildError::ProgramDir`]        |
+/// | 2    | [`BuildError::NoElf`]             |
+/// | 2-11 | [`BuildError::Invalid`]           |
+/// | 3    | [`BuildError::Skipped`]           |
+/// | 10   | [`BuildError::ToolchainMissing`]  |
+/// | 11   | [`BuildError::DockerUnavailable`] |
+/// | 12   | [`BuildError::ImagePull`]         |
+/// | 20   | [`BuildError::Compile`]           |
+/// | 30   | [`BuildError::Artifact`]          |
+/// | 30   | [`BuildError::Copy`]              |
+/// | 30   | [`BuildError::MissingElf`]        |
+/// | 30   | [`BuildError::ElfConflict`]       |
+/// | 40   | [`BuildError::SmokeTest`]         |
+/// | 40   | [`BuildError::Hook`]              |
+/// | 40   | [`BuildError::Test`]              |
+/// | 50   | [`BuildError::DeniedWarning`]     |
+/// | 130  | [`BuildError::Cancelled`]         |
+///
+/// Codes below 20 are failures of the build environment, which may succeed when retried. Code 50
+/// is a build which succeeded but is rejected by [`crate::BuildArgs::deny_warnings`], so it is kept
+/// apart from the codes of [`BuildError::Invalid`].
+#[derive(Debug, Error)]
+pub enum BuildError {
+    /// The build configuration is invalid.
+    #[error("invalid build configuration: {0}")]
+    Config(String),
+    /// The arguments failed validation, see [`crate::BuildArgs::validate`]. The exit code is that
+    /// of the first error, see [`ValidationError::exit_code`].
+    #[error("invalid build arguments:\n  - {}", list(.0))]
+    Invalid(Vec<ValidationError>),
+    /// The succinct toolchain is not installed.
+    #[error("the succinct toolchain is not installed, {}", crate::toolchain::INSTALL_HELP)]
+    ToolchainMissing,
+    /// Docker is not installed or not running.
+    #[error("docker is not installed or not running: https://docs.docker.com/get-docker/")]
+    DockerUnavailable,
+    /// The Docker image could not be pulled.
+    #[error("failed to pull the docker image {image}")]
+    ImagePull {
+        /// The image which could not be pulled.
+        image: String,
+    },
+    /// The program directory does not exist or does not contain a `Cargo.toml`, see
+    /// [`crate::check_program_dir`].
+    #[error(
+        "the program directory {path} {}{}",
+        if *.missing { "does not exist" } else { "does not contain a Cargo.toml" },
+        suggest(.suggestions)
+    )]
+    ProgramDir {
+        /// The program directory, relative to the crate of the build script in a build script.
+        path: String,
+        /// Whether the directory does not exist, rather than not containing a `Cargo.toml`.
+        missing: bool,
+        /// The directories with a `Cargo.toml` within or next to it.
+        suggestions: Vec<String>,
+    },
+    /// Cargo failed to compile the program.
+    #[error("failed to build program: cargo exited with {status}")]
+    Compile {
+        /// The exit status of cargo.
+        status: ExitStatus,
+        /// The first error printed by the compiler, such as `error[E0425]: cannot find value
+        /// `x` in this scope`, if any.
+        first_error: Option<String>,
+    },
+    /// The ELF could not be copied to the output directory or read.
+    #[error("failed to write or read the ELF at {path}")]
+    Artifact {
+        /// The path the ELF was copied to.
+        path: Utf8PathBuf,
+        /// The underlying I/O error.
+        #[source]
+        source: io::Error,
+    },
+    /// The ELF could not be copied to one of several output directories, see
+    /// [`crate::BuildArgs::output_directory`]. The ELF is only moved into place once it was copied
+    /// next to each destination, so it is left as it was in all of them unless moving it failed.
+    #[error("failed to copy the ELF to its output directories:\n  - {}", outcomes(.destinations))]
+    Copy {
+        /// The path of the ELF in each output directory, and what happened to it there.
+        destinations: Vec<(Utf8PathBuf, CopyOutcome)>,
+    },
+    /// Cargo succeeded, but the ELF is not where it should have compiled it.
+    #[error(
+        "the compiled ELF is missing at {expected}, where cargo compiled {}{}",
+        crate::missing_elf::found_list(.found),
+        .hint.as_ref().map(|hint| format!("; {}", hint)).unwrap_or_default()
+    )]
+    MissingElf {
+        /// The path the ELF should have been compiled to.
+        expected: Utf8PathBuf,
+        /// The files in the directory of `expected` and its `examples` subdirectory, which cargo
+        /// compiled instead.
+        found: Vec<String>,
+        /// The likely cause, such as a binary target not named after the package, if known.
+        hint: Option<String>,
+    },
+    /// Another program built by this process wrote an ELF to the same path.
+    #[error(
+        "the ELF of {second} would overwrite the ELF of {first} at {path}, set a different \
+         `elf_name` or `output_directory` for one of them"
+    )]
+    ElfConflict {
+        /// The path of the ELF.
+        path: Utf8PathBuf,
+        /// The package of the program which wrote the ELF first.
+        first: String,
+        /// The package of the program which would overwrite it.
+        second: String,
+    },
+    /// The ELF failed its smoke test, see [`crate::BuildArgs::smoke_test`]: executing it trapped,
+    /// panicked or exceeded the cycle budget.
+    #[error("the ELF at {elf} failed its smoke test: {reason}")]
+    SmokeTest {
+        /// The path of the ELF.
+        elf: Utf8PathBuf,
+        /// Why executing it failed, such as `execution failed with exit code 1`.
+        reason: String,
+    },
+    /// A post-build hook failed, see [`crate::BuildArgs::post_build_hooks`].
+    #[error("the post-build hook `{command}` exited with {status}")]
+    Hook {
+        /// The command of the hook.
+        command: String,
+        /// The exit status of the hook.
+        status: ExitStatus,
+    },
+    /// The unit tests of the program failed on the host, see [`crate::test_program`].
+    #[error("the tests of the program failed: cargo exited with {status}")]
+    Test {
+        /// The exit status of cargo.
+        status: ExitStatus,
+    },
+    /// A warning of a kind denied by [`crate::BuildArgs::deny_warnings`] was emitted.
+    #[error("denied warning `{}`: {}", kind_name(.0), .0.message)]
+    DeniedWarning(BuildWarning),
+    /// The build was cancelled with a [`crate::CancellationToken`].
+    #[error("the build was cancelled")]
+    Cancelled,
+    /// The program was not built: building programs is skipped with `SP1_SKIP_PROGRAM_BUILD` or
+    /// `SP1_BUILD_SKIP` or for the profile of the crate building it, see
+    /// [`crate::BuildArgs::build_when`], and the program was never built before, or it failed to
+    /// compile in a build script with [`crate::FailPolicy::WarnAndSkip`]. A build which is
+    /// skipped after a previous build returns the previous ELFs instead, see
+    /// [`crate::BuildOutput::skipped`].
+    ///
+    /// This is not a failure of the build, and callers such as build scripts may only warn about
+    /// it, as `sp1-helper` does.
+    #[error("the program build is skipped, and there is no ELF of a previous build")]
+    Skipped,
+    /// The build wrote no ELF, as it only type-checked the program with
+    /// [`crate::BuildArgs::check_only`], but its caller returns the path of the ELF.
+    #[error(
+        "the build only checks the program (`check_only`) and writes no ELF, use `check_program`"
+    )]
+    NoElf,
+}
+
+/// What happened to the ELF in one output directory of a [`BuildError::Copy`].
+#[derive(Debug)]
+pub enum CopyOutcome {
+    /// The ELF was copied there, before moving it into another output directory failed.
+    Copied,
+    /// The ELF was left as it was, because copying it to another output directory failed.
+    NotUpdated,
+    /// Copying the ELF there failed.
+    Failed(io::Error),
+}
+
+impl BuildError {
+    /// The exit code of a process terminating because of this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuildError::Config(_) | BuildError::ProgramDir { .. } | BuildError::NoElf => 2,
+            BuildError::Invalid(errors) => errors.first().map_or(2, ValidationError::exit_code),
+            BuildError::ToolchainMissing => 10,
+            BuildError::DockerUnavailable => 11,
+            BuildError::ImagePull { .. } => 12,
+            BuildError::Compile { .. } => 20,
+            BuildError::Artifact { .. } |
+            BuildError::Copy { .. } |
+            BuildError::MissingElf { .. } |
+            BuildError::ElfConflict { .. } => 30,
+            BuildError::SmokeTest { .. } | BuildError::Hook { .. } | BuildError::Test { .. } => 40,
+            BuildError::Cancelled => 130,
+            BuildError::Skipped => 3,
+            BuildError::DeniedWarning(_) => 50,
+        }
+    }
+
+    /// The exit code of a process terminating because of `err`: that of the [`BuildError`] it
+    /// wraps, or 1.
+    pub fn exit_code_of(err: &anyhow::Error) -> i32 {
+        err.downcast_ref::<BuildError>().map_or(1, BuildError::exit_code)
+    }
+}
+
+fn list(errors: &[ValidationError]) -> String {
+    errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n  - ")
+}
+
+fn outcomes(destinations: &[(Utf8PathBuf, CopyOutcome)]) -> String {
+    let outcome = |outcome: &CopyOutcome| match outcome {
+        CopyOutcome::Copied => "copied".to_string(),
+        CopyOutcome::NotUpdated => "not updated".to_string(),
+        CopyOutcome::Failed(err) => format!("failed: {}", err),
+    };
+    let outcomes = destinations.iter().map(|(path, o)| format!("{}: {}", path, outcome(o)));
+    outcomes.collect::<Vec<_>>().join("\n  - ")
+}
+
+fn kind_name(warning: &BuildWarning) -> String {
+    serde_json::to_value(warning.kind)
+        .ok()
+        .and_then(|kind| kind.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn suggest(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!("; directories with a Cargo.toml nearby: {}", suggestions.join(", "))
+    }
+}
+
+/// Recognizes the causes of a failed build in the lines printed to stderr by cargo, rustup and
+/// Docker.
+#[derive(Debug, Default)]
+pub(crate) struct FailureCause {
+    toolchain_missing: bool,
+    image_pull_failed: bool,
+    panic_strategy_mismatch: bool,
+    first_error: Option<String>,
+}
+
+impl FailureCause {
+    pub(crate) fn line(&mut self, line: &str) {
+        let line = line.trim();
+        if self.first_error.is_none() && line.starts_with("error") {
+            self.first_error = Some(line.to_string());
+        }
+        if line.contains("toolchain 'succinct'") && line.contains("not installed") {
+            self.toolchain_missing = true;
+        }
+        if crate::panic_strategy::is_mismatch(line) {
+            self.panic_strategy_mismatch = true;
+        }
+        if line.starts_with("docker: Error response from daemon") &&
+            (line.contains("pull") || line.contains("manifest"))
+        {
+            self.image_pull_failed = true;
+        }
+    }
+
+    /// The error of a build which exited with `status`, pulling `image` if built with Docker.
+    pub(crate) fn error(&self, status: ExitStatus, image: Option<&str>) -> BuildError {
+        match image {
+            Some(image) if self.image_pull_failed => {
+                BuildError::ImagePull { image: image.to_string() }
+            }
+            _ if self.toolchain_missing => BuildError::ToolchainMissing,
+            _ => BuildError::Compile { status, first_error: self.first_error.clone() },
+        }
+    }
+
+    /// An explanation of the error, added to it, if its cause is known.
+    pub(crate) fn help(&self) -> Option<&'static str> {
+        self.panic_strategy_mismatch.then_some(crate::panic_strategy::MISMATCH_HELP)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    /// The status of a process which exited with 1.
+    pub(crate) fn failed() -> ExitStatus {
+        #[cfg(unix)]
+        use std::os::unix::process::ExitStatusExt;
+        #[cfg(windows)]
+        use std::os::windows::process::ExitStatusExt;
+
+        // The raw status of unix is the wait status, with the exit code in the second byte.
+        ExitStatus::from_raw(if cfg!(unix) { 256 } else { 1 })
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let io_error = || io::Error::new(io::ErrorKind::NotFound, "missing");
+        let errors = [
+            (BuildError::Config("bad".to_string()), 2),
+            (BuildError::ProgramDir { path: "p".into(), missing: true, suggestions: vec![] }, 2),
+            (BuildError::Invalid(vec![ValidationError::InvalidElfName("/".to_string())]), 2),
+            (BuildError::Invalid(vec![ValidationError::ToolchainMissing]), 10),
+            (BuildError::ToolchainMissing, 10),
+            (BuildError::DockerUnavailable, 11),
+            (BuildError::ImagePull { image: "sp1".to_string() }, 12),
+            (BuildError::Compile { status: failed(), first_error: None }, 20),
+            (BuildError::Artifact { path: "elf".into(), source: io_error() }, 30),
+            (BuildError::Copy { destinations: vec![("elf".into(), CopyOutcome::NotUpdated)] }, 30),
+            (BuildError::MissingElf { expected: "elf".into(), found: vec![], hint: None }, 30),
+            (
+                BuildError::ElfConflict {
+                    path: "elf".into(),
+                    first: "a".into(),
+                    second: "b".into(),
+                },
+                30,
+            ),
+            (BuildError::SmokeTest { elf: "elf".into(), reason: "trap".into() }, 40),
+            (BuildError::Hook { command: "false".into(), status: failed() }, 40),
+            (BuildError::Test { status: failed() }, 40),
+            (BuildError::Cancelled, 130),
+            (BuildError::Skipped, 3),
+            (BuildError::NoElf, 2),
+            (
+                BuildError::DeniedWarning(BuildWarning::new(
+                    crate::WarningKind::NightlyFallback,
+                    "built with nightly",
+                )),
+                50,
+            ),
+        ];
+        for (error, code) in errors {
+            assert_eq!(error.exit_code(), code);
+            let err = Err::<(), _>(error).context("failed to build program").unwrap_err();
+            assert_eq!(BuildError::exit_code_of(&err), code);
+        }
+        assert_eq!(BuildError::exit_code_of(&anyhow::anyhow!("other")), 1);
+    }
+
+    #[test]
+    fn test_failure_cause() {
+        let mut cause = FailureCause::default();
+        cause.line("   Compiling fibonacci-program v0.1.0");
+        cause.line("error[E0425]: cannot find value `x` in this scope");
+        cause.line("error: could not compile `fibonacci-program`");
+        assert!(matches!(
+            cause.error(failed(), None),
+            BuildError::Compile { first_error: Some(error), .. }
+                if error == "error[E0425]: cannot find value `x` in this scope"
+        ));
+        assert_eq!(cause.help(), None);
+
+        let mut panic_cause = FailureCause::default();
+        panic_cause.line("  = note: rust-lld: error: undefined symbol: rust_eh_personality");
+        assert!(matches!(panic_cause.error(failed(), None), BuildError::Compile { .. }));
+        assert!(panic_cause.help().unwrap().contains("panic = \"unwind\""));
+
+        cause.line("error: toolchain 'succinct' is not installed");
+        assert!(matches!(cause.error(failed(), None), BuildError::ToolchainMissing));
+
+        cause.line("docker: Error response from daemon: manifest for sp1:v9 not found.");
+        assert!(matches!(cause.error(failed(), Some("sp1:v9")), BuildError::ImagePull { .. }));
+    }
+}
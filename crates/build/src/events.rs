@@ -0,0 +1,177 @@
+use std::sync::mpsc::Sender;
+
+use crate::{output, BuildOutput};
+
+/// A phase of a build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Reading the program's metadata and resolving the build arguments.
+    Resolving,
+    /// Pulling the Docker image to build in.
+    PullingImage,
+    /// Compiling the program and its dependencies.
+    Compiling,
+    /// Copying the ELF to the output directory.
+    Copying,
+}
+
+impl BuildPhase {
+    /// The name of the phase in machine-readable output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildPhase::Resolving => "resolve",
+            BuildPhase::PullingImage => "pull-image",
+            BuildPhase::Compiling => "compile",
+            BuildPhase::Copying => "copy",
+        }
+    }
+}
+
+/// An event sent while building a program, see [`crate::build_program_with_events`].
+///
+/// Compilation progress is derived from the `Compiling` lines cargo prints for each crate, and the
+/// total number of crates is estimated from the program's dependency graph before the build
+/// starts. Both are approximate: the total also counts crates which are already compiled or only
+/// used on other platforms, and the progress stops being reported if cargo changes the format of
+/// its output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildEvent {
+    /// The build entered a new phase.
+    Phase(BuildPhase),
+    /// Cargo started compiling a crate.
+    CompilingCrate {
+        /// The name of the crate.
+        name: String,
+        /// The version of the crate.
+        version: String,
+        /// The number of crates started so far, including this one.
+        current: usize,
+        /// The estimated number of crates in the build, if known.
+        total: Option<usize>,
+    },
+    /// The build finished successfully. This is the last event of a successful build.
+    Finished(BuildOutput),
+    /// The build failed with this error. This is the last event of a failed build.
+    Failed(String),
+}
+
+/// Sends [`BuildEvent`]s to an optional receiver, ignoring any errors.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EventSender(Option<Sender<BuildEvent>>);
+
+impl EventSender {
+    pub(crate) fn new(sender: Sender<BuildEvent>) -> Self {
+        Self(Some(sender))
+    }
+
+    /// Send `event`, if there is a receiver. Phase transitions are also emitted as output.
+    pub(crate) fn send(&self, event: BuildEvent) {
+        if let BuildEvent::Phase(phase) = event {
+            output::phase(phase);
+        }
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+/// Tracks the progress of cargo from the lines it prints to stderr.
+#[derive(Debug)]
+pub(crate) struct CargoProgress {
+    events: EventSender,
+    docker: bool,
+    compiling: bool,
+    pulling: bool,
+    current: usize,
+    total: Option<usize>,
+}
+
+impl CargoProgress {
+    /// Track the progress of a build of about `total` crates. Unless building with Docker, where
+    /// the image may have to be pulled first, the compilation phase starts immediately.
+    pub(crate) fn new(events: EventSender, docker: bool, total: Option<usize>) -> Self {
+        let progress =
+            Self { events, docker, compiling: !docker, pulling: false, current: 0, total };
+        if !docker {
+            progress.events.send(BuildEvent::Phase(BuildPhase::Compiling));
+        }
+        progress
+    }
+
+    /// Update the progress from a line printed by cargo, or by Docker before cargo starts.
+    pub(crate) fn line(&mut self, line: &str) {
+        let line = line.trim();
+        if self.docker && !self.compiling {
+            if line.starts_with("Unable to find image") {
+                if !self.pulling {
+                    self.pulling = true;
+                    self.events.send(BuildEvent::Phase(BuildPhase::PullingImage));
+                }
+                return;
+            }
+            if line.starts_with("Compiling") || line.starts_with("Finished") {
+                self.compiling = true;
+                self.events.send(BuildEvent::Phase(BuildPhase::Compiling));
+            }
+        }
+
+        let mut words = line.strip_prefix("Compiling ").unwrap_or_default().split_whitespace();
+        if let (Some(name), Some(version)) = (words.next(), words.next()) {
+            self.current += 1;
+            self.events.send(BuildEvent::CompilingCrate {
+                name: name.to_string(),
+                version: version.trim_start_matches('v').to_string(),
+                current: self.current,
+                total: self.total.map(|total| total.max(self.current)),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_cargo_progress() {
+        let (sender, receiver) = channel();
+        let mut progress = CargoProgress::new(EventSender::new(sender), true, Some(1));
+        progress.line("Unable to find image 'ghcr.io/succinctlabs/sp1:v1.1.0' locally");
+        progress.line("   Compiling libc v0.2.155");
+        progress.line("   Compiling fibonacci-program v0.1.0 (/root/program)");
+        progress.line("    Finished `release` profile [optimized] target(s) in 1.00s");
+        drop(progress);
+
+        let events: Vec<_> = receiver.iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                BuildEvent::Phase(BuildPhase::PullingImage),
+                BuildEvent::Phase(BuildPhase::Compiling),
+                BuildEvent::CompilingCrate {
+                    name: "libc".to_string(),
+                    version: "0.2.155".to_string(),
+                    current: 1,
+                    total: Some(1),
+                },
+                BuildEvent::CompilingCrate {
+                    name: "fibonacci-program".to_string(),
+                    version: "0.1.0".to_string(),
+                    current: 2,
+                    total: Some(2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dropped_receiver() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        let mut progress = CargoProgress::new(EventSender::new(sender), false, None);
+        progress.line("   Compiling libc v0.2.155");
+        assert_eq!(progress.current, 1);
+    }
+}
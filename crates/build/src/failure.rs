@@ -0,0 +1,152 @@
+//! What a build script does when the program it builds fails to compile, see [`FailPolicy`].
+
+use anyhow::Result;
+use cargo_metadata::camino::Utf8PathBuf;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{output, BuildArgs, BuildError, BuildOutput, BuildWarning, ElfArtifact, WarningKind};
+
+/// What a build in a build script does when the program fails to compile. Outside of build
+/// scripts, and for failures other than [`BuildError::Compile`], the build always fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailPolicy {
+    /// Fail the build.
+    #[default]
+    Error,
+    /// Warn with the first error of the compiler, and return the ELFs of the previous build if
+    /// they exist, marked as [`BuildOutput::stale`]. The build fails if they do not.
+    WarnAndUseStale,
+    /// Warn with the first error of the compiler, and fail with [`BuildError::Skipped`], which
+    /// `sp1-helper` only warns about.
+    WarnAndSkip,
+}
+
+/// Apply [`BuildArgs::on_failure`] to the error `err` of a build with `args`, which would copy
+/// the ELFs to `elf_paths`, if `in_build_script`. On success, `output` describes the stale ELFs.
+pub(crate) fn recover(
+    args: &BuildArgs,
+    elf_paths: &[Utf8PathBuf],
+    mut output: BuildOutput,
+    err: anyhow::Error,
+    in_build_script: bool,
+) -> Result<BuildOutput> {
+    let Some(BuildError::Compile { status, first_error }) = err.downcast_ref() else {
+        return Err(err);
+    };
+    if args.on_failure == FailPolicy::Error || !in_build_script {
+        return Err(err);
+    }
+    let summary = first_error.as_deref().unwrap_or("see the output of cargo");
+
+    match args.on_failure {
+        FailPolicy::WarnAndUseStale if elf_paths.iter().all(|path| path.exists()) => {
+            let paths: Vec<_> = elf_paths.iter().map(ToString::to_string).collect();
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::StaleElf,
+                    format!(
+                        "the program failed to build: {}\nUSING THE STALE ELF {} of a previous \
+                         build, which does not match the sources of the program. Do not generate \
+                         proofs with it",
+                        summary,
+                        paths.join(", ")
+                    ),
+                )
+                .field("error", summary)
+                .field("elf_paths", paths.join(", ")),
+            );
+            output.cargo_exit_code = status.code();
+            for elf_path in elf_paths {
+                output.artifacts.push(ElfArtifact::read(elf_path.clone())?);
+            }
+            output.stale = true;
+            Ok(output)
+        }
+        FailPolicy::WarnAndUseStale => {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::CompileFailed,
+                    format!(
+                        "the program failed to build: {}\nthere is no ELF of a previous build to \
+                         use instead",
+                        summary
+                    ),
+                )
+                .field("error", summary),
+            );
+            Err(err)
+        }
+        FailPolicy::WarnAndSkip => {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::CompileFailed,
+                    format!("the program failed to build, skipping it: {}", summary),
+                )
+                .field("error", summary),
+            );
+            Err(BuildError::Skipped.into())
+        }
+        FailPolicy::Error => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf_path = Utf8PathBuf::try_from(dir.path().join("elf")).unwrap();
+        let output = BuildOutput {
+            artifacts: vec![],
+            target_directory: Utf8PathBuf::from("target"),
+            docker: false,
+            cargo_exit_code: None,
+            timings: Default::default(),
+            skipped: false,
+            fresh: false,
+            stale: false,
+            patched_sp1_crates: vec![],
+            sboms: vec![],
+            manifests: vec![],
+            env_snapshot: None,
+            warnings: vec![],
+        };
+        let status = crate::error::tests::failed();
+        let compile = || {
+            let first_error = Some("error: expected one of `!` or `::`".to_string());
+            anyhow::Error::from(BuildError::Compile { status, first_error })
+        };
+        let recover = |on_failure, err, in_build_script| {
+            let args = BuildArgs { on_failure, ..Default::default() };
+            recover(&args, &[elf_path.clone()], output.clone(), err, in_build_script)
+        };
+        let is = |result: Result<BuildOutput>, code| {
+            assert_eq!(BuildError::exit_code_of(&result.unwrap_err()), code);
+        };
+
+        // There is no ELF of a previous build yet.
+        let warnings = crate::warning::Collector::start();
+        is(recover(FailPolicy::Error, compile(), true), 20);
+        is(recover(FailPolicy::WarnAndUseStale, compile(), true), 20);
+        is(recover(FailPolicy::WarnAndSkip, compile(), true), 3);
+        let kinds: Vec<_> = warnings.warnings().iter().map(|warning| warning.kind).collect();
+        assert_eq!(kinds, [WarningKind::CompileFailed, WarningKind::CompileFailed]);
+
+        fs::write(&elf_path, b"elf").unwrap();
+        let stale = recover(FailPolicy::WarnAndUseStale, compile(), true).unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.elf_path(), elf_path);
+        assert_eq!(stale.cargo_exit_code, Some(1));
+        let stale_warning = warnings.warnings().pop().unwrap();
+        assert_eq!(stale_warning.kind, WarningKind::StaleElf);
+        assert_eq!(stale_warning.fields["error"], "error: expected one of `!` or `::`");
+        is(recover(FailPolicy::WarnAndUseStale, compile(), false), 20);
+        is(recover(FailPolicy::WarnAndUseStale, BuildError::ToolchainMissing.into(), true), 10);
+    }
+}
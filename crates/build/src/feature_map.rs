@@ -0,0 +1,104 @@
+//! Enabling features of the program from the features of the host crate building it.
+//!
+//! Cargo runs the build script of the host crate with a `CARGO_FEATURE_<NAME>` variable for each
+//! of its enabled features, where `<NAME>` is the feature in uppercase with `_` for `-`. A
+//! [`FeatureMap`] links features of the host to features of the program, and adds the program
+//! features of the enabled host features to [`BuildArgs::features`]:
+//!
+//! ```
+//! use sp1_build::{BuildArgs, FeatureMap};
+//!
+//! let args = FeatureMap::new()
+//!     .link("bls", "bls")
+//!     .link("debug-exec", "guest-trace")
+//!     .apply(BuildArgs::default());
+//! ```
+
+use crate::BuildArgs;
+
+/// Links from features of the host crate to features of the program, see the
+/// [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureMap {
+    links: Vec<(String, String)>,
+}
+
+impl FeatureMap {
+    /// Create a map without links.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable the `guest` feature of the program when the `host` feature of the crate building it
+    /// is enabled. A host feature may enable several program features, and the other way around.
+    pub fn link(mut self, host: impl Into<String>, guest: impl Into<String>) -> Self {
+        self.links.push((host.into(), guest.into()));
+        self
+    }
+
+    /// The features of the program enabled by the features of the host crate, which are only set
+    /// in a build script.
+    pub fn guest_features(&self) -> Vec<String> {
+        self.guest_features_with(|name| std::env::var_os(name).is_some())
+    }
+
+    fn guest_features_with(&self, enabled: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut features: Vec<String> = vec![];
+        for (host, guest) in &self.links {
+            if enabled(&host_feature_var(host)) && !features.contains(guest) {
+                features.push(guest.clone());
+            }
+        }
+        features
+    }
+
+    /// Add the [`FeatureMap::guest_features`] to the features of `args`. Every linked program
+    /// feature is checked to be declared by the program when building, even if its host feature is
+    /// disabled, see [`BuildArgs::linked_features`].
+    pub fn apply(&self, args: BuildArgs) -> BuildArgs {
+        self.apply_with(args, |name| std::env::var_os(name).is_some())
+    }
+
+    fn apply_with(&self, mut args: BuildArgs, enabled: impl Fn(&str) -> bool) -> BuildArgs {
+        for feature in self.guest_features_with(enabled) {
+            if !args.features.contains(&feature) {
+                args.features.push(feature);
+            }
+        }
+        for (_, guest) in &self.links {
+            if !args.linked_features.contains(guest) {
+                args.linked_features.push(guest.clone());
+            }
+        }
+        args
+    }
+}
+
+/// The variable cargo sets for the enabled `feature` of the crate of a build script.
+fn host_feature_var(feature: &str) -> String {
+    format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let map = FeatureMap::new()
+            .link("bls", "bls")
+            .link("debug-exec", "guest-trace")
+            .link("debug-exec", "bls");
+        let args = BuildArgs { features: vec!["std".to_string()], ..Default::default() };
+
+        let apply = |enabled: &[&str]| map.apply_with(args.clone(), |name| enabled.contains(&name));
+        let none = apply(&[]);
+        assert_eq!(none.features, vec!["std"]);
+        assert_eq!(none.linked_features, vec!["bls", "guest-trace"]);
+        assert_eq!(apply(&["CARGO_FEATURE_BLS"]).features, vec!["std", "bls"]);
+        assert_eq!(
+            apply(&["CARGO_FEATURE_DEBUG_EXEC", "CARGO_FEATURE_BLS"]).features,
+            vec!["std", "bls", "guest-trace"]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Fingerprints of programs, which skip running cargo when nothing changed since the last build.
+//!
+//! The fingerprint of a build is a SHA-256 digest of:
+//!
+//! - the files of the program and of its local dependencies, except their target directories, the
+//!   output directory of the ELFs and hidden files,
+//! - the files the build script building the program is run again for, such as the `Cargo.lock` of
+//!   the workspace, the `sp1.toml` of the program and the cargo configuration, see
+//!   [`rerun::tracked_paths`],
+//! - the resolved [`BuildArgs`], except those which only affect how or when the build runs,
+//! - the command which builds the program, including its environment and the Docker image, and the
+//!   version of the toolchain for a local build,
+//! - the files the pre-build hooks declare as their outputs, see [`BuildArgs::pre_build_hooks`];
+//!   the hooks themselves are part of the arguments.
+//!
+//! It is stored next to each ELF in `<elf>.fingerprint`, with the digest of the ELF and how long
+//! cargo took, and next to the ELF cargo compiled in the target directory. A missing, unreadable
+//! or outdated file, or one of another [`FORMAT_VERSION`], means the program is built again.
+//!
+//! The variables the command removes from the environment are left out, since they depend on
+//! whether the build runs in a build script. A build from another process, such as a release
+//! script building the program which the build script of a host crate built before with the same
+//! arguments, then has the same fingerprint, and copies the ELF compiled in the target directory
+//! rather than running cargo.
+
+use std::{fs, io::Write, process::Command, time::Duration};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Metadata,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    rerun,
+    toolchain::{self, Toolchain},
+    BuildArgs, ColorChoice, ElfArtifact, OutputFormat,
+};
+
+/// The version of the format of the fingerprint files, and of what the fingerprint is computed
+/// from. Files of another version are outdated.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// The contents of a fingerprint file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored {
+    #[serde(default)]
+    version: u32,
+    fingerprint: String,
+    elf_sha256: String,
+    cargo: Duration,
+}
+
+/// The path of the fingerprint file of the ELF at `elf_path`.
+fn path(elf_path: &Utf8Path) -> Utf8PathBuf {
+    let mut path = elf_path.to_path_buf().into_string();
+    path.push_str(".fingerprint");
+    path.into()
+}
+
+/// The fingerprint of building the program in `program_dir` with `args` and `command`, after the
+/// pre-build hooks wrote `hook_outputs`. The name of the Docker `container` is left out, since it
+/// differs between builds.
+pub(crate) fn compute(
+    args: &BuildArgs,
+    program_dir: &Utf8Path,
+    metadata: &Metadata,
+    toolchain: Toolchain,
+    command: &Command,
+    container: Option<&str>,
+    hook_outputs: &[Utf8PathBuf],
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut field = |name: &str, value: &[u8]| {
+        hasher.update(name.as_bytes());
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value);
+    };
+
+    let args = BuildArgs {
+        force: false,
+        dry_run: false,
+        explain: false,
+        print_elf_path: false,
+        output_format: OutputFormat::Text,
+        color: ColorChoice::Auto,
+        log_file: None,
+        package_prefix: false,
+        build_when: vec![],
+        deny_warnings: vec![],
+        post_build_hooks: vec![],
+        allow_hook_failure: false,
+        run_hooks_on_skip: false,
+        capture_env: false,
+        ..args.clone()
+    };
+    field("args", &serde_json::to_vec(&args)?);
+
+    field("program", command.get_program().as_encoded_bytes());
+    // Neither the name of the container nor the colors of the output change the ELF.
+    let command_args = command.get_args().filter(|arg| {
+        container.map_or(true, |name| *arg != name) &&
+            !arg.as_encoded_bytes().starts_with(b"--color=")
+    });
+    for arg in command_args {
+        field("arg", arg.as_encoded_bytes());
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            field("env", key.as_encoded_bytes());
+            field("value", value.as_encoded_bytes());
+        }
+    }
+    if !args.docker {
+        let version = toolchain::installed_version(toolchain.name()).map(|version| version.line);
+        field("toolchain", version.unwrap_or_default().as_bytes());
+    }
+
+    // Hash the sources, leaving out what the build writes.
+    let mut excluded = vec![metadata.target_directory.clone()];
+    for elf_dir in crate::output_dirs(&args, metadata) {
+        if !program_dir.starts_with(&elf_dir) {
+            excluded.push(elf_dir);
+        }
+    }
+    let mut files = rerun::tracked_paths(program_dir, metadata);
+    for package in rerun::local_packages(metadata) {
+        if let Some(package_dir) = package.manifest_path.parent() {
+            source_files(package_dir, &excluded, &mut files)?;
+        }
+    }
+    for output in hook_outputs {
+        if output.is_dir() {
+            source_files(output, &[], &mut files)?;
+        } else {
+            files.push(output.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    for file in files.iter().filter(|file| file.is_file()) {
+        if file.extension() == Some("fingerprint") {
+            continue;
+        }
+        field("file", file.as_str().as_bytes());
+        field("contents", &fs::read(file).with_context(|| format!("failed to read {}", file))?);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Add the files in `dir` to `files`, recursively, except hidden files, `target` directories and
+/// the `excluded` directories.
+fn source_files(
+    dir: &Utf8Path,
+    excluded: &[Utf8PathBuf],
+    files: &mut Vec<Utf8PathBuf>,
+) -> Result<()> {
+    for entry in dir.read_dir_utf8().with_context(|| format!("failed to read {}", dir))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().starts_with('.') || excluded.iter().any(|dir| path == dir) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name() != "target" {
+                source_files(path, excluded, files)?;
+            }
+        } else if file_type.is_file() {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// The ELFs at `elf_paths` and how long cargo took to build them, if they were built with the same
+/// `fingerprint` and have not changed since.
+pub(crate) fn previous_build(
+    fingerprint: &str,
+    elf_paths: &[Utf8PathBuf],
+) -> Option<(Vec<ElfArtifact>, Duration)> {
+    let mut artifacts = vec![];
+    let mut cargo = Duration::ZERO;
+    for elf_path in elf_paths {
+        let stored: Stored = serde_json::from_slice(&fs::read(path(elf_path)).ok()?).ok()?;
+        let artifact = ElfArtifact::read(elf_path.clone()).ok()?;
+        if stored.version != FORMAT_VERSION ||
+            stored.fingerprint != fingerprint ||
+            artifact.sha256.as_ref() != Some(&stored.elf_sha256)
+        {
+            return None;
+        }
+        artifacts.push(artifact);
+        cargo = cargo.max(stored.cargo);
+    }
+    (!artifacts.is_empty()).then_some((artifacts, cargo))
+}
+
+/// Store `fingerprint` next to the `artifacts` cargo built in `cargo`.
+pub(crate) fn write(fingerprint: &str, artifacts: &[ElfArtifact], cargo: Duration) -> Result<()> {
+    for artifact in artifacts {
+        let stored = Stored {
+            version: FORMAT_VERSION,
+            fingerprint: fingerprint.to_string(),
+            elf_sha256: artifact.sha256.clone().unwrap_or_default(),
+            cargo,
+        };
+        let path = path(&artifact.path);
+        let mut file =
+            fs::File::create(&path).with_context(|| format!("failed to create {}", path))?;
+        file.write_all(&serde_json::to_vec(&stored)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program_metadata;
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_dir = Utf8PathBuf::try_from(dir.path().join("program")).unwrap();
+        fs::create_dir_all(program_dir.join("src")).unwrap();
+        fs::write(
+            program_dir.join("Cargo.toml"),
+            "[workspace]\n[package]\nname = \"fingerprinted\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(program_dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let metadata = program_metadata(&program_dir).unwrap();
+        let args = BuildArgs { docker: true, ..Default::default() };
+        let command = Command::new("docker");
+        let compute = |args: &BuildArgs| {
+            compute(args, &program_dir, &metadata, Toolchain::Succinct, &command, None, &[])
+                .unwrap()
+        };
+        let fingerprint = compute(&args);
+
+        // Neither the ELF, its fingerprint nor the target directory are part of the fingerprint.
+        let elf_path = program_dir.join("elf/riscv32im-succinct-zkvm-elf");
+        fs::create_dir_all(elf_path.parent().unwrap()).unwrap();
+        fs::write(&elf_path, b"elf").unwrap();
+        fs::create_dir_all(program_dir.join("target")).unwrap();
+        fs::write(program_dir.join("target/output"), b"output").unwrap();
+        let artifacts = vec![ElfArtifact::read(elf_path.clone()).unwrap()];
+        write(&fingerprint, &artifacts, Duration::from_secs(3)).unwrap();
+        assert_eq!(compute(&args), fingerprint);
+        assert_eq!(compute(&BuildArgs { force: true, ..args.clone() }), fingerprint);
+        let elf_paths = [elf_path.clone()];
+        let (previous, cargo) = previous_build(&fingerprint, &elf_paths).unwrap();
+        assert_eq!(previous, artifacts);
+        assert_eq!(cargo, Duration::from_secs(3));
+
+        // Changing the sources or the arguments changes the fingerprint.
+        fs::write(program_dir.join("src/main.rs"), "fn main() { }\n").unwrap();
+        assert_ne!(compute(&args), fingerprint);
+        assert_ne!(compute(&BuildArgs { locked: true, ..args.clone() }), fingerprint);
+        assert!(previous_build("other", &elf_paths).is_none());
+
+        // A changed ELF or a corrupted fingerprint file are built again.
+        fs::write(&elf_path, b"changed").unwrap();
+        assert!(previous_build(&fingerprint, &elf_paths).is_none());
+        write(&fingerprint, &[ElfArtifact::read(elf_path.clone()).unwrap()], Duration::ZERO)
+            .unwrap();
+        assert!(previous_build(&fingerprint, &elf_paths).is_some());
+        fs::write(path(&elf_path), b"{").unwrap();
+        assert!(previous_build(&fingerprint, &elf_paths).is_none());
+
+        // So are fingerprint files of another version.
+        write(&fingerprint, &[ElfArtifact::read(elf_path.clone()).unwrap()], Duration::ZERO)
+            .unwrap();
+        let stored = fs::read_to_string(path(&elf_path)).unwrap();
+        assert!(stored.starts_with(&format!("{{\"version\":{},", FORMAT_VERSION)), "{}", stored);
+        fs::write(path(&elf_path), stored.replace("\"version\":1,", "")).unwrap();
+        assert!(previous_build(&fingerprint, &elf_paths).is_none());
+
+        // Removing variables from the environment of the command does not change the fingerprint.
+        let mut removing = Command::new("docker");
+        removing.env_remove("CARGO_PKG_NAME");
+        let removed = super::compute(
+            &args,
+            &program_dir,
+            &metadata,
+            Toolchain::Succinct,
+            &removing,
+            None,
+            &[],
+        );
+        assert_eq!(removed.unwrap(), compute(&args));
+
+        // As do the pre-build hooks and the files they declare as outputs, wherever they are.
+        let hooks = BuildArgs { pre_build_hooks: vec!["./generate.sh".into()], ..args.clone() };
+        assert_ne!(compute(&hooks), compute(&args));
+        let output = Utf8PathBuf::try_from(dir.path().join("generated.rs")).unwrap();
+        fs::write(&output, "1").unwrap();
+        let with_output = |args: &BuildArgs| {
+            let outputs = [output.clone()];
+            super::compute(
+                args,
+                &program_dir,
+                &metadata,
+                Toolchain::Succinct,
+                &command,
+                None,
+                &outputs,
+            )
+            .unwrap()
+        };
+        let generated = with_output(&hooks);
+        assert_ne!(generated, compute(&hooks));
+        fs::write(&output, "2").unwrap();
+        assert_ne!(with_output(&hooks), generated);
+    }
+}
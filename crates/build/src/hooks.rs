@@ -0,0 +1,232 @@
+//! Running commands before and after a build, see [`BuildArgs::pre_build_hooks`] and
+//! [`BuildArgs::post_build_hooks`].
+//!
+//! Each hook runs in a shell in the program directory, with its output printed with an
+//! `[sp1] [hook]` prefix. Pre-build hooks run once before cargo, with the variables of
+//! [`pre_build_env`], and post-build hooks once for each distinct ELF the build copied, with the
+//! variables of [`post_build_env`].
+
+use std::{
+    fs,
+    process::{self, Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    output, pipe,
+    warning::{BuildWarning, WarningKind},
+    BuildArgs, BuildError, ElfArtifact, BUILD_TARGET,
+};
+
+/// The profile programs are compiled with, set in `SP1_BUILD_PROFILE`.
+const PROFILE: &str = "release";
+
+/// The variable naming the file a pre-build hook lists the files it writes in, one path per line,
+/// relative to the program directory, for the fingerprint to include them.
+const OUTPUTS_ENV: &str = "SP1_HOOK_OUTPUTS";
+
+/// Run the pre-build hooks of `args` for the package `package_name` in `program_dir`, compiled
+/// into `target_dir`, failing on the first hook which fails. Returns the files the hooks declared
+/// as their outputs in [`OUTPUTS_ENV`].
+pub(crate) fn run_pre_build(
+    args: &BuildArgs,
+    program_dir: &Utf8Path,
+    package_name: &str,
+    target_dir: &Utf8Path,
+) -> Result<Vec<Utf8PathBuf>> {
+    if args.pre_build_hooks.is_empty() {
+        return Ok(vec![]);
+    }
+    fs::create_dir_all(target_dir)
+        .with_context(|| format!("failed to create the target directory {}", target_dir))?;
+    let outputs_file = target_dir.join(format!("hook-outputs-{}", process::id()));
+    fs::write(&outputs_file, "").with_context(|| format!("failed to create {}", outputs_file))?;
+    let env = pre_build_env(args, package_name, target_dir, &outputs_file);
+    let result = args.pre_build_hooks.iter().try_for_each(|hook| run(hook, program_dir, &env));
+    let outputs = fs::read_to_string(&outputs_file).unwrap_or_default();
+    let _ = fs::remove_file(&outputs_file);
+    result?;
+    let outputs = outputs.lines().map(str::trim).filter(|line| !line.is_empty());
+    Ok(outputs.map(|path| program_dir.join(path)).collect())
+}
+
+/// The variables describing the build to a pre-build hook. Those of the resolved arguments are
+/// prefixed with `SP1_HOOK_`, so that they do not configure a build the hook would run.
+fn pre_build_env(
+    args: &BuildArgs,
+    package_name: &str,
+    target_dir: &Utf8Path,
+    outputs_file: &Utf8Path,
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("SP1_PACKAGE_NAME", package_name.to_string()),
+        ("SP1_BUILD_PROFILE", PROFILE.to_string()),
+        ("SP1_HOOK_FEATURES", args.features.join(",")),
+        ("SP1_HOOK_ALL_FEATURES", args.all_features.to_string()),
+        ("SP1_HOOK_NO_DEFAULT_FEATURES", args.no_default_features.to_string()),
+        ("SP1_HOOK_TARGET", BUILD_TARGET.to_string()),
+        ("SP1_HOOK_TARGET_DIR", target_dir.to_string()),
+        ("SP1_HOOK_DOCKER", args.docker.to_string()),
+        (OUTPUTS_ENV, outputs_file.to_string()),
+    ]
+}
+
+/// Run the post-build hooks of `args` for each of `artifacts` the build wrote, failing on the
+/// first hook which fails unless [`BuildArgs::allow_hook_failure`] is set.
+pub(crate) fn run_post_build(
+    args: &BuildArgs,
+    program_dir: &Utf8Path,
+    package_name: &str,
+    artifacts: &[ElfArtifact],
+) -> Result<()> {
+    if args.post_build_hooks.is_empty() {
+        return Ok(());
+    }
+    let mut seen = vec![];
+    for artifact in artifacts {
+        // Copies of the ELF in other output directories, and ELFs which were not built, are left
+        // out.
+        let Some(sha256) = artifact.sha256.as_deref().filter(|sha256| !seen.contains(sha256))
+        else {
+            continue;
+        };
+        seen.push(sha256);
+        for hook in &args.post_build_hooks {
+            let env = post_build_env(artifact, sha256, package_name);
+            match run(hook, program_dir, &env) {
+                Ok(()) => {}
+                Err(err) if args.allow_hook_failure => output::warning(
+                    BuildWarning::new(
+                        WarningKind::HookFailed,
+                        format!("the post-build hook `{}` failed: {:#}", hook, err),
+                    )
+                    .field("command", hook)
+                    .field("elf", &artifact.path),
+                ),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The variables describing the ELF of `artifact` to a post-build hook.
+fn post_build_env(
+    artifact: &ElfArtifact,
+    sha256: &str,
+    package_name: &str,
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("SP1_ELF_PATH", artifact.path.to_string()),
+        ("SP1_ELF_SHA256", sha256.to_string()),
+        ("SP1_PACKAGE_NAME", package_name.to_string()),
+        ("SP1_BUILD_PROFILE", PROFILE.to_string()),
+    ]
+}
+
+/// Run `hook` in a shell in `dir` with `env`, printing its output as it is written.
+fn run(hook: &str, dir: &Utf8Path, env: &[(&str, String)]) -> Result<()> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    output::info(&format!("[sp1] [hook] running `{}`", hook));
+    let mut child = command
+        .arg(hook)
+        .current_dir(dir)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run the post-build hook `{}`", hook))?;
+    let (stdout, stderr) = (child.stdout.take().unwrap(), child.stderr.take().unwrap());
+    pipe::read_lines(stdout, stderr, |_, line| output::info(&format!("[sp1] [hook] {}", line)));
+    let status = child.wait().with_context(|| format!("failed to wait for `{}`", hook))?;
+    if !status.success() {
+        return Err(BuildError::Hook { command: hook.to_string(), status }.into());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_run_pre_build() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let target_dir = dir.join("target");
+        let hooks = vec![
+            "echo \"pub const FEATURES: &str = \\\"$SP1_HOOK_FEATURES\\\";\" > generated.rs"
+                .to_string(),
+            "echo generated.rs >> \"$SP1_HOOK_OUTPUTS\"".to_string(),
+        ];
+        let args = BuildArgs {
+            pre_build_hooks: hooks,
+            features: vec!["a".into(), "b".into()],
+            ..Default::default()
+        };
+        let outputs = run_pre_build(&args, &dir, "program", &target_dir).unwrap();
+        assert_eq!(outputs, vec![dir.join("generated.rs")]);
+        let generated = fs::read_to_string(dir.join("generated.rs")).unwrap();
+        assert_eq!(generated, "pub const FEATURES: &str = \"a,b\";\n");
+        assert_eq!(fs::read_dir(&target_dir).unwrap().count(), 0);
+
+        let failing =
+            BuildArgs { pre_build_hooks: vec!["exit 2".into(), "touch ran".into()], ..args };
+        let err = run_pre_build(&failing, &dir, "program", &target_dir).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BuildError::Hook { .. })));
+        assert!(!dir.join("ran").exists());
+    }
+
+    #[test]
+    fn test_run_post_build() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let elf = |path: &str, sha256: Option<&str>| ElfArtifact {
+            path: dir.join(path),
+            size: sha256.map(|_| 1),
+            sha256: sha256.map(str::to_string),
+            cycles: None,
+        };
+        // Copies of the same ELF and ELFs which were not built run no hook.
+        let artifacts = [elf("elf/a", Some("aa")), elf("out/a", Some("aa")), elf("b", None)];
+        let hook = "echo \"$SP1_PACKAGE_NAME $SP1_ELF_PATH $SP1_ELF_SHA256 $SP1_BUILD_PROFILE\" \
+                    >> hooks.log"
+            .to_string();
+        let args = BuildArgs { post_build_hooks: vec![hook], ..Default::default() };
+        run_post_build(&args, &dir, "program", &artifacts).unwrap();
+        let log = fs::read_to_string(dir.join("hooks.log")).unwrap();
+        assert_eq!(log, format!("program {} aa release\n", dir.join("elf/a")));
+
+        let failing = BuildArgs { post_build_hooks: vec!["exit 3".to_string()], ..args };
+        let err = run_post_build(&failing, &dir, "program", &artifacts).unwrap_err();
+        match err.downcast_ref() {
+            Some(BuildError::Hook { command, status }) => {
+                assert_eq!(command, "exit 3");
+                assert_eq!(status.code(), Some(3));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(BuildError::exit_code_of(&err), 40);
+
+        let allowed = BuildArgs { allow_hook_failure: true, ..failing };
+        let warnings = crate::warning::Collector::start();
+        run_post_build(&allowed, &dir, "program", &artifacts).unwrap();
+        assert_eq!(warnings.warnings()[0].kind, WarningKind::HookFailed);
+        assert_eq!(warnings.warnings()[0].fields["command"], "exit 3");
+    }
+}
@@ -0,0 +1,170 @@
+//! Building the programs declared by the host crate, see [`build_programs_from_host_metadata`].
+
+use std::{env, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, MetadataCommand};
+use serde_json::Value;
+
+use crate::{build_program_with_output, config, output, rerun, BuildArgs, BuildError};
+
+/// The keys of an entry of `[[package.metadata.sp1.programs]]`, besides `path`.
+const ENTRY_KEYS: [&str; 3] = ["elf-name", "features", "docker"];
+
+/// A program declared in the `[[package.metadata.sp1.programs]]` array of the host crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostProgram {
+    /// The program directory, relative to the host crate.
+    pub path: String,
+    /// The arguments to build the program with.
+    pub args: BuildArgs,
+}
+
+/// Build the programs declared in the `[[package.metadata.sp1.programs]]` array of the crate whose
+/// build script calls this, one after the other:
+///
+/// ```toml
+/// [[package.metadata.sp1.programs]]
+/// path = "../program"
+/// elf-name = "fibonacci"
+/// features = ["bls"]
+/// docker = false
+/// ```
+///
+/// Each entry sets the `path` of the program relative to the host crate, and optionally its
+/// `elf-name`, `features` and whether to build it with `docker`. The other arguments are resolved
+/// as for [`crate::build_program_in`].
+///
+/// # Returns
+///
+/// * `Result<Vec<(String, Utf8PathBuf)>>` - The path of each program and the path to its ELF, in
+///   the order of the entries. Fails with [`BuildError::Config`] naming the index and key of an
+///   invalid entry, or as soon as a program fails to build.
+pub fn build_programs_from_host_metadata() -> Result<Vec<(String, Utf8PathBuf)>> {
+    let result = (|| {
+        let manifest_dir =
+            env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).ok_or_else(|| {
+                anyhow!("the programs of the host crate can only be built from its build script")
+            })?;
+        let manifest_path = manifest_dir.join("Cargo.toml");
+        if rerun::in_build_script() {
+            println!("cargo:rerun-if-changed={}", manifest_path.display());
+        }
+        let metadata = MetadataCommand::new()
+            .manifest_path(&manifest_path)
+            .no_deps()
+            .exec()
+            .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+        let package = metadata
+            .root_package()
+            .ok_or_else(|| anyhow!("{} does not contain a package", manifest_path.display()))?;
+
+        let mut built = vec![];
+        for program in host_programs(&package.metadata, package.manifest_path.as_str())? {
+            let output = build_program_with_output(&program.args, manifest_dir.join(&program.path))
+                .with_context(|| format!("failed to build the program at {}", program.path))?;
+            built.push((program.path, output.try_elf_path()?.to_path_buf()));
+        }
+        Ok(built)
+    })();
+    if let Err(err) = &result {
+        output::error(err);
+    }
+    result
+}
+
+/// The programs declared in the `programs` array of the `sp1` table of the `metadata` of a package,
+/// whose manifest is at `origin`.
+pub(crate) fn host_programs(metadata: &Value, origin: &str) -> Result<Vec<HostProgram>> {
+    let Some(programs) = metadata.get("sp1").and_then(|sp1| sp1.get("programs")) else {
+        return Ok(vec![]);
+    };
+    let invalid = |msg: String| {
+        BuildError::Config(format!("[package.metadata.sp1.programs] of {}: {}", origin, msg))
+    };
+    let programs = programs.as_array().ok_or_else(|| invalid("not an array".to_string()))?;
+
+    let mut host_programs = vec![];
+    for (index, entry) in programs.iter().enumerate() {
+        let entry =
+            entry.as_object().ok_or_else(|| invalid(format!("entry {} is not a table", index)))?;
+        let path = match entry.get("path") {
+            Some(Value::String(path)) => path.clone(),
+            Some(value) => {
+                return Err(invalid(format!(
+                    "invalid value {} for `path` of entry {}",
+                    value, index
+                ))
+                .into())
+            }
+            None => return Err(invalid(format!("entry {} has no `path`", index)).into()),
+        };
+
+        let mut layer = config::default_layer();
+        for (key, value) in entry {
+            if key == "path" {
+                continue;
+            }
+            if !ENTRY_KEYS.contains(&key.as_str()) {
+                return Err(invalid(format!(
+                    "unknown key `{}` in entry {}, expected `path`, {}",
+                    key,
+                    index,
+                    ENTRY_KEYS.map(|key| format!("`{}`", key)).join(", ")
+                ))
+                .into());
+            }
+            let field = key.replace('-', "_");
+            if !config::is_valid_value(&field, value) {
+                return Err(invalid(format!(
+                    "invalid value {} for `{}` of entry {}",
+                    value, key, index
+                ))
+                .into());
+            }
+            layer.insert(field, value.clone());
+        }
+        let args = serde_json::from_value(Value::Object(layer))
+            .context("failed to read the arguments of a program")?;
+        host_programs.push(HostProgram { path, args });
+    }
+    Ok(host_programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_host_programs() {
+        let metadata = json!({ "sp1": { "programs": [
+            { "path": "../program" },
+            { "path": "../bls", "elf-name": "bls", "features": ["bls"], "docker": true },
+        ]}});
+        let programs = host_programs(&metadata, "Cargo.toml").unwrap();
+        assert_eq!(
+            programs[0],
+            HostProgram { path: "../program".into(), args: BuildArgs::default() }
+        );
+        assert_eq!(programs[1].path, "../bls");
+        assert_eq!(programs[1].args.elf_name, "bls");
+        assert_eq!(programs[1].args.features, ["bls"]);
+        assert!(programs[1].args.docker);
+        assert!(host_programs(&json!({}), "Cargo.toml").unwrap().is_empty());
+
+        let error = |metadata: Value| {
+            let err = host_programs(&metadata, "Cargo.toml").unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(BuildError::Config(_))));
+            err.to_string()
+        };
+        let features =
+            json!({ "sp1": { "programs": [{ "path": "a" }, { "path": "b", "features": "bls" }] }});
+        assert!(error(features).contains("invalid value \"bls\" for `features` of entry 1"));
+        let unknown = json!({ "sp1": { "programs": [{ "path": "a", "tag": "v1" }] }});
+        assert!(error(unknown).contains("unknown key `tag` in entry 0"));
+        assert!(error(json!({ "sp1": { "programs": [{}] }})).contains("entry 0 has no `path`"));
+        assert!(error(json!({ "sp1": { "programs": {} }})).contains("not an array"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Paths of the host, which differ on Windows: `canonicalize` returns verbatim paths starting with
+//! `\\?\`, which Docker and the prefix checks of this crate do not understand, executables end
+//! with `.exe`, and paths are separated with `\`, while paths in the container use `/`.
+
+use std::{
+    borrow::Cow,
+    env::consts::EXE_SUFFIX,
+    io,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::camino::{Utf8Component, Utf8Path};
+
+use crate::BuildError;
+
+/// The canonical form of `path`, without the verbatim prefix of Windows, see [`strip_verbatim`].
+pub(crate) fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let path = path.canonicalize()?;
+    match path.to_str() {
+        Some(str) => Ok(PathBuf::from(strip_verbatim(str).as_ref())),
+        None => Ok(path),
+    }
+}
+
+/// Remove the verbatim prefix of a Windows path: `\\?\C:\dir` is `C:\dir`, and
+/// `\\?\UNC\server\share` is `\\server\share`. Other paths are unchanged.
+pub(crate) fn strip_verbatim(path: &str) -> Cow<'_, str> {
+    match path.strip_prefix(r"\\?\UNC\") {
+        Some(unc) => Cow::Owned(format!(r"\\{}", unc)),
+        None => Cow::Borrowed(path.strip_prefix(r"\\?\").unwrap_or(path)),
+    }
+}
+
+/// The executable at `path`, or with the suffix of executables of the host, such as `.exe` on
+/// Windows, if only that exists.
+pub(crate) fn executable(path: PathBuf) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path);
+    }
+    let mut suffixed = path.into_os_string();
+    suffixed.push(EXE_SUFFIX);
+    let suffixed = PathBuf::from(suffixed);
+    (!EXE_SUFFIX.is_empty() && suffixed.is_file()).then_some(suffixed)
+}
+
+/// The relative path `relative` in the Linux container of a Docker build, separated with `/`
+/// whatever the separator of the host.
+pub(crate) fn container_path(relative: &Utf8Path) -> String {
+    relative
+        .components()
+        .filter(|component| !matches!(component, Utf8Component::CurDir))
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The argument of `docker run --mount` binding the directory `source` of the host to `target` in
+/// the container.
+///
+/// Unlike `-v source:target`, which splits at the first colon, `--mount` takes comma separated
+/// fields, parsed as CSV: a field containing a comma or a quote is quoted, so that any path with
+/// spaces, colons or other characters is passed as is. Docker Desktop on Windows takes the path of
+/// the host with `/` separators and without a verbatim prefix, such as `C:/Users/me/program`.
+pub(crate) fn bind_mount(source: &Utf8Path, target: &str) -> Result<String, BuildError> {
+    let source =
+        if cfg!(windows) { windows_volume_source(source.as_str()) } else { source.to_string() };
+    // Docker reads the fields of `--mount` line by line, and passes the source to the kernel as a C
+    // string.
+    if let Some(c) = source.chars().find(|c| matches!(c, '\n' | '\r' | '\0')) {
+        return Err(BuildError::Config(format!(
+            "docker cannot mount the directory {:?}, whose path contains {:?}",
+            source, c
+        )));
+    }
+    Ok(format!("type=bind,{},{}", csv_field("source", &source), csv_field("target", target)))
+}
+
+/// The field `key=value` of a CSV record, quoted if it contains a separator or a quote.
+fn csv_field(key: &str, value: &str) -> String {
+    let field = format!("{}={}", key, value);
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn windows_volume_source(path: &str) -> String {
+    strip_verbatim(path).replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_strip_verbatim() {
+        assert_eq!(strip_verbatim(r"\\?\C:\Users\me\program"), r"C:\Users\me\program");
+        assert_eq!(strip_verbatim(r"\\?\UNC\server\share\program"), r"\\server\share\program");
+        assert_eq!(strip_verbatim(r"C:\program"), r"C:\program");
+        assert_eq!(strip_verbatim("/home/me/program"), "/home/me/program");
+        assert_eq!(windows_volume_source(r"\\?\C:\Users\me\program"), "C:/Users/me/program");
+    }
+
+    #[test]
+    fn test_container_path() {
+        assert_eq!(container_path(Utf8Path::new("programs/fibonacci")), "programs/fibonacci");
+        assert_eq!(container_path(Utf8Path::new("./target")), "target");
+        assert_eq!(container_path(Utf8Path::new("")), "");
+    }
+
+    #[test]
+    fn test_bind_mount() {
+        let mount = |source: &str| bind_mount(Utf8Path::new(source), "/root/program");
+        assert_eq!(
+            mount("/home/me/zk work/program").unwrap(),
+            "type=bind,source=/home/me/zk work/program,target=/root/program"
+        );
+        assert_eq!(
+            mount("/Volumes/Drive: 2/prögram").unwrap(),
+            "type=bind,source=/Volumes/Drive: 2/prögram,target=/root/program"
+        );
+        assert_eq!(
+            mount("/home/me/a,b \"c\"").unwrap(),
+            "type=bind,\"source=/home/me/a,b \"\"c\"\"\",target=/root/program"
+        );
+        let err = mount("/home/me/line\nbreak").unwrap_err();
+        assert!(matches!(err, BuildError::Config(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let gcc = dir.path().join("riscv32-unknown-elf-gcc");
+        assert_eq!(executable(gcc.clone()), None);
+        let name = format!("riscv32-unknown-elf-gcc{}", EXE_SUFFIX);
+        fs::write(dir.path().join(&name), "").unwrap();
+        assert_eq!(executable(gcc), Some(dir.path().join(name)));
+    }
+}
@@ -0,0 +1,207 @@
+//! Running the unit tests of a program on the host, see [`test_program`].
+
+use std::{
+    env,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    check_program_dir, output, pipe, resolve_program_dir, scrub, toolchain, BuildArgs, BuildError,
+    BUILD_TARGET,
+};
+
+/// The variables a build of the program for the zkVM sets, which are removed from the environment
+/// of the tests in addition to those of the `scrub` module.
+const ZKVM_VARS: &[&str] = &["CARGO_ENCODED_RUSTFLAGS", "CARGO_TARGET_DIR", "RUSTC"];
+
+/// The arguments of [`test_program`]: those of [`BuildArgs`] which select what is compiled, and
+/// the tests to run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TestArgs {
+    /// The features to enable, as in [`BuildArgs::features`].
+    pub features: Vec<String>,
+    /// Enable all the features, as in [`BuildArgs::all_features`].
+    pub all_features: bool,
+    /// Do not enable the default features, as in [`BuildArgs::no_default_features`].
+    pub no_default_features: bool,
+    /// Only test this binary, as in [`BuildArgs::binary`], or all the targets if empty.
+    pub binary: String,
+    /// Require an up to date `Cargo.lock`, as in [`BuildArgs::locked`].
+    pub locked: bool,
+    /// Only run the tests whose names contain this filter.
+    pub filter: Option<String>,
+}
+
+impl TestArgs {
+    /// Only run the tests whose names contain `filter`.
+    pub fn filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+}
+
+impl From<&BuildArgs> for TestArgs {
+    fn from(args: &BuildArgs) -> Self {
+        Self {
+            features: args.features.clone(),
+            all_features: args.all_features,
+            no_default_features: args.no_default_features,
+            binary: args.binary.clone(),
+            locked: args.locked,
+            filter: None,
+        }
+    }
+}
+
+/// Run the unit tests of the program in `program_dir` with `cargo test` on the host, printing
+/// the output with an `[sp1] [test]` prefix.
+///
+/// The tests run as `cargo test` would in the program directory, without the environment of a
+/// build for the zkVM: neither the flags of the zkVM, nor its target, nor the succinct toolchain
+/// are used, and the variables the cargo running this function sets are removed, see the `scrub`
+/// module. Tests of code which only compiles for the zkVM can be gated with
+/// `#[cfg(not(target_os = "zkvm"))]`, or the code with `#[cfg(target_os = "zkvm")]`.
+///
+/// Fails with [`BuildError::Config`] if a `.cargo/config.toml` of the program or its parent
+/// directories sets `build.target` to the zkVM target, which would compile the tests for it;
+/// the build of the program sets the target itself, and settings for the zkVM only belong in a
+/// `[target.'cfg(target_os = "zkvm")']` section. Fails with [`BuildError::Test`] if a test fails.
+pub fn test_program(program_dir: impl AsRef<Path>, test_args: &TestArgs) -> Result<()> {
+    check_program_dir(program_dir.as_ref())?;
+    let program_dir = resolve_program_dir(program_dir.as_ref())?;
+    if let Some(config) = forced_target(&program_dir) {
+        return Err(BuildError::Config(format!(
+            "{} sets `build.target` to {}, so the tests of the program cannot run on the host: \
+             remove it, as the build of the program sets the target, and move the settings for \
+             the zkVM to a `[target.'cfg(target_os = \"zkvm\")']` section",
+            config, BUILD_TARGET
+        ))
+        .into());
+    }
+
+    let mut command = test_command(&program_dir, test_args);
+    output::info(&format!("[sp1] [test] running the tests of {}", program_dir));
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run cargo test")?;
+    let (stdout, stderr) = (child.stdout.take().unwrap(), child.stderr.take().unwrap());
+    pipe::read_lines(stdout, stderr, |_, line| output::info(&format!("[sp1] [test] {}", line)));
+    let status = child.wait().context("failed to wait for cargo test")?;
+    if !status.success() {
+        return Err(BuildError::Test { status }.into());
+    }
+    Ok(())
+}
+
+/// The `cargo test` command testing the program in `program_dir` with `test_args`.
+fn test_command(program_dir: &Utf8Path, test_args: &TestArgs) -> Command {
+    let mut command = Command::new("cargo");
+    command.current_dir(program_dir).arg("test");
+    if !test_args.binary.is_empty() {
+        command.args(["--bin", &test_args.binary]);
+    }
+    if !test_args.features.is_empty() {
+        command.args(["--features", &test_args.features.join(",")]);
+    }
+    if test_args.all_features {
+        command.arg("--all-features");
+    }
+    if test_args.no_default_features {
+        command.arg("--no-default-features");
+    }
+    if test_args.locked {
+        command.arg("--locked");
+    }
+    if let Some(filter) = &test_args.filter {
+        command.args(["--", filter]);
+    }
+
+    for key in scrub::removed_vars(env::vars_os().map(|(key, _)| key)) {
+        command.env_remove(key);
+    }
+    for key in ZKVM_VARS {
+        command.env_remove(key);
+    }
+    // The toolchain of the host is inherited, unless it is the succinct toolchain.
+    if env::var("RUSTUP_TOOLCHAIN").is_ok_and(|name| name == toolchain::TOOLCHAIN_NAME) {
+        command.env_remove("RUSTUP_TOOLCHAIN");
+    }
+    command
+}
+
+/// The cargo configuration file of `program_dir` or of one of its parents which sets
+/// `build.target` to the zkVM target, if any.
+fn forced_target(program_dir: &Utf8Path) -> Option<Utf8PathBuf> {
+    let configs = program_dir
+        .ancestors()
+        .flat_map(|dir| [dir.join(".cargo/config.toml"), dir.join(".cargo/config")]);
+    configs.into_iter().find(|config| {
+        let Some(table) = std::fs::read_to_string(config)
+            .ok()
+            .and_then(|contents| contents.parse::<toml::Table>().ok())
+        else {
+            return false;
+        };
+        let targets = match table.get("build").and_then(|build| build.get("target")) {
+            Some(toml::Value::String(target)) => vec![target.as_str()],
+            Some(toml::Value::Array(targets)) => {
+                targets.iter().filter_map(|t| t.as_str()).collect()
+            }
+            _ => vec![],
+        };
+        targets.iter().any(|target| target.trim_end_matches(".json").ends_with(BUILD_TARGET))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_test_command() {
+        let args = BuildArgs {
+            features: vec!["a".into(), "b".into()],
+            no_default_features: true,
+            binary: "fib".into(),
+            docker: true,
+            ..Default::default()
+        };
+        let command = test_command(Utf8Path::new("program"), &TestArgs::from(&args).filter("add"));
+        let command_args: Vec<_> = command.get_args().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(
+            command_args,
+            ["test", "--bin", "fib", "--features", "a,b", "--no-default-features", "--", "add"]
+        );
+        let removed: Vec<_> = command.get_envs().filter(|(_, value)| value.is_none()).collect();
+        for key in ZKVM_VARS {
+            assert!(removed.iter().any(|(removed, _)| removed == key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_forced_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let program_dir = root.join("program");
+        fs::create_dir_all(program_dir.join(".cargo")).unwrap();
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        assert_eq!(forced_target(&program_dir), None);
+
+        let conditional = "[target.'cfg(target_os = \"zkvm\")']\nrustflags = [\"-Copt-level=3\"]\n";
+        fs::write(program_dir.join(".cargo/config.toml"), conditional).unwrap();
+        assert_eq!(forced_target(&program_dir), None);
+
+        let forced = format!("[build]\ntarget = [\"{}\"]\n", BUILD_TARGET);
+        fs::write(root.join(".cargo/config"), forced).unwrap();
+        assert_eq!(forced_target(&program_dir), Some(root.join(".cargo/config")));
+    }
+}
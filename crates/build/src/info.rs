@@ -0,0 +1,130 @@
+use std::{env, fmt, path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{c_toolchain, toolchain, BuildArgs, DEFAULT_TAG};
+
+/// The versions of the tools building programs on this machine, see [`toolchain_info`].
+///
+/// Displays as human readable text, one tool per line, and serializes as JSON with the same field
+/// names. Tools which are missing or unavailable are `None`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolchainInfo {
+    /// The version of this crate.
+    pub sp1_build_version: String,
+    /// The default tag of the Docker image, see [`BuildArgs::tag`].
+    pub default_docker_tag: String,
+    /// The target triple of the host.
+    pub host: String,
+    /// The version of the rustc of the succinct toolchain, as the first line of `rustc --version`.
+    pub toolchain_version: Option<String>,
+    /// The sysroot of the succinct toolchain.
+    pub toolchain_path: Option<PathBuf>,
+    /// The C compiler for the zkVM target, see [`BuildArgs::c_compiler`].
+    pub c_compiler: Option<PathBuf>,
+    /// The version of the C compiler, as the first line of `--version`.
+    pub c_compiler_version: Option<String>,
+    /// The version of the Docker server, if Docker is installed and running.
+    pub docker_version: Option<String>,
+}
+
+impl ToolchainInfo {
+    /// Describe the tools in a single line, for logs.
+    pub fn summary(&self) -> String {
+        format!(
+            "sp1-build {}, toolchain {}, C compiler {}, docker {}, default tag {}, host {}",
+            self.sp1_build_version,
+            self.toolchain_version.as_deref().unwrap_or("not installed"),
+            self.c_compiler_version.as_deref().unwrap_or("not installed"),
+            self.docker_version.as_deref().unwrap_or("unavailable"),
+            self.default_docker_tag,
+            self.host
+        )
+    }
+}
+
+impl fmt::Display for ToolchainInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |path: &Option<PathBuf>| match path {
+            Some(path) => format!(" at {}", path.display()),
+            None => String::new(),
+        };
+        writeln!(f, "sp1-build:          {}", self.sp1_build_version)?;
+        writeln!(f, "default docker tag: {}", self.default_docker_tag)?;
+        writeln!(f, "host:               {}", self.host)?;
+        writeln!(
+            f,
+            "{} toolchain: {}{}",
+            toolchain::TOOLCHAIN_NAME,
+            self.toolchain_version.as_deref().unwrap_or("not installed"),
+            at(&self.toolchain_path)
+        )?;
+        writeln!(
+            f,
+            "C compiler:         {}{}",
+            self.c_compiler_version.as_deref().unwrap_or("not installed"),
+            at(&self.c_compiler)
+        )?;
+        write!(f, "docker:             {}", self.docker_version.as_deref().unwrap_or("unavailable"))
+    }
+}
+
+/// Gather the versions of the tools building programs on this machine, for bug reports: the
+/// succinct toolchain, the C compiler for the zkVM target, Docker, and this crate.
+pub fn toolchain_info() -> ToolchainInfo {
+    let toolchain_version = toolchain::installed_version(toolchain::TOOLCHAIN_NAME).ok();
+    let toolchain_path =
+        toolchain_version.as_ref().and_then(|_| toolchain::sysroot(toolchain::TOOLCHAIN_NAME));
+    let c_compiler =
+        c_toolchain::compiler(&BuildArgs::default()).ok().map(|compiler| compiler.path);
+    let c_compiler_version =
+        c_compiler.as_ref().and_then(|cc| first_line(Command::new(cc).arg("--version")));
+    let docker_version =
+        first_line(Command::new("docker").args(["version", "--format", "{{.Server.Version}}"]));
+
+    ToolchainInfo {
+        sp1_build_version: env!("CARGO_PKG_VERSION").to_string(),
+        default_docker_tag: DEFAULT_TAG.to_string(),
+        host: toolchain::host_target()
+            .unwrap_or_else(|| format!("{}-{}", env::consts::ARCH, env::consts::OS)),
+        toolchain_version: toolchain_version.map(|version| version.line),
+        toolchain_path,
+        c_compiler,
+        c_compiler_version,
+        docker_version,
+    }
+}
+
+/// The first line `command` prints to stdout, if it succeeds.
+fn first_line(command: &mut Command) -> Option<String> {
+    let output = command.output().ok().filter(|output| output.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.trim().to_string()).filter(|line| !line.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toolchain_info() {
+        let info = toolchain_info();
+        assert_eq!(info.sp1_build_version, env!("CARGO_PKG_VERSION"));
+
+        let info = ToolchainInfo {
+            toolchain_version: Some("rustc 1.79.0-dev".to_string()),
+            toolchain_path: Some(PathBuf::from("/rustup/toolchains/succinct")),
+            c_compiler: None,
+            c_compiler_version: None,
+            docker_version: Some("27.0.3".to_string()),
+            ..info
+        };
+        assert!(info.summary().contains("toolchain rustc 1.79.0-dev, C compiler not installed"));
+        let text = info.to_string();
+        assert!(text.contains("rustc 1.79.0-dev at /rustup/toolchains/succinct\n"));
+        assert!(text.ends_with("docker:             27.0.3"));
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["docker_version"], "27.0.3");
+        assert_eq!(json["c_compiler"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,29 @@
+//! Sharing the job slots of the cargo running a build script when building several programs at
+//! once, see [`crate::build_programs`].
+//!
+//! Cargo runs build scripts with a jobserver in `CARGO_MAKEFLAGS`, whose tokens limit how many jobs
+//! run at once across the whole build. The build script holds one implicitly, which the first
+//! program is built with, and each other program built concurrently waits for a token of its own,
+//! so that building programs in parallel does not oversubscribe the machine. The jobserver is
+//! passed on to the nested cargo of each build, which runs its jobs within these tokens too.
+
+use std::sync::OnceLock;
+
+use jobserver::Client;
+
+use crate::rerun;
+
+/// The jobserver of the cargo running the build script, if the build runs in one which has it.
+pub(crate) fn host_jobserver() -> Option<&'static Client> {
+    static CLIENT: OnceLock<Option<Client>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            if !rerun::in_build_script() {
+                return None;
+            }
+            // SAFETY: cargo passes the descriptors of its jobserver to the build script, which are
+            // only opened once per process, and never closed since the client is never dropped.
+            unsafe { Client::from_env() }
+        })
+        .as_ref()
+}
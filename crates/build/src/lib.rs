@@ -1,28 +1,185 @@
+mod artifact;
+mod builder;
+mod c_toolchain;
+mod cancel;
+mod cargo_home;
+mod clean;
+mod compatibility;
+#[cfg(feature = "completions")]
+pub mod completions;
+mod config;
+mod copy;
+mod debuginfo;
 mod docker;
+mod dry_run;
+mod embed;
+mod env_snapshot;
+mod error;
+mod events;
+mod failure;
+mod feature_map;
+mod fingerprint;
+mod hooks;
+mod host_metadata;
+mod host_path;
+mod host_test;
+mod info;
+mod jobs;
+mod load;
+mod lock;
+mod lockfile;
+mod log_file;
+mod manifest;
+mod matching;
+mod metadata_cache;
+mod missing_elf;
+mod options;
+mod out_dir;
+mod output;
+mod overrides;
+mod panic_strategy;
+mod parallel;
+mod patches;
+mod pipe;
+mod profile;
+mod program_dir;
+mod prune;
+mod remap;
+mod rerun;
+mod rustflags;
+mod sbom;
+mod scrub;
+mod shared_target;
+mod smoke_test;
+mod sp1_dependency;
+mod std_check;
+mod style;
+mod summary;
+mod target_output;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod toolchain;
+mod tooling;
+mod validate;
+mod warning;
+mod written;
 
-use anyhow::{Context, Result};
-use cargo_metadata::camino::Utf8PathBuf;
-use clap::Parser;
-use dirs::home_dir;
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, semver::VersionReq};
+use clap::{Parser, ValueHint};
+use error::FailureCause;
+use events::{CargoProgress, EventSender};
+use lock::BuildLock;
+use log_file::LogFile;
+use pipe::Stream;
+use serde::{Deserialize, Serialize};
 use std::{
-    env, fs,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    process::{exit, Command, Stdio},
+    env,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
 };
+use toolchain::Toolchain;
+
+pub use artifact::{elf_sha256, verify_elf_sha256, BuildOutput, BuildTimings, ElfArtifact};
+pub use builder::BuildArgsBuilder;
+pub use cancel::CancellationToken;
+pub use clean::{clean_program, CleanReport};
+pub use config::{ConfigSource, ResolvedBuildArgs};
+pub use debuginfo::locate_debug_file;
+pub use env_snapshot::{compare_env_snapshots, EnvSnapshot};
+pub use error::{BuildError, CopyOutcome};
+pub use events::{BuildEvent, BuildPhase};
+pub use failure::FailPolicy;
+pub use feature_map::FeatureMap;
+pub use host_metadata::{build_programs_from_host_metadata, HostProgram};
+pub use host_test::{test_program, TestArgs};
+pub use info::{toolchain_info, ToolchainInfo};
+pub use load::{build_and_load, build_and_load_all, build_and_load_with_output};
+pub use manifest::{
+    diff_manifests, BuildManifest, ManifestDiff, ResolvedDependency, MANIFEST_VERSION,
+};
+pub use matching::build_programs_matching;
+pub use metadata_cache::invalidate_metadata_cache;
+pub use options::BuildOptions;
+pub use out_dir::ElfDestination;
+pub use output::{OutputFormat, TRACING_TARGET};
+pub use overrides::{build_env_vars, parse_bool};
+pub use parallel::{build_programs, build_programs_fail_fast};
+pub use patches::PatchedCrate;
+pub use program_dir::check_program_dir;
+pub use prune::{prune_build_caches, BuildCache, CacheStatus, PruneOptions};
+pub use rustflags::ZkvmRustFlags;
+pub use sbom::SbomFormat;
+pub use smoke_test::SmokeTest;
+pub use style::ColorChoice;
+pub use validate::ValidationError;
+pub use warning::{BuildWarning, WarningKind};
 
 const BUILD_TARGET: &str = "riscv32im-succinct-zkvm-elf";
 const DEFAULT_TAG: &str = "v1.1.0";
 const DEFAULT_OUTPUT_DIR: &str = "elf";
 const HELPER_TARGET_SUBDIR: &str = "elf-compilation";
+/// How often a cancellable build checks whether it was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The target triple programs are compiled for.
+pub fn build_target() -> &'static str {
+    BUILD_TARGET
+}
+
+/// The default [`BuildArgs::output_directory`], relative to the program directory.
+pub fn default_output_dir() -> &'static str {
+    DEFAULT_OUTPUT_DIR
+}
+
+/// The subdirectory of the program's target directory that a build with `args` compiles into.
+/// Builds with Docker use a `docker` subdirectory of the local one, and the builds of
+/// [`build_programs`] a subdirectory named after each program, see [`BuildArgs::target_subdir`].
+pub fn helper_target_subdir(args: &BuildArgs) -> String {
+    let subdir = if args.docker {
+        format!("{}/docker", HELPER_TARGET_SUBDIR)
+    } else {
+        HELPER_TARGET_SUBDIR.to_string()
+    };
+    match &args.target_subdir {
+        Some(program) => format!("{}/{}", subdir, program),
+        None => subdir,
+    }
+}
+
+/// The flags a build with `args` passes to rustc: the flags required by the zkVM, followed by
+/// [`BuildArgs::rustflags`]. A local build also inherits the flags of the environment, between the
+/// two, see [`BuildArgs::ignore_inherited_rustflags`].
+pub fn rust_flags(args: &BuildArgs) -> Vec<String> {
+    ZkvmRustFlags::default().flags(args.rustflags.iter().cloned()).to_vec()
+}
 
 /// Compile an SP1 program.
 ///
 /// Additional arguments are useful for configuring the build process, including options for using
 /// Docker, specifying binary and ELF names, ignoring Rust version checks, and enabling specific
 /// features.
-#[derive(Clone, Parser, Debug)]
+///
+/// Programs can declare their own defaults for these arguments in the `[package.metadata.sp1]`
+/// table of their `Cargo.toml` or in an `sp1.toml` file, using the field names in kebab-case. Any
+/// of the fields can be set with `SP1_BUILD_*` environment variables, see [`build_env_vars`] and
+/// [`BuildArgs::ignore_env`]. See [`ResolvedBuildArgs`] for the precedence of these layers.
+///
+/// The ELFs are copied to [`BuildArgs::output_directory`], or, when building from a build script
+/// such as with `sp1-helper`, into its `OUT_DIR`, which sets `SP1_ELF_<NAME>` to the path of each
+/// ELF for the crate of the build script; see [`BuildArgs::elf_destination`].
+///
+/// The arguments can be serialized, and any field missing when deserializing takes its value from
+/// [`BuildArgs::default`], so that stored arguments keep deserializing as fields are added.
+#[derive(Clone, Parser, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BuildArgs {
     #[clap(
         long,
@@ -43,12 +200,29 @@ pub struct BuildArgs {
         help = "Space or comma separated list of features to activate"
     )]
     pub features: Vec<String>,
+    #[clap(long, action, conflicts_with = "features", help = "Activate all available features")]
+    pub all_features: bool,
     #[clap(long, action, help = "Do not activate the `default` feature")]
     pub no_default_features: bool,
     #[clap(long, action, help = "Ignore `rust-version` specification in packages")]
     pub ignore_rust_version: bool,
     #[clap(long, action, help = "Assert that `Cargo.lock` will remain unchanged")]
     pub locked: bool,
+    #[clap(
+        long,
+        action,
+        help = "Assert that `Cargo.lock` will remain unchanged and that the network is not \
+                accessed, failing when it is missing even with `generate_lockfile`"
+    )]
+    pub frozen: bool,
+    #[clap(
+        long,
+        alias = "generate-lockfile-if-missing",
+        action,
+        help = "Generate the `Cargo.lock` of the program if it is missing in a `locked` build, \
+                rather than failing"
+    )]
+    pub generate_lockfile: bool,
     #[clap(
         alias = "bin",
         long,
@@ -63,10 +237,418 @@ pub struct BuildArgs {
         alias = "out-dir",
         long,
         action,
-        help = "Copy the compiled ELF to this directory",
-        default_value = DEFAULT_OUTPUT_DIR
+        value_hint = ValueHint::DirPath,
+        help = "Copy the compiled ELF to this directory, relative to the program directory, `elf` \
+                if unset. Repeat it to copy the ELF to several directories"
+    )]
+    #[serde(deserialize_with = "config::one_or_many")]
+    pub output_directory: Vec<String>,
+    #[clap(
+        long,
+        action,
+        help = "Resolve a relative output directory against the parent of the target directory of \
+                the program, as previous versions did, rather than the program directory"
+    )]
+    pub legacy_output_path: bool,
+    #[clap(
+        long,
+        action,
+        help = "Do not warn when the output directory is in a target directory, which `cargo \
+                clean` deletes along with the ELFs"
+    )]
+    pub allow_output_in_target: bool,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = ElfDestination::Auto,
+        help = "Where to copy the compiled ELF: the `OUT_DIR` of the build script building the \
+                program, setting `SP1_ELF_<NAME>` to its path, or the output directory. By default \
+                the `OUT_DIR` if there is one and the output directory is not set"
+    )]
+    pub elf_destination: ElfDestination,
+    #[clap(
+        long,
+        action,
+        allow_hyphen_values = true,
+        help = "Additional flag to pass to rustc, after the flags required by the zkVM"
+    )]
+    pub rustflags: Vec<String>,
+    #[clap(
+        long,
+        action,
+        help = "Accept `rustflags` which override the flags required by the zkVM, whose ELF may \
+                then not run"
+    )]
+    pub unchecked_rustflags: bool,
+    #[clap(
+        long,
+        action,
+        help = "Print the build command and its environment instead of running it"
+    )]
+    pub dry_run: bool,
+    #[clap(
+        long = "explain-config",
+        action,
+        help = "Print each resolved build argument and the configuration layer which supplied it"
+    )]
+    pub explain: bool,
+    #[clap(
+        long,
+        action,
+        help = "Print only the absolute path of each built ELF to stdout, and everything else to \
+                stderr"
+    )]
+    pub print_elf_path: bool,
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with = "print_elf_path",
+        default_value_t = OutputFormat::Text,
+        help = "The format of the build output: human readable text, or JSON lines on stdout"
+    )]
+    pub output_format: OutputFormat,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = ColorChoice::Auto,
+        help = "Whether to color the messages of sp1-build and cargo: when stderr is a terminal \
+                unless NO_COLOR is set, always or never. CARGO_TERM_COLOR overrides `auto`"
+    )]
+    pub color: ColorChoice,
+    #[clap(
+        long,
+        action,
+        value_hint = ValueHint::FilePath,
+        help = "Append the output of cargo to this file, with timestamps, as well as printing it"
+    )]
+    pub log_file: Option<PathBuf>,
+    #[clap(
+        long,
+        action,
+        help = "Only check that the program can be built with these arguments, without building it"
+    )]
+    pub check: bool,
+    #[clap(
+        long,
+        action,
+        help = "Only type-check the program with `cargo check` for the zkVM target, without \
+                compiling or copying an ELF"
+    )]
+    pub check_only: bool,
+    #[clap(
+        long,
+        action,
+        help = "Include the package name in the prefix of the build output, as in `[sp1:<package>]`"
+    )]
+    pub package_prefix: bool,
+    #[clap(
+        long,
+        action,
+        help = "Install the succinct toolchain if it is missing, as `sp1up` does, instead of failing"
+    )]
+    pub auto_install_toolchain: bool,
+    #[clap(
+        long,
+        action,
+        help = "Install the C toolchain with `sp1up --c-toolchain` if the program has C \
+                dependencies and no C compiler is installed, instead of failing"
+    )]
+    pub auto_install_c_toolchain: bool,
+    #[clap(
+        long,
+        help = "Require this version of the succinct toolchain, such as `1.79.0-dev` or a commit hash"
+    )]
+    pub toolchain_version: Option<String>,
+    #[clap(
+        long,
+        help = "Require the rustc of the succinct toolchain to match this version requirement, such \
+                as `>=1.79`"
+    )]
+    pub min_rustc: Option<VersionReq>,
+    #[clap(
+        long,
+        action,
+        help = "Fail instead of warning when the SP1 crates are not compatible with the toolchain"
+    )]
+    pub strict_version_check: bool,
+    #[clap(
+        long,
+        action,
+        help = "Fail instead of warning when the program depends on crates which need an operating \
+                system, does not set `#![no_main]`, or its ELF has undefined symbols"
+    )]
+    pub strict_no_std_check: bool,
+    #[clap(
+        long,
+        action,
+        help = "Fail instead of warning when a `[patch]` section replaces an SP1 crate of the \
+                program, such as in release pipelines"
+    )]
+    pub deny_patched_sp1: bool,
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Fail when a warning of one of these kinds is emitted, such as `nightly-fallback`, \
+                before compiling if it is emitted by then"
+    )]
+    pub deny_warnings: Vec<WarningKind>,
+    #[clap(
+        long,
+        action,
+        help = "Do not warn when the program does not depend on `sp1-zkvm`, such as to build a \
+                bare-metal RISC-V binary"
+    )]
+    pub allow_non_sp1_program: bool,
+    #[clap(
+        long,
+        action,
+        help = "Run cargo for a package with only a library target, which compiles the library but \
+                no ELF, rather than failing before building"
+    )]
+    pub allow_lib_build: bool,
+    #[clap(
+        long,
+        value_enum,
+        help = "Write a software bill of materials in this format next to the ELF, listing the \
+                crates the program is built from"
+    )]
+    pub sbom: Option<SbomFormat>,
+    #[clap(
+        long,
+        action,
+        help = "Write a JSON build manifest next to the ELF, with the digest of the `Cargo.lock` \
+                and the dependency versions the program is built from"
+    )]
+    pub manifest: bool,
+    #[clap(
+        long,
+        action,
+        help = "Record the environment cargo is run with into `<elf>.env.json` and the build \
+                manifest, with the values of secrets redacted"
+    )]
+    pub capture_env: bool,
+    #[clap(
+        long,
+        action,
+        help = "Strip the symbols and debug information from the ELF, copying the unstripped ELF \
+                next to it as `<elf>.debug`"
+    )]
+    pub split_debuginfo: bool,
+    #[clap(
+        long,
+        action,
+        help = "Remap the paths of the workspace, of CARGO_HOME and of the toolchain compiled into \
+                the ELF to placeholders which are the same on every machine and in Docker"
+    )]
+    pub remap_paths: bool,
+    #[clap(
+        long,
+        value_hint = ValueHint::DirPath,
+        help = "The cargo home of the build, relative to the program directory, rather than that \
+                of the user, so that its configuration and credentials do not apply. It is \
+                created if missing and kept between builds, which share its registry cache. Also \
+                read from `SP1_CARGO_HOME`"
+    )]
+    pub cargo_home: Option<PathBuf>,
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "SETTINGS",
+        help = "Execute the ELF after building it, failing the build if the program does not halt \
+                successfully, with settings such as `stdin=0a000000,max-cycles=1000000`. Requires \
+                the `execute` feature"
+    )]
+    pub smoke_test: Option<SmokeTest>,
+    #[clap(
+        long,
+        alias = "pre-build-hook",
+        action,
+        help = "A command run in a shell in the program directory before cargo, such as a code \
+                generator, with the resolved arguments in `SP1_HOOK_*` variables, failing the \
+                build if it fails. May be repeated"
+    )]
+    pub pre_build_hooks: Vec<String>,
+    #[clap(
+        long,
+        alias = "post-build-hook",
+        action,
+        help = "A command run in a shell in the program directory after the ELF is copied, with \
+                `SP1_ELF_PATH`, `SP1_ELF_SHA256`, `SP1_PACKAGE_NAME` and `SP1_BUILD_PROFILE` set, \
+                failing the build if it fails. May be repeated"
+    )]
+    pub post_build_hooks: Vec<String>,
+    #[clap(long, action, help = "Only warn when a post-build hook fails")]
+    pub allow_hook_failure: bool,
+    #[clap(
+        long,
+        action,
+        help = "Run the post-build hooks when cargo was not run as the program is unchanged"
+    )]
+    pub run_hooks_on_skip: bool,
+    #[clap(
+        long = "allow-std-crate",
+        action,
+        help = "A crate which needs an operating system, such as `tokio`, the program may depend on \
+                without a warning, as it only uses what the zkVM provides"
+    )]
+    pub allowed_std_crates: Vec<String>,
+    #[clap(
+        long,
+        action,
+        help = "Build with nightly and `-Z build-std` if the succinct toolchain is missing, for \
+                non-production ELFs"
+    )]
+    pub toolchain_fallback: bool,
+    #[clap(
+        long,
+        value_hint = ValueHint::ExecutablePath,
+        help = "The C compiler for the zkVM target, as `CC_riscv32im_succinct_zkvm_elf`. With \
+                `--docker`, a path within the container"
+    )]
+    pub c_compiler: Option<PathBuf>,
+    #[clap(
+        long = "c-flag",
+        action,
+        allow_hyphen_values = true,
+        help = "Additional flag to pass to the C compiler, as `CFLAGS_riscv32im_succinct_zkvm_elf`"
+    )]
+    pub c_flags: Vec<String>,
+    #[clap(
+        long = "cxx-flag",
+        action,
+        allow_hyphen_values = true,
+        help = "Additional flag to pass to the C++ compiler, as \
+                `CXXFLAGS_riscv32im_succinct_zkvm_elf`"
+    )]
+    pub cxx_flags: Vec<String>,
+    #[clap(
+        long,
+        value_hint = ValueHint::ExecutablePath,
+        help = "The archiver for the zkVM target, as `AR_riscv32im_succinct_zkvm_elf`. With \
+                `--docker`, a path within the container"
+    )]
+    pub ar: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint = ValueHint::ExecutablePath,
+        help = "The ranlib for the zkVM target, as `RANLIB_riscv32im_succinct_zkvm_elf`. With \
+                `--docker`, a path within the container"
+    )]
+    pub ranlib: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint = ValueHint::ExecutablePath,
+        help = "The objcopy for the zkVM target, as `OBJCOPY_riscv32im_succinct_zkvm_elf`. With \
+                `--docker`, a path within the container"
+    )]
+    pub objcopy: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint = ValueHint::ExecutablePath,
+        help = "Wrap rustc with this program, such as sccache, as `RUSTC_WRAPPER`. Defaults to the \
+                `RUSTC_WRAPPER` of the environment unless `--hermetic`, and is ignored with `--docker`"
+    )]
+    pub rustc_wrapper: Option<PathBuf>,
+    #[clap(
+        long,
+        action,
+        help = "Do not inherit the rustc wrappers of the environment, such as `RUSTC_WRAPPER`"
     )]
-    pub output_directory: String,
+    pub hermetic: bool,
+    #[clap(
+        long,
+        action,
+        help = "Inherit the variables of the environment which would affect the build of the \
+                program, such as `RUSTFLAGS` and `CARGO_CFG_*`, instead of removing them"
+    )]
+    pub inherit_env: bool,
+    #[clap(
+        long,
+        action,
+        help = "Ignore the `RUSTFLAGS` and `CARGO_ENCODED_RUSTFLAGS` of the environment instead of \
+                adding them after the flags required by the zkVM"
+    )]
+    pub ignore_inherited_rustflags: bool,
+    #[clap(
+        long,
+        action,
+        help = "Do not print `cargo:rerun-if-changed` directives for the program when building from \
+                a build script"
+    )]
+    pub no_rerun_directives: bool,
+    #[clap(
+        long,
+        action,
+        help = "Run cargo even if the program and the arguments are unchanged since the last build"
+    )]
+    pub force: bool,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = FailPolicy::Error,
+        help = "What a build script does when the program fails to compile: fail, or warn and use \
+                the ELF of the previous build, or warn and skip the program"
+    )]
+    pub on_failure: FailPolicy,
+    #[clap(
+        long,
+        action,
+        value_delimiter = ',',
+        help = "Only build the program from a build script when the profile of its crate is one of \
+                these, such as `release`, and use the ELF of a previous build otherwise"
+    )]
+    pub build_when: Vec<String>,
+    #[clap(
+        long,
+        action,
+        help = "Build the program from build scripts run by docs.rs, rustdoc, clippy or \
+                rust-analyzer, which otherwise use the ELF of a previous build or a placeholder"
+    )]
+    pub build_in_tooling: bool,
+    #[clap(
+        long,
+        action,
+        help = "Compile the program into a target directory shared by the programs built with the \
+                same toolchain and flags, in `~/.sp1/build-cache` or `SP1_BUILD_CACHE_DIR`, so that \
+                their common dependencies are compiled once"
+    )]
+    pub shared_target: bool,
+    #[clap(
+        long,
+        action,
+        help = "Run `cargo metadata` for every build, rather than reusing the metadata read by a \
+                previous build in this process while the manifests and lock file are unchanged"
+    )]
+    pub no_metadata_cache: bool,
+    #[clap(
+        long,
+        help = "Compile the program incrementally (`true`) or not (`false`), which is off by \
+                default. Incremental builds are faster after small changes, but not \
+                deterministic, and cannot be combined with `--docker`"
+    )]
+    pub incremental: Option<bool>,
+    /// Ignore the `SP1_BUILD_*` environment variables, see [`BuildArgs::ignore_env`].
+    #[clap(skip)]
+    #[serde(skip)]
+    pub ignore_env_overrides: bool,
+    /// The features a [`FeatureMap`] links features of the host crate to, which must be declared
+    /// by the program even when they are not enabled, see [`FeatureMap::apply`].
+    #[clap(skip)]
+    #[serde(skip)]
+    pub linked_features: Vec<String>,
+    /// The fields which count as passed explicitly even when they equal their defaults, see
+    /// [`BuildArgs::explicitly_set`].
+    #[clap(skip)]
+    #[serde(skip)]
+    pub explicit_fields: Vec<String>,
+    /// The subdirectory of the target directory the program is compiled into, see
+    /// [`helper_target_subdir`], which [`build_programs`] sets to the package of each program so
+    /// that the programs of one workspace build concurrently.
+    #[clap(skip)]
+    #[serde(skip)]
+    pub target_subdir: Option<String>,
 }
 
 // Implement default args to match clap defaults.
@@ -76,20 +658,83 @@ impl Default for BuildArgs {
             docker: false,
             tag: DEFAULT_TAG.to_string(),
             features: vec![],
+            all_features: false,
             ignore_rust_version: false,
             binary: "".to_string(),
             elf_name: "".to_string(),
-            output_directory: DEFAULT_OUTPUT_DIR.to_string(),
+            output_directory: vec![],
+            legacy_output_path: false,
+            allow_output_in_target: false,
+            elf_destination: ElfDestination::Auto,
+            rustflags: vec![],
+            unchecked_rustflags: false,
             locked: false,
+            frozen: false,
+            generate_lockfile: false,
             no_default_features: false,
+            dry_run: false,
+            explain: false,
+            print_elf_path: false,
+            output_format: OutputFormat::Text,
+            color: ColorChoice::Auto,
+            log_file: None,
+            check: false,
+            check_only: false,
+            package_prefix: false,
+            auto_install_toolchain: false,
+            auto_install_c_toolchain: false,
+            toolchain_version: None,
+            min_rustc: None,
+            strict_version_check: false,
+            strict_no_std_check: false,
+            deny_patched_sp1: false,
+            deny_warnings: vec![],
+            allow_non_sp1_program: false,
+            allow_lib_build: false,
+            sbom: None,
+            manifest: false,
+            capture_env: false,
+            split_debuginfo: false,
+            remap_paths: false,
+            cargo_home: None,
+            smoke_test: None,
+            pre_build_hooks: vec![],
+            post_build_hooks: vec![],
+            allow_hook_failure: false,
+            run_hooks_on_skip: false,
+            allowed_std_crates: vec![],
+            toolchain_fallback: false,
+            c_compiler: None,
+            c_flags: vec![],
+            cxx_flags: vec![],
+            ar: None,
+            ranlib: None,
+            objcopy: None,
+            rustc_wrapper: None,
+            hermetic: false,
+            inherit_env: false,
+            ignore_inherited_rustflags: false,
+            no_rerun_directives: false,
+            force: false,
+            on_failure: FailPolicy::Error,
+            build_when: vec![],
+            build_in_tooling: false,
+            shared_target: false,
+            no_metadata_cache: false,
+            incremental: None,
+            ignore_env_overrides: false,
+            linked_features: vec![],
+            explicit_fields: vec![],
+            target_subdir: None,
         }
     }
 }
 
 /// Get the arguments to build the program with the arguments from the [`BuildArgs`] struct.
 fn get_program_build_args(args: &BuildArgs) -> Vec<String> {
+    let subcommand = if args.check_only { "check" } else { "build" };
     let mut build_args = vec![
-        "build".to_string(),
+        subcommand.to_string(),
         "--release".to_string(),
         "--target".to_string(),
         BUILD_TARGET.to_string(),
@@ -109,28 +754,49 @@ fn get_program_build_args(args: &BuildArgs) -> Vec<String> {
         build_args.push(args.features.join(","));
     }
 
+    if args.all_features {
+        build_args.push("--all-features".to_string());
+    }
+
     if args.no_default_features {
         build_args.push("--no-default-features".to_string());
     }
 
-    if args.locked {
+    if args.frozen {
+        build_args.push("--frozen".to_string());
+    } else if args.locked {
         build_args.push("--locked".to_string());
     }
 
+    if args.output_format == OutputFormat::Json {
+        build_args.push("--message-format=json".to_string());
+    } else {
+        build_args.push(style::cargo_flag(style::stderr(style::choice(args))));
+    }
+
+    if args.incremental == Some(true) {
+        build_args.push("--config".to_string());
+        build_args.push("profile.release.incremental=true".to_string());
+    }
+
     build_args
 }
 
-/// Rust flags for compilation of C libraries.
-fn get_rust_compiler_flags() -> String {
-    let rust_flags = [
-        "-C".to_string(),
-        "passes=loweratomic".to_string(),
-        "-C".to_string(),
-        "link-arg=-Ttext=0x00200800".to_string(),
-        "-C".to_string(),
-        "panic=abort".to_string(),
-    ];
-    rust_flags.join("\x1f")
+/// The variables cargo reads the wrapper of rustc from.
+pub(crate) const RUSTC_WRAPPER_ENVS: &[&str] = &["RUSTC_WRAPPER", "CARGO_BUILD_RUSTC_WRAPPER"];
+
+/// The program a local build with `args` wraps rustc with: [`BuildArgs::rustc_wrapper`], or the
+/// `RUSTC_WRAPPER` (or `CARGO_BUILD_RUSTC_WRAPPER`) of the environment unless
+/// [`BuildArgs::hermetic`].
+fn rustc_wrapper(args: &BuildArgs) -> Option<PathBuf> {
+    if args.rustc_wrapper.is_some() || args.hermetic {
+        return args.rustc_wrapper.clone();
+    }
+    RUSTC_WRAPPER_ENVS
+        .iter()
+        .filter_map(env::var_os)
+        .find(|wrapper| !wrapper.is_empty())
+        .map(PathBuf::from)
 }
 
 /// Get the command to build the program locally.
@@ -138,21 +804,16 @@ fn create_local_command(
     args: &BuildArgs,
     program_dir: &Utf8PathBuf,
     program_metadata: &cargo_metadata::Metadata,
+    toolchain: Toolchain,
 ) -> Command {
     let mut command = Command::new("cargo");
-    let canonicalized_program_dir =
-        program_dir.canonicalize().expect("Failed to canonicalize program directory");
-
-    // If CC_riscv32im_succinct_zkvm_elf is not set, set it to the default C++ toolchain
-    // downloaded by 'sp1up --c-toolchain'.
-    if env::var("CC_riscv32im_succinct_zkvm_elf").is_err() {
-        if let Some(home_dir) = home_dir() {
-            let cc_path = home_dir.join(".sp1").join("bin").join("riscv32-unknown-elf-gcc");
-            if cc_path.exists() {
-                command.env("CC_riscv32im_succinct_zkvm_elf", cc_path);
-            }
-        }
-    }
+    let canonicalized_program_dir = host_path::canonicalize(program_dir.as_std_path())
+        .expect("Failed to canonicalize program directory");
+
+    // If CC_riscv32im_succinct_zkvm_elf and the variables of the binutils are not set, set them to
+    // the default C toolchain downloaded by 'sp1up --c-toolchain', unless configured by `args`.
+    // Programs with C dependencies are checked to have a compiler before.
+    command.envs(c_toolchain::default_env_vars()).envs(c_toolchain::env_vars(args));
 
     // When executing the local command:
     // 1. Set the target directory to a subdirectory of the program's target directory to avoid
@@ -163,86 +824,242 @@ fn create_local_command(
     // 4. Remove the rustc configuration, otherwise in a build script it will attempt to compile the
     //    program with the toolchain of the normal build process, rather than the Succinct
     //    toolchain.
+    let target_dir = helper_target_dir(args, program_metadata);
+    let mut build_args = get_program_build_args(args);
+    let mut rust_flags = ZkvmRustFlags::default();
+    if !args.ignore_inherited_rustflags {
+        let (inherited, dropped) = rustflags::inherited(&rust_flags.to_vec());
+        if !dropped.is_empty() {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::DroppedRustflags,
+                    format!(
+                        "dropping the inherited rustflags which conflict with the flags required \
+                         by the zkVM: {}",
+                        dropped.join(" ")
+                    ),
+                )
+                .field("flags", dropped.join(" ")),
+            );
+        }
+        rust_flags = rust_flags.flags(inherited);
+    }
+    rust_flags = rust_flags.flags(args.rustflags.iter().cloned());
+    let cargo_home = cargo_home::local(args, &canonicalized_program_dir);
+    if args.remap_paths {
+        let workspace_root = &program_metadata.workspace_root;
+        for (from, to) in remap::local_prefixes(workspace_root, cargo_home.as_deref(), toolchain) {
+            rust_flags = rust_flags.remap_path_prefix(from, to);
+        }
+    }
+    if toolchain == Toolchain::NightlyFallback {
+        // Nightly has no builtin zkVM target, so it is described by a target specification and
+        // the standard library is built from source.
+        let target = toolchain::fallback_target_spec(&target_dir).to_string();
+        for arg in build_args.iter_mut().filter(|arg| *arg == BUILD_TARGET) {
+            arg.clone_from(&target);
+        }
+        build_args.extend(toolchain::fallback_cargo_flags());
+        rust_flags = rust_flags.nightly();
+    }
+    // Versions of rustup differ in whether `RUSTUP_TOOLCHAIN` takes precedence over a toolchain
+    // file, so the toolchain is also selected with `cargo +<toolchain>` if the program pins one.
+    if toolchain::toolchain_file(program_dir, &program_metadata.workspace_root).is_some() {
+        command.arg(format!("+{}", toolchain.name()));
+    }
+    // Remove the variables set by the cargo running this build which would corrupt the nested one,
+    // see the `scrub` module.
+    if !args.inherit_env {
+        for key in scrub::removed_vars(env::vars_os().map(|(key, _)| key)) {
+            command.env_remove(key);
+        }
+    }
+    // The nested cargo takes its jobs from the slots of the cargo running the build script rather
+    // than running a job per CPU next to it, see the `jobs` module.
+    if let Some(jobserver) = jobs::host_jobserver() {
+        jobserver.configure_make(&mut command);
+    }
+    // The wrapper of rustc is set explicitly, replacing those of the environment. It is invoked
+    // with `rustc`, which resolves to the rustup proxy since `RUSTC` is removed below, and inherits
+    // `RUSTUP_TOOLCHAIN`, so that it runs the rustc of the selected toolchain.
+    for key in RUSTC_WRAPPER_ENVS {
+        command.env_remove(key);
+    }
+    if let Some(wrapper) = rustc_wrapper(args) {
+        command.env("RUSTC_WRAPPER", wrapper);
+    }
+    // `CARGO_INCREMENTAL` takes precedence over the profile, so it is set either way.
+    if let Some(incremental) = args.incremental {
+        command.env("CARGO_INCREMENTAL", if incremental { "1" } else { "0" });
+    }
+    if let Some(cargo_home) = cargo_home::isolated(args, &canonicalized_program_dir) {
+        command.env("CARGO_HOME", cargo_home);
+    }
     command
         .current_dir(canonicalized_program_dir)
-        .env("RUSTUP_TOOLCHAIN", "succinct")
-        .env("CARGO_ENCODED_RUSTFLAGS", get_rust_compiler_flags())
+        .env("RUSTUP_TOOLCHAIN", toolchain.name())
+        .env("CARGO_ENCODED_RUSTFLAGS", rust_flags.render_encoded())
         .env_remove("RUSTC")
-        .env("CARGO_TARGET_DIR", program_metadata.target_directory.join(HELPER_TARGET_SUBDIR))
-        .args(&get_program_build_args(args));
+        .env("CARGO_TARGET_DIR", target_dir)
+        .args(&build_args);
     command
 }
 
 /// Execute the command and handle the output depending on the context.
-fn execute_command(mut command: Command, docker: bool) -> Result<()> {
+///
+/// If `docker_image` is set, the command runs in a container of that image. If `program_name` is
+/// set, it is included in the prefix of every line so that the output of builds running
+/// concurrently can be told apart. The progress of cargo is sent to `events`,
+/// estimating the number of crates to compile as `total_crates`, and every line is also appended
+/// to `log_file` if set. The lines are written to the sinks of `options` rather than printed if
+/// set, see [`BuildOptions`]. If the build is cancelled, the command is killed along with the
+/// Docker `container` it runs in.
+#[allow(clippy::too_many_arguments)]
+fn execute_command(
+    mut command: Command,
+    docker_image: Option<&str>,
+    container: Option<&str>,
+    program_name: Option<&str>,
+    events: &EventSender,
+    total_crates: Option<usize>,
+    log_file: Option<Arc<LogFile>>,
+    options: &mut BuildOptions,
+) -> Result<ExitStatus> {
+    let BuildOptions { stdout_sink, stderr_sink, cancellation_token } = options;
+
     // Add necessary tags for stdout and stderr from the command.
-    let mut child = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("failed to spawn command")?;
-    let stdout = BufReader::new(child.stdout.take().unwrap());
-    let stderr = BufReader::new(child.stderr.take().unwrap());
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = match cancellation_token {
+        Some(_) => cancel::spawn(&mut command),
+        None => command.spawn(),
+    }
+    .context("failed to spawn command")?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
 
     // Add prefix to the output of the process depending on the context.
-    let msg = match docker {
-        true => "[sp1] [docker] ",
-        false => "[sp1] ",
-    };
+    let docker = docker_image.is_some();
+    let msg = output::prefix(program_name, docker);
+    let child = Mutex::new(child);
+    let finished = AtomicBool::new(false);
+
+    let mut progress = CargoProgress::new(events.clone(), docker, total_crates);
+    let mut failure_cause = FailureCause::default();
+    thread::scope(|s| {
+        // Kill the command once cancelled, which closes its output and ends the loops below.
+        if let Some(token) = cancellation_token.as_ref() {
+            let (child, finished) = (&child, &finished);
+            s.spawn(move || {
+                while !finished.load(Ordering::SeqCst) {
+                    if token.is_cancelled() {
+                        cancel::kill(&mut child.lock().unwrap(), container);
+                        break;
+                    }
+                    thread::sleep(CANCEL_POLL_INTERVAL);
+                }
+            });
+        }
 
-    // Pipe stdout and stderr to the parent process with [docker] prefix
-    let stdout_handle = thread::spawn(move || {
-        stdout.lines().for_each(|line| {
-            println!("{} {}", msg, line.unwrap());
+        // Pipe stdout and stderr to the parent process with [docker] prefix, in the order the
+        // lines are read, from this thread so that lines of stdout and stderr are not torn.
+        pipe::read_lines(stdout, stderr, |stream, line| {
+            let name = match stream {
+                Stream::Stdout => "stdout",
+                Stream::Stderr => "stderr",
+            };
+            if let Some(log_file) = &log_file {
+                log_file.line(name, line);
+            }
+            match stream {
+                Stream::Stdout => output::cargo_stdout(&msg, line, stdout_sink.as_deref_mut()),
+                Stream::Stderr => {
+                    progress.line(line);
+                    failure_cause.line(line);
+                    output::cargo_stderr(&msg, line, stderr_sink.as_deref_mut());
+                }
+            }
         });
+        finished.store(true, Ordering::SeqCst);
     });
-    stderr.lines().for_each(|line| {
-        eprintln!("{} {}", msg, line.unwrap());
-    });
-    stdout_handle.join().unwrap();
 
     // Wait for the child process to finish and check the result.
-    let result = child.wait()?;
+    let result = child.into_inner().unwrap().wait()?;
+    if cancellation_token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+        return Err(BuildError::Cancelled.into());
+    }
     if !result.success() {
-        // Error message is already printed by cargo.
-        exit(result.code().unwrap_or(1))
+        // The compiler errors are already printed by cargo, so only report the exit status.
+        let err = anyhow::Error::from(failure_cause.error(result, docker_image));
+        return Err(match failure_cause.help() {
+            Some(help) => err.context(help),
+            None => err,
+        });
     }
-    Ok(())
+    Ok(result)
 }
 
-/// Copy the ELF to the specified output directory.
-fn copy_elf_to_output_dir(
+/// The target directory the program is compiled into. This is a subdirectory of the program's
+/// target directory, with an additional `docker` subdirectory if built with Docker. A local build
+/// from the build script of a member of the program's workspace compiles into its `OUT_DIR`
+/// instead, see the `rerun` module, and a local build with [`BuildArgs::shared_target`] into the
+/// target directory shared by the programs, see the `shared_target` module.
+fn helper_target_dir(args: &BuildArgs, program_metadata: &cargo_metadata::Metadata) -> Utf8PathBuf {
+    if let Some(shared_target_dir) = shared_target::shared_target_dir(args) {
+        return shared_target_dir;
+    }
+    let target_dir = match rerun::shared_workspace_out_dir(program_metadata) {
+        Some(out_dir) if !args.docker => out_dir,
+        _ => program_metadata.target_directory.clone(),
+    };
+    target_dir.join(helper_target_subdir(args))
+}
+
+/// The ELFs a build with `args` copies to the output directory: the name of each binary target
+/// built, and the path its ELF is copied to. The ELF is named, in order of precedence:
+///
+/// 1. [`BuildArgs::elf_name`], if set.
+/// 2. [`BuildArgs::binary`], if set.
+/// 3. `riscv32im-succinct-zkvm-elf`, in which case the binary named after the root package is used.
+///
+/// The ELFs are copied to each of [`BuildArgs::output_directory`], relative to the program
+/// directory, see [`output_dirs`], with an entry for each, or into a directory named after the
+/// binary in the `OUT_DIR` of the build script building the program, see
+/// [`BuildArgs::elf_destination`]. The ELFs of a build falling back to nightly, see
+/// [`BuildArgs::toolchain_fallback`], have an additional `-nightly` suffix.
+pub fn generate_elf_paths(
     args: &BuildArgs,
     program_metadata: &cargo_metadata::Metadata,
-) -> Result<Utf8PathBuf> {
-    let root_package = program_metadata.root_package();
-    let root_package_name = root_package.as_ref().map(|p| &p.name);
+) -> Result<Vec<(String, Utf8PathBuf)>> {
+    elf_paths(args, program_metadata, Toolchain::select(args))
+}
 
-    // The ELF is written to a target folder specified by the program's package. If built with
-    // Docker, includes /docker after HELPER_TARGET_SUBDIR.
-    let mut target_dir_suffix = HELPER_TARGET_SUBDIR.to_string();
-    if args.docker {
-        target_dir_suffix = format!("{}/{}", HELPER_TARGET_SUBDIR, "docker");
-    }
+/// The ELFs a build with `args` and `toolchain` copies to the output directory, see
+/// [`generate_elf_paths`].
+fn elf_paths(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+    toolchain: Toolchain,
+) -> Result<Vec<(String, Utf8PathBuf)>> {
+    elf_paths_warning(args, program_metadata, toolchain, true)
+}
 
-    // The ELF's file name is the binary name if it's specified. Otherwise, it is the root package
-    // name.
-    let original_elf_file_name = if !args.binary.is_empty() {
+/// The ELFs a build with `args` and `toolchain` copies to the output directory, warning about an
+/// ELF renamed after its package if `warn` is set.
+fn elf_paths_warning(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+    toolchain: Toolchain,
+    warn: bool,
+) -> Result<Vec<(String, Utf8PathBuf)>> {
+    let bin_name = if !args.binary.is_empty() {
         args.binary.clone()
     } else {
-        root_package_name.unwrap().clone()
+        let root_package = program_metadata
+            .root_package()
+            .ok_or_else(|| anyhow!("the program directory does not contain a package"))?;
+        root_package.name.clone()
     };
 
-    let original_elf_path = program_metadata
-        .target_directory
-        .join(target_dir_suffix)
-        .join(BUILD_TARGET)
-        .join("release")
-        .join(original_elf_file_name);
-
-    // The order of precedence for the ELF name is:
-    // 1. --elf_name flag
-    // 2. --binary flag + -elf suffix (defaults to riscv32im-succinct-zkvm-elf)
     let elf_name = if !args.elf_name.is_empty() {
         args.elf_name.clone()
     } else if !args.binary.is_empty() {
@@ -252,15 +1069,101 @@ fn copy_elf_to_output_dir(
     } else {
         BUILD_TARGET.to_string()
     };
+    let with_toolchain = |elf_name: String| match toolchain {
+        Toolchain::Succinct => elf_name,
+        Toolchain::NightlyFallback => format!("{}-nightly", elf_name),
+    };
+
+    let elf_dirs = match out_dir::out_dir(args) {
+        Some(out_dir) => vec![out_dir.join(&bin_name)],
+        None => output_dirs(args, program_metadata),
+    };
+    let mut elf_paths = vec![];
+    for elf_dir in elf_dirs {
+        let mut elf_path = elf_dir.join(with_toolchain(elf_name.clone()));
+        if written::renamed(args, &elf_path, program_metadata, &bin_name, warn) {
+            elf_path = elf_dir.join(with_toolchain(bin_name.clone()));
+        }
+        elf_paths.push((bin_name.clone(), elf_path));
+    }
+    Ok(elf_paths)
+}
+
+/// The output directories of a build with `args`: [`BuildArgs::output_directory`], or
+/// [`DEFAULT_OUTPUT_DIR`] if it is empty.
+pub(crate) fn output_directories(args: &BuildArgs) -> Vec<&str> {
+    if args.output_directory.is_empty() {
+        vec![DEFAULT_OUTPUT_DIR]
+    } else {
+        args.output_directory.iter().map(String::as_str).collect()
+    }
+}
+
+/// The directories a build with `args` copies the ELFs to, unless it copies them to the `OUT_DIR`
+/// of a build script: each of [`BuildArgs::output_directory`], relative to the program directory,
+/// or to the parent of the target directory with [`BuildArgs::legacy_output_path`].
+fn output_dirs(args: &BuildArgs, program_metadata: &cargo_metadata::Metadata) -> Vec<Utf8PathBuf> {
+    if args.legacy_output_path {
+        return legacy_output_dirs(args, program_metadata);
+    }
+    // The metadata is read from the manifest of the program directory, which is either that of the
+    // root package or of a virtual workspace.
+    let program_dir = match program_metadata.root_package() {
+        Some(package) => package.manifest_path.parent().unwrap(),
+        None => &program_metadata.workspace_root,
+    };
+    output_directories(args).into_iter().map(|dir| program_dir.join(dir)).collect()
+}
 
-    let elf_dir = program_metadata.target_directory.parent().unwrap().join(&args.output_directory);
-    fs::create_dir_all(&elf_dir)?;
-    let result_elf_path = elf_dir.join(elf_name);
+/// The directories previous versions copied the ELFs to: [`BuildArgs::output_directory`],
+/// relative to the parent of the target directory.
+fn legacy_output_dirs(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+) -> Vec<Utf8PathBuf> {
+    let parent = program_metadata.target_directory.parent().unwrap();
+    output_directories(args).into_iter().map(|dir| parent.join(dir)).collect()
+}
 
-    // Copy the ELF to the specified output directory.
-    fs::copy(original_elf_path, &result_elf_path)?;
+/// The path of the ELF of the binary `bin_name` cargo compiles in the target directory.
+fn compiled_elf_path(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+    bin_name: &str,
+) -> Utf8PathBuf {
+    helper_target_dir(args, program_metadata).join(BUILD_TARGET).join("release").join(bin_name)
+}
 
-    Ok(result_elf_path)
+/// Copy the ELFs to the specified output directory, returning the paths they were copied to.
+fn copy_elf_to_output_dir(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+    toolchain: Toolchain,
+) -> Result<Vec<Utf8PathBuf>> {
+    let elf_paths = elf_paths(args, program_metadata, toolchain)?;
+    let mut sources = vec![];
+    for (bin_name, _) in &elf_paths {
+        let compiled = compiled_elf_path(args, program_metadata, bin_name);
+        if !compiled.is_file() {
+            return Err(missing_elf::error(args, program_metadata, bin_name, &compiled).into());
+        }
+        sources.push(compiled);
+    }
+    let destinations: Vec<_> = elf_paths.into_iter().map(|(_, elf_path)| elf_path).collect();
+    if !args.split_debuginfo {
+        copy::copy_all(&sources, &destinations)
+            .with_context(|| format!("failed to copy the ELF compiled at {}", sources[0]))?;
+        return Ok(destinations);
+    }
+    // The stripped ELFs and their debug files are copied together.
+    let stripped = debuginfo::strip_all(&sources, &destinations)?;
+    let debug_paths = destinations.iter().map(|elf_path| debuginfo::debug_path(elf_path));
+    copy::copy_all(
+        &[stripped, sources.clone()].concat(),
+        &destinations.iter().cloned().chain(debug_paths).collect::<Vec<_>>(),
+    )
+    .with_context(|| format!("failed to copy the ELF compiled at {}", sources[0]))?;
+    Ok(destinations)
 }
 
 /// Build a program with the specified [`BuildArgs`]. The `program_dir` is specified as an argument
@@ -270,33 +1173,1043 @@ fn copy_elf_to_output_dir(
 ///
 /// * `args` - A reference to a `BuildArgs` struct that holds various arguments used for building
 ///   the program.
-/// * `program_dir` - An optional `PathBuf` specifying the directory of the program to be built.
+/// * `program_dir` - The directory of the program to be built, such as a `&str`, `PathBuf` or
+///   `Utf8PathBuf`. A relative path is resolved against the current directory, which is the
+///   directory of the crate being built when called from a build script.
 ///
 /// # Returns
 ///
 /// * `Result<Utf8PathBuf>` - The path to the built program as a `Utf8PathBuf` on success, or an
-///   error on failure.
+///   error on failure. If [`BuildArgs::dry_run`] is set, nothing is built and this is the path the
+///   ELF would be written to, which may not exist. Fails with [`BuildError::NoElf`] if
+///   [`BuildArgs::check_only`] is set, as no ELF is written.
+pub fn build_program_in(args: &BuildArgs, program_dir: impl AsRef<Path>) -> Result<Utf8PathBuf> {
+    let output = build_program_with_output(args, program_dir)?;
+    Ok(output.try_elf_path()?.to_path_buf())
+}
+
+/// Build a program with the specified [`BuildArgs`] in `program_dir`, defaulting to the current
+/// directory. See [`build_program_in`].
+#[deprecated(since = "1.2.0", note = "use `build_program_in`, which accepts any path-like type")]
 pub fn build_program(args: &BuildArgs, program_dir: Option<PathBuf>) -> Result<Utf8PathBuf> {
-    // If the program directory is not specified, use the current directory.
-    let program_dir = program_dir
-        .unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory."));
-    let program_dir: Utf8PathBuf =
-        program_dir.try_into().expect("Failed to convert PathBuf to Utf8PathBuf");
+    build_program_in(args, program_dir.unwrap_or_else(|| PathBuf::from(".")))
+}
+
+/// Build a program with the specified [`BuildArgs`] like [`build_program_in`], returning a
+/// [`BuildOutput`] describing the ELF and the build instead of only the path to the ELF.
+pub fn build_program_with_output(
+    args: &BuildArgs,
+    program_dir: impl AsRef<Path>,
+) -> Result<BuildOutput> {
+    build_program_internal(
+        args,
+        program_dir.as_ref(),
+        &EventSender::default(),
+        &mut BuildOptions::default(),
+    )
+}
+
+/// Type-check a program with the specified [`BuildArgs`] like [`build_program_with_output`], with
+/// `cargo check` for the zkVM target, see [`BuildArgs::check_only`]. The diagnostics of rustc are
+/// printed as those of a build, and nothing is copied to the output directory.
+pub fn check_program(args: &BuildArgs, program_dir: impl AsRef<Path>) -> Result<()> {
+    let args = BuildArgs { check_only: true, ..args.clone() };
+    build_program_with_output(&args, program_dir).map(|_| ())
+}
+
+/// Build a program with the specified [`BuildArgs`] like [`build_program_with_output`], with the
+/// given [`BuildOptions`], such as where to write the output of cargo.
+///
+/// ```no_run
+/// use sp1_build::{build_program_with_options, BuildArgs, BuildOptions};
+///
+/// let mut log = Vec::new();
+/// let options = BuildOptions::default().stdout_sink(&mut log).stderr_sink(std::io::sink());
+/// build_program_with_options(&BuildArgs::default(), "program", options).unwrap();
+/// ```
+pub fn build_program_with_options(
+    args: &BuildArgs,
+    program_dir: impl AsRef<Path>,
+    mut options: BuildOptions,
+) -> Result<BuildOutput> {
+    build_program_internal(args, program_dir.as_ref(), &EventSender::default(), &mut options)
+}
+
+/// Build a program with the specified [`BuildArgs`] like [`build_program_with_output`], sending
+/// [`BuildEvent`]s to `sender` as the build progresses. The last event is
+/// [`BuildEvent::Finished`] with the returned [`BuildOutput`], or [`BuildEvent::Failed`].
+///
+/// Events are best-effort, and dropping the receiver does not abort the build. See [`BuildEvent`]
+/// for the accuracy of the compilation progress.
+pub fn build_program_with_events(
+    args: &BuildArgs,
+    program_dir: impl AsRef<Path>,
+    sender: Sender<BuildEvent>,
+) -> Result<BuildOutput> {
+    let events = EventSender::new(sender);
+    let result =
+        build_program_internal(args, program_dir.as_ref(), &events, &mut BuildOptions::default());
+    match &result {
+        Ok(output) => events.send(BuildEvent::Finished(output.clone())),
+        Err(err) => events.send(BuildEvent::Failed(format!("{:#}", err))),
+    }
+    result
+}
+
+fn build_program_internal(
+    args: &BuildArgs,
+    program_dir: &Path,
+    events: &EventSender,
+    options: &mut BuildOptions,
+) -> Result<BuildOutput> {
+    // The output mode may also be set by the config, so it is set again once resolved.
+    let _mode = output::set_mode(output::OutputMode::of(args));
+    let _warnings = warning::Collector::start();
+    let result = (|| {
+        events.send(BuildEvent::Phase(BuildPhase::Resolving));
+        let start = Instant::now();
+        check_program_dir(program_dir)?;
+        let program_dir = resolve_program_dir(program_dir)?;
+        let program_metadata = metadata_cache::metadata(args, &program_dir)?;
+        build_program_from_metadata(
+            args,
+            &program_dir,
+            &program_metadata,
+            start.elapsed(),
+            events,
+            options,
+            None,
+        )
+    })();
+    if let Err(err) = &result {
+        output::error(err);
+    }
+    result
+}
+
+/// Build the program in `program_dir` whose metadata has already been read, in `metadata_elapsed`,
+/// resolving `args` against its configuration. The `cargo:` directives for a build script are
+/// added to `directives` if set, for the caller to print, and printed otherwise.
+#[allow(clippy::too_many_arguments)]
+fn build_program_from_metadata(
+    args: &BuildArgs,
+    program_dir: &Utf8PathBuf,
+    program_metadata: &cargo_metadata::Metadata,
+    metadata_elapsed: Duration,
+    events: &EventSender,
+    options: &mut BuildOptions,
+    mut directives: Option<&mut Vec<String>>,
+) -> Result<BuildOutput> {
+    let warnings = warning::Collector::start();
+    let args = resolve_args(args, program_dir, program_metadata)?;
+    let _mode = output::set_mode(output::OutputMode::of(&args));
+    smoke_test::check_available(&args)?;
+    if !args.linked_features.is_empty() {
+        output::info(&format!("[sp1] building the program with features: {}", feature_list(&args)));
+    }
+    if !args.no_rerun_directives && rerun::in_build_script() {
+        emit_directives(rerun::directives(program_dir, program_metadata), &mut directives);
+    }
+    // TODO: Remove in the release after the next one, along with `legacy_output_path`.
+    let legacy_elf_dirs = legacy_output_dirs(&args, program_metadata);
+    for (elf_dir, legacy_elf_dir) in
+        output_dirs(&args, program_metadata).iter().zip(legacy_elf_dirs)
+    {
+        if out_dir::out_dir(&args).is_some() {
+            break;
+        }
+        if !args.legacy_output_path && *elf_dir != legacy_elf_dir {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::OutputPathChanged,
+                    format!(
+                        "the ELF is copied to {}, relative to the program directory, where \
+                         previous versions copied it to {}, relative to the parent of the target \
+                         directory. Set `legacy_output_path` to keep copying it there",
+                        elf_dir, legacy_elf_dir
+                    ),
+                )
+                .field("path", elf_dir)
+                .field("legacy_path", &legacy_elf_dir),
+            );
+        }
+        if let Some(target_dir) =
+            target_output::containing_target_dir(&args, program_metadata, elf_dir)
+        {
+            output::warning(
+                BuildWarning::new(
+                    WarningKind::OutputInTargetDir,
+                    format!(
+                        "the ELF is copied to {}, in the target directory {}, so `cargo clean` \
+                         deletes it until the program is built again. Set `output_directory` to a \
+                         directory outside of it, or `allow_output_in_target` to keep copying it \
+                         there",
+                        elf_dir, target_dir
+                    ),
+                )
+                .field("path", elf_dir)
+                .field("target_dir", &target_dir),
+            );
+        }
+    }
+    let lookup = |name: &str| env::var(name).ok();
+    if let Some((origin, value)) =
+        panic_strategy::profile_setting(program_metadata, args.docker, lookup)
+    {
+        output::warning(
+            BuildWarning::new(
+                WarningKind::PanicStrategyOverridden,
+                format!(
+                    "{} sets the panic strategy of the release profile to `{}`, which is \
+                     overridden with `-C panic=abort`, as the zkVM only supports aborting. Set it \
+                     to \"abort\" to build the program as configured",
+                    origin, value
+                ),
+            )
+            .field("origin", &origin)
+            .field("strategy", &value),
+        );
+    }
+    if !args.docker && rerun::shared_workspace_out_dir(program_metadata).is_some() {
+        let target_dir = helper_target_dir(&args, program_metadata);
+        output::warning(
+            BuildWarning::new(
+                WarningKind::SharedWorkspace,
+                format!(
+                    "the program is in the workspace of the crate building it, so it is compiled \
+                     into {} rather than the target directory of the workspace, which would \
+                     invalidate the build of the crate. Consider moving the program into a \
+                     workspace of its own",
+                    target_dir
+                ),
+            )
+            .field("target_dir", target_dir),
+        );
+    }
+
+    let name = program_name(&args, program_dir, program_metadata);
+    let mut output = build_program_with_metadata(
+        &args,
+        program_dir,
+        program_metadata,
+        args.package_prefix.then_some(name.as_str()),
+        events,
+        options,
+    )?;
+    if !output.skipped && !output.stale && !args.check_only {
+        let toolchain = Toolchain::select(&args);
+        output.sboms = sbom::write(&args, program_metadata, toolchain, &output.artifacts)?;
+        smoke_test::run_all(&args, &mut output.artifacts)?;
+        let env = output.env_snapshot.as_ref();
+        output.manifests = manifest::write(
+            &args,
+            program_metadata,
+            &output.artifacts,
+            &output.patched_sp1_crates,
+            env,
+        )?;
+        if let Some(snapshot) = env {
+            env_snapshot::write(snapshot, &output.artifacts)?;
+        }
+        if !output.fresh || args.run_hooks_on_skip {
+            let package_name = program_metadata.root_package().map_or(&name, |p| &p.name);
+            hooks::run_post_build(&args, program_dir, package_name, &output.artifacts)?;
+        }
+    }
+    for artifact in &output.artifacts {
+        output::artifact(artifact, args.print_elf_path);
+    }
+    if out_dir::out_dir(&args).is_some() {
+        emit_directives(out_dir::rustc_env(&output.artifacts), &mut directives);
+    }
+    output.timings.metadata = metadata_elapsed;
+    output.warnings = warnings.warnings();
+    summary::print(&name, &args, &output);
+    warning::check_denied(&args)?;
+    Ok(output)
+}
+
+/// Print the `cargo:` directives `new`, or add them to `directives` if set.
+fn emit_directives(new: Vec<String>, directives: &mut Option<&mut Vec<String>>) {
+    match directives {
+        Some(directives) => directives.extend(new),
+        None => new.iter().for_each(|directive| println!("{}", directive)),
+    }
+}
+
+/// The features `args` enable, for messages.
+fn feature_list(args: &BuildArgs) -> String {
+    if args.all_features {
+        "all".to_string()
+    } else if args.features.is_empty() {
+        "none".to_string()
+    } else {
+        args.features.join(", ")
+    }
+}
+
+/// Resolve the arguments to build with from all layers of configuration, printing where each
+/// value came from if [`BuildArgs::explain`] is set.
+fn resolve_args(
+    args: &BuildArgs,
+    program_dir: &Utf8PathBuf,
+    program_metadata: &cargo_metadata::Metadata,
+) -> Result<BuildArgs> {
+    let resolved = config::resolve(args, program_dir, program_metadata)
+        .map_err(|err| BuildError::Config(format!("{:#}", err)))?;
+    if resolved.args().explain {
+        output::info(&resolved.explain());
+    }
+    if rerun::in_build_script() && !resolved.args().ignore_env_overrides {
+        for notice in overrides::env_notices(&resolved, |name| env::var(name).ok()) {
+            output::warning(notice);
+        }
+    }
+    Ok(resolved.into_args())
+}
+
+/// Claim the ELF paths the program in `program_dir` would be built to with `args`, before building
+/// it, see the `written` module. Errors are left for the build to report.
+fn claim_elf_paths(args: &BuildArgs, program_dir: &Path) -> Result<()> {
+    check_program_dir(program_dir)?;
+    let program_dir = resolve_program_dir(program_dir)?;
+    let program_metadata = metadata_cache::metadata(args, &program_dir)?;
+    let args = config::resolve(args, &program_dir, &program_metadata)?.into_args();
+    let toolchain = Toolchain::select(&args);
+    for (bin_name, elf_path) in elf_paths_warning(&args, &program_metadata, toolchain, false)? {
+        written::claim(&elf_path, &program_metadata, &bin_name)?;
+    }
+    Ok(())
+}
+
+/// Resolve the program directory to an absolute path, relative to the current directory.
+fn resolve_program_dir(program_dir: &Path) -> Result<Utf8PathBuf> {
+    let program_dir = std::path::absolute(program_dir).with_context(|| {
+        format!("failed to resolve the program directory {}", program_dir.display())
+    })?;
+    Utf8PathBuf::from_path_buf(program_dir)
+        .map_err(|dir| anyhow!("the program directory {} is not valid UTF-8", dir.display()))
+}
+
+/// The name of the program in the prefix of the build output: the name of the package, or of the
+/// program directory if it has none, followed by the binary if one is selected.
+fn program_name(
+    args: &BuildArgs,
+    program_dir: &Utf8PathBuf,
+    program_metadata: &cargo_metadata::Metadata,
+) -> String {
+    let package_name = program_metadata
+        .root_package()
+        .map(|p| p.name.clone())
+        .or_else(|| program_dir.file_name().map(str::to_string))
+        .unwrap_or_else(|| program_dir.to_string());
+    if args.binary.is_empty() {
+        package_name
+    } else {
+        format!("{}/{}", package_name, args.binary)
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of times `cargo metadata` ran on this thread, for the tests of the cache.
+    pub(crate) static METADATA_SPAWNS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
 
-    // Get the program metadata.
+/// Get the metadata of the program in `program_dir`, without the cache of the `metadata_cache`
+/// module.
+fn program_metadata(program_dir: &Utf8PathBuf) -> Result<cargo_metadata::Metadata> {
+    program_metadata_with(program_dir, &[])
+}
+
+/// Get the metadata of the program in `program_dir`, passing `options` to cargo, such as
+/// `--locked`.
+fn program_metadata_with(
+    program_dir: &Utf8PathBuf,
+    options: &[&str],
+) -> Result<cargo_metadata::Metadata> {
+    #[cfg(test)]
+    METADATA_SPAWNS.with(|spawns| spawns.set(spawns.get() + 1));
     let program_metadata_file = program_dir.join("Cargo.toml");
     let mut program_metadata_cmd = cargo_metadata::MetadataCommand::new();
-    let program_metadata =
-        program_metadata_cmd.manifest_path(program_metadata_file).exec().unwrap();
+    // Cargo runs rustc to read the metadata, which must not go through the workspace wrapper of a
+    // host cargo, such as clippy-driver, see the `scrub` module. An empty wrapper disables it.
+    program_metadata_cmd
+        .manifest_path(&program_metadata_file)
+        .env("RUSTC_WORKSPACE_WRAPPER", "")
+        .other_options(options.iter().map(|option| option.to_string()).collect::<Vec<_>>())
+        .exec()
+        .with_context(|| format!("failed to read the metadata of {}", program_metadata_file))
+}
+
+/// Build a program whose metadata has already been resolved. The `program_name`, if set, is added
+/// to the prefix of the build output. The metadata phase of the returned timings is left empty for
+/// the caller to fill in.
+fn build_program_with_metadata(
+    args: &BuildArgs,
+    program_dir: &Utf8PathBuf,
+    program_metadata: &cargo_metadata::Metadata,
+    program_name: Option<&str>,
+    events: &EventSender,
+    options: &mut BuildOptions,
+) -> Result<BuildOutput> {
+    tracing::debug!(target: TRACING_TARGET, %program_dir, docker = args.docker, "building program");
+
+    // Skip before anything is installed or validated, in the same way with Docker or locally.
+    let lookup = |name: &str| std::env::var(name).ok();
+    let tool = tooling::skipped_tool(args, rerun::in_build_script(), lookup);
+    let skip_notice = if let Some(tool) = tool {
+        Some(format!(
+            "[sp1] skipping program build under {}, set `build_in_tooling` to build it anyway",
+            tool
+        ))
+    } else if let Some(profile) = profile::skipped_profile(args, lookup) {
+        Some(format!(
+            "[sp1] skipping program build for the {} profile of the crate building it",
+            profile
+        ))
+    } else if overrides::skip_build(lookup)
+        .map_err(|err| BuildError::Config(format!("{:#}", err)))?
+    {
+        Some("[sp1] skipping program build".to_string())
+    } else {
+        None
+    };
+    if let Some(notice) = skip_notice {
+        output::info(&notice);
+        let toolchain = Toolchain::select(args);
+        let elf_paths = elf_paths(args, program_metadata, toolchain)?;
+        for (bin_name, elf_path) in &elf_paths {
+            written::claim(elf_path, program_metadata, bin_name)?;
+        }
+        for (_, elf_path) in elf_paths.iter().filter(|(_, elf_path)| !elf_path.exists()) {
+            // Tools checking the crate building the program only need `SP1_ELF_<NAME>` to be set.
+            if tool.is_some() && out_dir::out_dir(args).is_some() {
+                tooling::write_placeholder(elf_path)?;
+            } else {
+                return Err(BuildError::Skipped.into());
+            }
+        }
+        return Ok(BuildOutput {
+            artifacts: elf_paths
+                .into_iter()
+                .map(|(_, elf_path)| ElfArtifact::read(elf_path))
+                .collect::<Result<_>>()?,
+            target_directory: helper_target_dir(args, program_metadata),
+            docker: args.docker,
+            cargo_exit_code: None,
+            timings: BuildTimings::default(),
+            skipped: true,
+            fresh: false,
+            stale: false,
+            patched_sp1_crates: patches::patched_sp1_crates(program_metadata),
+            sboms: vec![],
+            manifests: vec![],
+            env_snapshot: None,
+            warnings: vec![],
+        });
+    }
+
+    // Install a missing toolchain if requested, leaving it to the validation to report the failure
+    // and to resolve the C compiler again.
+    let will_build = !args.docker && !args.dry_run && !args.check;
+    if args.auto_install_toolchain &&
+        will_build &&
+        toolchain::check_enabled().unwrap_or(true) &&
+        !toolchain::is_installed(toolchain::TOOLCHAIN_NAME)
+    {
+        if let Err(err) = toolchain::install(options.cancellation_token.as_ref()) {
+            if matches!(err.downcast_ref(), Some(BuildError::Cancelled)) {
+                return Err(err);
+            }
+            output::warning(BuildWarning::new(
+                WarningKind::ToolchainInstallFailed,
+                format!("failed to install the succinct toolchain: {:#}", err),
+            ));
+        }
+    }
+    if args.auto_install_c_toolchain &&
+        will_build &&
+        matches!(c_toolchain::compiler(args), Err(None)) &&
+        !c_toolchain::crates_with_c_code(program_metadata).is_empty()
+    {
+        if let Err(err) = c_toolchain::install(options.cancellation_token.as_ref()) {
+            if matches!(err.downcast_ref(), Some(BuildError::Cancelled)) {
+                return Err(err);
+            }
+            output::warning(BuildWarning::new(
+                WarningKind::CToolchainInstallFailed,
+                format!("failed to install the C toolchain: {:#}", err),
+            ));
+        }
+    }
 
-    // Get the command corresponding to Docker or local build.
+    // Report every problem that can be found without compiling before starting the build.
+    let errors = validate::validate_with_metadata(args, program_metadata);
+    if !errors.is_empty() {
+        return Err(BuildError::Invalid(errors).into());
+    }
+
+    let toolchain = Toolchain::select(args);
+    if toolchain == Toolchain::NightlyFallback {
+        output::warning(
+            BuildWarning::new(
+                WarningKind::NightlyFallback,
+                format!(
+                    "the {} toolchain is not installed, building with {} and `-Z build-std` \
+                     instead. The ELF may differ from one built with the official toolchain and \
+                     must not be used in production; it is suffixed with `-nightly`",
+                    toolchain::TOOLCHAIN_NAME,
+                    toolchain::FALLBACK_TOOLCHAIN_NAME
+                ),
+            )
+            .field("toolchain", toolchain::FALLBACK_TOOLCHAIN_NAME),
+        );
+    }
+
+    if let Some(path) = toolchain::toolchain_file(program_dir, &program_metadata.workspace_root) {
+        let name = if args.docker { toolchain::TOOLCHAIN_NAME } else { toolchain.name() };
+        output::warning(
+            BuildWarning::new(
+                WarningKind::ToolchainFileOverridden,
+                format!(
+                    "{} pins the toolchain of the program, it is overridden with the {} toolchain",
+                    path, name
+                ),
+            )
+            .field("path", &path)
+            .field("toolchain", name),
+        );
+    }
+    // Fail before compiling if a denied warning was emitted so far.
+    warning::check_denied(args)?;
+
+    // Get the command corresponding to Docker or local build. The container of a cancellable build
+    // is named, so that it can be stopped.
+    let container =
+        (args.docker && options.cancellation_token.is_some()).then(cancel::container_name);
+    if !args.dry_run && !args.check {
+        cargo_home::create(args, program_dir.as_std_path())
+            .map_err(|error| BuildError::Invalid(vec![error]))?;
+    }
     let cmd = if args.docker {
-        docker::create_docker_command(args, &program_dir, &program_metadata)?
+        docker::create_docker_command(args, program_dir, program_metadata, container.as_deref())?
     } else {
-        create_local_command(args, &program_dir, &program_metadata)
+        create_local_command(args, program_dir, program_metadata, toolchain)
+    };
+
+    let mut output = BuildOutput {
+        artifacts: vec![],
+        target_directory: helper_target_dir(args, program_metadata),
+        docker: args.docker,
+        cargo_exit_code: None,
+        timings: BuildTimings::default(),
+        skipped: false,
+        fresh: false,
+        stale: false,
+        patched_sp1_crates: patches::patched_sp1_crates(program_metadata),
+        sboms: vec![],
+        manifests: vec![],
+        env_snapshot: args.capture_env.then(|| env_snapshot::capture(args, &cmd, toolchain)),
+        warnings: vec![],
+    };
+
+    // In a dry run, only print the command and where the ELF would be written. When only checking
+    // the arguments, there is nothing left to do.
+    if args.dry_run || args.check {
+        if args.dry_run {
+            dry_run::print_command(&cmd);
+        }
+        for (_, elf_path) in elf_paths(args, program_metadata, toolchain)? {
+            output.artifacts.push(ElfArtifact::not_built(elf_path));
+        }
+        return Ok(output);
+    }
+
+    // Open the log file before spawning cargo, so that an unwritable path fails fast.
+    let log_file = args.log_file.as_deref().map(LogFile::open).transpose()?.map(Arc::new);
+
+    // Other processes building the same program wait until the ELF is copied.
+    let _lock = BuildLock::acquire(&output.target_directory, options.cancellation_token.as_ref())?;
+    if toolchain == Toolchain::NightlyFallback {
+        toolchain::write_fallback_target_spec(&output.target_directory)?;
+    }
+
+    // The pre-build hooks run before the fingerprint is computed, as they may write sources.
+    let package_name = program_metadata.root_package().map_or("", |package| package.name.as_str());
+    let hook_outputs =
+        hooks::run_pre_build(args, program_dir, package_name, &output.target_directory)?;
+
+    // A type-check only runs cargo, in the same target directory so that a build reuses its work,
+    // and leaves the ELFs and their fingerprints as they are.
+    if args.check_only {
+        let start = Instant::now();
+        let docker_image = args.docker.then(|| docker::get_docker_image(&args.tag));
+        let total_crates = program_metadata.resolve.as_ref().map(|resolve| resolve.nodes.len());
+        let status = execute_command(
+            cmd,
+            docker_image.as_deref(),
+            container.as_deref(),
+            program_name,
+            events,
+            total_crates,
+            log_file.clone(),
+            options,
+        );
+        let status = match (status, &log_file) {
+            (Err(err), Some(log_file)) => {
+                Err(err
+                    .context(format!("check failed, see the log at {}", log_file.path().display())))
+            }
+            (status, _) => status,
+        }?;
+        output.cargo_exit_code = status.code();
+        output.timings.cargo = start.elapsed();
+        return Ok(output);
+    }
+
+    // Cargo is not run if nothing changed since the ELFs were built, unless forced.
+    let fingerprint = fingerprint::compute(
+        args,
+        program_dir,
+        program_metadata,
+        toolchain,
+        &cmd,
+        container.as_deref(),
+        &hook_outputs,
+    )?;
+    let elf_paths = elf_paths(args, program_metadata, toolchain)?;
+    for (bin_name, elf_path) in &elf_paths {
+        written::claim(elf_path, program_metadata, bin_name)?;
+    }
+    let mut compiled: Vec<_> = elf_paths
+        .iter()
+        .map(|(bin_name, _)| compiled_elf_path(args, program_metadata, bin_name))
+        .collect();
+    compiled.dedup();
+    let elf_paths: Vec<_> = elf_paths.into_iter().map(|(_, path)| path).collect();
+    if !args.force {
+        if let Some((artifacts, cargo)) = fingerprint::previous_build(&fingerprint, &elf_paths) {
+            tracing::debug!(target: TRACING_TARGET, %fingerprint, saved = ?cargo, "program is fresh");
+            output::info(&format!(
+                "[sp1] the program is unchanged since the last build, skipped running cargo, which \
+                 took {:.2}s",
+                cargo.as_secs_f64()
+            ));
+            output.artifacts = artifacts;
+            output.fresh = true;
+            return Ok(output);
+        }
+        // Another build with the same fingerprint, which copied the ELFs elsewhere, such as the
+        // build script of a host crate, compiled them already.
+        if let Some((_, cargo)) = fingerprint::previous_build(&fingerprint, &compiled) {
+            tracing::debug!(target: TRACING_TARGET, %fingerprint, saved = ?cargo, "reusing ELF");
+            output::info(&format!(
+                "[sp1] reusing the ELF at {} compiled by a previous build of the unchanged program \
+                 with the same arguments and toolchain, skipped running cargo, which took {:.2}s. \
+                 Set `force` to build it again",
+                compiled[0],
+                cargo.as_secs_f64()
+            ));
+            for elf_path in copy_elf_to_output_dir(args, program_metadata, toolchain)? {
+                output.artifacts.push(ElfArtifact::read(elf_path)?);
+            }
+            if let Err(err) = fingerprint::write(&fingerprint, &output.artifacts, cargo) {
+                tracing::debug!(target: TRACING_TARGET, "failed to write the fingerprint: {:#}", err);
+            }
+            output.fresh = true;
+            return Ok(output);
+        }
+    }
+
+    let start = Instant::now();
+    let total_crates = program_metadata.resolve.as_ref().map(|resolve| resolve.nodes.len());
+    let docker_image = args.docker.then(|| docker::get_docker_image(&args.tag));
+    let status = match execute_command(
+        cmd,
+        docker_image.as_deref(),
+        container.as_deref(),
+        program_name,
+        events,
+        total_crates,
+        log_file.clone(),
+        options,
+    ) {
+        Ok(status) => status,
+        Err(err) => {
+            let err = match &log_file {
+                Some(log_file) => err
+                    .context(format!("build failed, see the log at {}", log_file.path().display())),
+                None => err,
+            };
+            return failure::recover(args, &elf_paths, output, err, rerun::in_build_script());
+        }
     };
+    output.cargo_exit_code = status.code();
+    output.timings.cargo = start.elapsed();
+    tracing::debug!(target: TRACING_TARGET, elapsed = ?output.timings.cargo, "compiled program");
 
-    execute_command(cmd, args.docker)?;
+    events.send(BuildEvent::Phase(BuildPhase::Copying));
+    let start = Instant::now();
+    // The symbols are checked in the compiled ELFs, before they are stripped and copied, so that a
+    // failed check leaves the output directory as it was.
+    for elf_path in &compiled {
+        std_check::check_elf(args, elf_path)?;
+    }
+    for elf_path in copy_elf_to_output_dir(args, program_metadata, toolchain)? {
+        output.artifacts.push(ElfArtifact::read(elf_path)?);
+    }
+    output.timings.copy = start.elapsed();
+    tracing::debug!(
+        target: TRACING_TARGET,
+        elapsed = ?output.timings.copy,
+        elf_path = %output.elf_path(),
+        "copied ELF"
+    );
+    let compiled = compiled.into_iter().map(ElfArtifact::read).collect::<Result<Vec<_>>>()?;
+    for artifacts in [&output.artifacts, &compiled] {
+        if let Err(err) = fingerprint::write(&fingerprint, artifacts, output.timings.cargo) {
+            tracing::debug!(target: TRACING_TARGET, "failed to write the fingerprint: {:#}", err);
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_default_args_match_clap_and_serde() {
+        let default = BuildArgs::default();
+        assert_eq!(BuildArgs::parse_from(["build"]), default);
+        assert_eq!(serde_json::from_str::<BuildArgs>("{}").unwrap(), default);
+    }
+
+    #[test]
+    fn test_args_serde_roundtrip() {
+        let args = BuildArgs::parse_from([
+            "build",
+            "--docker",
+            "--tag",
+            "v2.0.0",
+            "--features",
+            "a,b",
+            "--no-default-features",
+            "--locked",
+            "--bin",
+            "fib",
+            "--elf-name",
+            "fib-elf",
+            "--out-dir",
+            "out",
+        ]);
+        let json = serde_json::to_string(&args).unwrap();
+        let deserialized: BuildArgs = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, args);
+        assert_eq!(get_program_build_args(&deserialized), get_program_build_args(&args));
+        let check = BuildArgs { check_only: true, ..args };
+        assert_eq!(get_program_build_args(&check)[..2], ["check", "--release"]);
+
+        // Fields missing from stored arguments take their default values.
+        let partial: BuildArgs = serde_json::from_str(r#"{"docker":true}"#).unwrap();
+        assert_eq!(partial, BuildArgs { docker: true, ..Default::default() });
+    }
+
+    #[test]
+    fn test_build_reads_package_metadata() {
+        let program_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metadata");
+        let args = BuildArgs { dry_run: true, ..Default::default() }.ignore_env();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let output = build_program_with_events(&args, program_dir, sender).unwrap();
+        assert_eq!(output.elf_path().file_name(), Some("metadata-elf"));
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            vec![BuildEvent::Phase(BuildPhase::Resolving), BuildEvent::Finished(output.clone())]
+        );
+        assert_eq!(output.artifacts[0].sha256, None);
+        assert_eq!(output.cargo_exit_code, None);
+        assert!(output.target_directory.ends_with(HELPER_TARGET_SUBDIR));
+    }
+
+    #[test]
+    fn test_public_constants() {
+        assert_eq!(build_target(), "riscv32im-succinct-zkvm-elf");
+        assert_eq!(output_directories(&BuildArgs::default()), [default_output_dir()]);
 
-    copy_elf_to_output_dir(args, &program_metadata)
+        let docker = BuildArgs { docker: true, ..Default::default() };
+        assert_eq!(helper_target_subdir(&BuildArgs::default()), "elf-compilation");
+        assert_eq!(helper_target_subdir(&docker), "elf-compilation/docker");
+        let program = BuildArgs { target_subdir: Some("fibonacci".to_string()), ..docker };
+        assert_eq!(helper_target_subdir(&program), "elf-compilation/docker/fibonacci");
+
+        let args = BuildArgs { rustflags: vec!["-Copt-level=3".to_string()], ..Default::default() };
+        let flags = rust_flags(&args);
+        assert_eq!(flags[..2], ["-C", "passes=loweratomic"]);
+        assert_eq!(flags.last().unwrap(), "-Copt-level=3");
+    }
+
+    #[test]
+    fn test_resolve_program_dir() {
+        let current_dir = Utf8PathBuf::try_from(std::env::current_dir().unwrap()).unwrap();
+        assert_eq!(resolve_program_dir(Path::new("program")).unwrap(), current_dir.join("program"));
+        assert_eq!(resolve_program_dir(Path::new("/program")).unwrap(), "/program");
+        assert_eq!(
+            resolve_program_dir(current_dir.join("a").as_ref()).unwrap(),
+            current_dir.join("a")
+        );
+    }
+
+    #[test]
+    fn test_generate_elf_paths_precedence() {
+        let program_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metadata");
+        let program_metadata =
+            program_metadata(&resolve_program_dir(&program_dir).unwrap()).unwrap();
+        let elf_dir = program_metadata.root_package().unwrap().manifest_path.with_file_name("elf");
+        let paths = |args: BuildArgs| generate_elf_paths(&args, &program_metadata).unwrap();
+
+        let default = BuildArgs::default();
+        assert_eq!(
+            paths(default.clone()),
+            vec![("metadata-program".into(), elf_dir.join(BUILD_TARGET))]
+        );
+
+        let binary = BuildArgs { binary: "fib".to_string(), ..default.clone() };
+        assert_eq!(paths(binary.clone()), vec![("fib".into(), elf_dir.join("fib"))]);
+
+        let elf_name = BuildArgs { elf_name: "fib-elf".to_string(), ..binary };
+        assert_eq!(paths(elf_name), vec![("fib".into(), elf_dir.join("fib-elf"))]);
+
+        let output_directory = BuildArgs { output_directory: vec!["out".to_string()], ..default };
+        assert_eq!(paths(output_directory)[0].1, elf_dir.with_file_name("out").join(BUILD_TARGET));
+
+        let output_directories = BuildArgs {
+            output_directory: vec!["elf".into(), "out".into()],
+            ..BuildArgs::default()
+        };
+        assert_eq!(
+            paths(output_directories),
+            vec![
+                ("metadata-program".into(), elf_dir.join(BUILD_TARGET)),
+                ("metadata-program".into(), elf_dir.with_file_name("out").join(BUILD_TARGET)),
+            ]
+        );
+
+        let legacy = BuildArgs { legacy_output_path: true, ..BuildArgs::default() };
+        let legacy_dir = program_metadata.target_directory.parent().unwrap().join("elf");
+        assert_eq!(paths(legacy)[0].1, legacy_dir.join(BUILD_TARGET));
+
+        let nightly =
+            elf_paths(&BuildArgs::default(), &program_metadata, Toolchain::NightlyFallback);
+        assert_eq!(nightly.unwrap()[0].1, elf_dir.join(format!("{}-nightly", BUILD_TARGET)));
+    }
+
+    #[test]
+    fn test_config_file_overrides_package_metadata() {
+        let program_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config-file");
+        let resolved = BuildArgs::default().ignore_env().resolve(program_dir).unwrap();
+        assert_eq!(resolved.args().elf_name, "file-elf");
+        assert_eq!(resolved.args().tag, "v2.0.0");
+        assert_eq!(resolved.args().features, vec!["metadata", "file"]);
+        assert_eq!(resolved.sources("tag"), [ConfigSource::PackageMetadata]);
+        assert!(resolved.args().ignore_env_overrides);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_execute_command_writes_whole_lines_to_sinks() {
+        /// Writes one byte at a time into a shared buffer, so that unsynchronized writers would
+        /// tear each other's lines.
+        struct ByteWriter(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for ByteWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                match buf.first() {
+                    Some(byte) => {
+                        self.0.lock().unwrap().push(*byte);
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let mut options = BuildOptions::default()
+            .stdout_sink(ByteWriter(buffer.clone()))
+            .stderr_sink(ByteWriter(buffer.clone()));
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "for i in $(seq 1 200); do echo \"out $i\"; echo \"err $i\" >&2; done"]);
+        execute_command(
+            command,
+            None,
+            None,
+            Some("fib"),
+            &EventSender::default(),
+            None,
+            None,
+            &mut options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 400);
+        for stream in ["out", "err"] {
+            let expected: Vec<String> =
+                (1..=200).map(|i| format!("[sp1:fib]  {} {}", stream, i)).collect();
+            let actual: Vec<&str> =
+                lines.iter().copied().filter(|line| line.contains(stream)).collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_execute_command_invalid_utf8() {
+        // A build script printing binary data before failing.
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "printf 'descriptor \\377\\376\\n'; printf 'error: bad \\377 descriptor\\n' >&2; \
+             printf '\\200 unterminated' >&2; exit 3",
+        ]);
+        let mut stderr = vec![];
+        let mut options =
+            BuildOptions::default().stdout_sink(std::io::sink()).stderr_sink(&mut stderr);
+        let err = execute_command(
+            command,
+            None,
+            None,
+            None,
+            &EventSender::default(),
+            None,
+            None,
+            &mut options,
+        )
+        .unwrap_err();
+        drop(options);
+
+        match err.downcast_ref::<BuildError>() {
+            Some(BuildError::Compile { status, first_error }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(first_error.as_deref(), Some("error: bad \u{FFFD} descriptor"));
+            }
+            _ => panic!("unexpected error: {:#}", err),
+        }
+        assert!(String::from_utf8(stderr).unwrap().contains("\u{FFFD} unterminated"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_execute_command_cancelled() {
+        let token = CancellationToken::new();
+        let mut options = BuildOptions::default().cancellation_token(token.clone());
+        // A slow build, whose grandchild keeps the output open.
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo compiling; sleep 30 & wait"]);
+
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        });
+        let err = execute_command(
+            command,
+            None,
+            None,
+            None,
+            &EventSender::default(),
+            None,
+            None,
+            &mut options,
+        )
+        .unwrap_err();
+        canceller.join().unwrap();
+
+        assert!(matches!(err.downcast_ref::<BuildError>(), Some(BuildError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[ignore = "builds a program, see `sp1_build::testing`"]
+    fn test_toolchain_file_is_overridden() {
+        // The fixture pins stable, and prints the version of the compiler its build script runs
+        // with. It builds with nightly if the succinct toolchain is missing.
+        let fixture = crate::testing::copy_fixture(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/toolchain-file"),
+        );
+        let output_dir = tempfile::tempdir().unwrap();
+        let args = BuildArgs {
+            toolchain_fallback: true,
+            output_directory: vec![output_dir.path().to_str().unwrap().to_string()],
+            ..Default::default()
+        }
+        .ignore_env();
+        crate::testing::require_toolchain(&args, fixture.path());
+        let expected = toolchain::installed_version(Toolchain::select(&args).name())
+            .expect("failed to read the version of the toolchain");
+
+        // Only the build script matters, the program itself may not compile with nightly.
+        let mut stderr = vec![];
+        let _ = build_program_with_options(
+            &args,
+            fixture.path(),
+            BuildOptions::default().stderr_sink(&mut stderr),
+        );
+        let stderr = String::from_utf8(stderr).unwrap();
+        assert!(stderr.contains(&format!("rustc -vV: {}", expected.line)), "{}", stderr);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "builds a program, see `sp1_build::testing`"]
+    fn test_rustc_wrapper_is_invoked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A program of its own, so that nothing is compiled already. It builds with nightly if the
+        // succinct toolchain is missing.
+        let dir = tempfile::tempdir().unwrap();
+        let program_dir = dir.path().join("program");
+        fs::create_dir_all(program_dir.join("src")).unwrap();
+        fs::write(
+            program_dir.join("Cargo.toml"),
+            "[workspace]\n[package]\nname = \"wrapped\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(program_dir.join("src/main.rs"), "#![no_main]\n\nfn main() {}\n").unwrap();
+        let wrapper = dir.path().join("wrapper");
+        let count = dir.path().join("count");
+        fs::write(&wrapper, format!("#!/bin/sh\necho >> {}\nexec \"$@\"\n", count.display()))
+            .unwrap();
+        fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let args = BuildArgs {
+            toolchain_fallback: true,
+            rustc_wrapper: Some(wrapper.clone()),
+            output_directory: vec![dir.path().join("elf").to_str().unwrap().to_string()],
+            ..Default::default()
+        }
+        .ignore_env();
+        crate::testing::require_toolchain(&args, &program_dir);
+        let _ = build_program_with_options(
+            &args,
+            &program_dir,
+            BuildOptions::default().stderr_sink(std::io::sink()),
+        );
+        assert!(fs::read_to_string(&count).unwrap().lines().count() > 0);
+
+        let args = BuildArgs { rustc_wrapper: Some(dir.path().join("missing")), ..args };
+        let errors = args.validate(&program_dir).unwrap_err();
+        assert!(errors.contains(&ValidationError::InvalidRustcWrapper(dir.path().join("missing"))));
+    }
 }
@@ -0,0 +1,130 @@
+//! Building a program and reading its ELFs, see [`build_and_load`].
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use anyhow::{bail, Result};
+use cargo_metadata::camino::Utf8PathBuf;
+
+use crate::{
+    build_program_with_output, verify_elf_sha256, BuildArgs, BuildError, BuildOutput, ElfArtifact,
+};
+
+/// Build the program in `program_dir` with `args` like [`build_program_with_output`], and read the
+/// first (and usually only) ELF, such as to pass it to the prover.
+///
+/// The ELF is checked to be the one the build produced: it must not be empty, as the placeholder
+/// of a skipped build is, and must have the digest of [`ElfArtifact::sha256`], as well as the one
+/// of the checksum file `<elf>.sha256` next to it if there is one. A failure to read or check it is
+/// a [`BuildError::Artifact`]. Nothing is read in a dry run, which fails, nor with
+/// [`crate::BuildArgs::check_only`], which fails with [`BuildError::NoElf`].
+///
+/// ```no_run
+/// use sp1_build::{build_and_load, BuildArgs};
+///
+/// let elf = build_and_load(&BuildArgs::default(), "program").unwrap();
+/// ```
+pub fn build_and_load(args: &BuildArgs, program_dir: impl AsRef<Path>) -> Result<Vec<u8>> {
+    Ok(build_and_load_with_output(args, program_dir)?.0)
+}
+
+/// Build the program in `program_dir` with `args` and read the first ELF like [`build_and_load`],
+/// returning the [`BuildOutput`] as well.
+pub fn build_and_load_with_output(
+    args: &BuildArgs,
+    program_dir: impl AsRef<Path>,
+) -> Result<(Vec<u8>, BuildOutput)> {
+    let output = build_program_with_output(args, program_dir)?;
+    let elf = load(output.artifacts.first().ok_or(BuildError::NoElf)?)?;
+    Ok((elf, output))
+}
+
+/// Build the program in `program_dir` with `args` and read every ELF like [`build_and_load`], by
+/// the file name of the ELF.
+pub fn build_and_load_all(
+    args: &BuildArgs,
+    program_dir: impl AsRef<Path>,
+) -> Result<BTreeMap<String, Vec<u8>>> {
+    let output = build_program_with_output(args, program_dir)?;
+    if output.artifacts.is_empty() {
+        return Err(BuildError::NoElf.into());
+    }
+    let mut elfs = BTreeMap::new();
+    for artifact in &output.artifacts {
+        let name = artifact.path.file_name().unwrap_or(artifact.path.as_str()).to_string();
+        elfs.insert(name, load(artifact)?);
+    }
+    Ok(elfs)
+}
+
+/// Read the ELF of `artifact`, checking that it is the one the build produced.
+fn load(artifact: &ElfArtifact) -> Result<Vec<u8>> {
+    let Some(sha256) = &artifact.sha256 else {
+        bail!("the ELF at {} was not built, as the build is a dry run", artifact.path);
+    };
+    let path = &artifact.path;
+    let invalid = |message: String| BuildError::Artifact {
+        path: path.clone(),
+        source: io::Error::new(io::ErrorKind::InvalidData, message),
+    };
+    let elf =
+        fs::read(path).map_err(|source| BuildError::Artifact { path: path.clone(), source })?;
+    if elf.is_empty() {
+        return Err(invalid("the ELF is empty".to_string()).into());
+    }
+    if !verify_elf_sha256(&elf, sha256) {
+        return Err(invalid(format!(
+            "the ELF changed since it was built, its SHA-256 is not {}",
+            sha256
+        ))
+        .into());
+    }
+    let checksum_path = Utf8PathBuf::from(format!("{}.sha256", path));
+    if let Ok(checksum) = fs::read_to_string(&checksum_path) {
+        // The format of `sha256sum`, the digest followed by the name of the file.
+        let expected = checksum.split_whitespace().next().unwrap_or_default();
+        if !verify_elf_sha256(&elf, expected) {
+            return Err(invalid(format!(
+                "the SHA-256 of the ELF does not match {} in {}",
+                expected, checksum_path
+            ))
+            .into());
+        }
+    }
+    Ok(elf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("elf")).unwrap();
+        let error = |artifact: &ElfArtifact| {
+            let err = load(artifact).unwrap_err();
+            match err.downcast_ref::<BuildError>() {
+                Some(BuildError::Artifact { source, .. }) => source.to_string(),
+                _ => panic!("unexpected error: {:#}", err),
+            }
+        };
+
+        let err = load(&ElfArtifact::not_built(path.clone())).unwrap_err();
+        assert!(err.to_string().ends_with("as the build is a dry run"), "{}", err);
+        fs::write(&path, b"").unwrap();
+        assert_eq!(error(&ElfArtifact::read(path.clone()).unwrap()), "the ELF is empty");
+
+        fs::write(&path, b"abc").unwrap();
+        let artifact = ElfArtifact::read(path.clone()).unwrap();
+        assert_eq!(load(&artifact).unwrap(), b"abc");
+        fs::write(&path, b"abd").unwrap();
+        assert!(error(&artifact).starts_with("the ELF changed since it was built"));
+
+        fs::write(&path, b"abc").unwrap();
+        let checksum = format!("{}  elf\n", crate::elf_sha256(b"abc"));
+        fs::write(dir.path().join("elf.sha256"), checksum).unwrap();
+        assert_eq!(load(&artifact).unwrap(), b"abc");
+        fs::write(dir.path().join("elf.sha256"), crate::elf_sha256(b"abd")).unwrap();
+        assert!(error(&artifact).starts_with("the SHA-256 of the ELF does not match"));
+    }
+}
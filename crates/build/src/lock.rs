@@ -0,0 +1,146 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use fs2::FileExt;
+
+use crate::{output, BuildError, CancellationToken};
+
+/// The name of the lock file in the target directory a program is compiled into.
+pub(crate) const LOCK_FILE_NAME: &str = ".sp1-build.lock";
+
+/// How long to wait for a concurrent build before saying so.
+const WAIT_NOTICE: Duration = Duration::from_secs(2);
+
+/// An advisory lock on the target directory a program is compiled into, held while cargo runs and
+/// the ELF is copied, so that concurrent builds of the same program (e.g. the build scripts of two
+/// crates depending on it) do not race on the target and output directories. The lock is released
+/// when dropped, including while unwinding, and by the OS if the process exits.
+#[derive(Debug)]
+pub(crate) struct BuildLock {
+    path: Utf8PathBuf,
+    file: File,
+}
+
+impl BuildLock {
+    /// Lock `target_dir`, waiting for any other build holding the lock to finish, unless `cancel`
+    /// is triggered while waiting.
+    pub(crate) fn acquire(
+        target_dir: &Utf8Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Self> {
+        Self::acquire_with_notice(target_dir, cancel, WAIT_NOTICE)
+    }
+
+    fn acquire_with_notice(
+        target_dir: &Utf8Path,
+        cancel: Option<&CancellationToken>,
+        notice_after: Duration,
+    ) -> Result<Self> {
+        fs::create_dir_all(target_dir)
+            .with_context(|| format!("failed to create the target directory {}", target_dir))?;
+        let path = target_dir.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open the build lock {}", path))?;
+
+        let start = Instant::now();
+        let mut noticed = false;
+        while file.try_lock_exclusive().is_err() {
+            if cancel.is_some_and(CancellationToken::is_cancelled) {
+                return Err(BuildError::Cancelled.into());
+            }
+            if !noticed && start.elapsed() >= notice_after {
+                output::info(&format!(
+                    "[sp1] waiting for concurrent sp1 build to release {}",
+                    path
+                ));
+                noticed = true;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        tracing::debug!(target: output::TRACING_TARGET, lock = %path, "acquired build lock");
+        Ok(Self { path, file })
+    }
+
+    /// Lock the existing `target_dir` without waiting, or return `None` if a build holds the lock.
+    pub(crate) fn try_acquire(target_dir: &Utf8Path) -> Result<Option<Self>> {
+        let path = target_dir.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open the build lock {}", path))?;
+        if file.try_lock_exclusive().is_err() {
+            return Ok(None);
+        }
+        tracing::debug!(target: output::TRACING_TARGET, lock = %path, "acquired build lock");
+        Ok(Some(Self { path, file }))
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+        tracing::debug!(target: output::TRACING_TARGET, lock = %self.path, "released build lock");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_build_lock_serializes_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = Utf8PathBuf::try_from(dir.path().join("elf-compilation")).unwrap();
+
+        let lock = BuildLock::acquire(&target_dir, None).unwrap();
+        let acquired = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let (target_dir, acquired) = (target_dir.clone(), acquired.clone());
+            thread::spawn(move || {
+                let _lock =
+                    BuildLock::acquire_with_notice(&target_dir, None, Duration::ZERO).unwrap();
+                acquired.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(300));
+        assert!(!acquired.load(Ordering::SeqCst));
+        drop(lock);
+        waiter.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_build_lock_released_on_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+
+        let result = panic::catch_unwind(|| {
+            let _lock = BuildLock::acquire(&target_dir, None).unwrap();
+            panic!("build panicked");
+        });
+        assert!(result.is_err());
+        let file = File::open(target_dir.join(LOCK_FILE_NAME)).unwrap();
+        file.try_lock_exclusive().unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! The checks of the lock file of a program built with [`BuildArgs::locked`], see [`metadata`].
+//!
+//! Reading the metadata of a program resolves its dependencies, which creates or updates its
+//! `Cargo.lock` unless cargo runs with `--locked`. A `locked` build therefore checks the lock file
+//! as it reads the metadata, so that a missing or outdated lock file fails the build with the
+//! reason, rather than as an error of cargo in the middle of the build output.
+
+use std::{fs, process::Command};
+
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, Metadata};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    docker, output, overrides, program_metadata, program_metadata_with, toolchain,
+    warning::{BuildWarning, WarningKind},
+    BuildArgs, BuildError, ValidationError,
+};
+
+/// What cargo prints when `--locked` or `--frozen` prevents it from updating the lock file,
+/// followed by the flag.
+const NEEDS_UPDATE: &str = "needs to be updated but --";
+
+/// Read the metadata of the program in `program_dir`, checking its lock file first if `args`, or
+/// the environment unless `args` ignore it, set [`BuildArgs::locked`] or [`BuildArgs::frozen`].
+///
+/// A missing lock file fails with [`ValidationError::LockfileMissing`], unless
+/// [`BuildArgs::generate_lockfile`] is set without [`BuildArgs::frozen`], and a lock file which
+/// cargo would change fails with [`ValidationError::LockfileStale`], both as
+/// [`BuildError::Invalid`].
+pub(crate) fn metadata(args: &BuildArgs, program_dir: &Utf8PathBuf) -> Result<Metadata> {
+    let frozen = overrides::flag_before_config(args, args.frozen, "frozen")?;
+    if !frozen && !overrides::flag_before_config(args, args.locked, "locked")? {
+        return program_metadata(program_dir);
+    }
+    let lockfile = workspace_lockfile(program_dir)?;
+    if !lockfile.is_file() {
+        let dir = lockfile.parent().unwrap().to_path_buf();
+        if frozen ||
+            !overrides::flag_before_config(args, args.generate_lockfile, "generate_lockfile")?
+        {
+            let missing = ValidationError::LockfileMissing { dir, frozen };
+            return Err(BuildError::Invalid(vec![missing]).into());
+        }
+        generate(args, program_dir, &lockfile)?;
+    }
+
+    let flag = if frozen { "--frozen" } else { "--locked" };
+    match program_metadata_with(program_dir, &[flag]) {
+        Err(err) if needs_update(&err) => {
+            let changes = changes(program_dir, &lockfile, frozen)?;
+            Err(BuildError::Invalid(vec![ValidationError::LockfileStale {
+                path: lockfile,
+                changes,
+            }])
+            .into())
+        }
+        result => result,
+    }
+}
+
+/// Generate the missing lock file at `lockfile` with `cargo generate-lockfile`, with the succinct
+/// toolchain if installed, or in the container of a [`BuildArgs::docker`] build, and emit a
+/// [`WarningKind::LockfileGenerated`] with its digest, for CI to check it in or compare it.
+fn generate(args: &BuildArgs, program_dir: &Utf8PathBuf, lockfile: &Utf8PathBuf) -> Result<()> {
+    let dir = lockfile.parent().unwrap().to_path_buf();
+    output::info(&format!("[sp1] generating the missing lock file {}", lockfile));
+    let mut command = if overrides::flag_before_config(args, args.docker, "docker")? {
+        docker::check_docker_available()?;
+        docker::generate_lockfile_command(args, program_dir, &dir)?
+    } else {
+        let mut command = Command::new("cargo");
+        command.args(["generate-lockfile", "--manifest-path"]).arg(program_dir.join("Cargo.toml"));
+        if toolchain::is_installed(toolchain::TOOLCHAIN_NAME) {
+            command.env("RUSTUP_TOOLCHAIN", toolchain::TOOLCHAIN_NAME);
+        }
+        command
+    };
+    let status = command.status().context("failed to run `cargo generate-lockfile`")?;
+    if !status.success() {
+        return Err(anyhow!("`cargo generate-lockfile` in {} exited with {}", dir, status));
+    }
+    let contents = fs::read(lockfile)
+        .with_context(|| format!("failed to read the generated lock file {}", lockfile))?;
+    let sha256 = hex::encode(Sha256::digest(contents));
+    output::warning(
+        BuildWarning::new(
+            WarningKind::LockfileGenerated,
+            format!(
+                "generated the missing lock file {} (sha256 {}); check it in to build reproducibly",
+                lockfile, sha256
+            ),
+        )
+        .field("path", lockfile)
+        .field("sha256", sha256),
+    );
+    Ok(())
+}
+
+/// The lock file of the workspace of the program in `program_dir`, found without resolving the
+/// dependencies, which would create it.
+fn workspace_lockfile(program_dir: &Utf8PathBuf) -> Result<Utf8PathBuf> {
+    let output = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain", "--manifest-path"])
+        .arg(program_dir.join("Cargo.toml"))
+        .output()
+        .context("failed to run `cargo locate-project`")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to find the workspace of {}: {}",
+            program_dir,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let manifest = Utf8PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(manifest.with_file_name("Cargo.lock"))
+}
+
+/// Whether cargo failed because `--locked` or `--frozen` prevented it from updating the lock file.
+fn needs_update(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<cargo_metadata::Error>(),
+        Some(cargo_metadata::Error::CargoMetadata { stderr }) if stderr.contains(NEEDS_UPDATE)
+    )
+}
+
+/// The changes cargo would make to the lock file at `path` when resolving the dependencies of the
+/// program in `program_dir` without `--locked`, found with `cargo update --workspace --dry-run`,
+/// which resolves like reading the metadata does but leaves the lock file untouched.
+fn changes(program_dir: &Utf8PathBuf, path: &Utf8PathBuf, frozen: bool) -> Result<Vec<String>> {
+    let mut command = Command::new("cargo");
+    command
+        .args(["update", "--workspace", "--dry-run", "--manifest-path"])
+        .arg(program_dir.join("Cargo.toml"))
+        .env("CARGO_TERM_COLOR", "never");
+    if frozen {
+        command.arg("--offline");
+    }
+    let output = command.output().context("failed to run `cargo update --dry-run`")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to find the changes to the lock file {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_changes(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// The packages added, removed or changed to another version in the output of `cargo update`,
+/// such as `updated serde 1.0.203 -> 1.0.204` for `Updating serde v1.0.203 -> v1.0.204`.
+fn parse_changes(stderr: &str) -> Vec<String> {
+    let version = |version: &str| version.trim_start_matches('v').to_string();
+    stderr
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let (action, name, from) = (words.next()?, words.next()?, version(words.next()?));
+            let to = match words.next() {
+                Some("->") => words.next().map(version),
+                _ => None,
+            };
+            match (action, to) {
+                ("Adding", None) => Some(format!("added {} {}", name, from)),
+                ("Removing", None) => Some(format!("removed {} {}", name, from)),
+                ("Updating" | "Downgrading", Some(to)) => {
+                    Some(format!("updated {} {} -> {}", name, from, to))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str) -> (tempfile::TempDir, Utf8PathBuf) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::try_from(temp_dir.path().to_path_buf()).unwrap();
+        let manifest =
+            format!("[workspace]\n[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        (temp_dir, dir)
+    }
+
+    fn invalid(err: anyhow::Error) -> ValidationError {
+        match err.downcast::<BuildError>() {
+            Ok(BuildError::Invalid(mut errors)) => errors.remove(0),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_locked_metadata() {
+        let (_program, program_dir) = package("locked");
+        let args = BuildArgs { locked: true, ..Default::default() }.ignore_env();
+
+        let missing = invalid(metadata(&args, &program_dir).unwrap_err());
+        let expected = ValidationError::LockfileMissing { dir: program_dir.clone(), frozen: false };
+        assert_eq!(missing, expected);
+        assert!(missing.to_string().contains("run `cargo generate-lockfile` in"));
+        assert!(!program_dir.join("Cargo.lock").exists());
+
+        // `frozen` never generates the lock file.
+        let frozen = BuildArgs { frozen: true, generate_lockfile: true, ..args.clone() };
+        let missing = invalid(metadata(&frozen, &program_dir).unwrap_err());
+        let expected = ValidationError::LockfileMissing { dir: program_dir.clone(), frozen: true };
+        assert_eq!(missing, expected);
+        assert!(missing.to_string().contains("`frozen` is set"));
+        assert!(!program_dir.join("Cargo.lock").exists());
+
+        let generate = BuildArgs { generate_lockfile: true, ..args.clone() };
+        let warnings = crate::warning::Collector::start();
+        metadata(&generate, &program_dir).unwrap();
+        let lockfile = fs::read_to_string(program_dir.join("Cargo.lock")).unwrap();
+        let warnings = warnings.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::LockfileGenerated);
+        assert_eq!(warnings[0].fields["sha256"], hex::encode(Sha256::digest(&lockfile)));
+        metadata(&frozen, &program_dir).unwrap();
+
+        // A new dependency would change the lock file, which is left as it was.
+        let (_dependency, dependency) = package("dependency");
+        let manifest = fs::read_to_string(program_dir.join("Cargo.toml")).unwrap();
+        let manifest =
+            format!("{}[dependencies]\ndependency = {{ path = \"{}\" }}\n", manifest, dependency);
+        fs::write(program_dir.join("Cargo.toml"), manifest).unwrap();
+        let stale = invalid(metadata(&args, &program_dir).unwrap_err());
+        let expected = ValidationError::LockfileStale {
+            path: program_dir.join("Cargo.lock"),
+            changes: vec!["added dependency 0.1.0".to_string()],
+        };
+        assert_eq!(stale, expected);
+        assert_eq!(fs::read_to_string(program_dir.join("Cargo.lock")).unwrap(), lockfile);
+
+        let unlocked = BuildArgs { locked: false, ..args };
+        metadata(&unlocked, &program_dir).unwrap();
+        assert_ne!(fs::read_to_string(program_dir.join("Cargo.lock")).unwrap(), lockfile);
+    }
+
+    #[test]
+    fn test_parse_changes() {
+        let stderr = "    Updating crates.io index
+     Locking 3 packages to latest compatible versions
+    Removing hex v0.4.3
+    Updating serde v1.0.203 -> v1.0.204
+ Downgrading syn v2.0.1 -> v2.0.0
+      Adding sha2 v0.10.8
+      Adding dependency v0.1.0 (/tmp/dependency)
+warning: not updating lockfile due to dry run
+";
+        assert_eq!(
+            parse_changes(stderr),
+            vec![
+                "removed hex 0.4.3",
+                "updated serde 1.0.203 -> 1.0.204",
+                "updated syn 2.0.1 -> 2.0.0",
+                "added sha2 0.10.8",
+                "added dependency 0.1.0",
+            ]
+        );
+        assert!(parse_changes("warning: not updating lockfile due to dry run").is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+
+/// A file which the output of the cargo command is appended to, see [`crate::BuildArgs::log_file`].
+#[derive(Debug)]
+pub(crate) struct LogFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl LogFile {
+    /// Open the file at `path` for appending, creating it and its parent directories if needed.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create the directory of the log file {}", path.display())
+            })?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open the log file {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), file: Mutex::new(file) })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a `line` printed to `stream` with a timestamp, flushing it immediately. Errors are
+    /// ignored, so that a full disk does not fail the build.
+    pub(crate) fn line(&self, stream: &str, line: &str) {
+        let timestamp = Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z");
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(file, "{} [{}] {}", timestamp, stream, line);
+        let _ = file.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("build.log");
+
+        LogFile::open(&path).unwrap().line("stdout", "first");
+        let log = LogFile::open(&path).unwrap();
+        log.line("stderr", "   Compiling libc v0.2.155");
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" [stdout] first"));
+        assert!(lines[1].ends_with(" [stderr]    Compiling libc v0.2.155"));
+
+        assert!(LogFile::open(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+//! The build manifest of an ELF, see [`BuildArgs::manifest`].
+//!
+//! The manifest records what went into the ELF, so that the dependencies of an ELF deployed long
+//! ago can be recovered after the sources moved on: the package and its path in the workspace, the
+//! SHA-256 digest of the `Cargo.lock` the build used, the crates of the dependency graph of the
+//! program as resolved from it, the SP1 crates replaced by a `[patch]` section, and the cycles of
+//! the smoke test, if run. It is written next to the ELF, as `<elf>.manifest.json`, and two
+//! manifests are compared with [`diff_manifests`].
+
+use std::{collections::BTreeMap, fs};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, Metadata};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{debuginfo, sbom, BuildArgs, ElfArtifact, EnvSnapshot, PatchedCrate};
+
+/// The version of the format of the manifests written by this crate, bumped when fields are added.
+/// Manifests of older versions still parse, with the fields they lack left empty.
+pub const MANIFEST_VERSION: u32 = 4;
+
+/// The suffix of the manifest, appended to the path of the ELF.
+pub(crate) const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// The build manifest of an ELF, written next to it with [`BuildArgs::manifest`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    /// The version of the format of the manifest, see [`MANIFEST_VERSION`].
+    pub version: u32,
+    /// The package of the program.
+    pub package: String,
+    /// The version of the package.
+    #[serde(default)]
+    pub package_version: String,
+    /// The directory of the package, relative to the root of its workspace, such as `program`, or
+    /// empty if it is the root.
+    #[serde(default)]
+    pub package_path: Utf8PathBuf,
+    /// The SHA-256 digest of the ELF.
+    pub elf_sha256: String,
+    /// The SHA-256 digest of the debug file of the ELF, if it is stripped, see
+    /// [`BuildArgs::split_debuginfo`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_sha256: Option<String>,
+    /// The version of sp1-build which built the ELF.
+    #[serde(default)]
+    pub sp1_build_version: String,
+    /// The SHA-256 digest of the `Cargo.lock` of the workspace as used by the build, if any.
+    #[serde(default)]
+    pub cargo_lock_sha256: Option<String>,
+    /// The crates of the dependency graph of the program, leaving out those only used by its
+    /// tests, sorted by name and version.
+    #[serde(default)]
+    pub dependencies: Vec<ResolvedDependency>,
+    /// The SP1 crates of the program replaced by a `[patch]` section, which change its
+    /// verification key, see [`BuildArgs::deny_patched_sp1`].
+    #[serde(default)]
+    pub patched_sp1_crates: Vec<PatchedCrate>,
+    /// The cycles the ELF executed in as a smoke test, if run, see [`BuildArgs::smoke_test`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cycles: Option<u64>,
+    /// The environment cargo was run with, see [`BuildArgs::capture_env`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<EnvSnapshot>,
+}
+
+/// A crate of the dependency graph of a program, as resolved from its `Cargo.lock`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ResolvedDependency {
+    /// The name of the crate.
+    pub name: String,
+    /// The version of the crate.
+    pub version: String,
+    /// The source of the crate, such as `registry+https://github.com/rust-lang/crates.io-index`,
+    /// or `None` for a local crate.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The checksum of the crate in the lock file, for crates of a registry.
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+impl BuildManifest {
+    /// Read the manifest at `path`, such as `elf/riscv32im-succinct-zkvm-elf.manifest.json`.
+    pub fn read(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read(path)
+            .with_context(|| format!("failed to read the manifest {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("invalid manifest {}", path.display()))
+    }
+}
+
+/// How the dependencies of two builds differ, see [`diff_manifests`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDiff {
+    /// The dependencies of the second build only.
+    pub added: Vec<ResolvedDependency>,
+    /// The dependencies of the first build only.
+    pub removed: Vec<ResolvedDependency>,
+    /// The dependencies of both builds with another version, source or checksum, as in the first
+    /// build and in the second.
+    pub changed: Vec<(ResolvedDependency, ResolvedDependency)>,
+}
+
+impl ManifestDiff {
+    /// Whether both builds have the same dependencies.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the dependencies of the builds of the manifests `a` and `b`.
+///
+/// A crate of both builds in the same version with another source or checksum is changed, as is a
+/// crate of which each build has a single version, and these versions differ. The other crates of
+/// only one build are added or removed.
+pub fn diff_manifests(a: &BuildManifest, b: &BuildManifest) -> ManifestDiff {
+    let by_version = |manifest: &BuildManifest| -> BTreeMap<(String, String), ResolvedDependency> {
+        let dependencies = manifest.dependencies.iter().cloned();
+        dependencies.map(|dep| ((dep.name.clone(), dep.version.clone()), dep)).collect()
+    };
+    let (a, b) = (by_version(a), by_version(b));
+
+    let mut diff = ManifestDiff::default();
+    for (key, old) in &a {
+        match b.get(key) {
+            Some(new) if new != old => diff.changed.push((old.clone(), new.clone())),
+            Some(_) => {}
+            None => diff.removed.push(old.clone()),
+        }
+    }
+    diff.added =
+        b.iter().filter(|(key, _)| !a.contains_key(key)).map(|(_, dep)| dep).cloned().collect();
+
+    // A crate updated to another version is removed in one version and added in the other.
+    let single = |deps: &BTreeMap<(String, String), ResolvedDependency>, name: &str| {
+        deps.keys().filter(|(dep, _)| dep == name).count() == 1
+    };
+    let updated: Vec<String> = diff
+        .removed
+        .iter()
+        .map(|dep| dep.name.clone())
+        .filter(|name| single(&a, name) && single(&b, name))
+        .filter(|name| diff.added.iter().any(|dep| dep.name == *name))
+        .collect();
+    for name in updated {
+        let old = diff.removed.iter().position(|dep| dep.name == name).unwrap();
+        let new = diff.added.iter().position(|dep| dep.name == name).unwrap();
+        diff.changed.push((diff.removed.remove(old), diff.added.remove(new)));
+    }
+    diff.changed.sort();
+    diff
+}
+
+/// Write the manifest of each of the `artifacts` of the program described by `program_metadata`,
+/// with its `patched` SP1 crates and the environment of the build `env`, if captured, returning
+/// the paths it is written to. The artifacts record the cycles of the smoke test, so it runs first.
+pub(crate) fn write(
+    args: &BuildArgs,
+    program_metadata: &Metadata,
+    artifacts: &[ElfArtifact],
+    patched: &[PatchedCrate],
+    env: Option<&EnvSnapshot>,
+) -> Result<Vec<Utf8PathBuf>> {
+    if !args.manifest {
+        return Ok(vec![]);
+    }
+    let lock = fs::read(program_metadata.workspace_root.join("Cargo.lock")).ok();
+    let cargo_lock_sha256 = lock.as_ref().map(|lock| hex::encode(Sha256::digest(lock)));
+    let lock = lock.map(|lock| String::from_utf8_lossy(&lock).into_owned()).unwrap_or_default();
+    let dependencies = sbom::components(program_metadata, &lock)
+        .into_iter()
+        .map(|component| ResolvedDependency {
+            name: component.name,
+            version: component.version,
+            source: component.source,
+            checksum: component.checksum,
+        })
+        .collect::<Vec<_>>();
+    let package = program_metadata.root_package();
+    let package_path = package
+        .and_then(|package| package.manifest_path.parent())
+        .and_then(|dir| dir.strip_prefix(&program_metadata.workspace_root).ok())
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+
+    let mut paths = vec![];
+    for artifact in artifacts {
+        let Some(sha256) = &artifact.sha256 else {
+            continue;
+        };
+        let manifest = BuildManifest {
+            version: MANIFEST_VERSION,
+            package: package.map(|package| package.name.clone()).unwrap_or_default(),
+            package_version: package.map(|package| package.version.to_string()).unwrap_or_default(),
+            package_path: package_path.clone(),
+            elf_sha256: sha256.clone(),
+            debug_sha256: args
+                .split_debuginfo
+                .then(|| fs::read(debuginfo::debug_path(&artifact.path)).ok())
+                .flatten()
+                .map(|debug| hex::encode(Sha256::digest(debug))),
+            sp1_build_version: env!("CARGO_PKG_VERSION").to_string(),
+            cargo_lock_sha256: cargo_lock_sha256.clone(),
+            dependencies: dependencies.clone(),
+            patched_sp1_crates: patched.to_vec(),
+            cycles: artifact.cycles,
+            env: env.cloned(),
+        };
+        let path = Utf8PathBuf::from(format!("{}{}", artifact.path, MANIFEST_SUFFIX));
+        fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+            .with_context(|| format!("failed to write the manifest to {}", path))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dep(name: &str, version: &str, checksum: Option<&str>) -> ResolvedDependency {
+        ResolvedDependency {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: Some("registry+https://github.com/rust-lang/crates.io-index".to_string()),
+            checksum: checksum.map(str::to_string),
+        }
+    }
+
+    fn manifest(dependencies: Vec<ResolvedDependency>) -> BuildManifest {
+        BuildManifest { version: MANIFEST_VERSION, dependencies, ..Default::default() }
+    }
+
+    #[test]
+    fn test_diff_manifests() {
+        let a = manifest(vec![
+            dep("bincode", "1.3.3", Some("aa")),
+            dep("hex", "0.4.3", Some("bb")),
+            dep("serde", "1.0.200", Some("cc")),
+            dep("syn", "1.0.109", None),
+            dep("syn", "2.0.60", None),
+        ]);
+        let b = manifest(vec![
+            dep("bincode", "1.3.3", Some("dd")),
+            dep("serde", "1.0.204", Some("ee")),
+            dep("sha2", "0.10.8", None),
+            dep("syn", "2.0.60", None),
+            dep("syn", "2.0.72", None),
+        ]);
+        let diff = diff_manifests(&a, &b);
+        assert_eq!(
+            diff.removed,
+            vec![dep("hex", "0.4.3", Some("bb")), dep("syn", "1.0.109", None)]
+        );
+        assert_eq!(diff.added, vec![dep("sha2", "0.10.8", None), dep("syn", "2.0.72", None)]);
+        assert_eq!(
+            diff.changed,
+            vec![
+                (dep("bincode", "1.3.3", Some("aa")), dep("bincode", "1.3.3", Some("dd"))),
+                (dep("serde", "1.0.200", Some("cc")), dep("serde", "1.0.204", Some("ee"))),
+            ]
+        );
+        assert!(diff_manifests(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let write = |path: &str, contents: &str| {
+            fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            fs::write(root.join(path), contents).unwrap();
+        };
+        write("Cargo.toml", "[workspace]\nmembers = [\"program\", \"lib\"]\n");
+        write("lib/Cargo.toml", "[package]\nname = \"lib\"\nversion = \"0.2.0\"\n");
+        write("lib/src/lib.rs", "");
+        let manifest = "[package]\nname = \"program\"\nversion = \"0.1.0\"\n\
+                        [dependencies]\nlib = { path = \"../lib\" }\n";
+        write("program/Cargo.toml", manifest);
+        write("program/src/main.rs", "fn main() {}");
+        let metadata = crate::program_metadata(&root.join("program")).unwrap();
+        write("elf/program", "elf");
+        let artifact = ElfArtifact::read(root.join("elf/program")).unwrap();
+
+        assert!(write_manifests(&BuildArgs::default(), &metadata, &artifact).is_empty());
+        let args = BuildArgs { manifest: true, ..Default::default() };
+        let paths = write_manifests(&args, &metadata, &artifact);
+        assert_eq!(paths, vec![root.join("elf/program.manifest.json")]);
+        let manifest = BuildManifest::read(&paths[0]).unwrap();
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        assert_eq!(
+            (manifest.package.as_str(), manifest.package_path.as_str()),
+            ("program", "program")
+        );
+        assert_eq!(Some(&manifest.elf_sha256), artifact.sha256.as_ref());
+        let lock = fs::read(root.join("Cargo.lock")).unwrap();
+        assert_eq!(manifest.cargo_lock_sha256, Some(hex::encode(Sha256::digest(lock))));
+        assert_eq!(manifest.dependencies.len(), 1);
+        assert_eq!(
+            (manifest.dependencies[0].name.as_str(), manifest.dependencies[0].source.as_deref()),
+            ("lib", None)
+        );
+        assert_eq!(manifest.env, None);
+        assert_eq!(manifest.debug_sha256, None);
+        assert!(manifest.patched_sp1_crates.is_empty() && manifest.cycles.is_none());
+
+        let patched = PatchedCrate {
+            name: "sp1-lib".to_string(),
+            version: "3.0.0".to_string(),
+            source: "git+https://github.com/example/sp1".to_string(),
+        };
+        let executed = ElfArtifact { cycles: Some(1234), ..artifact.clone() };
+        let patches = std::slice::from_ref(&patched);
+        let paths = super::write(&args, &metadata, &[executed], patches, None).unwrap();
+        let manifest = BuildManifest::read(&paths[0]).unwrap();
+        assert_eq!(manifest.patched_sp1_crates, vec![patched]);
+        assert_eq!(manifest.cycles, Some(1234));
+
+        write("elf/program.debug", "debug");
+        let args = BuildArgs { split_debuginfo: true, ..args };
+        let manifest =
+            BuildManifest::read(&write_manifests(&args, &metadata, &artifact)[0]).unwrap();
+        assert_eq!(manifest.debug_sha256, Some(hex::encode(Sha256::digest("debug"))));
+    }
+
+    fn write_manifests(
+        args: &BuildArgs,
+        metadata: &Metadata,
+        artifact: &ElfArtifact,
+    ) -> Vec<Utf8PathBuf> {
+        write(args, metadata, std::slice::from_ref(artifact), &[], None).unwrap()
+    }
+
+    #[test]
+    fn test_read_manifest_without_optional_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elf.manifest.json");
+        fs::write(&path, r#"{"version": 1, "package": "fibonacci", "elf_sha256": "ab12"}"#)
+            .unwrap();
+        let manifest = BuildManifest::read(&path).unwrap();
+        assert_eq!(manifest.elf_sha256, "ab12");
+        assert_eq!(manifest.package, "fibonacci");
+        assert_eq!(manifest.cargo_lock_sha256, None);
+        assert!(manifest.dependencies.is_empty());
+        assert!(manifest.patched_sp1_crates.is_empty());
+        assert_eq!((manifest.cycles, manifest.env), (None, None));
+    }
+}
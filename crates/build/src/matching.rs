@@ -0,0 +1,139 @@
+//! Building the programs in the directories matching a glob, see [`build_programs_matching`].
+
+use std::{env, path::PathBuf, time::Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, Metadata};
+
+use crate::{
+    build_program_from_metadata, events::EventSender, metadata_cache, output, program_name,
+    resolve_program_dir, summary, BuildArgs, BuildOptions, TRACING_TARGET,
+};
+
+/// Build the program in each directory matching the glob `pattern`, such as `programs/*`.
+///
+/// A relative pattern is resolved against the directory of the crate being built when called from
+/// a build script, `CARGO_MANIFEST_DIR`, or else the current directory. Directories without a
+/// package with a binary target, such as libraries shared by the programs, are skipped. The
+/// programs are built one after the other, as with [`crate::build_program_in`] with
+/// [`BuildArgs::package_prefix`], so that programs in the same workspace share the compiled
+/// dependencies.
+///
+/// # Returns
+///
+/// * `Result<Vec<(String, Utf8PathBuf)>>` - The name of each program and the path to its ELF, in
+///   the order of their directories. Fails if no program matches, as with a mistyped pattern, or as
+///   soon as a program fails to build.
+pub fn build_programs_matching(
+    args: &BuildArgs,
+    pattern: &str,
+) -> Result<Vec<(String, Utf8PathBuf)>> {
+    let _mode = output::set_mode(output::OutputMode::of(args));
+    let base_dir =
+        env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+    let result = (|| {
+        let programs = matching_programs(args, &base_dir, pattern)?;
+        if programs.is_empty() {
+            bail!("no program matches `{}` in {}", pattern, base_dir.display());
+        }
+        let args = BuildArgs { package_prefix: true, ..args.clone() };
+        let start = Instant::now();
+        let mut built = vec![];
+        let mut artifacts = vec![];
+        for (program_dir, program_metadata) in programs {
+            let output = build_program_from_metadata(
+                &args,
+                &program_dir,
+                &program_metadata,
+                Default::default(),
+                &EventSender::default(),
+                &mut BuildOptions::default(),
+                None,
+            )?;
+            let name = program_name(&args, &program_dir, &program_metadata);
+            built.push((name, output.try_elf_path()?.to_path_buf()));
+            artifacts.extend(output.artifacts);
+        }
+        let built_artifacts: Vec<_> = artifacts.iter().filter(|a| a.size.is_some()).collect();
+        if built.len() > 1 && !built_artifacts.is_empty() {
+            summary::print_total(&built_artifacts, start.elapsed());
+        }
+        Ok(built)
+    })();
+    if let Err(err) = &result {
+        output::error(err);
+    }
+    result
+}
+
+/// The directories matching `pattern`, relative to `base_dir`, which contain a program, along
+/// with its metadata.
+fn matching_programs(
+    args: &BuildArgs,
+    base_dir: &std::path::Path,
+    pattern: &str,
+) -> Result<Vec<(Utf8PathBuf, Metadata)>> {
+    let pattern = base_dir.join(pattern);
+    let pattern =
+        pattern.to_str().ok_or_else(|| anyhow!("{} is not valid UTF-8", pattern.display()))?;
+    let mut programs = vec![];
+    for dir in glob::glob(pattern).with_context(|| format!("invalid glob `{}`", pattern))? {
+        let dir = dir?;
+        if !dir.join("Cargo.toml").is_file() {
+            tracing::debug!(target: TRACING_TARGET, dir = %dir.display(), "skipping, no Cargo.toml");
+            continue;
+        }
+        let program_dir = resolve_program_dir(&dir)?;
+        let program_metadata = metadata_cache::metadata(args, &program_dir)?;
+        let has_binary = program_metadata.root_package().is_some_and(|package| {
+            package.targets.iter().any(|target| target.kind.iter().any(|kind| kind == "bin"))
+        });
+        if !has_binary {
+            tracing::debug!(target: TRACING_TARGET, %program_dir, "skipping, no binary target");
+            continue;
+        }
+        programs.push((program_dir, program_metadata));
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_build_programs_matching() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = |path: &str, name: &str, src: &str| {
+            let package_dir = dir.path().join(path);
+            fs::create_dir_all(package_dir.join("src")).unwrap();
+            fs::write(
+                package_dir.join("Cargo.toml"),
+                format!("[workspace]\n[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            )
+            .unwrap();
+            fs::write(package_dir.join("src").join(src), "").unwrap();
+        };
+        package("programs/b", "second", "main.rs");
+        package("programs/a", "first", "main.rs");
+        package("programs/shared", "shared", "lib.rs");
+        fs::create_dir_all(dir.path().join("programs/notes")).unwrap();
+
+        let pattern = dir.path().join("programs/*");
+        let args = BuildArgs { dry_run: true, ..Default::default() }.ignore_env();
+        let programs = matching_programs(&args, dir.path(), pattern.to_str().unwrap()).unwrap();
+        let names: Vec<_> =
+            programs.iter().map(|(_, metadata)| &metadata.root_package().unwrap().name).collect();
+        assert_eq!(names, ["first", "second"]);
+
+        let built = build_programs_matching(&args, pattern.to_str().unwrap()).unwrap();
+        assert_eq!(built[1].0, "second");
+        assert!(built[1].1.ends_with("programs/b/elf/riscv32im-succinct-zkvm-elf"));
+
+        let missing = dir.path().join("program/*");
+        let err = build_programs_matching(&args, missing.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("no program matches"), "{}", err);
+    }
+}
@@ -0,0 +1,190 @@
+//! The cache of the metadata of programs within a process, see [`invalidate_metadata_cache`].
+//!
+//! Reading the metadata of a program runs `cargo metadata`, which takes seconds for programs with
+//! many dependencies. The metadata is kept for each manifest, along with the digests of the files
+//! it is read from: the manifests of the local packages, the `src` and `src/bin` directories cargo
+//! discovers their targets in, and the manifest and lock file of the workspace. It is read again
+//! when any of them changes.
+//!
+//! The metadata also depends on the environment, such as `CARGO_TARGET_DIR`, which sets its
+//! `target_directory`, so the values of the [`KEY_ENVS`] are part of the key of the cache. The
+//! configuration files of cargo are not: a `target-dir` set in one which changes while the process
+//! runs is only seen after [`invalidate_metadata_cache`].
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use cargo_metadata::{camino::Utf8PathBuf, Metadata};
+use sha2::{Digest, Sha256};
+
+use crate::{lockfile, overrides, BuildArgs};
+
+/// A cached metadata, with the digest of each file it is read from, or `None` for a missing file.
+struct Entry {
+    stamps: Vec<(Utf8PathBuf, Option<[u8; 32]>)>,
+    metadata: Metadata,
+}
+
+/// The variables which change the metadata cargo reads for a manifest.
+const KEY_ENVS: [&str; 3] = ["CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR", "CARGO_HOME"];
+
+/// The manifest of a program, with the values of the [`KEY_ENVS`] its metadata was read with.
+type Key = (Utf8PathBuf, Vec<Option<String>>);
+
+fn cache() -> &'static Mutex<HashMap<Key, Entry>> {
+    static CACHE: OnceLock<Mutex<HashMap<Key, Entry>>> = OnceLock::new();
+    CACHE.get_or_init(Mutex::default)
+}
+
+/// The key of the metadata of the manifest at `manifest_path`, with the variables found by
+/// `lookup`.
+fn key(manifest_path: Utf8PathBuf, lookup: impl Fn(&str) -> Option<String>) -> Key {
+    (manifest_path, KEY_ENVS.iter().map(|name| lookup(name)).collect())
+}
+
+/// Forget the metadata of every program read by this process, so that the next build of each
+/// program runs `cargo metadata` again, along with the versions of the toolchains it read.
+///
+/// The cached metadata is read again when the manifests or the lock file of the program change, so
+/// this is only needed after changes the cache cannot see, such as a new version of a dependency
+/// from a registry without a lock file, or another cargo or toolchain installed.
+pub fn invalidate_metadata_cache() {
+    cache().lock().unwrap_or_else(|e| e.into_inner()).clear();
+    crate::toolchain::invalidate_versions();
+}
+
+/// The metadata of the program in `program_dir`, from the cache unless `args` disable it, see
+/// [`BuildArgs::no_metadata_cache`].
+pub(crate) fn metadata(args: &BuildArgs, program_dir: &Utf8PathBuf) -> Result<Metadata> {
+    if disabled(args)? {
+        return lockfile::metadata(args, program_dir);
+    }
+    let manifest_path = program_dir.join("Cargo.toml");
+    let key = key(manifest_path.clone(), |name| env::var(name).ok());
+    if let Some(entry) = cache().lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        if entry.stamps.iter().all(|(path, digest)| digest_of(path) == *digest) {
+            return Ok(entry.metadata.clone());
+        }
+    }
+
+    // The lock is not held while cargo runs, so that programs are read concurrently. Concurrent
+    // reads of the same program both run cargo, and store the same metadata.
+    let metadata = lockfile::metadata(args, program_dir)?;
+    let stamps = stamped_files(&manifest_path, &metadata)
+        .into_iter()
+        .map(|path| {
+            let digest = digest_of(&path);
+            (path, digest)
+        })
+        .collect();
+    cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, Entry { stamps, metadata: metadata.clone() });
+    Ok(metadata)
+}
+
+/// Whether the cache is disabled by `args`, or by the environment unless `args` ignore it. As the
+/// metadata is read before the configuration of the program, the configuration files cannot.
+fn disabled(args: &BuildArgs) -> Result<bool> {
+    overrides::flag_before_config(args, args.no_metadata_cache, "no_metadata_cache")
+}
+
+/// The files the metadata of the program at `manifest_path` is read from.
+fn stamped_files(manifest_path: &Utf8PathBuf, metadata: &Metadata) -> Vec<Utf8PathBuf> {
+    let mut files = vec![
+        manifest_path.clone(),
+        metadata.workspace_root.join("Cargo.toml"),
+        metadata.workspace_root.join("Cargo.lock"),
+    ];
+    for package in metadata.packages.iter().filter(|package| package.source.is_none()) {
+        let package_dir = package.manifest_path.parent().unwrap();
+        for path in
+            [package.manifest_path.clone(), package_dir.join("src"), package_dir.join("src/bin")]
+        {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// The digest of the contents of the file at `path`, or of the names of the entries of the
+/// directory at `path`.
+fn digest_of(path: &Utf8PathBuf) -> Option<[u8; 32]> {
+    if path.is_dir() {
+        let mut names: Vec<_> = path
+            .read_dir_utf8()
+            .ok()?
+            .flatten()
+            .map(|entry| entry.file_name().to_string())
+            .collect();
+        names.sort();
+        return Some(Sha256::digest(names.join("\n")).into());
+    }
+    fs::read(path).ok().map(|contents| Sha256::digest(contents).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::METADATA_SPAWNS;
+
+    fn spawns() -> usize {
+        METADATA_SPAWNS.with(|spawns| spawns.get())
+    }
+
+    #[test]
+    fn test_metadata_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_dir = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        let manifest = "[workspace]\n[package]\nname = \"cached\"\nversion = \"0.1.0\"\n";
+        fs::create_dir_all(program_dir.join("src")).unwrap();
+        fs::write(program_dir.join("Cargo.toml"), manifest).unwrap();
+        fs::write(program_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        let args = BuildArgs::default().ignore_env();
+
+        let first = metadata(&args, &program_dir).unwrap();
+        let spawned = spawns();
+        let second = metadata(&args, &program_dir).unwrap();
+        assert_eq!(spawns(), spawned);
+        assert_eq!(first.root_package().unwrap().name, second.root_package().unwrap().name);
+
+        // A changed manifest is read again.
+        fs::write(program_dir.join("Cargo.toml"), manifest.replace("0.1.0", "0.2.0")).unwrap();
+        let changed = metadata(&args, &program_dir).unwrap();
+        assert_eq!(spawns(), spawned + 1);
+        assert_eq!(changed.root_package().unwrap().version.to_string(), "0.2.0");
+
+        // So is a program with a new binary.
+        fs::create_dir_all(program_dir.join("src/bin")).unwrap();
+        fs::write(program_dir.join("src/bin/other.rs"), "fn main() {}").unwrap();
+        let targets = metadata(&args, &program_dir).unwrap().root_package().unwrap().targets.len();
+        assert_eq!((targets, spawns()), (2, spawned + 2));
+
+        let no_cache = BuildArgs { no_metadata_cache: true, ..args.clone() };
+        metadata(&no_cache, &program_dir).unwrap();
+        assert_eq!(spawns(), spawned + 3);
+
+        invalidate_metadata_cache();
+        metadata(&args, &program_dir).unwrap();
+        assert_eq!(spawns(), spawned + 4);
+    }
+
+    #[test]
+    fn test_key_includes_target_dir() {
+        let manifest_path = Utf8PathBuf::from("program/Cargo.toml");
+        let target_dir = |dir: &'static str| {
+            move |name: &str| (name == "CARGO_TARGET_DIR").then(|| dir.to_string())
+        };
+        let key_a = key(manifest_path.clone(), target_dir("a"));
+        assert_eq!(key_a, key(manifest_path.clone(), target_dir("a")));
+        assert_ne!(key_a, key(manifest_path.clone(), target_dir("b")));
+        assert_ne!(key_a, key(manifest_path, |_| None));
+    }
+}
@@ -0,0 +1,173 @@
+//! The error of a build whose ELF is not where cargo should have compiled it, see
+//! [`BuildError::MissingElf`].
+//!
+//! Cargo names the ELF after the binary target, in a subdirectory of the target directory which
+//! depends on whether the program is built with Docker. When either differs from what the build
+//! expects, the copy fails with the same "No such file or directory", so the error lists what
+//! cargo compiled instead.
+
+use cargo_metadata::{camino::Utf8Path, Metadata};
+
+use crate::{compiled_elf_path, BuildArgs, BuildError};
+
+/// The error of a build with `args` which did not find the ELF of the binary `bin_name` at
+/// `expected`.
+pub(crate) fn error(
+    args: &BuildArgs,
+    program_metadata: &Metadata,
+    bin_name: &str,
+    expected: &Utf8Path,
+) -> BuildError {
+    let release_dir = expected.parent().unwrap();
+    let mut found = files(release_dir, "");
+    found.extend(files(&release_dir.join("examples"), "examples/"));
+    let hint = build_kind_hint(args, program_metadata, bin_name)
+        .or_else(|| library_hint(program_metadata, &found))
+        .or_else(|| name_hint(program_metadata, bin_name, &found));
+    BuildError::MissingElf { expected: expected.to_path_buf(), found, hint }
+}
+
+/// The hint when the ELF was compiled by a build with Docker rather than a local one, or the
+/// other way around, which compile into other directories.
+fn build_kind_hint(
+    args: &BuildArgs,
+    program_metadata: &Metadata,
+    bin_name: &str,
+) -> Option<String> {
+    let other = BuildArgs { docker: !args.docker, ..args.clone() };
+    let other_path = compiled_elf_path(&other, program_metadata, bin_name);
+    other_path.is_file().then(|| {
+        format!(
+            "an ELF of a {} build exists at {}, check that `docker` is set as for the build \
+             which compiled it",
+            if args.docker { "local" } else { "Docker" },
+            other_path
+        )
+    })
+}
+
+/// The hint when the package has no binary target, which is only built with
+/// [`BuildArgs::allow_lib_build`], so that cargo compiled its library alone.
+fn library_hint(program_metadata: &Metadata, found: &[String]) -> Option<String> {
+    let package = program_metadata.root_package()?;
+    if package.targets.iter().any(|target| target.is_bin()) {
+        return None;
+    }
+    let rlibs: Vec<&str> =
+        found.iter().map(String::as_str).filter(|name| name.ends_with(".rlib")).collect();
+    Some(format!(
+        "the package `{}` has no binary target, so cargo only compiled its library{}, as \
+         `allow_lib_build` is set",
+        package.name,
+        if rlibs.is_empty() { String::new() } else { format!(" into {}", rlibs.join(", ")) }
+    ))
+}
+
+/// The hint when the binary target is named differently than the build expects, such as a package
+/// whose binary target is not named after it.
+fn name_hint(program_metadata: &Metadata, bin_name: &str, found: &[String]) -> Option<String> {
+    let binaries: Vec<&str> = program_metadata
+        .root_package()?
+        .targets
+        .iter()
+        .filter(|target| target.is_bin())
+        .map(|target| target.name.as_str())
+        .collect();
+    if !binaries.is_empty() && !binaries.contains(&bin_name) {
+        return Some(format!(
+            "the package has no binary target named `{}`, set `binary` to one of: {}",
+            bin_name,
+            binaries.join(", ")
+        ));
+    }
+    let executables: Vec<&str> =
+        found.iter().map(String::as_str).filter(|name| !name.contains('.')).collect();
+    (!executables.is_empty()).then(|| {
+        format!(
+            "cargo compiled {} rather than `{}`, the binary target may be named differently than \
+             the package, set `binary` to its name",
+            executables.join(", "),
+            bin_name
+        )
+    })
+}
+
+/// The names of the files in `dir`, prefixed with `prefix`, leaving out hidden files.
+fn files(dir: &Utf8Path, prefix: &str) -> Vec<String> {
+    let Ok(entries) = dir.read_dir_utf8() else {
+        return vec![];
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.file_name().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .map(|name| format!("{}{}", prefix, name))
+        .collect();
+    names.sort();
+    names
+}
+
+/// The files found instead of the ELF, for the message of [`BuildError::MissingElf`].
+pub(crate) fn found_list(found: &[String]) -> String {
+    if found.is_empty() {
+        "nothing".to_string()
+    } else {
+        found.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(program_dir.join("src/bin")).unwrap();
+        let manifest = "[workspace]\n[package]\nname = \"my-program\"\nversion = \"0.1.0\"\n";
+        fs::write(program_dir.join("Cargo.toml"), manifest).unwrap();
+        fs::write(program_dir.join("src/bin/prover.rs"), "fn main() {}").unwrap();
+        let metadata = crate::program_metadata(&program_dir).unwrap();
+        let args = BuildArgs::default();
+
+        let expected = compiled_elf_path(&args, &metadata, "my-program");
+        let err = error(&args, &metadata, "my-program", &expected);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "the compiled ELF is missing at {}, where cargo compiled nothing; the package has \
+                 no binary target named `my-program`, set `binary` to one of: prover",
+                expected
+            )
+        );
+
+        let release_dir = expected.parent().unwrap();
+        fs::create_dir_all(release_dir.join("examples")).unwrap();
+        for file in ["prover", "prover.d", ".cargo-lock", "examples/demo"] {
+            fs::write(release_dir.join(file), "").unwrap();
+        }
+        let BuildError::MissingElf { found, .. } = error(&args, &metadata, "prover", &expected)
+        else {
+            panic!("expected a missing ELF");
+        };
+        assert_eq!(found, vec!["prover", "prover.d", "examples/demo"]);
+        assert_eq!(
+            name_hint(&metadata, "prover", &["my_program".to_string()]).unwrap(),
+            "cargo compiled my_program rather than `prover`, the binary target may be named \
+             differently than the package, set `binary` to its name"
+        );
+
+        let docker = BuildArgs { docker: true, ..args };
+        let BuildError::MissingElf { hint, .. } = error(&docker, &metadata, "prover", &expected)
+        else {
+            panic!("expected a missing ELF");
+        };
+        assert!(hint.unwrap().starts_with("an ELF of a local build exists at"));
+    }
+}
@@ -0,0 +1,59 @@
+use std::{fmt, io::Write};
+
+use crate::CancellationToken;
+
+/// Options of a build which are not part of its configuration, see
+/// [`crate::build_program_with_options`].
+///
+/// Each sink receives the lines cargo prints to one of its streams, formatted as they would have
+/// been printed: prefixed with `[sp1]` in [`crate::OutputFormat::Text`], or as JSON lines in
+/// [`crate::OutputFormat::Json`]. The lines are still emitted as tracing events and appended to
+/// [`crate::BuildArgs::log_file`], but are no longer printed to the process's stdio. The crate's
+/// own messages, such as warnings and the paths of the ELFs, are printed as before.
+///
+/// Every line is written to its sink with a single `write_all` call including the trailing newline
+/// and flushed before the next line of either stream is written, so lines are never torn, even if
+/// both sinks write into the same buffer. The lines of each stream keep their order, while lines of
+/// different streams are interleaved in the order they are read from cargo's pipes.
+#[derive(Default)]
+pub struct BuildOptions<'a> {
+    /// Where the lines cargo prints to stdout are written. Printed to stdout if `None`.
+    pub stdout_sink: Option<Box<dyn Write + Send + 'a>>,
+    /// Where the lines cargo prints to stderr are written. Printed to stderr if `None`.
+    pub stderr_sink: Option<Box<dyn Write + Send + 'a>>,
+    /// Cancels the build when triggered, see [`CancellationToken`].
+    ///
+    /// On Unix, cargo runs in its own process group in a cancellable build, so that everything it
+    /// spawns can be killed. It then no longer receives the interrupt of Ctrl-C in a terminal.
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+impl<'a> BuildOptions<'a> {
+    /// Write the lines cargo prints to stdout to `sink`.
+    pub fn stdout_sink(mut self, sink: impl Write + Send + 'a) -> Self {
+        self.stdout_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Write the lines cargo prints to stderr to `sink`.
+    pub fn stderr_sink(mut self, sink: impl Write + Send + 'a) -> Self {
+        self.stderr_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Cancel the build when `token` is triggered.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+}
+
+impl fmt::Debug for BuildOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuildOptions")
+            .field("stdout_sink", &self.stdout_sink.is_some())
+            .field("stderr_sink", &self.stderr_sink.is_some())
+            .field("cancellation_token", &self.cancellation_token)
+            .finish()
+    }
+}
@@ -0,0 +1,109 @@
+//! Copying the ELFs into the `OUT_DIR` of the build script building the program, rather than into
+//! the source tree of the program.
+//!
+//! Cargo gives build scripts an `OUT_DIR` to write to. When the ELFs are copied there, the build
+//! prints a `cargo:rustc-env=SP1_ELF_<NAME>=<path>` directive for each, which sets the variable
+//! when compiling the crate of the build script:
+//!
+//! ```ignore
+//! const ELF: &[u8] = include_bytes!(env!("SP1_ELF_FIBONACCI_PROGRAM"));
+//! ```
+//!
+//! It also prints `cargo:rustc-env=SP1_ELF_<NAME>_SHA256=<hex>` with the SHA-256 digest of the
+//! copied ELF, so that a host which loads the ELF at runtime rather than embedding it can check
+//! that it is the one it was compiled against, see [`crate::verify_elf_sha256`]:
+//!
+//! ```ignore
+//! let elf = std::fs::read(env!("SP1_ELF_FIBONACCI_PROGRAM"))?;
+//! assert!(sp1_build::verify_elf_sha256(&elf, env!("SP1_ELF_FIBONACCI_PROGRAM_SHA256")));
+//! ```
+
+use std::env;
+
+use cargo_metadata::camino::Utf8PathBuf;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{BuildArgs, ElfArtifact};
+
+/// Where the ELFs of a build are copied to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ElfDestination {
+    /// The `OUT_DIR` of the build script building the program, if the build runs in one and
+    /// [`BuildArgs::output_directory`] is not set, and the output directory otherwise.
+    #[default]
+    Auto,
+    /// The `OUT_DIR` of the build script building the program, in a directory named after the
+    /// binary. Building outside of a build script fails.
+    OutDir,
+    /// [`BuildArgs::output_directory`].
+    OutputDirectory,
+}
+
+/// The `OUT_DIR` the ELFs of a build with `args` are copied to, if any.
+pub(crate) fn out_dir(args: &BuildArgs) -> Option<Utf8PathBuf> {
+    let out_dir = env::var("OUT_DIR").ok().filter(|dir| !dir.is_empty()).map(Utf8PathBuf::from);
+    match args.elf_destination {
+        ElfDestination::Auto if args.output_directory.is_empty() => out_dir,
+        ElfDestination::Auto | ElfDestination::OutputDirectory => None,
+        ElfDestination::OutDir => out_dir,
+    }
+}
+
+/// The variable set to the path of the ELF of `bin_name`, such as `SP1_ELF_FIBONACCI_PROGRAM`.
+pub(crate) fn elf_env_var(bin_name: &str) -> String {
+    let name: String = bin_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("SP1_ELF_{}", name)
+}
+
+/// The `cargo:rustc-env` directives for the `artifacts` built into the `OUT_DIR`, which are in a
+/// directory named after their binary: the path and the SHA-256 digest of each ELF.
+pub(crate) fn rustc_env(artifacts: &[ElfArtifact]) -> Vec<String> {
+    let mut directives = vec![];
+    for artifact in artifacts.iter().filter(|artifact| artifact.size.is_some()) {
+        if let Some(bin_name) = artifact.path.parent().and_then(|dir| dir.file_name()) {
+            let var = elf_env_var(bin_name);
+            directives.push(format!("cargo:rustc-env={}={}", var, artifact.path));
+            if let Some(sha256) = &artifact.sha256 {
+                directives.push(format!("cargo:rustc-env={}_SHA256={}", var, sha256));
+            }
+        }
+    }
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elf_env_var() {
+        assert_eq!(elf_env_var("fibonacci"), "SP1_ELF_FIBONACCI");
+        assert_eq!(elf_env_var("fibonacci-program"), "SP1_ELF_FIBONACCI_PROGRAM");
+        assert_eq!(elf_env_var("is_prime.v2"), "SP1_ELF_IS_PRIME_V2");
+    }
+
+    #[test]
+    fn test_rustc_env() {
+        let artifacts = [
+            ElfArtifact {
+                path: "/out/fibonacci/elf".into(),
+                size: Some(3),
+                sha256: Some("ab".to_string()),
+                cycles: None,
+            },
+            ElfArtifact::not_built("/out/dry-run/elf".into()),
+        ];
+        assert_eq!(
+            rustc_env(&artifacts),
+            vec![
+                "cargo:rustc-env=SP1_ELF_FIBONACCI=/out/fibonacci/elf",
+                "cargo:rustc-env=SP1_ELF_FIBONACCI_SHA256=ab",
+            ]
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Output of a build: the crate's own messages and the output of the cargo command.
+//!
+//! Every line is emitted as a [`tracing`] event with the `sp1_build` target. The output of cargo is
+//! emitted at the `info` level, except for the warnings and errors it prints, which use `warn` and
+//! `error`. Phase transitions and timings of the build are emitted at the `debug` level.
+//!
+//! When the `print` feature is enabled (the default) and no tracing subscriber is installed, the
+//! output is also printed to stdout and stderr with the `[sp1]` prefix. While a build runs with
+//! [`crate::BuildArgs::print_elf_path`], everything is printed to stderr instead, and stdout only
+//! receives the paths of the ELFs. With [`OutputFormat::Json`], the output is always printed, as
+//! JSON lines on stdout.
+//!
+//! Cargo hides what build scripts print unless it runs with `-vv`, and interprets the lines of
+//! their stdout starting with `cargo:` as directives. Within a build script, the warnings of this
+//! crate are therefore printed as `cargo:warning=` directives, which cargo always shows, and the
+//! other lines printed to stdout which would be mistaken for directives are prefixed with `[sp1]`.
+
+use std::{borrow::Cow, cell::Cell, io::Write};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{dispatcher, subscriber::NoSubscriber};
+
+use crate::{style, BuildArgs, BuildPhase, BuildWarning, ElfArtifact};
+
+/// The target of the tracing events emitted by this crate.
+pub const TRACING_TARGET: &str = "sp1_build";
+
+/// The width at which warnings are wrapped when printed as `cargo:warning=` directives.
+const WARNING_WIDTH: usize = 100;
+
+/// The format in which the output of a build is printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human readable lines with the `[sp1]` prefix.
+    #[default]
+    Text,
+    /// One JSON object per line on stdout.
+    ///
+    /// Every object has a `type` field, which determines its other fields:
+    ///
+    /// | `type`          | Fields                         | Emitted for                          |
+    /// |-----------------|--------------------------------|--------------------------------------|
+    /// | `cargo-stdout`  | `line`                         | each line cargo prints to stdout     |
+    /// | `cargo-stderr`  | `line`                         | each line cargo prints to stderr     |
+    /// | `cargo-message` | `message`, as printed by cargo | each `--message-format=json` message |
+    /// | `info`          | `message`                      | each message from this crate         |
+    /// | `warning`       | `message`                      | each warning from this crate         |
+    /// | `phase`         | `name`                         | the start of each phase of the build |
+    /// | `result`        | `elf_path`, `size`, `sha256`   | each ELF built                       |
+    /// | `summary`       | `message`, `program`, `status` | the end of the build of each ELF     |
+    /// | `error`         | `message`                      | the failure of the build             |
+    ///
+    /// Phases are named `resolve`, `pull-image`, `compile` and `copy`. The `size` and `sha256` of
+    /// a result are `null` in a dry run. A summary also has the `elf_path`, `size`, `sha256`,
+    /// `cycles` and `elapsed_secs` of the ELF, and its `status` is `built`, `fresh`, `skipped` or
+    /// `stale`. Builds of several ELFs end with a summary with `total` set, and their number of
+    /// `elfs`, total `size` and `elapsed_secs` instead. Cargo runs with `--message-format=json`,
+    /// and its messages are passed through as is rather than re-parsed.
+    /// Warnings with a [`crate::WarningKind`] also have their `kind` and `fields`, see
+    /// [`crate::BuildWarning`].
+    Json,
+}
+
+/// How the output of a build is printed on the current thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutputMode {
+    /// Print the output meant for stdout to stderr instead.
+    pub stdout_to_stderr: bool,
+    /// The format of the output.
+    pub format: OutputFormat,
+    /// Whether the messages of this crate are colored, or `None` if they are when printed to a
+    /// terminal, see [`crate::BuildArgs::color`].
+    pub color: Option<bool>,
+}
+
+impl OutputMode {
+    /// The mode to build with `args` in.
+    pub fn of(args: &BuildArgs) -> Self {
+        Self {
+            stdout_to_stderr: args.print_elf_path,
+            format: args.output_format,
+            color: style::choice(args),
+        }
+    }
+}
+
+thread_local! {
+    static MODE: Cell<OutputMode> = const {
+        Cell::new(OutputMode { stdout_to_stderr: false, format: OutputFormat::Text, color: None })
+    };
+}
+
+/// Restores the previous output mode of the current thread when dropped.
+pub struct ModeGuard(OutputMode);
+
+impl Drop for ModeGuard {
+    fn drop(&mut self) {
+        MODE.with(|mode| mode.set(self.0));
+    }
+}
+
+/// Use `mode` on the current thread until the returned guard is dropped.
+pub fn set_mode(mode: OutputMode) -> ModeGuard {
+    ModeGuard(MODE.with(|current| current.replace(mode)))
+}
+
+/// The output mode of the current thread.
+pub fn mode() -> OutputMode {
+    MODE.with(Cell::get)
+}
+
+/// Whether output should be printed, rather than only be emitted as tracing events.
+fn print_enabled() -> bool {
+    cfg!(feature = "print") && dispatcher::get_default(|dispatch| dispatch.is::<NoSubscriber>())
+}
+
+fn json_mode() -> bool {
+    mode().format == OutputFormat::Json
+}
+
+fn print_stdout(line: &str) {
+    if mode().stdout_to_stderr {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", escape_directives(line, crate::rerun::in_build_script()));
+    }
+}
+
+/// Print a message of this crate like [`print_stdout`], with its prefix colored as the current
+/// mode says. Lines of cargo are printed as they are, with [`print_stdout`].
+fn print_message(msg: &str) {
+    let color = mode().color;
+    if mode().stdout_to_stderr {
+        eprintln!("{}", style::info(msg, style::stderr(color)));
+    } else {
+        let msg = escape_directives(msg, crate::rerun::in_build_script());
+        println!("{}", style::info(&msg, style::stdout(color)));
+    }
+}
+
+fn print_json(value: Value) {
+    println!("{}", value);
+}
+
+/// Prefix the lines of `text` which cargo would interpret as directives of a build script with
+/// `[sp1]`, if `in_build_script`.
+fn escape_directives(text: &str, in_build_script: bool) -> Cow<'_, str> {
+    if !in_build_script || !text.lines().any(|line| line.starts_with("cargo:")) {
+        return Cow::Borrowed(text);
+    }
+    let lines: Vec<_> = text
+        .split('\n')
+        .map(|line| {
+            if line.starts_with("cargo:") {
+                Cow::Owned(format!("[sp1] {}", line))
+            } else {
+                Cow::Borrowed(line)
+            }
+        })
+        .collect();
+    Cow::Owned(lines.join("\n"))
+}
+
+/// The `cargo:warning=` directives which print the warning `msg` from a build script, one per
+/// line of the message, wrapped at [`WARNING_WIDTH`].
+fn warning_directives(msg: &str) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in format!("[sp1] {}", msg).lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            if !line.is_empty() && line.len() + 1 + word.len() > WARNING_WIDTH {
+                lines.push(std::mem::take(&mut line));
+            } else if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines.into_iter().map(|line| format!("cargo:warning={}", line)).collect()
+}
+
+/// The prefix of the lines printed by the cargo command: `[sp1]`, or `[sp1:<program>]` if the
+/// program is named, followed by `[docker]` or `:docker` if built with Docker.
+pub fn prefix(program_name: Option<&str>, docker: bool) -> String {
+    match (program_name, docker) {
+        (None, false) => "[sp1] ".to_string(),
+        (None, true) => "[sp1] [docker] ".to_string(),
+        (Some(name), false) => format!("[sp1:{}] ", name),
+        (Some(name), true) => format!("[sp1:{}:docker] ", name),
+    }
+}
+
+/// Write `line` to `sink` followed by a newline, at once so that it is not torn by other writers of
+/// the same sink. Errors are ignored, as they are when printing.
+fn write_line(sink: &mut (dyn Write + Send), line: &str) {
+    let _ = sink.write_all(format!("{}\n", line).as_bytes()).and_then(|()| sink.flush());
+}
+
+/// Emit a line written by the cargo command to stdout, writing it to `sink` instead of printing it
+/// if set.
+pub fn cargo_stdout(prefix: &str, line: &str, sink: Option<&mut (dyn Write + Send + '_)>) {
+    tracing::info!(target: TRACING_TARGET, "{}", line);
+    if let Some(sink) = sink {
+        if json_mode() {
+            write_line(sink, &json::cargo_stdout(line).to_string());
+        } else {
+            write_line(sink, &format!("{} {}", prefix, line));
+        }
+    } else if json_mode() {
+        print_json(json::cargo_stdout(line));
+    } else if print_enabled() {
+        print_stdout(&format!("{} {}", prefix, line));
+    }
+}
+
+/// Emit a line written by the cargo command to stderr, writing it to `sink` instead of printing it
+/// if set.
+pub fn cargo_stderr(prefix: &str, line: &str, sink: Option<&mut (dyn Write + Send + '_)>) {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("error") {
+        tracing::error!(target: TRACING_TARGET, "{}", line);
+    } else if trimmed.starts_with("warning") {
+        tracing::warn!(target: TRACING_TARGET, "{}", line);
+    } else {
+        tracing::info!(target: TRACING_TARGET, "{}", line);
+    }
+    if let Some(sink) = sink {
+        if json_mode() {
+            write_line(sink, &json::cargo_stderr(line).to_string());
+        } else {
+            write_line(sink, &format!("{} {}", prefix, line));
+        }
+    } else if json_mode() {
+        print_json(json::cargo_stderr(line));
+    } else if print_enabled() {
+        eprintln!("{} {}", prefix, line);
+    }
+}
+
+/// Emit a warning from this crate of a [`crate::WarningKind`], and collect it into the output of
+/// the build, see the `warning` module.
+pub(crate) fn warning(warning: BuildWarning) {
+    tracing::warn!(target: TRACING_TARGET, kind = ?warning.kind, "{}", warning.message);
+    if json_mode() {
+        print_json(json::warning(&warning));
+    } else {
+        print_warning(&warning.message);
+    }
+    crate::warning::record(warning);
+}
+
+fn print_warning(msg: &str) {
+    if print_enabled() {
+        if crate::rerun::in_build_script() {
+            for directive in warning_directives(msg) {
+                println!("{}", directive);
+            }
+        } else {
+            eprintln!("{}", style::warning(msg, style::stderr(mode().color)));
+        }
+    }
+}
+
+/// Emit an informational message from this crate, printed to stdout as is.
+pub fn info(msg: &str) {
+    tracing::info!(target: TRACING_TARGET, "{}", msg);
+    if json_mode() {
+        print_json(json::message("info", msg));
+    } else if print_enabled() {
+        print_message(msg);
+    }
+}
+
+/// Emit the summary `line` of a build, or its JSON `value` in JSON mode, see the `summary` module.
+pub(crate) fn summary(line: &str, value: Value) {
+    tracing::info!(target: TRACING_TARGET, "{}", line);
+    if json_mode() {
+        print_json(value);
+    } else if print_enabled() {
+        print_message(line);
+    }
+}
+
+/// Emit the start of a phase of the build.
+pub fn phase(phase: BuildPhase) {
+    tracing::debug!(target: TRACING_TARGET, phase = phase.name(), "starting phase");
+    if json_mode() {
+        print_json(json!({"type": "phase", "name": phase.name()}));
+    }
+}
+
+/// Emit an ELF produced by the build. Its path is printed to stdout regardless of the `print`
+/// feature if `print_path` is set, or as a `result` line in JSON mode.
+pub fn artifact(artifact: &ElfArtifact, print_path: bool) {
+    tracing::info!(target: TRACING_TARGET, elf_path = %artifact.path, "built ELF");
+    if json_mode() {
+        print_json(json::result(artifact));
+    } else if print_path {
+        println!("{}", escape_directives(artifact.path.as_str(), crate::rerun::in_build_script()));
+    }
+}
+
+/// Emit the error a build failed with.
+pub fn error(err: &anyhow::Error) {
+    tracing::error!(target: TRACING_TARGET, "{:#}", err);
+    if json_mode() {
+        print_json(json::message("error", &format!("{:#}", err)));
+    }
+}
+
+/// The JSON lines of [`OutputFormat::Json`].
+mod json {
+    use serde_json::{json, Value};
+
+    use crate::{BuildWarning, ElfArtifact};
+
+    pub(super) fn cargo_stdout(line: &str) -> Value {
+        // Cargo's own messages are passed through, as long as they parse.
+        match serde_json::from_str::<Value>(line) {
+            Ok(message @ Value::Object(_)) => json!({"type": "cargo-message", "message": message}),
+            _ => json!({"type": "cargo-stdout", "line": line}),
+        }
+    }
+
+    pub(super) fn cargo_stderr(line: &str) -> Value {
+        json!({"type": "cargo-stderr", "line": line})
+    }
+
+    pub(super) fn message(kind: &str, msg: &str) -> Value {
+        json!({"type": kind, "message": msg})
+    }
+
+    pub(super) fn warning(warning: &BuildWarning) -> Value {
+        json!({
+            "type": "warning",
+            "message": warning.message,
+            "kind": warning.kind,
+            "fields": warning.fields,
+        })
+    }
+
+    pub(super) fn result(artifact: &ElfArtifact) -> Value {
+        json!({
+            "type": "result",
+            "elf_path": artifact.path,
+            "size": artifact.size,
+            "sha256": artifact.sha256,
+            "cycles": artifact.cycles,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_only_without_subscriber() {
+        assert_eq!(print_enabled(), cfg!(feature = "print"));
+        let subscriber = tracing_subscriber::fmt().with_test_writer().finish();
+        tracing::subscriber::with_default(subscriber, || assert!(!print_enabled()));
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix(None, false), "[sp1] ");
+        assert_eq!(prefix(None, true), "[sp1] [docker] ");
+        assert_eq!(prefix(Some("fibonacci"), false), "[sp1:fibonacci] ");
+        assert_eq!(prefix(Some("fibonacci/fib"), true), "[sp1:fibonacci/fib:docker] ");
+    }
+
+    #[test]
+    fn test_set_mode() {
+        let json = OutputMode { stdout_to_stderr: true, format: OutputFormat::Json, color: None };
+        assert_eq!(mode(), OutputMode::default());
+        {
+            let _guard = set_mode(json);
+            assert!(json_mode());
+            let _inner = set_mode(OutputMode::default());
+            assert!(!json_mode());
+        }
+        assert_eq!(mode(), OutputMode::default());
+    }
+
+    #[test]
+    fn test_build_script_directives() {
+        // The output of cargo and the messages of this crate are never taken for directives.
+        let stdout = format!("{} {}", prefix(None, false), "cargo:rustc-link-lib=evil");
+        assert_eq!(escape_directives(&stdout, true), stdout);
+        let text = "explained:\ncargo:rerun-if-changed=src\ncargo::rustc-cfg=x\n  cargo:indented";
+        assert_eq!(
+            escape_directives(text, true),
+            "explained:\n[sp1] cargo:rerun-if-changed=src\n[sp1] cargo::rustc-cfg=x\n  cargo:indented"
+        );
+        assert_eq!(escape_directives(text, false), text);
+
+        let warning = format!("first line\n{}", ["word"; 30].join(" "));
+        let directives = warning_directives(&warning);
+        assert_eq!(directives[0], "cargo:warning=[sp1] first line");
+        assert_eq!(directives.len(), 3);
+        for directive in &directives {
+            assert!(directive.starts_with("cargo:warning="));
+            assert!(!directive.contains('\n'));
+            assert!(directive.len() <= "cargo:warning=".len() + WARNING_WIDTH);
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let artifact = ElfArtifact {
+            path: "/tmp/my \"program\"/elf".into(),
+            size: Some(3),
+            sha256: Some("abc".to_string()),
+            cycles: None,
+        };
+        let lines = [
+            json::cargo_stdout("plain \"output\" \x1b[1m"),
+            json::cargo_stdout(r#"{"reason":"build-finished","success":true}"#),
+            json::cargo_stderr("   Compiling libc v0.2.155\t"),
+            json::message("warning", "line\nbreak"),
+            json::result(&artifact),
+            json::result(&ElfArtifact::not_built("/tmp/elf".into())),
+        ];
+        let types =
+            ["cargo-stdout", "cargo-message", "cargo-stderr", "warning", "result", "result"];
+        for (line, ty) in lines.iter().zip(types) {
+            let printed = line.to_string();
+            assert!(!printed.contains('\n'));
+            let parsed: Value = serde_json::from_str(&printed).unwrap();
+            assert_eq!(parsed["type"], ty);
+        }
+        assert_eq!(lines[1]["message"]["reason"], "build-finished");
+        assert_eq!(lines[4]["elf_path"], "/tmp/my \"program\"/elf");
+        assert_eq!(lines[5]["sha256"], Value::Null);
+    }
+}
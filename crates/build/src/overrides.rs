@@ -0,0 +1,277 @@
+//! Configuration of [`BuildArgs`] from `SP1_BUILD_*` environment variables.
+//!
+//! Each field of [`BuildArgs`] is read from the variable named after it, such as
+//! `SP1_BUILD_ELF_NAME` for `elf_name`; [`crate::build_env_vars`] lists them all. Values are parsed
+//! according to the type of the field:
+//!
+//! | Type    | Value                                                                         |
+//! |---------|-------------------------------------------------------------------------------|
+//! | boolean | `1`/`true`/`yes`/`on` or `0`/`false`/`no`/`off`, case-insensitively           |
+//! | string  | the value, trimmed                                                            |
+//! | list    | comma separated, except the lists of flags and commands, such as              |
+//! |         | `SP1_BUILD_RUSTFLAGS` and `SP1_BUILD_POST_BUILD_HOOKS`, which are `\x1f`      |
+//! |         | separated                                                                     |
+//!
+//! `tag` is also read from `SP1_BUILD_DOCKER_TAG`, if `SP1_BUILD_TAG` is not set, and `cargo_home`
+//! from `SP1_CARGO_HOME`, if `SP1_BUILD_CARGO_HOME` is not set. Variables which
+//! are unset or empty are ignored. The environment takes precedence over the
+//! configuration file and the package metadata, but not over the arguments passed explicitly in
+//! code; see [`crate::ResolvedBuildArgs`]. A build can opt out of the environment with
+//! [`BuildArgs::ignore_env`].
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use crate::{
+    config::{self, Layer},
+    BuildArgs, BuildWarning, ConfigSource, ResolvedBuildArgs, WarningKind,
+};
+
+/// Other names of the variables of some fields, which the variable named after the field takes
+/// precedence over.
+const ENV_ALIASES: [(&str, &str); 3] = [
+    ("tag", "SP1_BUILD_DOCKER_TAG"),
+    ("generate_lockfile", "SP1_BUILD_GENERATE_LOCKFILE_IF_MISSING"),
+    ("cargo_home", "SP1_CARGO_HOME"),
+];
+
+/// The names of all the environment variables which configure [`BuildArgs`].
+pub fn build_env_vars() -> Vec<String> {
+    config::default_layer().keys().flat_map(|field| env_var_names(field)).collect()
+}
+
+/// The names of the variables which configure `field`, from the highest precedence.
+fn env_var_names(field: &str) -> Vec<String> {
+    let aliases = ENV_ALIASES.iter().filter(|(aliased, _)| *aliased == field);
+    std::iter::once(env_var_name(field)).chain(aliases.map(|(_, name)| name.to_string())).collect()
+}
+
+/// The boolean `field` of `args` before the configuration of the program is resolved, such as when
+/// reading its metadata: `value`, the field in `args`, if set, or its variable in the environment
+/// unless `args` ignore it. As the configuration files are read along with the metadata, they
+/// cannot set it.
+pub(crate) fn flag_before_config(args: &BuildArgs, value: bool, field: &str) -> Result<bool> {
+    if value || args.ignore_env_overrides {
+        return Ok(value);
+    }
+    match env_var(field, &|name| std::env::var(name).ok()) {
+        Some((name, value)) => parse_bool(&name, &value),
+        None => Ok(false),
+    }
+}
+
+/// The variable `lookup` finds for `field`, with its non-empty value, if any.
+fn env_var(field: &str, lookup: &impl Fn(&str) -> Option<String>) -> Option<(String, String)> {
+    env_var_names(field).into_iter().find_map(|name| {
+        let value = lookup(&name).filter(|value| !value.trim().is_empty())?;
+        Some((name, value))
+    })
+}
+
+/// The name of the environment variable which configures `field`.
+pub(crate) fn env_var_name(field: &str) -> String {
+    format!("SP1_BUILD_{}", field.to_ascii_uppercase())
+}
+
+impl BuildArgs {
+    /// Ignore the `SP1_BUILD_*` environment variables when building with these arguments.
+    pub fn ignore_env(mut self) -> Self {
+        self.ignore_env_overrides = true;
+        self
+    }
+}
+
+/// The variables which skip building programs when set to true, see [`crate::BuildError::Skipped`].
+pub(crate) const SKIP_ENVS: [&str; 2] = ["SP1_SKIP_PROGRAM_BUILD", "SP1_BUILD_SKIP"];
+
+/// Whether building programs is skipped by one of the [`SKIP_ENVS`] found by `lookup`, failing if
+/// one is set to something other than a boolean.
+pub(crate) fn skip_build(lookup: impl Fn(&str) -> Option<String>) -> Result<bool> {
+    for name in SKIP_ENVS {
+        let Some(value) = lookup(name).filter(|value| !value.trim().is_empty()) else {
+            continue;
+        };
+        if parse_bool(name, &value)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Read the layer of configuration from the variables found by `lookup`.
+pub(crate) fn env_layer(lookup: impl Fn(&str) -> Option<String>) -> Result<Layer> {
+    let mut layer = Layer::new();
+    for (field, default) in config::default_layer() {
+        let Some((name, value)) = env_var(&field, &lookup) else {
+            continue;
+        };
+        let value = match default {
+            Value::Bool(_) => Value::Bool(parse_bool(&name, &value)?),
+            // An optional boolean, such as `incremental`.
+            Value::Null if config::is_valid_value(&field, &Value::Bool(true)) => {
+                Value::Bool(parse_bool(&name, &value)?)
+            }
+            Value::Array(_) => {
+                let flags = matches!(
+                    field.as_str(),
+                    "rustflags" | "c_flags" | "cxx_flags" | "pre_build_hooks" | "post_build_hooks"
+                );
+                let separator = if flags { '\x1f' } else { ',' };
+                value
+                    .split(separator)
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect()
+            }
+            _ => Value::String(value.trim().to_string()),
+        };
+        if !config::is_valid_value(&field, &value) {
+            bail!("invalid value `{}` for {}", value, name);
+        }
+        layer.insert(field, value);
+    }
+    Ok(layer)
+}
+
+/// Describe the variables found by `lookup` which set a field of `resolved`, or which are ignored
+/// because the field was passed explicitly, one per variable. Build scripts print them as warnings,
+/// so that a build configured by the environment, such as with Docker in CI, is visible in logs.
+pub(crate) fn env_notices(
+    resolved: &ResolvedBuildArgs,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<BuildWarning> {
+    let values = config::to_layer(resolved.args());
+    let mut notices = vec![];
+    for (field, value) in &values {
+        let Some((name, _)) = env_var(field, &lookup) else {
+            continue;
+        };
+        let sources = resolved.sources(field);
+        let notice = if sources.contains(&ConfigSource::Env) {
+            format!("{} sets `{}` to {}", name, field, value)
+        } else if sources.contains(&ConfigSource::Explicit) {
+            format!("{} is ignored, as `{}` is set explicitly", name, field)
+        } else {
+            continue;
+        };
+        notices.push(
+            BuildWarning::new(WarningKind::EnvOverride, notice)
+                .field("variable", name)
+                .field("field", field),
+        );
+    }
+    notices
+}
+
+/// Parse the boolean `value` of the variable `name`, such as `1`, `true`, `off` or `no`, as the
+/// `SP1_BUILD_*` variables are.
+pub fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => bail!("invalid value `{}` for {}: expected a boolean such as `1` or `0`", value, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn layer(vars: &[(&str, &str)]) -> Result<Layer> {
+        let vars: HashMap<String, String> =
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        env_layer(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_env_layer() {
+        let layer = layer(&[
+            ("SP1_BUILD_DOCKER", "1"),
+            ("SP1_BUILD_TAG", " v3.0.0 "),
+            ("SP1_BUILD_FEATURES", "a, b"),
+            ("SP1_BUILD_LOCKED", "FALSE"),
+            ("SP1_BUILD_ELF_NAME", ""),
+            ("SP1_BUILD_RUSTFLAGS", "-C\x1fopt-level=3"),
+            ("SP1_BUILD_INCREMENTAL", "true"),
+        ])
+        .unwrap();
+        assert_eq!(layer.get("docker"), Some(&json!(true)));
+        assert_eq!(layer.get("locked"), Some(&json!(false)));
+        assert_eq!(layer.get("tag"), Some(&json!("v3.0.0")));
+        assert_eq!(layer.get("features"), Some(&json!(["a", "b"])));
+        assert_eq!(layer.get("rustflags"), Some(&json!(["-C", "opt-level=3"])));
+        assert_eq!(layer.get("incremental"), Some(&json!(true)));
+        assert!(!layer.contains_key("elf_name"));
+    }
+
+    #[test]
+    fn test_env_aliases() {
+        let aliased = layer(&[("SP1_BUILD_DOCKER_TAG", "v2.0.0")]).unwrap();
+        assert_eq!(aliased.get("tag"), Some(&json!("v2.0.0")));
+        let both = layer(&[("SP1_BUILD_DOCKER_TAG", "v2.0.0"), ("SP1_BUILD_TAG", "v3.0.0")]);
+        assert_eq!(both.unwrap().get("tag"), Some(&json!("v3.0.0")));
+        assert!(build_env_vars().contains(&"SP1_BUILD_DOCKER_TAG".to_string()));
+    }
+
+    #[test]
+    fn test_env_notices() {
+        let vars: HashMap<String, String> =
+            [("SP1_BUILD_DOCKER", "1"), ("SP1_BUILD_DOCKER_TAG", "v2.0.0")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+        let lookup = |name: &str| vars.get(name).cloned();
+        let explicit = BuildArgs { tag: "v4.0.0".to_string(), ..Default::default() };
+        let resolved = config::resolve_layers(vec![
+            (ConfigSource::Env, env_layer(lookup).unwrap()),
+            (ConfigSource::Explicit, config::explicit_layer(&explicit)),
+        ])
+        .unwrap();
+        assert!(resolved.args().docker);
+        assert_eq!(resolved.args().tag, "v4.0.0");
+        let notices = env_notices(&resolved, lookup);
+        assert_eq!(notices[1].fields["variable"], "SP1_BUILD_DOCKER_TAG");
+        assert_eq!(notices[1].fields["field"], "tag");
+        assert_eq!(
+            notices.into_iter().map(|notice| notice.message).collect::<Vec<_>>(),
+            vec![
+                "SP1_BUILD_DOCKER sets `docker` to true",
+                "SP1_BUILD_DOCKER_TAG is ignored, as `tag` is set explicitly",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_env_invalid_bool() {
+        let err = layer(&[("SP1_BUILD_DOCKER", "maybe")]).unwrap_err();
+        assert!(err.to_string().contains("SP1_BUILD_DOCKER"));
+    }
+
+    #[test]
+    fn test_skip_build() {
+        let skip = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> =
+                vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            skip_build(|name| vars.get(name).cloned())
+        };
+        assert!(skip(&[("SP1_SKIP_PROGRAM_BUILD", "true")]).unwrap());
+        assert!(skip(&[("SP1_BUILD_SKIP", "1")]).unwrap());
+        assert!(!skip(&[("SP1_SKIP_PROGRAM_BUILD", "0"), ("SP1_BUILD_SKIP", "")]).unwrap());
+        assert!(!skip(&[]).unwrap());
+        let err = skip(&[("SP1_SKIP_PROGRAM_BUILD", "maybe")]).unwrap_err();
+        assert!(err.to_string().contains("invalid value `maybe` for SP1_SKIP_PROGRAM_BUILD"));
+    }
+
+    #[test]
+    fn test_build_env_vars() {
+        let vars = build_env_vars();
+        assert!(vars.contains(&"SP1_BUILD_NO_DEFAULT_FEATURES".to_string()));
+        assert!(vars.contains(&"SP1_BUILD_DRY_RUN".to_string()));
+        assert!(!vars.contains(&"SP1_BUILD_IGNORE_ENV_OVERRIDES".to_string()));
+    }
+}
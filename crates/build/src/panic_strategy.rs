@@ -0,0 +1,84 @@
+//! The panic strategy of programs, which must be `abort` in the zkVM.
+//!
+//! The build passes `-C panic=abort` to rustc, which overrides the `panic` setting of the release
+//! profile for the crates of the program. A program whose profile sets `panic = "unwind"`, or which
+//! depends on a crate requiring unwinding, may still link the unwinding runtime with crates
+//! compiled to abort, which fails with errors about `rust_eh_personality` or the panic runtime
+//! rather than about the profile. The setting is found before building, and such errors explained
+//! after.
+
+use cargo_metadata::Metadata;
+
+/// The explanation added to the error of a build which failed because of the panic strategy.
+pub(crate) const MISMATCH_HELP: &str =
+    "the program links a panic runtime which unwinds, while the zkVM requires `-C panic=abort`: \
+     remove `panic = \"unwind\"` from the release profile in the Cargo.toml of the workspace of \
+     the program, or set it to \"abort\", and remove dependencies which require unwinding";
+
+/// The variable setting the panic strategy of the release profile, which a local build inherits.
+const PROFILE_ENV: &str = "CARGO_PROFILE_RELEASE_PANIC";
+
+/// Where the panic strategy of the release profile the program is built with is set to something
+/// other than `abort`, with the value: the manifest of the workspace, or the environment of a
+/// local build found by `lookup`.
+pub(crate) fn profile_setting(
+    program_metadata: &Metadata,
+    docker: bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<(String, String)> {
+    if let Some(value) = lookup(PROFILE_ENV).filter(|value| !docker && value != "abort") {
+        return Some((PROFILE_ENV.to_string(), value));
+    }
+    let manifest_path = program_metadata.workspace_root.join("Cargo.toml");
+    let manifest: toml::Table =
+        toml::from_str(&std::fs::read_to_string(&manifest_path).ok()?).ok()?;
+    let value = manifest.get("profile")?.get("release")?.get("panic")?.as_str()?;
+    (value != "abort").then(|| (manifest_path.to_string(), value.to_string()))
+}
+
+/// Whether `line`, printed by cargo to stderr, is an error of a build linking panic runtimes of
+/// different strategies.
+pub(crate) fn is_mismatch(line: &str) -> bool {
+    line.contains("rust_eh_personality") ||
+        line.contains("panic strategy") ||
+        (line.contains("panic_unwind") && line.contains("error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_profile_setting() {
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let metadata = |name: &str| {
+            let program_dir = crate::resolve_program_dir(&fixtures.join(name)).unwrap();
+            crate::program_metadata(&program_dir).unwrap()
+        };
+        let unwind = metadata("panic-unwind");
+        let (origin, value) = profile_setting(&unwind, false, |_| None).unwrap();
+        assert!(origin.ends_with("panic-unwind/Cargo.toml"));
+        assert_eq!(value, "unwind");
+
+        let no_std = metadata("no-std");
+        assert_eq!(profile_setting(&no_std, false, |_| None), None);
+        let env = |name: &str| (name == PROFILE_ENV).then(|| "unwind".to_string());
+        assert_eq!(
+            profile_setting(&no_std, false, env),
+            Some((PROFILE_ENV.to_string(), "unwind".to_string()))
+        );
+        assert_eq!(profile_setting(&no_std, true, env), None);
+    }
+
+    #[test]
+    fn test_is_mismatch() {
+        assert!(is_mismatch("error: undefined symbol: rust_eh_personality"));
+        assert!(is_mismatch(
+            "error: the linked panic runtime `panic_unwind` is not compiled with this crate's \
+             panic strategy `abort`"
+        ));
+        assert!(!is_mismatch("error[E0425]: cannot find value `x` in this scope"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{HashMap, HashSet},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use cargo_metadata::camino::Utf8PathBuf;
+use jobserver::Client;
+
+use crate::{
+    build_program_from_metadata, check_program_dir, claim_elf_paths, config, events::EventSender,
+    helper_target_dir, jobs, metadata_cache, output, resolve_program_dir, summary, BuildArgs,
+    BuildError, BuildOptions,
+};
+
+/// Build several programs concurrently, using at most `parallelism` worker threads.
+///
+/// Each program is built as with [`crate::build_program_in`], with its output prefixed by the name
+/// of the program's package, as with [`BuildArgs::package_prefix`]. Each program compiles into a
+/// subdirectory of its target directory named after its package, see [`BuildArgs::target_subdir`],
+/// so that the programs of one workspace build concurrently. Programs which still compile into the
+/// same target directory, with [`BuildArgs::shared_target`], are built one at a time, as cargo
+/// would otherwise serialize them on its build directory lock. A failure in one program does not
+/// stop the others from building, see [`build_programs_fail_fast`] for that.
+///
+/// In a build script, each build beyond the first waits for a token of the jobserver of the cargo
+/// running it, if any, which the nested cargo of each build takes its jobs from, and the
+/// `cargo:rerun-if-*` and `cargo:rustc-env` directives of all the programs are printed once they
+/// are built, without duplicates.
+///
+/// # Returns
+///
+/// * `Vec<Result<Utf8PathBuf>>` - The path to each built program (or the error it failed with), in
+///   the same order as `args_list`.
+pub fn build_programs(
+    args_list: Vec<(BuildArgs, PathBuf)>,
+    parallelism: usize,
+) -> Vec<Result<Utf8PathBuf>> {
+    build_programs_internal(args_list, parallelism, false)
+}
+
+/// Build several programs concurrently like [`build_programs`], but stop starting new builds once
+/// one of them fails. Builds already in progress are run to completion, and the programs that were
+/// never started are reported as skipped.
+pub fn build_programs_fail_fast(
+    args_list: Vec<(BuildArgs, PathBuf)>,
+    parallelism: usize,
+) -> Vec<Result<Utf8PathBuf>> {
+    build_programs_internal(args_list, parallelism, true)
+}
+
+fn build_programs_internal(
+    args_list: Vec<(BuildArgs, PathBuf)>,
+    parallelism: usize,
+    fail_fast: bool,
+) -> Vec<Result<Utf8PathBuf>> {
+    // Builds sharing a target directory hold the same lock for the duration of the build.
+    let target_dir_locks: Mutex<HashMap<Utf8PathBuf, Arc<Mutex<()>>>> = Mutex::default();
+    let start = Instant::now();
+    let mode = args_list.first().map(|(args, _)| output::OutputMode::of(args)).unwrap_or_default();
+    // Programs sharing a default ELF path are named in the order they are listed, rather than in
+    // the order they finish in.
+    for (args, program_dir) in &args_list {
+        let _ = claim_elf_paths(args, program_dir);
+    }
+
+    let results = run_bounded(args_list, parallelism, fail_fast, jobs::host_jobserver(), |job| {
+        let (args, program_dir) = job;
+        let _mode = output::set_mode(output::OutputMode::of(&args));
+        let metadata_start = Instant::now();
+        check_program_dir(&program_dir)?;
+        let program_dir = resolve_program_dir(&program_dir)?;
+        let program_metadata = metadata_cache::metadata(&args, &program_dir)?;
+        let metadata_elapsed = metadata_start.elapsed();
+
+        // The arguments are resolved against the configuration by the build, and only here to
+        // find the target directory the program compiles into.
+        let mut resolved = config::resolve(&args, &program_dir, &program_metadata)
+            .map_err(|err| BuildError::Config(format!("{:#}", err)))?
+            .into_args();
+        let mut args = BuildArgs { package_prefix: true, ..args };
+        if !resolved.shared_target {
+            let package = program_metadata.root_package().map(|package| package.name.clone());
+            resolved.target_subdir.clone_from(&package);
+            args.target_subdir = package;
+        }
+        let lock = target_dir_locks
+            .lock()
+            .unwrap()
+            .entry(helper_target_dir(&resolved, &program_metadata))
+            .or_default()
+            .clone();
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut directives = vec![];
+        let output = build_program_from_metadata(
+            &args,
+            &program_dir,
+            &program_metadata,
+            metadata_elapsed,
+            &EventSender::default(),
+            &mut BuildOptions::default(),
+            Some(&mut directives),
+        )?;
+        Ok((output.try_elf_path()?.to_path_buf(), directives, output.artifacts))
+    });
+
+    let mut printed = HashSet::new();
+    for (_, directives, _) in results.iter().flatten() {
+        for directive in directives.iter().filter(|directive| printed.insert(*directive)) {
+            println!("{}", directive);
+        }
+    }
+    let built: Vec<_> = results
+        .iter()
+        .flatten()
+        .flat_map(|(_, _, artifacts)| artifacts)
+        .filter(|artifact| artifact.size.is_some())
+        .collect();
+    if results.len() > 1 && !built.is_empty() {
+        let _mode = output::set_mode(mode);
+        summary::print_total(&built, start.elapsed());
+    }
+    results.into_iter().map(|result| result.map(|(elf_path, _, _)| elf_path)).collect()
+}
+
+/// Run `job` on every input using a pool of at most `parallelism` threads, returning the results
+/// in input order. Panics inside a job are reported as errors for that input. If `fail_fast` is
+/// set, inputs which have not been started when a job fails are skipped. With a `jobserver`, every
+/// thread but the first holds one of its tokens while running a job.
+fn run_bounded<I, T, F>(
+    inputs: Vec<I>,
+    parallelism: usize,
+    fail_fast: bool,
+    jobserver: Option<&Client>,
+    job: F,
+) -> Vec<Result<T>>
+where
+    I: Send,
+    T: Send,
+    F: Fn(I) -> Result<T> + Sync,
+{
+    let num_inputs = inputs.len();
+    let inputs: Vec<Mutex<Option<I>>> = inputs.into_iter().map(|i| Mutex::new(Some(i))).collect();
+    let results: Vec<Mutex<Option<Result<T>>>> =
+        (0..num_inputs).map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        for worker in 0..parallelism.clamp(1, num_inputs.max(1)) {
+            let (next, failed, inputs, results, job) = (&next, &failed, &inputs, &results, &job);
+            s.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= num_inputs {
+                    break;
+                }
+                // Without a token, the job runs anyway rather than failing the build.
+                let _token =
+                    jobserver.filter(|_| worker > 0).and_then(|client| client.acquire().ok());
+                let input = inputs[index].lock().unwrap().take().unwrap();
+                let result = if fail_fast && failed.load(Ordering::SeqCst) {
+                    Err(anyhow!("build skipped because another program failed to build"))
+                } else {
+                    panic::catch_unwind(AssertUnwindSafe(|| job(input))).unwrap_or_else(|e| {
+                        let msg = e
+                            .downcast_ref::<&str>()
+                            .map(|s| s.to_string())
+                            .or_else(|| e.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "unknown panic".to_string());
+                        Err(anyhow!("build panicked: {}", msg))
+                    })
+                };
+                if result.is_err() {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                *results[index].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    results.into_iter().map(|r| r.into_inner().unwrap().unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::sleep, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_run_bounded_preserves_order() {
+        let inputs: Vec<u64> = (0..16).collect();
+        let results = run_bounded(inputs, 4, false, None, |i| {
+            // Finish later inputs first.
+            sleep(Duration::from_millis(32 - 2 * i));
+            Ok(i * 2)
+        });
+        let results: Vec<u64> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results, (0..16).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_run_bounded_limits_parallelism() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        run_bounded((0..12).collect(), 3, false, None, |_: usize| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        });
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_run_bounded_jobserver() {
+        // One token besides the implicit one of the first thread.
+        let client = Client::new(1).unwrap();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        run_bounded((0..12).collect(), 4, false, Some(&client), |_: usize| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        });
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_run_bounded_failures() {
+        let job = |i: usize| match i {
+            1 => Err(anyhow!("failed")),
+            2 => panic!("panicked"),
+            _ => Ok(i),
+        };
+
+        // Without fail-fast, every input is attempted.
+        let results = run_bounded((0..5).collect(), 1, false, None, job);
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 3);
+        assert!(results[2].as_ref().unwrap_err().to_string().contains("panicked"));
+
+        // With fail-fast and a single worker, everything after the first failure is skipped.
+        let results = run_bounded((0..5).collect(), 1, true, None, job);
+        assert_eq!(*results[0].as_ref().unwrap(), 0);
+        assert_eq!(results[1].as_ref().unwrap_err().to_string(), "failed");
+        for result in &results[2..] {
+            assert!(result.as_ref().unwrap_err().to_string().contains("skipped"));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! The SP1 crates of a program replaced by a `[patch]` section, see [`PatchedCrate`].
+//!
+//! A `[patch.crates-io]` section of the workspace of a program, such as one pointing `sp1-zkvm` to
+//! a local checkout of SP1, applies to every build of the program. A program built against a
+//! patched SP1 crate has another verification key than one built against the release, so the
+//! build warns about each one, or fails with [`crate::BuildArgs::deny_patched_sp1`].
+
+use std::{collections::BTreeSet, fs};
+
+use cargo_metadata::Metadata;
+use serde::{Deserialize, Serialize};
+
+/// An SP1 crate the program resolved to a patched source, listed in
+/// [`crate::BuildOutput::patched_sp1_crates`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchedCrate {
+    /// The name of the crate, such as `sp1-zkvm`.
+    pub name: String,
+    /// The version of the patched crate.
+    pub version: String,
+    /// Where the patch points: the directory of the crate, or its git repository.
+    pub source: String,
+}
+
+/// The SP1 crates, named `sp1-*`, which a `[patch]` section of the manifest or the cargo
+/// configuration of the workspace of the program replaces, and the program depends on.
+pub(crate) fn patched_sp1_crates(program_metadata: &Metadata) -> Vec<PatchedCrate> {
+    let root = &program_metadata.workspace_root;
+    let patched: BTreeSet<String> =
+        [root.join("Cargo.toml"), root.join(".cargo/config.toml"), root.join(".cargo/config")]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .filter_map(|contents| contents.parse::<toml::Table>().ok())
+            .flat_map(|table| patched_names(&table))
+            .filter(|name| name.starts_with("sp1-"))
+            .collect();
+
+    let mut crates: Vec<PatchedCrate> = program_metadata
+        .packages
+        .iter()
+        .filter(|package| patched.contains(&package.name))
+        .filter(|package| !package.source.as_ref().is_some_and(|source| source.is_crates_io()))
+        .map(|package| PatchedCrate {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            source: match &package.source {
+                Some(source) => source.repr.clone(),
+                None => package.manifest_path.parent().unwrap().to_string(),
+            },
+        })
+        .collect();
+    crates.sort_by(|a, b| a.name.cmp(&b.name));
+    crates
+}
+
+/// The names of the crates patched by the `[patch.<registry>]` sections of `table`, by their key
+/// or the `package` they rename.
+fn patched_names(table: &toml::Table) -> Vec<String> {
+    let registries = table.get("patch").and_then(|patch| patch.as_table());
+    registries
+        .into_iter()
+        .flat_map(|registries| registries.values())
+        .filter_map(|registry| registry.as_table())
+        .flat_map(|registry| registry.iter())
+        .map(|(key, value)| {
+            let package = value.get("package").and_then(|package| package.as_str());
+            package.unwrap_or(key).to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{BuildArgs, ValidationError};
+
+    #[test]
+    fn test_patched_sp1_crates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let package = |name: &str, manifest: &str| {
+            fs::create_dir_all(root.join(name).join("src")).unwrap();
+            fs::write(root.join(name).join("src/lib.rs"), "").unwrap();
+            fs::write(
+                root.join(name).join("Cargo.toml"),
+                format!("[package]\nname = \"{}\"\nversion = \"1.1.0\"\n{}", name, manifest),
+            )
+            .unwrap();
+        };
+        package("sp1-zkvm", "[workspace]\n");
+        package("local-lib", "[workspace]\n");
+        // Patches of crates other than SP1's are not reported.
+        package(
+            "program",
+            "[workspace]\n\n[dependencies]\nsp1-zkvm = \"1.1.0\"\nlocal-lib = \"1.1.0\"\n\n\
+             [patch.crates-io]\nsp1-zkvm = { path = \"../sp1-zkvm\" }\n\
+             local-lib = { path = \"../local-lib\" }\n",
+        );
+        let metadata = crate::program_metadata(&root.join("program")).unwrap();
+        assert_eq!(
+            patched_sp1_crates(&metadata),
+            vec![PatchedCrate {
+                name: "sp1-zkvm".to_string(),
+                version: "1.1.0".to_string(),
+                source: root.join("sp1-zkvm").to_string(),
+            }]
+        );
+
+        let deny = BuildArgs { deny_patched_sp1: true, dry_run: true, ..Default::default() };
+        let errors = deny.ignore_env().validate(root.join("program").as_std_path()).unwrap_err();
+        assert!(errors.iter().any(|error| matches!(error, ValidationError::PatchedSp1Crates(_))));
+    }
+}
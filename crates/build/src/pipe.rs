@@ -0,0 +1,109 @@
+//! Reading the lines cargo prints to stdout and stderr, see [`read_lines`].
+
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    sync::mpsc,
+    thread,
+};
+
+/// The stream a line was printed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Read the lines of `stdout` and `stderr` concurrently, calling `on_line` on the calling thread
+/// with each line in the order the lines are read, until both streams are closed.
+///
+/// Each stream is read by a thread of its own, so that neither pipe fills up while the other is
+/// read, which would block the process writing to it. The lines are read as bytes and decoded
+/// lossily, as build scripts may print anything, such as binary data, and the trailing `\n` or
+/// `\r\n` is removed. Neither reading nor decoding panics, so that the process can always be
+/// waited on for its exit status.
+pub(crate) fn read_lines(
+    stdout: impl Read + Send,
+    stderr: impl Read + Send,
+    mut on_line: impl FnMut(Stream, &str),
+) {
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        for (stream, reader) in [
+            (Stream::Stdout, Box::new(stdout) as Box<dyn Read + Send>),
+            (Stream::Stderr, Box::new(stderr)),
+        ] {
+            let sender = sender.clone();
+            s.spawn(move || {
+                let mut reader = BufReader::new(reader);
+                let mut buf = vec![];
+                loop {
+                    match reader.read_until(b'\n', &mut buf) {
+                        Ok(0) => break,
+                        Ok(_) => {}
+                        // The rest of the stream is discarded, rather than left in the pipe for
+                        // the process to block on.
+                        Err(_) => {
+                            let _ = io::copy(&mut reader, &mut io::sink());
+                            break;
+                        }
+                    }
+                    let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    let line = String::from_utf8_lossy(line).into_owned();
+                    if sender.send((stream, line)).is_err() {
+                        break;
+                    }
+                    buf.clear();
+                }
+            });
+        }
+        // The loop ends once both readers have dropped their senders.
+        drop(sender);
+        for (stream, line) in receiver {
+            on_line(stream, &line);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Stdio};
+
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_read_lines() {
+        // Writes more than a pipe holds to each stream in turn, and a line of invalid UTF-8.
+        let script = "for i in $(seq 1 200); do \
+                          printf \"out $i %01000d\\n\" 0; printf \"err $i %01000d\\r\\n\" 0 >&2; \
+                      done; printf 'bad \\377\\n' >&2; printf 'last'";
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (stdout, stderr) = (child.stdout.take().unwrap(), child.stderr.take().unwrap());
+        let mut lines = vec![];
+        read_lines(stdout, stderr, |stream, line| lines.push((stream, line.to_string())));
+        assert!(child.wait().unwrap().success());
+
+        let of = |stream| -> Vec<&String> {
+            lines.iter().filter(|(s, _)| *s == stream).map(|(_, line)| line).collect()
+        };
+        let (out, err) = (of(Stream::Stdout), of(Stream::Stderr));
+        assert_eq!((out.len(), err.len()), (201, 201));
+        for i in 0..200 {
+            assert!(out[i].starts_with(&format!("out {} 0", i + 1)) && out[i].len() > 1000);
+            assert!(err[i].starts_with(&format!("err {} 0", i + 1)) && err[i].ends_with('0'));
+        }
+        assert_eq!(err[200], "bad \u{FFFD}");
+        assert_eq!(out[200], "last");
+
+        // The streams are interleaved, rather than one of them read after the other.
+        let first_err = lines.iter().position(|(s, _)| *s == Stream::Stderr).unwrap();
+        let last_out = lines.iter().rposition(|(s, _)| *s == Stream::Stdout).unwrap();
+        assert!(first_err < last_out);
+    }
+}
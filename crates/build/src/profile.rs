@@ -0,0 +1,98 @@
+//! Building programs only for some profiles of the host crate, see
+//! [`crate::BuildArgs::build_when`].
+//!
+//! Cargo runs the build script of the host crate with `PROFILE` set to `release` for the release
+//! profile and the profiles inheriting from it, and to `debug` otherwise. The name of a custom
+//! profile is only found in the directory of `OUT_DIR`, such as `target/ci/build/host-<hash>/out`
+//! for a `ci` profile. A profile in the allow list matches either, and `dev` matches `debug`.
+
+use std::path::Path;
+
+use crate::BuildArgs;
+
+/// The variable listing the allowed profiles, comma separated, as [`BuildArgs::build_when`].
+pub(crate) const ONLY_PROFILES_ENV: &str = "SP1_BUILD_ONLY_PROFILES";
+
+/// The names of the profile of the host crate found by `lookup`, if the build runs in a build
+/// script.
+fn host_profiles(lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let (Some(profile), Some(out_dir)) = (lookup("PROFILE"), lookup("OUT_DIR")) else {
+        return vec![];
+    };
+    let mut profiles = vec![profile];
+    // OUT_DIR is `<target>/<profile>/build/<package>-<hash>/out`.
+    let profile_dir = Path::new(&out_dir).ancestors().nth(3).and_then(|dir| dir.file_name());
+    if let Some(profile_dir) = profile_dir.and_then(|dir| dir.to_str()) {
+        profiles.push(profile_dir.to_string());
+    }
+    if profiles.iter().any(|profile| profile == "debug") {
+        profiles.push("dev".to_string());
+    }
+    profiles
+}
+
+/// The profile of the host crate, if the program is not built for it: the build runs in a build
+/// script, and neither [`BuildArgs::build_when`] nor `SP1_BUILD_ONLY_PROFILES` list its profile.
+pub(crate) fn skipped_profile(
+    args: &BuildArgs,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let mut allowed = args.build_when.clone();
+    if let Some(only_profiles) = lookup(ONLY_PROFILES_ENV) {
+        allowed.extend(only_profiles.split(',').map(str::trim).map(String::from));
+    }
+    allowed.retain(|profile| !profile.is_empty());
+    let profiles = host_profiles(&lookup);
+    if allowed.is_empty() || profiles.is_empty() {
+        return None;
+    }
+    // The directory names custom profiles, which `PROFILE` does not.
+    if profiles.iter().any(|profile| allowed.contains(profile)) {
+        None
+    } else {
+        profiles.get(1).or(profiles.first()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn skipped(build_when: &[&str], vars: &[(&str, &str)]) -> Option<String> {
+        let vars: HashMap<String, String> =
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let args = BuildArgs {
+            build_when: build_when.iter().map(|profile| profile.to_string()).collect(),
+            ..Default::default()
+        };
+        skipped_profile(&args, |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_skipped_profile() {
+        let debug = [("PROFILE", "debug"), ("OUT_DIR", "/host/target/debug/build/host-1a2b/out")];
+        let release =
+            [("PROFILE", "release"), ("OUT_DIR", "/host/target/release/build/host-1a2b/out")];
+        let custom = [
+            ("PROFILE", "release"),
+            ("OUT_DIR", "/host/target/x86_64-unknown-linux-gnu/ci/build/host-1a2b/out"),
+        ];
+
+        // Built for every profile by default, and outside of build scripts.
+        assert_eq!(skipped(&[], &debug), None);
+        assert_eq!(skipped(&["release"], &[]), None);
+
+        assert_eq!(skipped(&["release"], &debug), Some("debug".to_string()));
+        assert_eq!(skipped(&["release"], &release), None);
+        assert_eq!(skipped(&["dev"], &debug), None);
+        assert_eq!(skipped(&["dev"], &release), Some("release".to_string()));
+        assert_eq!(skipped(&["ci"], &custom), None);
+        assert_eq!(skipped(&["bench"], &custom), Some("ci".to_string()));
+
+        let only_release = [debug[0], debug[1], (ONLY_PROFILES_ENV, "bench, release")];
+        assert_eq!(skipped(&[], &only_release), Some("debug".to_string()));
+        assert_eq!(skipped(&["debug"], &only_release), None);
+    }
+}
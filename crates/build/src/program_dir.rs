@@ -0,0 +1,145 @@
+//! Checking that the program directory passed to a build is a crate, with suggestions when it is
+//! not.
+//!
+//! Build scripts usually build a program in a directory relative to their crate, such as
+//! `../program`, which breaks whenever the layout of a project differs. The error names the
+//! directory relative to the crate of the build script, which is the directory the path is relative
+//! to, and lists the directories next to it which contain a `Cargo.toml`.
+
+use std::{
+    env,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::BuildError;
+
+/// The most directories suggested.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Check that `program_dir` contains a `Cargo.toml`, returning [`BuildError::ProgramDir`]
+/// otherwise. Relative paths are relative to the current directory, which is the directory of the
+/// crate of a build script.
+pub fn check_program_dir(program_dir: impl AsRef<Path>) -> Result<(), BuildError> {
+    let base = env::var_os("CARGO_MANIFEST_DIR").filter(|_| crate::rerun::in_build_script());
+    check(program_dir.as_ref(), base.as_deref().map(Path::new))
+}
+
+/// Check `program_dir`, naming it and the suggestions relative to `base` if set.
+fn check(program_dir: &Path, base: Option<&Path>) -> Result<(), BuildError> {
+    if program_dir.join("Cargo.toml").is_file() {
+        return Ok(());
+    }
+    let absolute = normalize(&std::path::absolute(program_dir).unwrap_or(program_dir.into()));
+    let display = |path: &Path| match base {
+        Some(base) => relative_to(path, &normalize(base)).display().to_string(),
+        None => path.display().to_string(),
+    };
+
+    // The crates within the directory, and next to it, or next to its closest existing ancestor.
+    let mut suggestions = vec![];
+    let mut dirs = vec![];
+    if absolute.is_dir() {
+        dirs.push(absolute.clone());
+    }
+    dirs.extend(absolute.ancestors().skip(1).find(|dir| dir.is_dir()).map(Path::to_path_buf));
+    for dir in dirs {
+        let Ok(entries) = dir.read_dir() else {
+            continue;
+        };
+        let mut crates: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.join("Cargo.toml").is_file() && *path != absolute)
+            .filter(|path| base.map_or(true, |base| *path != normalize(base)))
+            .collect();
+        crates.sort();
+        suggestions.extend(crates.iter().map(|path| display(path)));
+    }
+    suggestions.truncate(MAX_SUGGESTIONS);
+
+    Err(BuildError::ProgramDir {
+        path: match base {
+            Some(_) => display(&absolute),
+            None => program_dir.display().to_string(),
+        },
+        missing: !absolute.is_dir(),
+        suggestions,
+    })
+}
+
+/// Remove the `.` and `..` components of an absolute `path`, without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The relative path from `base` to `path`, both absolute and normalized.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<_> = path.components().collect();
+    let base: Vec<_> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base[common..].iter().map(|_| Component::ParentDir).collect();
+    relative.extend(&path[common..]);
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_relative_to() {
+        let relative = |path: &str, base: &str| relative_to(Path::new(path), Path::new(base));
+        assert_eq!(relative("/a/b/program", "/a/b/script"), Path::new("../program"));
+        assert_eq!(relative("/a/b/script/program", "/a/b/script"), Path::new("program"));
+        assert_eq!(relative("/a/b", "/a/b"), Path::new("."));
+        assert_eq!(normalize(Path::new("/a/b/script/../program/.")), Path::new("/a/b/program"));
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for name in ["script", "programs/fibonacci", "programs/is-prime"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("Cargo.toml"), "").unwrap();
+        }
+        fs::create_dir_all(root.join("docs")).unwrap();
+        let script = root.join("script");
+        assert!(check(&script, None).is_ok());
+
+        // A missing directory, relative to the crate of the build script, which is not suggested.
+        let err = check(&script.join("../program"), Some(&script)).unwrap_err();
+        assert_eq!(err.to_string(), "the program directory ../program does not exist");
+        let err = check(&script.join("../program"), None).unwrap_err();
+        let suggestion = format!("Cargo.toml nearby: {}", normalize(&script).display());
+        assert!(err.to_string().ends_with(&suggestion), "{}", err);
+        // A directory which is not a crate, and one within a missing directory.
+        let err = check(&root.join("programs"), Some(&script)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the program directory ../programs does not contain a Cargo.toml; directories with a \
+             Cargo.toml nearby: ../programs/fibonacci, ../programs/is-prime"
+        );
+        let err = check(&root.join("docs/program"), None).unwrap_err();
+        assert!(matches!(
+            &err,
+            BuildError::ProgramDir { missing: true, suggestions, .. } if suggestions.is_empty()
+        ));
+        assert!(err.to_string().ends_with("docs/program does not exist"), "{}", err);
+    }
+}
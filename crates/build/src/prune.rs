@@ -0,0 +1,365 @@
+//! Deleting the target directories programs are compiled into, see [`prune_build_caches`].
+//!
+//! Each program compiles into an `elf-compilation` directory of its target directory, and into
+//! `elf-compilation/docker` when built with Docker, or into a shared target directory of the build
+//! cache, see [`crate::BuildArgs::shared_target`]. Nothing deletes them but `cargo clean`, so they
+//! accumulate the artifacts of every toolchain and dependency version the programs were built with.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{lock::BuildLock, output, shared_target, summary::human_size, HELPER_TARGET_SUBDIR};
+
+/// Directories which are never searched for target directories.
+const SKIPPED_DIRS: [&str; 2] = [".git", "node_modules"];
+
+/// Which target directories [`prune_build_caches`] deletes.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use sp1_build::{prune_build_caches, PruneOptions};
+///
+/// let options = PruneOptions::default()
+///     .max_age(Duration::from_secs(14 * 24 * 60 * 60))
+///     .max_total_size(20 << 30)
+///     .dry_run(true);
+/// for cache in prune_build_caches(&options).unwrap() {
+///     println!("{} {:?}", cache.path, cache.status);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PruneOptions {
+    /// The directories searched for `elf-compilation` target directories, the current directory
+    /// by default. The shared target directories of the build cache are always included.
+    pub roots: Vec<PathBuf>,
+    /// Delete the target directories which were not modified for longer than this.
+    pub max_age: Option<Duration>,
+    /// Delete the least recently modified target directories until the others take at most this
+    /// many bytes.
+    pub max_total_size: Option<u64>,
+    /// Only list the target directories which would be deleted.
+    pub dry_run: bool,
+    /// The directory of the shared target directories, `SP1_BUILD_CACHE_DIR` or
+    /// `~/.sp1/build-cache` by default.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for PruneOptions {
+    fn default() -> Self {
+        Self {
+            roots: vec![PathBuf::from(".")],
+            max_age: None,
+            max_total_size: None,
+            dry_run: false,
+            cache_dir: None,
+        }
+    }
+}
+
+impl PruneOptions {
+    /// Search `root` for target directories, instead of the current directory along with the other
+    /// roots added.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        if self.roots == [PathBuf::from(".")] {
+            self.roots.clear();
+        }
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Delete the target directories which were not modified for longer than `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Keep at most `max_total_size` bytes of target directories.
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = Some(max_total_size);
+        self
+    }
+
+    /// Only list the target directories which would be deleted.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Search `cache_dir` for shared target directories, instead of the build cache.
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+}
+
+/// A target directory found by [`prune_build_caches`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// The path of the directory.
+    pub path: Utf8PathBuf,
+    /// The size of the files within it, in bytes.
+    pub size: u64,
+    /// When a file within it was last modified.
+    pub modified: SystemTime,
+    /// What was done with it.
+    pub status: CacheStatus,
+}
+
+/// What [`prune_build_caches`] did with a target directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheStatus {
+    /// It is within the age and size limits.
+    Kept,
+    /// It was deleted.
+    Deleted,
+    /// It would have been deleted, but this was a dry run.
+    WouldDelete,
+    /// It would have been deleted, but a build holds its lock.
+    InUse,
+}
+
+/// Find the target directories programs were compiled into, and delete those exceeding the limits
+/// of `options`, the oldest first. The directories are returned from the most to the least
+/// recently modified, and each is reported with its size.
+///
+/// The target directories are the `elf-compilation` directories within the roots of `options`,
+/// and the shared target directories of the build cache, `~/.sp1/build-cache` by default, which
+/// are not empty. A
+/// directory locked by a build in progress is never deleted, nor is it in a dry run, which only
+/// lists what would be deleted.
+pub fn prune_build_caches(options: &PruneOptions) -> Result<Vec<BuildCache>> {
+    let mut dirs = vec![];
+    for root in &options.roots {
+        find_target_dirs(root, &mut dirs)?;
+    }
+    let cache_dir = match &options.cache_dir {
+        Some(cache_dir) => Some(cache_dir.clone()),
+        None => shared_target::cache_dir().map(Utf8PathBuf::into_std_path_buf),
+    };
+    if let Some(cache_dir) = cache_dir.filter(|dir| dir.is_dir()) {
+        for entry in fs::read_dir(cache_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+
+    let mut caches = vec![];
+    for dir in dirs {
+        let path = Utf8PathBuf::from_path_buf(dir)
+            .map_err(|dir| anyhow::anyhow!("{} is not valid UTF-8", dir.display()))?;
+        let (size, modified) =
+            usage(path.as_std_path()).with_context(|| format!("failed to measure {}", path))?;
+        // A directory which was pruned only has its build lock left.
+        let Some(modified) = modified else { continue };
+        caches.push(BuildCache { path, size, modified, status: CacheStatus::Kept });
+    }
+    caches.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+    caches.dedup_by(|a, b| a.path == b.path);
+
+    let now = SystemTime::now();
+    let mut kept_size = 0;
+    for cache in &mut caches {
+        let age = now.duration_since(cache.modified).unwrap_or_default();
+        let too_old = options.max_age.is_some_and(|max_age| age > max_age);
+        let too_large =
+            options.max_total_size.is_some_and(|max_size| kept_size + cache.size > max_size);
+        if too_old || too_large {
+            cache.status = delete(&cache.path, options.dry_run)?;
+        }
+        if matches!(cache.status, CacheStatus::Kept | CacheStatus::InUse) {
+            kept_size += cache.size;
+        }
+        let verb = match cache.status {
+            CacheStatus::Kept => "kept",
+            CacheStatus::Deleted => "deleted",
+            CacheStatus::WouldDelete => "would delete",
+            CacheStatus::InUse => "kept, as a build is using it,",
+        };
+        output::info(&format!(
+            "[sp1] {} {} ({}, last modified {} days ago)",
+            verb,
+            cache.path,
+            human_size(cache.size),
+            age.as_secs() / (24 * 60 * 60)
+        ));
+    }
+    Ok(caches)
+}
+
+/// Add the `elf-compilation` directories within `dir` to `dirs`, without searching within them
+/// or following symbolic links.
+fn find_target_dirs(dir: &Path, dirs: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", dir.display())),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name();
+        if name == HELPER_TARGET_SUBDIR {
+            dirs.push(std::path::absolute(entry.path())?);
+        } else if !SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+            find_target_dirs(&entry.path(), dirs)?;
+        }
+    }
+    Ok(())
+}
+
+/// The total size of the files within `dir`, and when the most recently modified of them was
+/// modified, if any. The build lock is left out, as it is created when the directory is pruned.
+pub(crate) fn usage(dir: &Path) -> std::io::Result<(u64, Option<SystemTime>)> {
+    let mut size = 0;
+    let mut modified = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let (dir_size, dir_modified) = usage(&entry.path())?;
+            size += dir_size;
+            modified = modified.max(dir_modified);
+        } else if entry.file_name() != crate::lock::LOCK_FILE_NAME {
+            size += metadata.len();
+            modified = modified.max(Some(metadata.modified()?));
+        }
+    }
+    Ok((size, modified))
+}
+
+/// Delete the contents of the target directory `dir` unless a build holds its lock, or that of the
+/// target directory of the Docker builds within it. The lock files are kept, as a build waiting for
+/// them would otherwise lock a new file while another build locks the one it recreates.
+pub(crate) fn delete(dir: &Utf8Path, dry_run: bool) -> Result<CacheStatus> {
+    let mut locks = vec![];
+    for target_dir in [dir.to_path_buf(), dir.join("docker")] {
+        if !target_dir.is_dir() {
+            continue;
+        }
+        match BuildLock::try_acquire(&target_dir)? {
+            Some(lock) => locks.push(lock),
+            None => return Ok(CacheStatus::InUse),
+        }
+    }
+    if dry_run {
+        return Ok(CacheStatus::WouldDelete);
+    }
+    remove_contents(dir.as_std_path(), true)
+        .with_context(|| format!("failed to delete {}", dir))?;
+    Ok(CacheStatus::Deleted)
+}
+
+/// Remove what `dir` contains but its build lock, and that of its `docker` directory if `top`.
+fn remove_contents(dir: &Path, top: bool) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == crate::lock::LOCK_FILE_NAME {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            if top && name == "docker" {
+                remove_contents(&entry.path(), false)?;
+            } else {
+                fs::remove_dir_all(entry.path())?;
+            }
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    /// A target directory with a file of `size` bytes modified `age` ago.
+    fn target_dir(root: &Path, program: &str, size: usize, age: Duration) -> Utf8PathBuf {
+        let dir = root.join(program).join("target").join(HELPER_TARGET_SUBDIR);
+        fs::create_dir_all(dir.join("release")).unwrap();
+        let elf = dir.join("release").join("elf");
+        fs::write(&elf, vec![0; size]).unwrap();
+        File::options()
+            .write(true)
+            .open(elf)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        Utf8PathBuf::try_from(std::path::absolute(dir).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_prune_build_caches() {
+        let root = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let old = target_dir(root.path(), "old", 100, day * 30);
+        let recent = target_dir(root.path(), "recent", 300, day * 2);
+        let newest = target_dir(root.path(), "newest", 200, Duration::ZERO);
+        fs::create_dir_all(root.path().join(".git").join(HELPER_TARGET_SUBDIR)).unwrap();
+
+        // The shared target directories of the user are left alone.
+        let cache_dir = tempfile::tempdir().unwrap();
+        let options = PruneOptions::default()
+            .root(root.path())
+            .cache_dir(cache_dir.path())
+            .max_age(day * 7)
+            .dry_run(true);
+        let caches = prune_build_caches(&options).unwrap();
+        let caches: Vec<_> =
+            caches.into_iter().map(|cache| (cache.path, cache.size, cache.status)).collect();
+        assert_eq!(
+            caches,
+            vec![
+                (newest.clone(), 200, CacheStatus::Kept),
+                (recent.clone(), 300, CacheStatus::Kept),
+                (old.clone(), 100, CacheStatus::WouldDelete),
+            ]
+        );
+        assert!(old.exists());
+
+        // A locked directory is skipped, and counts towards the size budget.
+        let lock = BuildLock::try_acquire(&newest).unwrap().unwrap();
+        let options = PruneOptions::default()
+            .root(root.path())
+            .cache_dir(cache_dir.path())
+            .max_total_size(150);
+        let statuses = |options: &PruneOptions| -> Vec<_> {
+            prune_build_caches(options).unwrap().into_iter().map(|cache| cache.status).collect()
+        };
+        assert_eq!(
+            statuses(&options.clone().dry_run(true)),
+            [CacheStatus::InUse, CacheStatus::WouldDelete, CacheStatus::WouldDelete]
+        );
+        assert!(recent.join("release").exists() && old.join("release").exists());
+        assert_eq!(
+            statuses(&options),
+            [CacheStatus::InUse, CacheStatus::Deleted, CacheStatus::Deleted]
+        );
+        assert!(newest.join("release").exists());
+
+        // Only the locks of the deleted directories are left, and they are not listed again.
+        for dir in [&recent, &old] {
+            let entries: Vec<_> =
+                fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+            assert_eq!(entries, [crate::lock::LOCK_FILE_NAME]);
+        }
+        assert_eq!(statuses(&options), [CacheStatus::InUse]);
+        drop(lock);
+    }
+}
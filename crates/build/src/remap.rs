@@ -0,0 +1,89 @@
+//! Remapping the paths compiled into the ELF, see [`BuildArgs::remap_paths`].
+//!
+//! Panic messages and debug information name the source files of the program and its dependencies
+//! by their paths on the machine which built it, such as `/home/alice/work/program/src/main.rs` or
+//! `/home/alice/.cargo/registry/src/...`, which differ from one machine to another and reveal its
+//! layout. The build passes `--remap-path-prefix` to rustc for each of these directories, mapping
+//! them to placeholders which are the same for local and Docker builds:
+//!
+//! | Directory                                     | Placeholder    |
+//! |-----------------------------------------------|----------------|
+//! | the workspace of the program, containing it   | `/sp1/program` |
+//! | `CARGO_HOME`, with the sources of the crates  | `/cargo`       |
+//! | the sysroot of the toolchain                  | `/rustc`       |
+//!
+//! Rustc applies the last prefix which matches, so the more specific directories come last, such
+//! as a `CARGO_HOME` within the workspace.
+//!
+//! The remapping only removes the paths from the ELF: a local build writes the same ELF as a Docker
+//! build only with the succinct toolchain of the Docker image, the same lock file and the same
+//! flags.
+
+use std::path::Path;
+
+use cargo_metadata::camino::Utf8Path;
+
+use crate::toolchain::{self, Toolchain};
+
+/// The placeholder of the workspace of the program.
+const PROGRAM: &str = "/sp1/program";
+/// The placeholder of `CARGO_HOME`.
+const CARGO_HOME: &str = "/cargo";
+/// The placeholder of the sysroot of the toolchain.
+const SYSROOT: &str = "/rustc";
+
+/// Where the Docker image mounts the workspace of the program, and has the succinct toolchain.
+const DOCKER_WORKSPACE: &str = "/root/program";
+const DOCKER_SYSROOT: &str = "/root/.rustup/toolchains/succinct";
+
+/// The prefixes of the paths of a local build of the workspace in `workspace_root` with
+/// `cargo_home` and `toolchain`, and their placeholders.
+pub(crate) fn local_prefixes(
+    workspace_root: &Utf8Path,
+    cargo_home: Option<&Path>,
+    toolchain: Toolchain,
+) -> Vec<(String, &'static str)> {
+    let workspace_root = crate::host_path::canonicalize(workspace_root.as_std_path())
+        .unwrap_or_else(|_| workspace_root.as_std_path().to_path_buf());
+    let mut prefixes = vec![(workspace_root, PROGRAM)];
+    if let Some(cargo_home) = cargo_home {
+        prefixes.push((cargo_home.to_path_buf(), CARGO_HOME));
+    }
+    if let Some(sysroot) = toolchain::sysroot(toolchain.name()) {
+        prefixes.push((sysroot, SYSROOT));
+    }
+    prefixes.into_iter().map(|(from, to)| (from.to_string_lossy().into_owned(), to)).collect()
+}
+
+/// The prefixes of the paths of a Docker build with `cargo_home`, in the container, and their
+/// placeholders.
+pub(crate) fn docker_prefixes(cargo_home: &str) -> Vec<(String, &'static str)> {
+    let prefixes =
+        [(DOCKER_WORKSPACE, PROGRAM), (cargo_home, CARGO_HOME), (DOCKER_SYSROOT, SYSROOT)];
+    prefixes.into_iter().map(|(from, to)| (from.to_string(), to)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cargo_home::CONTAINER_CARGO_HOME;
+
+    #[test]
+    fn test_prefixes() {
+        assert_eq!(
+            docker_prefixes(CONTAINER_CARGO_HOME),
+            [
+                ("/root/program".to_string(), PROGRAM),
+                ("/root/.cargo".to_string(), CARGO_HOME),
+                ("/root/.rustup/toolchains/succinct".to_string(), SYSROOT),
+            ]
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let workspace_root = Utf8Path::from_path(dir.path()).unwrap();
+        let prefixes =
+            local_prefixes(workspace_root, Some(Path::new("/cargo-home")), Toolchain::Succinct);
+        assert_eq!(prefixes[0], (root.to_string_lossy().into_owned(), PROGRAM));
+        assert_eq!(prefixes[1], ("/cargo-home".to_string(), CARGO_HOME));
+    }
+}
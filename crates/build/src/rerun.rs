@@ -0,0 +1,199 @@
+//! Directives telling cargo when to run the build script which builds a program again.
+//!
+//! When a program is built from the `build.rs` of a host crate, as `sp1-helper` does, cargo only
+//! knows what the build script depends on from its `cargo:rerun-if-*` directives. The build
+//! prints them for the sources of the program and of its local dependencies, its lock file, its
+//! `sp1.toml`, the `rust-toolchain` files and cargo configuration which apply to it, and the
+//! variables which affect the build, see [`tracked_envs`], unless
+//! [`crate::BuildArgs::no_rerun_directives`] is set.
+//!
+//! The host crate may be a member of the workspace of the program. Its own sources are then left
+//! out of the directives and of the fingerprint of the program, which only cover the packages the
+//! program depends on, and the program is compiled into the `OUT_DIR` of the build script rather
+//! than the target directory of the workspace, which the build of the host uses as well.
+
+use std::{collections::HashSet, env};
+
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Metadata, Package, PackageId,
+};
+
+use crate::{c_toolchain, config, docker, overrides, profile, shared_target, toolchain, tooling};
+
+/// The files of a local package which its build depends on. Directories such as `target` or the
+/// output directory of the ELF are left out, as the build writes to them.
+const PACKAGE_FILES: [&str; 4] = ["src", "bin", "build.rs", "Cargo.toml"];
+
+/// The configuration files of the program, see [`crate::ResolvedBuildArgs`].
+const CONFIG_FILES: [&str; 2] = [config::CONFIG_FILE, ".sp1/build.toml"];
+
+/// The files which rustup and cargo look for in the directory of the program and its ancestors.
+const DIR_FILES: [&str; 4] =
+    ["rust-toolchain", "rust-toolchain.toml", ".cargo/config", ".cargo/config.toml"];
+
+/// Whether the build runs in a build script, which cargo runs with `OUT_DIR` and
+/// `CARGO_MANIFEST_DIR` set.
+pub(crate) fn in_build_script() -> bool {
+    env::var_os("OUT_DIR").is_some() && env::var_os("CARGO_MANIFEST_DIR").is_some()
+}
+
+/// The `OUT_DIR` of the build script building the program, if its crate is a member of the
+/// workspace of the program.
+pub(crate) fn shared_workspace_out_dir(metadata: &Metadata) -> Option<Utf8PathBuf> {
+    if !in_build_script() {
+        return None;
+    }
+    let host_manifest = Utf8PathBuf::from(env::var("CARGO_MANIFEST_DIR").ok()?).join("Cargo.toml");
+    let host_manifest = host_manifest.canonicalize_utf8().unwrap_or(host_manifest);
+    metadata
+        .workspace_packages()
+        .iter()
+        .any(|package| {
+            package.manifest_path.canonicalize_utf8().is_ok_and(|path| path == host_manifest)
+        })
+        .then(|| env::var("OUT_DIR").ok().map(Utf8PathBuf::from))
+        .flatten()
+}
+
+/// The packages without a source, which are members of the workspace or path dependencies, that
+/// the root package of the program depends on, including itself. All of them if the program has
+/// no root package.
+pub(crate) fn local_packages(metadata: &Metadata) -> Vec<&Package> {
+    let local = metadata.packages.iter().filter(|package| package.source.is_none());
+    let (Some(resolve), Some(root)) = (&metadata.resolve, metadata.root_package()) else {
+        return local.collect();
+    };
+    let mut dependencies: HashSet<&PackageId> = HashSet::from([&root.id]);
+    let mut queue = vec![&root.id];
+    while let Some(id) = queue.pop() {
+        let Some(node) = resolve.nodes.iter().find(|node| &node.id == id) else {
+            continue;
+        };
+        for dep in &node.deps {
+            if dependencies.insert(&dep.pkg) {
+                queue.push(&dep.pkg);
+            }
+        }
+    }
+    local.filter(|package| dependencies.contains(&package.id)).collect()
+}
+
+/// The files of the program in `program_dir` which its build depends on, and which exist: the
+/// sources of its local packages, the manifest and lock file of its workspace, its `sp1.toml`, and
+/// the toolchain files and cargo configuration of its directory and its ancestors.
+pub(crate) fn tracked_paths(program_dir: &Utf8Path, metadata: &Metadata) -> Vec<Utf8PathBuf> {
+    let mut paths = vec![
+        metadata.workspace_root.join("Cargo.toml"),
+        metadata.workspace_root.join("Cargo.lock"),
+    ];
+    paths.extend(CONFIG_FILES.iter().map(|file| program_dir.join(file)));
+    for dir in program_dir.ancestors() {
+        paths.extend(DIR_FILES.iter().map(|file| dir.join(file)));
+    }
+    for package in local_packages(metadata) {
+        if let Some(package_dir) = package.manifest_path.parent() {
+            paths.extend(PACKAGE_FILES.iter().map(|file| package_dir.join(file)));
+        }
+    }
+    paths.sort();
+    paths.dedup();
+
+    // Nothing the builds write to is watched, which would run the build script again after each
+    // build, nor missing files, which cargo would consider changed on every build.
+    let out_dir = env::var("OUT_DIR").ok().map(Utf8PathBuf::from);
+    let generated = |path: &Utf8PathBuf| {
+        path.starts_with(&metadata.target_directory) ||
+            out_dir.as_ref().is_some_and(|out_dir| path.starts_with(out_dir))
+    };
+    paths.retain(|path| path.exists() && !generated(path));
+    paths
+}
+
+/// The variables the build depends on: the `SP1_BUILD_*` variables and those skipping the build,
+/// including those of the tools it is skipped for, and the variables of the environment which
+/// select the Docker image, the C compiler or the wrapper of rustc, or disable the check of the
+/// toolchain.
+pub(crate) fn tracked_envs() -> Vec<String> {
+    let mut envs = overrides::build_env_vars();
+    envs.extend(overrides::SKIP_ENVS.map(String::from));
+    envs.push(profile::ONLY_PROFILES_ENV.to_string());
+    envs.extend(tooling::ENVS.map(String::from));
+    envs.extend(
+        [
+            docker::IMAGE_ENV,
+            c_toolchain::CC_ENV,
+            toolchain::SKIP_CHECK_ENV,
+            shared_target::CACHE_DIR_ENV,
+        ]
+        .into_iter()
+        .chain(crate::RUSTC_WRAPPER_ENVS.iter().copied())
+        .map(String::from),
+    );
+    envs
+}
+
+/// The directives for the build of the program in `program_dir`.
+pub(crate) fn directives(program_dir: &Utf8Path, metadata: &Metadata) -> Vec<String> {
+    let paths = tracked_paths(program_dir, metadata);
+    let mut directives: Vec<String> =
+        paths.iter().map(|path| format!("cargo:rerun-if-changed={}", path)).collect();
+    directives.extend(
+        tracked_envs().into_iter().map(|name| format!("cargo:rerun-if-env-changed={}", name)),
+    );
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_directives() {
+        let program_dir =
+            Utf8PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/c-dependency");
+        let metadata = crate::program_metadata(&program_dir).unwrap();
+        let directives = directives(&program_dir, &metadata);
+
+        let changed = |path: &str| format!("cargo:rerun-if-changed={}", program_dir.join(path));
+        let paths = ["Cargo.toml", "Cargo.lock", "src", "build.rs", "sys/src", "host-sys/build.rs"];
+        for path in paths {
+            assert!(directives.contains(&changed(path)), "missing {}", path);
+        }
+        assert!(!directives.iter().any(|directive| directive.ends_with("/target")));
+        assert!(directives.contains(&"cargo:rerun-if-env-changed=SP1_BUILD_DOCKER".to_string()));
+    }
+
+    #[test]
+    fn test_tracked_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let program_dir = root.join("program");
+        fs::create_dir_all(program_dir.join("src")).unwrap();
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::write(
+            program_dir.join("Cargo.toml"),
+            "[workspace]\n[package]\nname = \"tracked\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(program_dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let files = ["program/sp1.toml", "program/rust-toolchain.toml", ".cargo/config.toml"];
+        for file in files {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let metadata = crate::program_metadata(&program_dir).unwrap();
+        let paths = tracked_paths(&program_dir, &metadata);
+
+        for file in files.iter().chain(&["program/Cargo.lock", "program/src"]) {
+            assert!(paths.contains(&root.join(file)), "missing {}", file);
+        }
+        // Missing files are left out.
+        assert!(!paths.contains(&program_dir.join(".sp1/build.toml")));
+        assert!(!paths.contains(&program_dir.join("rust-toolchain")));
+        assert!(tracked_envs().contains(&"SP1_DOCKER_IMAGE".to_string()));
+    }
+}
@@ -0,0 +1,313 @@
+//! The flags of rustc of a build, see [`ZkvmRustFlags`].
+
+use std::env;
+
+use crate::ValidationError;
+
+/// The flags of rustc required by the zkVM: lowering atomics to plain loads and stores, placing the
+/// text section where the zkVM starts executing, and aborting on panics.
+const REQUIRED: [&str; 6] =
+    ["-C", "passes=loweratomic", "-C", "link-arg=-Ttext=0x00200800", "-C", "panic=abort"];
+
+/// The flags of rustc a program is compiled with, as passed in `CARGO_ENCODED_RUSTFLAGS`: the flags
+/// required by the zkVM, which [`ZkvmRustFlags::default`] starts from, followed by the flags added.
+///
+/// Flags which override a required one, such as `-C panic=unwind`, or which set the target, are
+/// rejected by [`ZkvmRustFlags::check`], unless [`ZkvmRustFlags::unchecked`] is set. Local and
+/// Docker builds render their flags with this type, see [`crate::rust_flags`].
+///
+/// ```
+/// # use sp1_build::ZkvmRustFlags;
+/// let flags = ZkvmRustFlags::default().codegen("opt-level=3").cfg("feature=\"fast\"");
+/// assert!(flags.check().is_ok());
+/// assert!(flags.render_encoded().ends_with("-C\x1fopt-level=3\x1f--cfg\x1ffeature=\"fast\""));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZkvmRustFlags {
+    required: Vec<String>,
+    added: Vec<String>,
+    unchecked: bool,
+}
+
+impl Default for ZkvmRustFlags {
+    fn default() -> Self {
+        Self {
+            required: REQUIRED.iter().map(|flag| flag.to_string()).collect(),
+            added: vec![],
+            unchecked: false,
+        }
+    }
+}
+
+impl ZkvmRustFlags {
+    /// Add the codegen option `option`, as `-C <option>`.
+    pub fn codegen(self, option: impl Into<String>) -> Self {
+        self.flags(["-C".to_string(), option.into()])
+    }
+
+    /// Add an argument of the linker, as `-C link-arg=<arg>`.
+    pub fn link_arg(self, arg: impl AsRef<str>) -> Self {
+        self.codegen(format!("link-arg={}", arg.as_ref()))
+    }
+
+    /// Add the configuration `cfg`, as `--cfg <cfg>`.
+    pub fn cfg(self, cfg: impl Into<String>) -> Self {
+        self.flags(["--cfg".to_string(), cfg.into()])
+    }
+
+    /// Remap the paths starting with `from` to `to`, as `--remap-path-prefix <from>=<to>`.
+    pub fn remap_path_prefix(self, from: impl AsRef<str>, to: impl AsRef<str>) -> Self {
+        let prefix = format!("{}={}", from.as_ref(), to.as_ref());
+        self.flags(["--remap-path-prefix".to_string(), prefix])
+    }
+
+    /// Add `flags` as they are, such as those of [`crate::BuildArgs::rustflags`].
+    pub fn flags<I, S>(mut self, flags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.added.extend(flags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Accept flags which override those required by the zkVM, whose ELF may then not run.
+    pub fn unchecked(mut self) -> Self {
+        self.unchecked = true;
+        self
+    }
+
+    /// Fail with [`ValidationError::ConflictingRustflags`] if a flag added overrides one required
+    /// by the zkVM, unless the flags are [`ZkvmRustFlags::unchecked`].
+    pub fn check(&self) -> Result<(), ValidationError> {
+        let (_, conflicting) = merge(&self.required, self.added.clone());
+        if self.unchecked || conflicting.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError::ConflictingRustflags(conflicting))
+        }
+    }
+
+    /// The flags, the required ones first.
+    pub fn to_vec(&self) -> Vec<String> {
+        self.required.iter().chain(&self.added).cloned().collect()
+    }
+
+    /// The flags as the value of `CARGO_ENCODED_RUSTFLAGS`, separated by `\x1f`.
+    pub fn render_encoded(&self) -> String {
+        self.to_vec().join("\x1f")
+    }
+
+    /// The flags for nightly, whose LLVM only knows the atomics lowering pass by its new name, and
+    /// which needs `-Zunstable-options` for the target specification of the zkVM.
+    pub(crate) fn nightly(mut self) -> Self {
+        for flag in self.required.iter_mut().filter(|flag| *flag == "passes=loweratomic") {
+            *flag = "passes=lower-atomic".to_string();
+        }
+        self.flags(["-Zunstable-options"])
+    }
+}
+
+/// The flags of rustc which take a value as the next argument, so that a flag and its value are
+/// kept or dropped together.
+const FLAGS_WITH_VALUE: &[&str] = &[
+    "-C",
+    "--codegen",
+    "-Z",
+    "-L",
+    "-l",
+    "-A",
+    "-W",
+    "-D",
+    "-F",
+    "--cfg",
+    "--check-cfg",
+    "--cap-lints",
+    "--target",
+    "--remap-path-prefix",
+];
+
+/// The flags of rustc of the environment a local build inherits: `CARGO_ENCODED_RUSTFLAGS`, or
+/// `RUSTFLAGS` split on whitespace, with the same precedence as cargo. Returns the flags to build
+/// with, after the `required` ones, and those dropped because they conflict with a required flag.
+pub(crate) fn inherited(required: &[String]) -> (Vec<String>, Vec<String>) {
+    let encoded = env::var("CARGO_ENCODED_RUSTFLAGS").ok();
+    let plain = env::var("RUSTFLAGS").ok();
+    merge(required, parse(encoded.as_deref(), plain.as_deref()))
+}
+
+/// Parse the flags of `CARGO_ENCODED_RUSTFLAGS`, separated by `\x1f`, or else of `RUSTFLAGS`,
+/// separated by whitespace. Empty variables count as unset.
+fn parse(encoded: Option<&str>, plain: Option<&str>) -> Vec<String> {
+    match (encoded.filter(|e| !e.is_empty()), plain) {
+        (Some(encoded), _) => encoded.split('\x1f').map(str::to_string).collect(),
+        (None, Some(plain)) => plain.split_whitespace().map(str::to_string).collect(),
+        (None, None) => vec![],
+    }
+}
+
+/// Split `flags` into the ones which can be added after the `required` flags and the ones which
+/// conflict with them. Flags which repeat a required flag are dropped silently.
+fn merge(required: &[String], flags: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let required_codegen: Vec<String> =
+        group(required.to_vec()).iter().filter_map(|f| codegen(f)).collect();
+    let (mut kept, mut dropped) = (vec![], vec![]);
+    for flag in group(flags) {
+        match codegen(&flag) {
+            Some(option) if required_codegen.contains(&option) => {}
+            Some(option) if required_codegen.iter().any(|r| conflicts(r, &option)) => {
+                dropped.extend(flag)
+            }
+            _ if flag[0] == "--target" || flag[0].starts_with("--target=") => dropped.extend(flag),
+            _ => kept.extend(flag),
+        }
+    }
+    (kept, dropped)
+}
+
+/// Group `flags` into the flags and their values.
+fn group(flags: Vec<String>) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = vec![];
+    let mut flags = flags.into_iter();
+    while let Some(flag) = flags.next() {
+        let mut group = vec![flag];
+        if FLAGS_WITH_VALUE.contains(&group[0].as_str()) {
+            group.extend(flags.next());
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// The codegen option set by `flag`, such as `panic=abort` for `-C panic=abort` or `-Cpanic=abort`.
+fn codegen(flag: &[String]) -> Option<String> {
+    match flag {
+        [c, option] if c == "-C" || c == "--codegen" => Some(option.clone()),
+        [flag] => {
+            flag.strip_prefix("--codegen=").or_else(|| flag.strip_prefix("-C")).map(str::to_string)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the codegen option `option` overrides the `required` one: setting the panic strategy
+/// or the address of the text section to other values.
+fn conflicts(required: &str, option: &str) -> bool {
+    let key = |o: &str| match o.split_once('=') {
+        Some(("link-arg", arg)) if arg.starts_with("-Ttext") => "link-arg=-Ttext".to_string(),
+        Some((key, _)) => key.to_string(),
+        None => o.to_string(),
+    };
+    key(required) == key(option) && key(required) != "passes"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(flags: &[&str]) -> Vec<String> {
+        flags.iter().map(|flag| flag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(Some("-C\x1ftarget-cpu=generic-rv32\x1f--cfg\x1ffoo bar"), Some("-g")),
+            flags(&["-C", "target-cpu=generic-rv32", "--cfg", "foo bar"])
+        );
+        assert_eq!(parse(Some(""), Some(" -C  opt-level=3 ")), flags(&["-C", "opt-level=3"]));
+        assert_eq!(parse(None, None), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_merge() {
+        let required = ZkvmRustFlags::default().to_vec();
+        let (kept, dropped) = merge(
+            &required,
+            flags(&[
+                "-C",
+                "opt-level=3",
+                "-Cpanic=unwind",
+                "-C",
+                "panic=abort",
+                "--codegen=link-arg=-Ttext=0x1000",
+                "-C",
+                "link-arg=--gc-sections",
+                "-C",
+                "passes=inline",
+                "--target",
+                "x86_64-unknown-linux-gnu",
+                "-g",
+            ]),
+        );
+        assert_eq!(
+            kept,
+            flags(&[
+                "-C",
+                "opt-level=3",
+                "-C",
+                "link-arg=--gc-sections",
+                "-C",
+                "passes=inline",
+                "-g"
+            ])
+        );
+        assert_eq!(
+            dropped,
+            flags(&[
+                "-Cpanic=unwind",
+                "--codegen=link-arg=-Ttext=0x1000",
+                "--target",
+                "x86_64-unknown-linux-gnu"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_zkvm_rust_flags() {
+        let flags = ZkvmRustFlags::default()
+            .codegen("opt-level=3")
+            .link_arg("--gc-sections")
+            .cfg("fast")
+            .remap_path_prefix("/home/me/program", "/sp1/program")
+            .flags(["-g"]);
+        assert_eq!(
+            flags.render_encoded(),
+            "-C\x1fpasses=loweratomic\x1f-C\x1flink-arg=-Ttext=0x00200800\x1f-C\x1fpanic=abort\x1f\
+             -C\x1fopt-level=3\x1f-C\x1flink-arg=--gc-sections\x1f--cfg\x1ffast\x1f\
+             --remap-path-prefix\x1f/home/me/program=/sp1/program\x1f-g"
+        );
+        assert_eq!(flags.check(), Ok(()));
+        assert_eq!(ZkvmRustFlags::default().to_vec(), REQUIRED);
+
+        let nightly = ZkvmRustFlags::default().nightly().to_vec();
+        assert_eq!(nightly[1], "passes=lower-atomic");
+        assert_eq!(nightly.last().unwrap(), "-Zunstable-options");
+    }
+
+    #[test]
+    fn test_zkvm_rust_flags_conflicts() {
+        // Repeating a required flag, or running other passes, is not a conflict.
+        let zkvm = ZkvmRustFlags::default().codegen("panic=abort").codegen("passes=inline");
+        assert_eq!(zkvm.check(), Ok(()));
+
+        let zkvm = ZkvmRustFlags::default()
+            .codegen("panic=unwind")
+            .link_arg("-Ttext=0x1000")
+            .flags(["--target", "x86_64-unknown-linux-gnu"]);
+        assert_eq!(
+            zkvm.check(),
+            Err(ValidationError::ConflictingRustflags(flags(&[
+                "-C",
+                "panic=unwind",
+                "-C",
+                "link-arg=-Ttext=0x1000",
+                "--target",
+                "x86_64-unknown-linux-gnu"
+            ])))
+        );
+        assert_eq!(zkvm.clone().unchecked().check(), Ok(()));
+        let encoded = zkvm.unchecked().render_encoded();
+        assert!(encoded.ends_with("--target\x1fx86_64-unknown-linux-gnu"));
+    }
+}
@@ -0,0 +1,549 @@
+//! The software bill of materials of a program, see [`BuildArgs::sbom`].
+//!
+//! The document lists every crate of the dependency graph of the program, as resolved by cargo
+//! for the build, with its version, source and the checksum of the lock file, and the tools which
+//! built the ELF. It describes the ELF by its SHA-256 digest, and is written next to it, as
+//! `<elf>.cdx.json` or `<elf>.spdx.json`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{camino::Utf8PathBuf, DependencyKind, Metadata, PackageId};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{docker, toolchain, BuildArgs, ElfArtifact, Toolchain};
+
+/// The format of the SBOM written next to the ELF, see [`BuildArgs::sbom`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SbomFormat {
+    /// CycloneDX 1.5, in JSON.
+    #[value(name = "cyclonedx")]
+    CycloneDx,
+    /// SPDX 2.3, in JSON.
+    Spdx,
+}
+
+impl SbomFormat {
+    /// The extension of the SBOM, appended to the path of the ELF.
+    fn extension(self) -> &'static str {
+        match self {
+            SbomFormat::CycloneDx => "cdx.json",
+            SbomFormat::Spdx => "spdx.json",
+        }
+    }
+}
+
+/// A crate of the dependency graph of the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Component {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// The source of the crate, such as `registry+https://github.com/rust-lang/crates.io-index`,
+    /// or `None` for a local crate.
+    pub(crate) source: Option<String>,
+    /// The SHA-256 checksum of the crate in the lock file, for crates of a registry.
+    pub(crate) checksum: Option<String>,
+}
+
+/// The sources of crates.io in the lock file, as a git and as a sparse index.
+const CRATES_IO: [&str; 2] =
+    ["registry+https://github.com/rust-lang/crates.io-index", "sparse+https://index.crates.io/"];
+
+impl Component {
+    fn purl(&self) -> String {
+        format!("pkg:cargo/{}@{}", self.name, self.version)
+    }
+
+    /// The SPDX download location of the crate: the archive of a crate of crates.io, the
+    /// repository of a git dependency, and `NOASSERTION` for local crates and other registries,
+    /// whose index is not where the crate is downloaded from.
+    fn download_location(&self) -> String {
+        match self.source.as_deref() {
+            Some(source) if CRATES_IO.contains(&source) => format!(
+                "https://static.crates.io/crates/{0}/{0}-{1}.crate",
+                self.name, self.version
+            ),
+            Some(source) if source.starts_with("git+") => {
+                // `git+<url>?rev=<rev>#<commit>` in the lock file, `git+<url>@<commit>` for SPDX.
+                let (url, commit) = source.split_once('#').unwrap_or((source, ""));
+                let url = url.split_once('?').map_or(url, |(url, _)| url);
+                if commit.is_empty() {
+                    url.to_string()
+                } else {
+                    format!("{}@{}", url, commit)
+                }
+            }
+            _ => "NOASSERTION".to_string(),
+        }
+    }
+}
+
+/// A tool which built the ELF, with its version if known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Tool {
+    name: String,
+    version: Option<String>,
+}
+
+/// Write the SBOM of each of the `artifacts` of the program described by `program_metadata`, built
+/// with `args` and `toolchain`, returning the paths it is written to.
+pub(crate) fn write(
+    args: &BuildArgs,
+    program_metadata: &Metadata,
+    toolchain: Toolchain,
+    artifacts: &[ElfArtifact],
+) -> Result<Vec<Utf8PathBuf>> {
+    let Some(format) = args.sbom else {
+        return Ok(vec![]);
+    };
+    let lock_path = program_metadata.workspace_root.join("Cargo.lock");
+    let lock = fs::read_to_string(lock_path).unwrap_or_default();
+    let components = components(program_metadata, &lock);
+    let tools = tools(args, toolchain);
+    let mut paths = vec![];
+    for artifact in artifacts {
+        let Some(sha256) = &artifact.sha256 else {
+            continue;
+        };
+        let package = program_metadata.root_package();
+        let name = artifact.path.file_name().unwrap_or_default();
+        let version = package.map(|package| package.version.to_string()).unwrap_or_default();
+        let document = match format {
+            SbomFormat::CycloneDx => cyclonedx(name, &version, sha256, &components, &tools),
+            SbomFormat::Spdx => {
+                let created = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+                spdx(name, &version, sha256, &components, &tools, &created)
+            }
+        };
+        let path = Utf8PathBuf::from(format!("{}.{}", artifact.path, format.extension()));
+        fs::write(&path, serde_json::to_string_pretty(&document)?)
+            .with_context(|| format!("failed to write the SBOM to {}", path))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// The crates of the dependency graph of the root package of `program_metadata`, leaving out those
+/// only used by its tests, sorted by name and version, with their checksums in the lock file
+/// `lock`.
+pub(crate) fn components(program_metadata: &Metadata, lock: &str) -> Vec<Component> {
+    let Some(resolve) = &program_metadata.resolve else {
+        return vec![];
+    };
+    let Some(root) = &resolve.root else {
+        return vec![];
+    };
+    let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let mut visited: HashSet<&PackageId> = HashSet::from([root]);
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        for dep in nodes.get(id).map(|node| node.deps.as_slice()).unwrap_or_default() {
+            let used = dep.dep_kinds.iter().any(|kind| kind.kind != DependencyKind::Development);
+            if used && visited.insert(&dep.pkg) {
+                stack.push(&dep.pkg);
+            }
+        }
+    }
+
+    let checksums = checksums(lock);
+    let mut components: Vec<_> = program_metadata
+        .packages
+        .iter()
+        .filter(|package| visited.contains(&package.id) && package.id != *root)
+        .map(|package| {
+            let version = package.version.to_string();
+            Component {
+                checksum: checksums.get(&(package.name.clone(), version.clone())).cloned(),
+                name: package.name.clone(),
+                version,
+                source: package.source.as_ref().map(ToString::to_string),
+            }
+        })
+        .collect();
+    components.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    components
+}
+
+/// The checksum of each package of the lock file `lock`, by name and version.
+fn checksums(lock: &str) -> HashMap<(String, String), String> {
+    let Ok(lock) = lock.parse::<toml::Table>() else {
+        return HashMap::new();
+    };
+    let packages = lock.get("package").and_then(|packages| packages.as_array());
+    packages
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let field = |key: &str| Some(package.get(key)?.as_str()?.to_string());
+            Some(((field("name")?, field("version")?), field("checksum")?))
+        })
+        .collect()
+}
+
+/// The tools building the program with `args` and `toolchain`: this crate, and the Docker image
+/// or the Rust toolchain.
+fn tools(args: &BuildArgs, toolchain: Toolchain) -> Vec<Tool> {
+    let mut tools = vec![Tool {
+        name: "sp1-build".to_string(),
+        version: Some(env!("CARGO_PKG_VERSION").into()),
+    }];
+    if args.docker {
+        tools.push(Tool { name: docker::get_docker_image(&args.tag), version: None });
+    } else {
+        let version = toolchain::installed_version(toolchain.name()).ok();
+        tools.push(Tool {
+            name: format!("rustc ({} toolchain)", toolchain.name()),
+            version: version.map(|version| version.release),
+        });
+    }
+    tools
+}
+
+/// A serial number of the document, derived from the digest of the ELF, so that the SBOM of an
+/// ELF is reproducible.
+fn serial_uuid(sha256: &str) -> String {
+    let digest = hex::encode(Sha256::digest(format!("sp1-sbom:{}", sha256)));
+    // A version 5 UUID with the variant of RFC 4122.
+    format!(
+        "{}-{}-5{}-8{}-{}",
+        &digest[..8],
+        &digest[8..12],
+        &digest[13..16],
+        &digest[17..20],
+        &digest[20..32]
+    )
+}
+
+/// The CycloneDX document of the ELF `name` of the program of `version` with the digest `sha256`.
+fn cyclonedx(
+    name: &str,
+    version: &str,
+    sha256: &str,
+    components: &[Component],
+    tools: &[Tool],
+) -> Value {
+    let tools: Vec<_> = tools
+        .iter()
+        .map(|tool| {
+            let mut entry = json!({ "type": "application", "name": tool.name });
+            if let Some(version) = &tool.version {
+                entry["version"] = json!(version);
+            }
+            entry
+        })
+        .collect();
+    let elf_ref = format!("elf:{}", name);
+    let entries: Vec<_> = components
+        .iter()
+        .map(|component| {
+            let mut entry = json!({
+                "type": "library",
+                "bom-ref": component.purl(),
+                "name": component.name,
+                "version": component.version,
+                "purl": component.purl(),
+            });
+            if let Some(checksum) = &component.checksum {
+                entry["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+            }
+            if let Some(source) = &component.source {
+                entry["properties"] = json!([{ "name": "cargo:source", "value": source }]);
+            }
+            entry
+        })
+        .collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", serial_uuid(sha256)),
+        "version": 1,
+        "metadata": {
+            "tools": { "components": tools },
+            "component": {
+                "type": "firmware",
+                "bom-ref": elf_ref,
+                "name": name,
+                "version": version,
+                "hashes": [{ "alg": "SHA-256", "content": sha256 }],
+            },
+        },
+        "components": entries,
+        "dependencies": [{
+            "ref": elf_ref,
+            "dependsOn": components.iter().map(Component::purl).collect::<Vec<_>>(),
+        }],
+    })
+}
+
+/// The SPDX document of the ELF `name` of the program of `version` with the digest `sha256`,
+/// created at `created`.
+fn spdx(
+    name: &str,
+    version: &str,
+    sha256: &str,
+    components: &[Component],
+    tools: &[Tool],
+    created: &str,
+) -> Value {
+    let creators: Vec<_> = tools
+        .iter()
+        .map(|tool| match &tool.version {
+            Some(version) => format!("Tool: {}-{}", tool.name, version),
+            None => format!("Tool: {}", tool.name),
+        })
+        .collect();
+    let mut packages = vec![];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": "SPDXRef-ELF",
+    })];
+    for (i, component) in components.iter().enumerate() {
+        let id = format!("SPDXRef-Package-{}", i);
+        let mut package = json!({
+            "SPDXID": id,
+            "name": component.name,
+            "versionInfo": component.version,
+            "downloadLocation": component.download_location(),
+            "filesAnalyzed": false,
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": component.purl(),
+            }],
+        });
+        if let Some(checksum) = &component.checksum {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": checksum }]);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-ELF",
+            "relationshipType": "GENERATED_FROM",
+            "relatedSpdxElement": id,
+        }));
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", name, version),
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", name, serial_uuid(sha256)),
+        "creationInfo": { "created": created, "creators": creators },
+        "documentDescribes": ["SPDXRef-ELF"],
+        "files": [{
+            "SPDXID": "SPDXRef-ELF",
+            "fileName": format!("./{}", name),
+            "checksums": [{ "algorithm": "SHA256", "checksumValue": sha256 }],
+        }],
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use jsonschema::{Draft, JSONSchema};
+
+    use super::*;
+
+    const SHA256: &str = "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4";
+
+    /// The metadata of a program with a normal, a build and a development dependency, with the
+    /// checksum of the normal dependency in its lock file.
+    fn program() -> (tempfile::TempDir, Metadata, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        for name in ["normal", "build", "dev"] {
+            let manifest = format!("[package]\nname = \"{}\"\nversion = \"0.2.0\"\n", name);
+            fs::create_dir_all(root.join(name).join("src")).unwrap();
+            fs::write(root.join(name).join("Cargo.toml"), manifest).unwrap();
+            fs::write(root.join(name).join("src/lib.rs"), "").unwrap();
+        }
+        let manifest = "[workspace]\n[package]\nname = \"program\"\nversion = \"0.1.0\"\n\
+                        [dependencies]\nnormal = { path = \"normal\" }\n\
+                        [build-dependencies]\nbuild = { path = \"build\" }\n\
+                        [dev-dependencies]\ndev = { path = \"dev\" }\n";
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("Cargo.toml"), manifest).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        let metadata = crate::program_metadata(&root).unwrap();
+        let lock = format!(
+            "version = 3\n\n[[package]]\nname = \"normal\"\nversion = \"0.2.0\"\nchecksum = \"{}\"\n",
+            SHA256
+        );
+        (dir, metadata, lock)
+    }
+
+    fn tools() -> Vec<Tool> {
+        vec![
+            Tool { name: "sp1-build".to_string(), version: Some("1.1.1".to_string()) },
+            Tool { name: "ghcr.io/succinctlabs/sp1:v1.1.1".to_string(), version: None },
+        ]
+    }
+
+    /// Validate `document` against the JSON schema `schema` of `tests/fixtures/sbom`, as published
+    /// with the CycloneDX and SPDX specifications.
+    fn assert_valid(document: &Value, schema: &str, draft: Draft) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sbom").join(schema);
+        let schema: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let schema = JSONSchema::options().with_draft(draft).compile(&schema).unwrap();
+        let errors: Vec<_> = match schema.validate(document) {
+            Ok(()) => vec![],
+            Err(errors) => {
+                errors.map(|error| format!("{} at {}", error, error.instance_path)).collect()
+            }
+        };
+        assert!(errors.is_empty(), "invalid document: {}", errors.join("; "));
+    }
+
+    fn is_sha256(value: &Value) -> bool {
+        value
+            .as_str()
+            .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    #[test]
+    fn test_components() {
+        let (_dir, metadata, lock) = program();
+        let components = components(&metadata, &lock);
+        let names: Vec<_> = components.iter().map(|component| component.name.as_str()).collect();
+        assert_eq!(names, vec!["build", "normal"]);
+        assert_eq!(components[1].checksum.as_deref(), Some(SHA256));
+        assert_eq!(components[1].purl(), "pkg:cargo/normal@0.2.0");
+        assert_eq!(components[0].checksum, None);
+        assert_eq!(checksums("[[package"), HashMap::new());
+    }
+
+    /// Round trips the CycloneDX document through JSON, validates it against the CycloneDX 1.5
+    /// schema, and checks the constraints the schema does not express, such as unique references.
+    #[test]
+    fn test_cyclonedx() {
+        let (_dir, metadata, lock) = program();
+        let document =
+            cyclonedx("program-elf", "0.1.0", SHA256, &components(&metadata, &lock), &tools());
+        let document: Value =
+            serde_json::from_str(&serde_json::to_string_pretty(&document).unwrap()).unwrap();
+
+        assert_valid(&document, "bom-1.5.schema.json", Draft::Draft7);
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert_eq!(document["specVersion"], "1.5");
+        assert_eq!(document["version"], 1);
+        let serial = document["serialNumber"].as_str().unwrap().strip_prefix("urn:uuid:").unwrap();
+        let groups: Vec<_> = serial.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert!(serial.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+        assert!(serial[14..15] == *"5" && "89ab".contains(&serial[19..20]));
+
+        let elf = &document["metadata"]["component"];
+        assert_eq!(
+            (elf["type"].as_str(), elf["name"].as_str()),
+            (Some("firmware"), Some("program-elf"))
+        );
+        assert_eq!(elf["hashes"][0]["alg"], "SHA-256");
+        assert_eq!(elf["hashes"][0]["content"], SHA256);
+        for tool in document["metadata"]["tools"]["components"].as_array().unwrap() {
+            assert_eq!(tool["type"], "application");
+            assert!(tool["name"].is_string());
+        }
+
+        let mut refs = HashSet::from([elf["bom-ref"].as_str().unwrap()]);
+        for component in document["components"].as_array().unwrap() {
+            assert_eq!(component["type"], "library");
+            assert!(component["name"].is_string() && component["version"].is_string());
+            assert!(component["purl"].as_str().unwrap().starts_with("pkg:cargo/"));
+            for hash in component["hashes"].as_array().into_iter().flatten() {
+                assert_eq!(hash["alg"], "SHA-256");
+                assert!(is_sha256(&hash["content"]));
+            }
+            assert!(refs.insert(component["bom-ref"].as_str().unwrap()), "duplicate bom-ref");
+        }
+        for dependency in document["dependencies"].as_array().unwrap() {
+            assert!(refs.contains(dependency["ref"].as_str().unwrap()));
+            for depends_on in dependency["dependsOn"].as_array().unwrap() {
+                assert!(refs.contains(depends_on.as_str().unwrap()));
+            }
+        }
+    }
+
+    /// Round trips the SPDX document through JSON, validates it against the SPDX 2.3 schema, and
+    /// checks the constraints the schema does not express, such as the format of the identifiers.
+    #[test]
+    fn test_spdx() {
+        let (_dir, metadata, lock) = program();
+        let components = components(&metadata, &lock);
+        let created = "2024-08-01T12:00:00Z";
+        let document = spdx("program-elf", "0.1.0", SHA256, &components, &tools(), created);
+        let document: Value =
+            serde_json::from_str(&serde_json::to_string(&document).unwrap()).unwrap();
+
+        assert_valid(&document, "spdx-2.3.schema.json", Draft::Draft201909);
+        assert_eq!(document["spdxVersion"], "SPDX-2.3");
+        assert_eq!(document["dataLicense"], "CC0-1.0");
+        assert_eq!(document["SPDXID"], "SPDXRef-DOCUMENT");
+        assert!(document["name"].is_string() && document["documentNamespace"].is_string());
+        assert_eq!(document["creationInfo"]["created"], created);
+        let creators = document["creationInfo"]["creators"].as_array().unwrap();
+        assert_eq!(creators[0], "Tool: sp1-build-1.1.1");
+
+        let is_spdx_id = |id: &Value| {
+            id.as_str().and_then(|id| id.strip_prefix("SPDXRef-")).is_some_and(|id| {
+                !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || ".-".contains(c))
+            })
+        };
+        let mut ids = HashSet::from(["SPDXRef-DOCUMENT"]);
+        for element in document["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .chain(document["packages"].as_array().unwrap())
+        {
+            assert!(is_spdx_id(&element["SPDXID"]), "{}", element);
+            assert!(ids.insert(element["SPDXID"].as_str().unwrap()), "duplicate SPDXID");
+            for checksum in element["checksums"].as_array().into_iter().flatten() {
+                assert_eq!(checksum["algorithm"], "SHA256");
+                assert!(is_sha256(&checksum["checksumValue"]));
+            }
+        }
+        assert_eq!(document["files"][0]["checksums"][0]["checksumValue"], SHA256);
+        let packages = document["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), components.len());
+        for package in packages {
+            assert!(package["name"].is_string() && package["downloadLocation"].is_string());
+        }
+        for relationship in document["relationships"].as_array().unwrap() {
+            assert!(ids.contains(relationship["spdxElementId"].as_str().unwrap()));
+            assert!(ids.contains(relationship["relatedSpdxElement"].as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_download_location() {
+        let component = |source: Option<&str>| Component {
+            name: "serde".to_string(),
+            version: "1.0.0".to_string(),
+            source: source.map(str::to_string),
+            checksum: None,
+        };
+        let archive = "https://static.crates.io/crates/serde/serde-1.0.0.crate";
+        for source in CRATES_IO {
+            assert_eq!(component(Some(source)).download_location(), archive);
+        }
+        let git = "git+https://github.com/serde-rs/serde?rev=1a2b#1a2b3c4d";
+        assert_eq!(
+            component(Some(git)).download_location(),
+            "git+https://github.com/serde-rs/serde@1a2b3c4d"
+        );
+        let other = "registry+https://example.com/index";
+        assert_eq!(component(Some(other)).download_location(), "NOASSERTION");
+        assert_eq!(component(None).download_location(), "NOASSERTION");
+    }
+}
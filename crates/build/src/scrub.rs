@@ -0,0 +1,79 @@
+//! The environment of the cargo command of a local build.
+//!
+//! Builds usually run within another cargo, from the build script of a host crate or from
+//! `cargo prove`. Cargo sets variables for the programs it runs which would corrupt the build of
+//! the program for the zkVM target, such as the flags and the wrapper of the host's rustc, the
+//! features and `cfg`s of the host crate, or job server descriptors which the nested cargo cannot
+//! use. These variables are removed from the environment of the cargo command:
+//!
+//! | Variables                                                      | Why                          |
+//! |----------------------------------------------------------------|------------------------------|
+//! | `RUSTFLAGS`, `CARGO_BUILD_RUSTFLAGS`                           | flags for the host (1)       |
+//! | `CARGO`, `RUSTDOC`, `RUSTC_WORKSPACE_WRAPPER`                  | tools of the host's cargo    |
+//! | `CARGO_BUILD_TARGET`, `CARGO_UNSTABLE_*`                       | configuration of the host    |
+//! | `CARGO_CFG_*`, `CARGO_FEATURE_*`, `DEP_*`                      | the host's build script      |
+//! | `CARGO_MAKEFLAGS`, `MAKEFLAGS`, `MFLAGS`                       | job server descriptors (2)   |
+//!
+//! (1) The flags of `RUSTFLAGS` are added to `CARGO_ENCODED_RUSTFLAGS` instead, unless
+//! [`crate::BuildArgs::ignore_inherited_rustflags`].
+//!
+//! (2) The jobserver of the cargo running the build script is set again, with descriptors the
+//! nested cargo inherits, see the `jobs` module.
+//!
+//! Set [`crate::BuildArgs::inherit_env`] to keep them. Regardless, `RUSTC` is always removed, and
+//! the variables which configure the build, such as `CARGO_ENCODED_RUSTFLAGS`, `CARGO_TARGET_DIR`
+//! and `RUSTUP_TOOLCHAIN`, are always replaced. All other variables are inherited.
+
+use std::ffi::OsString;
+
+/// The variables removed from the environment of the cargo command.
+const REMOVED: &[&str] = &[
+    "RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "CARGO",
+    "RUSTDOC",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "CARGO_BUILD_TARGET",
+    "CARGO_MAKEFLAGS",
+    "MAKEFLAGS",
+    "MFLAGS",
+];
+
+/// The prefixes of the variables removed from the environment of the cargo command.
+const REMOVED_PREFIXES: &[&str] = &["CARGO_UNSTABLE_", "CARGO_CFG_", "CARGO_FEATURE_", "DEP_"];
+
+/// The variables among `vars` which are removed from the environment of the cargo command.
+pub(crate) fn removed_vars(vars: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    vars.into_iter()
+        .filter(|var| {
+            let var = var.to_string_lossy();
+            REMOVED.contains(&var.as_ref()) ||
+                REMOVED_PREFIXES.iter().any(|prefix| var.starts_with(prefix))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removed_vars() {
+        let vars = [
+            "RUSTFLAGS",
+            "CARGO_UNSTABLE_BUILD_STD",
+            "CARGO_CFG_TARGET_OS",
+            "CARGO_FEATURE_DEFAULT",
+            "DEP_Z_INCLUDE",
+            "CARGO_MAKEFLAGS",
+            "CARGO_HOME",
+            "CARGO_TARGET_DIR",
+            "PATH",
+            "RUSTUP_TOOLCHAIN",
+        ];
+        assert_eq!(
+            removed_vars(vars.map(OsString::from)),
+            vars[..6].iter().map(OsString::from).collect::<Vec<_>>()
+        );
+    }
+}
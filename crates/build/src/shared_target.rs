@@ -0,0 +1,92 @@
+//! The target directory shared by the programs built with [`BuildArgs::shared_target`].
+//!
+//! Programs compile into a target directory of their own by default, so the dependencies they have
+//! in common, such as `sp1-zkvm`, are compiled once per program. With a shared target directory,
+//! they are compiled once for all of them, as long as the programs are built with the same
+//! toolchain and flags. The directory is named after a digest of both, so that builds with other
+//! flags, which cargo would otherwise compile again over the same artifacts, use another one.
+//! Builds into the same directory take turns through the lock of the `lock` module.
+
+use std::{env, path::PathBuf};
+
+use cargo_metadata::camino::Utf8PathBuf;
+use sha2::{Digest, Sha256};
+
+use crate::{rustflags, toolchain::Toolchain, BuildArgs, ZkvmRustFlags};
+
+/// The environment variable setting the directory the shared target directories are created in,
+/// `~/.sp1/build-cache` by default.
+pub(crate) const CACHE_DIR_ENV: &str = "SP1_BUILD_CACHE_DIR";
+
+/// The shared target directory a local build with `args` compiles into, or `None` if the build does
+/// not use one, or the directory is unknown because there is no home directory.
+pub(crate) fn shared_target_dir(args: &BuildArgs) -> Option<Utf8PathBuf> {
+    // A Docker build only sees the workspace of the program.
+    if !args.shared_target || args.docker {
+        return None;
+    }
+    let root = cache_dir()?;
+    let toolchain = Toolchain::select(args);
+    Some(root.join(format!(
+        "{}-{}",
+        toolchain.name(),
+        &configuration_digest(args, toolchain)[..16]
+    )))
+}
+
+/// The directory the shared target directories are created in, or `None` if there is no home
+/// directory.
+pub(crate) fn cache_dir() -> Option<Utf8PathBuf> {
+    let root = match env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()?.join(".sp1").join("build-cache"),
+    };
+    Utf8PathBuf::from_path_buf(root).ok()
+}
+
+/// The digest of what makes the artifacts of a build incompatible with those of another build:
+/// the toolchain, the flags of rustc and the C compiler.
+fn configuration_digest(args: &BuildArgs, toolchain: Toolchain) -> String {
+    let mut flags = ZkvmRustFlags::default();
+    if !args.ignore_inherited_rustflags {
+        let (inherited, _) = rustflags::inherited(&flags.to_vec());
+        flags = flags.flags(inherited);
+    }
+    let flags = flags.flags(args.rustflags.iter().cloned()).to_vec();
+
+    let mut hasher = Sha256::new();
+    hasher.update(toolchain.name());
+    for part in [flags, args.c_flags.clone(), args.cxx_flags.clone()] {
+        hasher.update([0]);
+        hasher.update(part.join("\x1f"));
+    }
+    hasher.update([0]);
+    if let Some(c_compiler) = &args.c_compiler {
+        hasher.update(c_compiler.as_os_str().as_encoded_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configuration_digest() {
+        let args = BuildArgs { ignore_inherited_rustflags: true, ..Default::default() };
+        let digest = configuration_digest(&args, Toolchain::Succinct);
+        assert_eq!(digest, configuration_digest(&args.clone(), Toolchain::Succinct));
+        assert_ne!(digest, configuration_digest(&args, Toolchain::NightlyFallback));
+
+        let features = BuildArgs { features: vec!["bls".to_string()], ..args.clone() };
+        assert_eq!(digest, configuration_digest(&features, Toolchain::Succinct));
+        let rustflags = BuildArgs { rustflags: vec!["-Copt-level=s".to_string()], ..args.clone() };
+        assert_ne!(digest, configuration_digest(&rustflags, Toolchain::Succinct));
+        let c_flags = BuildArgs { c_flags: vec!["-O2".to_string()], ..args.clone() };
+        assert_ne!(digest, configuration_digest(&c_flags, Toolchain::Succinct));
+
+        assert_eq!(shared_target_dir(&args), None);
+        let docker = BuildArgs { shared_target: true, docker: true, ..args };
+        assert_eq!(shared_target_dir(&docker), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Executing the built ELF as a smoke test, see [`BuildArgs::smoke_test`].
+//!
+//! The ELF is run in the SP1 executor, without generating a proof, which catches programs which
+//! compile but fail as soon as they run in the zkVM. Running it requires the `execute` feature,
+//! which pulls in the executor.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{output, BuildArgs, BuildError, ElfArtifact};
+
+/// Execute the ELF after the build, failing it if the program does not halt successfully, see
+/// [`BuildArgs::smoke_test`].
+///
+/// In `[package.metadata.sp1.smoke-test]` or `sp1.toml`, it is a table:
+///
+/// ```toml
+/// [package.metadata.sp1.smoke-test]
+/// # Buffers written to the stdin of the program, hex encoded, such as a `u32` read with
+/// # `sp1_zkvm::io::read`.
+/// stdin = ["0a000000"]
+/// max-cycles = 1000000
+/// ```
+///
+/// On the command line and in `SP1_BUILD_SMOKE_TEST`, it is a comma separated list of the same
+/// keys, such as `stdin=0a000000,max-cycles=1000000`, where `stdin` may be repeated, or empty to
+/// execute the ELF without input or budget.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SmokeTest {
+    /// The buffers written to the stdin of the program, hex encoded.
+    pub stdin: Vec<String>,
+    /// The most cycles the program may execute, or `None` for no limit.
+    pub max_cycles: Option<u64>,
+}
+
+/// The table or string a [`SmokeTest`] is deserialized from.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum Repr {
+    Table {
+        #[serde(default)]
+        stdin: Vec<String>,
+        #[serde(default, rename = "max-cycles")]
+        max_cycles: Option<u64>,
+    },
+    String(String),
+}
+
+impl<'de> Deserialize<'de> for SmokeTest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Repr::deserialize(deserializer)? {
+            Repr::Table { stdin, max_cycles } => {
+                for buffer in &stdin {
+                    check_hex(buffer).map_err(serde::de::Error::custom)?;
+                }
+                Ok(SmokeTest { stdin, max_cycles })
+            }
+            Repr::String(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl FromStr for SmokeTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut smoke_test = SmokeTest::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match item.split_once('=') {
+                Some(("stdin", buffer)) => {
+                    check_hex(buffer)?;
+                    smoke_test.stdin.push(buffer.to_string());
+                }
+                Some(("max-cycles", cycles)) => {
+                    let cycles = cycles
+                        .parse()
+                        .map_err(|_| format!("invalid number of cycles `{}`", cycles))?;
+                    smoke_test.max_cycles = Some(cycles);
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid smoke test setting `{}`, expected `stdin=<hex>` or \
+                         `max-cycles=<cycles>`",
+                        item
+                    ))
+                }
+            }
+        }
+        Ok(smoke_test)
+    }
+}
+
+impl fmt::Display for SmokeTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items: Vec<_> =
+            self.stdin.iter().map(|buffer| format!("stdin={}", buffer)).collect();
+        items.extend(self.max_cycles.map(|cycles| format!("max-cycles={}", cycles)));
+        f.write_str(&items.join(","))
+    }
+}
+
+fn check_hex(buffer: &str) -> Result<(), String> {
+    hex::decode(buffer).map(drop).map_err(|err| format!("invalid stdin `{}`: {}", buffer, err))
+}
+
+/// Fail if `args` enable the smoke test, but this crate cannot execute ELFs, before building.
+pub(crate) fn check_available(args: &BuildArgs) -> Result<(), BuildError> {
+    if args.smoke_test.is_some() && !cfg!(feature = "execute") {
+        return Err(BuildError::Config(
+            "`smoke_test` requires the `execute` feature of sp1-build".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Execute the ELFs of `artifacts` if `args` enable the smoke test, recording the cycles each
+/// executed in. ELFs which were not built, in a dry run, are not executed.
+pub(crate) fn run_all(args: &BuildArgs, artifacts: &mut [ElfArtifact]) -> Result<()> {
+    let Some(smoke_test) = &args.smoke_test else {
+        return Ok(());
+    };
+    for artifact in artifacts.iter_mut().filter(|artifact| artifact.size.is_some()) {
+        let elf = match std::fs::read(&artifact.path) {
+            Ok(elf) => elf,
+            Err(source) => {
+                return Err(BuildError::Artifact { path: artifact.path.clone(), source }.into())
+            }
+        };
+        let cycles = run(smoke_test, &elf)
+            .map_err(|reason| BuildError::SmokeTest { elf: artifact.path.clone(), reason })?;
+        output::info(&format!("[sp1] executed {} in {} cycles", artifact.path, cycles));
+        artifact.cycles = Some(cycles);
+    }
+    Ok(())
+}
+
+/// Execute `elf` with the stdin of `smoke_test`, returning the cycles it executed in, or why it
+/// failed.
+#[cfg(feature = "execute")]
+fn run(smoke_test: &SmokeTest, elf: &[u8]) -> Result<u64, String> {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use sp1_core_executor::{Executor, Program};
+    use sp1_stark::SP1CoreOpts;
+
+    let program = Program::from(elf).map_err(|err| format!("invalid ELF: {}", err))?;
+    let mut executor = Executor::new(program, SP1CoreOpts::default());
+    executor.max_cycles = smoke_test.max_cycles;
+    for buffer in &smoke_test.stdin {
+        executor.write_stdin_slice(&hex::decode(buffer).map_err(|err| err.to_string())?);
+    }
+    // The executor panics on some invalid programs, such as those reading past their stdin.
+    match panic::catch_unwind(AssertUnwindSafe(|| executor.run_untraced())) {
+        Ok(Ok(())) => Ok(executor.state.global_clk),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(panic) => Err(panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| panic.downcast_ref::<&str>().map(|msg| msg.to_string()))
+            .map_or_else(
+                || "the executor panicked".to_string(),
+                |msg| format!("panicked: {}", msg),
+            )),
+    }
+}
+
+#[cfg(not(feature = "execute"))]
+fn run(_smoke_test: &SmokeTest, _elf: &[u8]) -> Result<u64, String> {
+    unreachable!("the smoke test is checked to be available before building")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_smoke_test() {
+        assert_eq!("".parse::<SmokeTest>().unwrap(), SmokeTest::default());
+        let smoke_test: SmokeTest = "stdin=0a000000, stdin=ff,max-cycles=1000".parse().unwrap();
+        assert_eq!(smoke_test.stdin, vec!["0a000000".to_string(), "ff".to_string()]);
+        assert_eq!(smoke_test.max_cycles, Some(1000));
+        assert_eq!(smoke_test.to_string().parse::<SmokeTest>().unwrap(), smoke_test);
+
+        assert!("stdin=0g".parse::<SmokeTest>().is_err());
+        assert!("max-cycles=many".parse::<SmokeTest>().is_err());
+        assert!("cycles=1".parse::<SmokeTest>().is_err());
+    }
+
+    #[test]
+    fn test_smoke_test_table() {
+        let smoke_test: SmokeTest =
+            serde_json::from_value(serde_json::json!({"stdin": ["0a000000"], "max-cycles": 5}))
+                .unwrap();
+        assert_eq!(smoke_test, "stdin=0a000000,max-cycles=5".parse().unwrap());
+        assert!(serde_json::from_value::<SmokeTest>(serde_json::json!({"stdin": ["xyz"]})).is_err());
+        assert!(serde_json::from_value::<SmokeTest>(serde_json::json!({"cycles": 5})).is_err());
+        let smoke_test: SmokeTest =
+            serde_json::from_value(serde_json::json!("max-cycles=5")).unwrap();
+        assert_eq!(smoke_test.max_cycles, Some(5));
+    }
+
+    #[cfg(feature = "execute")]
+    #[test]
+    fn test_run_smoke_test() {
+        const FIBONACCI_ELF: &[u8] =
+            include_bytes!("../../../tests/fibonacci/elf/riscv32im-succinct-zkvm-elf");
+
+        let cycles = run(&SmokeTest::default(), FIBONACCI_ELF).unwrap();
+        assert!(cycles > 0);
+        let budget = SmokeTest { max_cycles: Some(cycles / 2), ..SmokeTest::default() };
+        assert!(run(&budget, FIBONACCI_ELF).unwrap_err().contains("exceeded cycle limit"));
+        assert!(run(&SmokeTest::default(), b"not an elf").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! The checks that a program is an SP1 program, see [`BuildArgs::allow_non_sp1_program`].
+//!
+//! A crate which does not depend on `sp1-zkvm` compiles for the zkVM target just as well, into an
+//! ELF the zkVM cannot execute, which is only noticed when proving it. So is one which does not
+//! call `sp1_zkvm::entrypoint!`, or depends on a release of SP1 much older than this crate.
+
+use cargo_metadata::{semver::Version, DependencyKind, Metadata};
+
+use crate::{BuildArgs, BuildWarning, WarningKind};
+
+/// The crates an SP1 program depends on, one of them at least.
+const SP1_CRATES: &[&str] = &["sp1-zkvm", "sp1-lib"];
+
+/// The warnings about the program described by `program_metadata` not being an SP1 program, unless
+/// `args` allow it.
+pub(crate) fn warnings(args: &BuildArgs, program_metadata: &Metadata) -> Vec<BuildWarning> {
+    let Some(package) = program_metadata.root_package() else {
+        return vec![];
+    };
+    if args.allow_non_sp1_program {
+        return vec![];
+    }
+    let depends_on = |name: &str| {
+        package
+            .dependencies
+            .iter()
+            .any(|dep| dep.name == name && dep.kind == DependencyKind::Normal)
+    };
+    if !SP1_CRATES.iter().any(|name| depends_on(name)) {
+        let message = format!(
+            "the program `{}` does not depend on `sp1-zkvm`, so its ELF cannot be executed by the \
+             zkVM. Add `sp1-zkvm` to its dependencies and call `sp1_zkvm::entrypoint!(main)` in its \
+             binary, or set `allow_non_sp1_program` to build a bare-metal RISC-V binary",
+            package.name
+        );
+        return vec![
+            BuildWarning::new(WarningKind::NotSp1Program, message).field("package", &package.name)
+        ];
+    }
+
+    let mut warnings = vec![];
+    let binary = if args.binary.is_empty() { &package.name } else { &args.binary };
+    let target = package.targets.iter().find(|target| target.is_bin() && target.name == *binary);
+    if let Some(target) = target.filter(|_| depends_on("sp1-zkvm")) {
+        let source = std::fs::read_to_string(&target.src_path).unwrap_or_default();
+        if !source.contains("entrypoint!") {
+            let message = format!(
+                "the binary `{}` does not call `sp1_zkvm::entrypoint!(main)` in {}, so its ELF \
+                 has no entrypoint the zkVM can execute",
+                binary, target.src_path
+            );
+            warnings.push(
+                BuildWarning::new(WarningKind::MissingEntrypoint, message)
+                    .field("binary", binary)
+                    .field("path", &target.src_path),
+            );
+        }
+    }
+
+    let own = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+    let resolved = program_metadata
+        .packages
+        .iter()
+        .filter(|package| SP1_CRATES.contains(&package.name.as_str()))
+        .filter(|package| release(&package.version) != release(&own));
+    for package in resolved {
+        let message = format!(
+            "the program depends on {} {}, which is of another release than sp1-build {}: \
+             update it to {}.x, as programs built against another release of SP1 may not be \
+             provable",
+            package.name,
+            package.version,
+            own,
+            release_name(&own)
+        );
+        warnings.push(
+            BuildWarning::new(WarningKind::Sp1ReleaseMismatch, message)
+                .field("crate", &package.name)
+                .field("version", &package.version),
+        );
+    }
+    warnings
+}
+
+/// The release of SP1 of `version`, whose versions are compatible: the major version, or the minor
+/// one before 1.0.
+fn release(version: &Version) -> (u64, u64) {
+    if version.major == 0 {
+        (0, version.minor)
+    } else {
+        (version.major, 0)
+    }
+}
+
+fn release_name(version: &Version) -> String {
+    match release(version) {
+        (0, minor) => format!("0.{}", minor),
+        (major, _) => major.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let write = |path: &str, contents: &str| {
+            fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            fs::write(root.join(path), contents).unwrap();
+        };
+        write("sp1-zkvm/Cargo.toml", "[package]\nname = \"sp1-zkvm\"\nversion = \"0.1.0\"\n");
+        write("sp1-zkvm/src/lib.rs", "");
+        let manifest = "[workspace]\n[package]\nname = \"program\"\nversion = \"0.1.0\"\n";
+        write("program/Cargo.toml", manifest);
+        write("program/src/main.rs", "#![no_main]\nfn main() {}");
+        let metadata = || crate::program_metadata(&root.join("program")).unwrap();
+
+        let args = BuildArgs::default();
+        let warnings = warnings(&args, &metadata());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::NotSp1Program);
+        assert!(warnings[0].message.contains("does not depend on `sp1-zkvm`"), "{:?}", warnings);
+        let allowed = BuildArgs { allow_non_sp1_program: true, ..args.clone() };
+        assert_eq!(super::warnings(&allowed, &metadata()), vec![]);
+
+        let dependency = "[dependencies]\nsp1-zkvm = { path = \"../sp1-zkvm\" }\n";
+        write("program/Cargo.toml", &format!("{}{}", manifest, dependency));
+        let warnings = super::warnings(&args, &metadata());
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert_eq!(warnings[0].kind, WarningKind::MissingEntrypoint);
+        assert!(warnings[0].message.contains("does not call `sp1_zkvm::entrypoint!(main)`"));
+        assert_eq!(warnings[1].kind, WarningKind::Sp1ReleaseMismatch);
+        assert_eq!(warnings[1].fields["version"], "0.1.0");
+        assert!(warnings[1].message.starts_with("the program depends on sp1-zkvm 0.1.0"));
+    }
+
+    #[test]
+    fn test_release() {
+        let version = |version: &str| release(&Version::parse(version).unwrap());
+        assert_eq!(version("1.1.0"), version("1.2.3-rc.1"));
+        assert_ne!(version("1.1.0"), version("2.0.0"));
+        assert_ne!(version("0.1.0"), version("0.2.0"));
+        assert_eq!(release_name(&Version::parse("0.2.1").unwrap()), "0.2");
+    }
+}
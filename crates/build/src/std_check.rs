@@ -0,0 +1,261 @@
+//! The checks of programs using what the zkVM does not provide, see
+//! [`BuildArgs::strict_no_std_check`].
+//!
+//! The zkVM has no operating system: a program may use the parts of `std` which do not need one,
+//! but no threads, sockets or async runtimes. Crates built on them compile for the zkVM target,
+//! then fail to link with confusing messages, or produce an ELF which fails when it runs. Before
+//! building, the dependencies of the program are checked for such crates, and its binary for
+//! `#![no_main]`, without which cargo links the `main` of `std` rather than the entrypoint of
+//! `sp1-zkvm`. After building, the ELF is checked for undefined symbols, which the linker leaves
+//! at address zero when they are weak.
+
+use std::collections::{HashMap, VecDeque};
+
+use cargo_metadata::{camino::Utf8Path, DependencyKind, Metadata, PackageId};
+
+use crate::{BuildArgs, BuildError, ValidationError};
+
+/// The crates which need an operating system, and fail to link or to run in the zkVM. Crates which
+/// are fine in a program, such as one only using the parts of one of them which do not need an
+/// operating system, are allowed with [`BuildArgs::allowed_std_crates`].
+const HOST_ONLY_CRATES: &[&str] = &[
+    "tokio",
+    "async-std",
+    "mio",
+    "socket2",
+    "hyper",
+    "reqwest",
+    "ureq",
+    "native-tls",
+    "openssl",
+    "openssl-sys",
+    "rayon",
+];
+
+/// The problems found in the program described by `program_metadata` before building it: a
+/// [`ValidationError::HostOnlyDependency`] for each dependency which needs an operating system,
+/// and a [`ValidationError::MissingNoMain`] if the binary built does not set `#![no_main]`.
+pub(crate) fn check(args: &BuildArgs, program_metadata: &Metadata) -> Vec<ValidationError> {
+    let mut problems = host_only_dependencies(args, program_metadata);
+    let Some(package) = program_metadata.root_package() else {
+        return problems;
+    };
+    let binary = if args.binary.is_empty() { &package.name } else { &args.binary };
+    let target = package.targets.iter().find(|target| target.is_bin() && target.name == *binary);
+    if let Some(target) = target {
+        let source = std::fs::read_to_string(&target.src_path).unwrap_or_default();
+        if !source.contains("no_main") {
+            problems.push(ValidationError::MissingNoMain {
+                binary: binary.clone(),
+                path: target.src_path.clone(),
+            });
+        }
+    }
+    problems
+}
+
+/// The dependencies of the program which need an operating system, each with the chain of
+/// dependencies from the program to it. Only the dependencies compiled for every target are
+/// followed, as those of other targets, such as `cfg(not(target_os = "zkvm"))`, may not be built.
+fn host_only_dependencies(args: &BuildArgs, program_metadata: &Metadata) -> Vec<ValidationError> {
+    let (Some(resolve), Some(package)) =
+        (&program_metadata.resolve, program_metadata.root_package())
+    else {
+        return vec![];
+    };
+    let nodes: HashMap<&PackageId, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let names: HashMap<&PackageId, &str> = program_metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package.name.as_str()))
+        .collect();
+
+    // A breadth first search, so that each chain is one of the shortest.
+    let mut parents: HashMap<&PackageId, &PackageId> = HashMap::new();
+    let mut queue = VecDeque::from([&package.id]);
+    let mut problems = vec![];
+    while let Some(id) = queue.pop_front() {
+        let name = names[id];
+        if HOST_ONLY_CRATES.contains(&name) && !args.allowed_std_crates.iter().any(|c| c == name) {
+            let mut chain = vec![name.to_string()];
+            let mut current = id;
+            while let Some(parent) = parents.get(current) {
+                chain.push(names[parent].to_string());
+                current = parent;
+            }
+            chain.reverse();
+            problems
+                .push(ValidationError::HostOnlyDependency { crate_name: name.to_string(), chain });
+            continue;
+        }
+        let Some(node) = nodes.get(id) else { continue };
+        for dep in &node.deps {
+            let always_normal = dep
+                .dep_kinds
+                .iter()
+                .any(|kind| kind.kind == DependencyKind::Normal && kind.target.is_none());
+            if always_normal && dep.pkg != package.id && !parents.contains_key(&dep.pkg) {
+                parents.insert(&dep.pkg, id);
+                queue.push_back(&dep.pkg);
+            }
+        }
+    }
+    problems
+}
+
+/// Check the ELF at `elf_path` for undefined symbols, warning about them, or failing with
+/// [`ValidationError::UndefinedSymbols`] with [`BuildArgs::strict_no_std_check`].
+pub(crate) fn check_elf(args: &BuildArgs, elf_path: &Utf8Path) -> Result<(), BuildError> {
+    let elf = std::fs::read(elf_path)
+        .map_err(|source| BuildError::Artifact { path: elf_path.to_path_buf(), source })?;
+    let symbols = undefined_symbols(&elf);
+    if symbols.is_empty() {
+        return Ok(());
+    }
+    let problem = ValidationError::UndefinedSymbols { elf: elf_path.to_path_buf(), symbols };
+    if args.strict_no_std_check {
+        return Err(BuildError::Invalid(vec![problem]));
+    }
+    problem.warn();
+    Ok(())
+}
+
+/// The names of the undefined symbols in the symbol table of the 32-bit little endian ELF `elf`.
+/// An ELF which cannot be parsed has none.
+fn undefined_symbols(elf: &[u8]) -> Vec<String> {
+    let u16_at =
+        |offset: usize| Some(u16::from_le_bytes(elf.get(offset..offset + 2)?.try_into().ok()?));
+    let u32_at = |offset: usize| {
+        Some(u32::from_le_bytes(elf.get(offset..offset + 4)?.try_into().ok()?) as usize)
+    };
+    let parse = || -> Option<Vec<String>> {
+        // The magic number, then the class and the data encoding: 32-bit, little endian.
+        if elf.get(..6)? != b"\x7fELF\x01\x01" {
+            return None;
+        }
+        let (shoff, shentsize, shnum) = (u32_at(32)?, u16_at(46)? as usize, u16_at(48)? as usize);
+        let section = |index: usize| shoff + index * shentsize;
+        let mut symbols = vec![];
+        for index in 0..shnum {
+            // The section headers of symbol tables, of type SHT_SYMTAB.
+            if u32_at(section(index) + 4)? != 2 {
+                continue;
+            }
+            let (offset, size) = (u32_at(section(index) + 16)?, u32_at(section(index) + 20)?);
+            let strtab = u32_at(section(u32_at(section(index) + 24)?) + 16)?;
+            // The first symbol is the null symbol, and each symbol takes 16 bytes.
+            for symbol in (offset + 16..offset + size).step_by(16) {
+                let (name, shndx) = (u32_at(symbol)?, u16_at(symbol + 14)?);
+                if shndx != 0 || name == 0 {
+                    continue;
+                }
+                let name = elf.get(strtab + name..)?;
+                let end = name.iter().position(|&byte| byte == 0)?;
+                symbols.push(String::from_utf8_lossy(&name[..end]).into_owned());
+            }
+        }
+        Some(symbols)
+    };
+    parse().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use cargo_metadata::camino::Utf8PathBuf;
+
+    use super::*;
+
+    /// Write a package `name` depending on `dependencies` by path, with a binary if `main` is set.
+    fn package(root: &Utf8Path, name: &str, dependencies: &[&str], main: Option<&str>) {
+        let dir = root.join(name);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let dependencies = dependencies
+            .iter()
+            .map(|dependency| format!("{} = {{ path = \"../{}\" }}", dependency, dependency))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let workspace = if main.is_some() { "[workspace]\n" } else { "" };
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "{}[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}\n",
+                workspace, name, dependencies
+            ),
+        )
+        .unwrap();
+        match main {
+            Some(main) => fs::write(dir.join("src/main.rs"), main).unwrap(),
+            None => fs::write(dir.join("src/lib.rs"), "").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        package(&root, "tokio", &[], None);
+        package(&root, "client", &["tokio"], None);
+        package(&root, "program", &["client"], Some("fn main() {}"));
+        let metadata = crate::program_metadata(&root.join("program")).unwrap();
+
+        let args = BuildArgs::default();
+        assert_eq!(
+            check(&args, &metadata),
+            vec![
+                ValidationError::HostOnlyDependency {
+                    crate_name: "tokio".to_string(),
+                    chain: vec!["program".into(), "client".into(), "tokio".into()],
+                },
+                ValidationError::MissingNoMain {
+                    binary: "program".to_string(),
+                    path: root.join("program/src/main.rs"),
+                },
+            ]
+        );
+        assert!(check(&args, &metadata)[0].to_string().contains("program -> client -> tokio"));
+
+        let allowed = BuildArgs { allowed_std_crates: vec!["tokio".to_string()], ..args };
+        fs::write(root.join("program/src/main.rs"), "#![no_main]\nfn main() {}").unwrap();
+        assert_eq!(check(&allowed, &metadata), vec![]);
+    }
+
+    #[test]
+    fn test_undefined_symbols() {
+        // An ELF with a null section, a string table and a symbol table of a null symbol, a
+        // defined symbol and an undefined one.
+        let strtab = b"\0start\0open\0";
+        let symbol = |name: u32, shndx: u16| {
+            let mut symbol = [0u8; 16];
+            symbol[..4].copy_from_slice(&name.to_le_bytes());
+            symbol[14..].copy_from_slice(&shndx.to_le_bytes());
+            symbol
+        };
+        let symtab = [symbol(0, 0), symbol(1, 1), symbol(7, 0)].concat();
+        let (strtab_offset, symtab_offset) = (52, 52 + strtab.len());
+        let shoff = symtab_offset + symtab.len();
+        let section = |kind: u32, offset: usize, size: usize, link: u32| {
+            let mut header = [0u8; 40];
+            header[4..8].copy_from_slice(&kind.to_le_bytes());
+            header[16..20].copy_from_slice(&(offset as u32).to_le_bytes());
+            header[20..24].copy_from_slice(&(size as u32).to_le_bytes());
+            header[24..28].copy_from_slice(&link.to_le_bytes());
+            header
+        };
+        let mut elf = vec![0u8; 52];
+        elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        elf[32..36].copy_from_slice(&(shoff as u32).to_le_bytes());
+        elf[46..48].copy_from_slice(&40u16.to_le_bytes());
+        elf[48..50].copy_from_slice(&3u16.to_le_bytes());
+        elf.extend_from_slice(strtab);
+        elf.extend_from_slice(&symtab);
+        elf.extend_from_slice(&section(0, 0, 0, 0));
+        elf.extend_from_slice(&section(3, strtab_offset, strtab.len(), 0));
+        elf.extend_from_slice(&section(2, symtab_offset, symtab.len(), 1));
+
+        assert_eq!(undefined_symbols(&elf), vec!["open".to_string()]);
+        assert_eq!(undefined_symbols(&elf[..40]), Vec::<String>::new());
+        assert_eq!(undefined_symbols(b"not an elf"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,141 @@
+//! Colors of the crate's own messages printed to a terminal, see [`BuildArgs::color`].
+//!
+//! Only the `[sp1]` prefix and the level of the messages of this crate are styled. The lines
+//! printed by cargo are passed through as is, colored by cargo itself, which is told to use colors
+//! when they are used here with `--color`.
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::BuildArgs;
+
+const RESET: &str = "\x1b[0m";
+const BOLD_GREEN: &str = "\x1b[1;32m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+
+/// Whether the output of a build is colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Color the output when stderr is a terminal, unless `NO_COLOR` is set, or as
+    /// `CARGO_TERM_COLOR` says if it is set to `always` or `never`.
+    #[default]
+    Auto,
+    /// Always color the output.
+    Always,
+    /// Never color the output.
+    Never,
+}
+
+/// Whether a build with `args` colors its output, or `None` if it does when printing to a terminal.
+pub(crate) fn choice(args: &BuildArgs) -> Option<bool> {
+    resolve(args.color, |name| env::var(name).ok())
+}
+
+fn resolve(choice: ColorChoice, lookup: impl Fn(&str) -> Option<String>) -> Option<bool> {
+    match choice {
+        ColorChoice::Always => Some(true),
+        ColorChoice::Never => Some(false),
+        ColorChoice::Auto => match lookup("CARGO_TERM_COLOR").as_deref().map(str::trim) {
+            Some("always") => Some(true),
+            Some("never") => Some(false),
+            _ if lookup("NO_COLOR").is_some_and(|value| !value.is_empty()) => Some(false),
+            _ => None,
+        },
+    }
+}
+
+/// Whether the lines printed to stderr are colored, with the `choice` of the build.
+pub(crate) fn stderr(choice: Option<bool>) -> bool {
+    choice.unwrap_or_else(|| io::stderr().is_terminal())
+}
+
+/// Whether the lines printed to stdout are colored, with the `choice` of the build.
+pub(crate) fn stdout(choice: Option<bool>) -> bool {
+    choice.unwrap_or_else(|| io::stdout().is_terminal())
+}
+
+/// The `--color` flag passing the choice of colors on to cargo.
+pub(crate) fn cargo_flag(color: bool) -> String {
+    format!("--color={}", if color { "always" } else { "never" })
+}
+
+/// A message of this crate, which starts with its `[sp1]` prefix unless it is a continuation, with
+/// the prefix in green if `color`.
+pub(crate) fn info(msg: &str, color: bool) -> String {
+    match prefix_end(msg) {
+        Some(end) if color => format!("{}{}{}{}", BOLD_GREEN, &msg[..end], RESET, &msg[end..]),
+        _ => msg.to_string(),
+    }
+}
+
+/// A warning of this crate, with the level in yellow if `color`.
+pub(crate) fn warning(msg: &str, color: bool) -> String {
+    level("warning", BOLD_YELLOW, msg, color)
+}
+
+fn level(level: &str, style: &str, msg: &str, color: bool) -> String {
+    if color {
+        format!("{}[sp1]{} {}{}:{} {}", BOLD_GREEN, RESET, style, level, RESET, msg)
+    } else {
+        format!("[sp1] {}: {}", level, msg)
+    }
+}
+
+/// The end of the `[sp1]` or `[sp1:<program>]` prefix at the start of `msg`, if any.
+fn prefix_end(msg: &str) -> Option<usize> {
+    if !msg.starts_with("[sp1") {
+        return None;
+    }
+    msg.find(']').map(|end| end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(resolve(ColorChoice::Auto, env(&[])), None);
+        assert_eq!(resolve(ColorChoice::Auto, env(&[("NO_COLOR", "1")])), Some(false));
+        assert_eq!(resolve(ColorChoice::Auto, env(&[("NO_COLOR", "")])), None);
+        assert_eq!(resolve(ColorChoice::Auto, env(&[("CARGO_TERM_COLOR", "auto")])), None);
+        let forced = env(&[("CARGO_TERM_COLOR", "always"), ("NO_COLOR", "1")]);
+        assert_eq!(resolve(ColorChoice::Auto, forced), Some(true));
+        assert_eq!(resolve(ColorChoice::Auto, env(&[("CARGO_TERM_COLOR", "never")])), Some(false));
+        assert_eq!(resolve(ColorChoice::Always, env(&[("NO_COLOR", "1")])), Some(true));
+        assert_eq!(
+            resolve(ColorChoice::Never, env(&[("CARGO_TERM_COLOR", "always")])),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_plain_output() {
+        assert_eq!(info("[sp1] built fibonacci in 1.0s", false), "[sp1] built fibonacci in 1.0s");
+        assert_eq!(info("[sp1:fibonacci] done", false), "[sp1:fibonacci] done");
+        assert_eq!(
+            warning("the docker image is stale", false),
+            "[sp1] warning: the docker image is stale"
+        );
+    }
+
+    #[test]
+    fn test_colored_output() {
+        assert_eq!(info("[sp1:fib] done", true), "\x1b[1;32m[sp1:fib]\x1b[0m done");
+        assert_eq!(info("continued", true), "continued");
+        let warning = warning("stale", true);
+        assert!(warning.contains("\x1b[1;33mwarning:\x1b[0m"));
+        assert!(warning.ends_with(" stale"));
+    }
+}
@@ -0,0 +1,219 @@
+//! The one-line summary printed for each ELF at the end of a build, and the total of builds of
+//! several ELFs.
+//!
+//! ```text
+//! [sp1] built fibonacci-program (bls) in 42.3s -> elf/fibonacci.elf (3.1 MiB, sha256:ab12cd34…)
+//! ```
+
+use std::{env, time::Duration};
+
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use serde_json::{json, Value};
+
+use crate::{output, BuildArgs, BuildOutput, ElfArtifact};
+
+/// How the ELFs of a build came to be, which the summary spells out so that reused ELFs are
+/// obvious.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Built,
+    /// Cargo was not run, see [`BuildOutput::fresh`].
+    Fresh,
+    /// See [`BuildOutput::skipped`].
+    Skipped,
+    /// See [`BuildOutput::stale`].
+    Stale,
+}
+
+impl Status {
+    fn of(output: &BuildOutput) -> Self {
+        if output.stale {
+            Status::Stale
+        } else if output.skipped {
+            Status::Skipped
+        } else if output.fresh {
+            Status::Fresh
+        } else {
+            Status::Built
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Status::Built => "built",
+            Status::Fresh => "fresh",
+            Status::Skipped => "skipped",
+            Status::Stale => "stale",
+        }
+    }
+}
+
+/// Print the summary of each ELF of `output`, built by `args` for the program `program`, followed
+/// by a total if there are several. The ELFs of a program are compiled by the same cargo command,
+/// so with several of them the time of the build is only in the total, rather than in each line.
+/// Nothing is printed for a dry run, which builds no ELF.
+pub(crate) fn print(program: &str, args: &BuildArgs, output: &BuildOutput) {
+    let artifacts: Vec<_> = output.artifacts.iter().filter(|a| a.size.is_some()).collect();
+    let elapsed = output.timings.total();
+    let elapsed_per_elf = (artifacts.len() == 1).then_some(elapsed);
+    for artifact in &artifacts {
+        let line = line(program, args, Status::of(output), elapsed_per_elf, artifact);
+        output::summary(&line, json(program, Status::of(output), elapsed_per_elf, artifact, &line));
+    }
+    if artifacts.len() > 1 {
+        print_total(&artifacts, elapsed);
+    }
+}
+
+/// Print the total of the ELFs of several builds, which took `elapsed` in all.
+pub(crate) fn print_total(artifacts: &[&ElfArtifact], elapsed: Duration) {
+    let size = artifacts.iter().filter_map(|artifact| artifact.size).sum();
+    let line = format!(
+        "[sp1] built {} ELFs in {:.1}s ({} in total)",
+        artifacts.len(),
+        elapsed.as_secs_f64(),
+        human_size(size)
+    );
+    output::summary(
+        &line,
+        json!({
+            "type": "summary",
+            "message": line,
+            "total": true,
+            "elfs": artifacts.len(),
+            "size": size,
+            "elapsed_secs": elapsed.as_secs_f64(),
+        }),
+    );
+}
+
+fn line(
+    program: &str,
+    args: &BuildArgs,
+    status: Status,
+    elapsed: Option<Duration>,
+    artifact: &ElfArtifact,
+) -> String {
+    let features = if args.all_features {
+        " (all features)".to_string()
+    } else if args.features.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", args.features.join(", "))
+    };
+    let time =
+        elapsed.map_or(String::new(), |elapsed| format!(" in {:.1}s", elapsed.as_secs_f64()));
+    let what = match status {
+        Status::Built => format!("built {}{}{}", program, features, time),
+        Status::Fresh => format!("reused the unchanged build of {}{}{}", program, features, time),
+        Status::Skipped => {
+            format!("SKIPPED building {}{}, reusing the ELF of a previous build", program, features)
+        }
+        Status::Stale => format!(
+            "FAILED to build {}{}, reusing the STALE ELF of a previous build",
+            program, features
+        ),
+    };
+    let mut details = vec![human_size(artifact.size.unwrap_or_default())];
+    if let Some(sha256) = &artifact.sha256 {
+        details.push(format!("sha256:{}…", &sha256[..sha256.len().min(8)]));
+    }
+    if let Some(cycles) = artifact.cycles {
+        details.push(format!("{} cycles", cycles));
+    }
+    format!("[sp1] {} -> {} ({})", what, display_path(&artifact.path), details.join(", "))
+}
+
+fn json(
+    program: &str,
+    status: Status,
+    elapsed: Option<Duration>,
+    artifact: &ElfArtifact,
+    line: &str,
+) -> Value {
+    json!({
+        "type": "summary",
+        "message": line,
+        "program": program,
+        "status": status.name(),
+        "elf_path": artifact.path,
+        "size": artifact.size,
+        "sha256": artifact.sha256,
+        "cycles": artifact.cycles,
+        "elapsed_secs": elapsed.map(|elapsed| elapsed.as_secs_f64()),
+    })
+}
+
+/// `path` relative to the current directory if it is within it, such as `elf/fibonacci.elf`
+/// within the program directory.
+fn display_path(path: &Utf8Path) -> Utf8PathBuf {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Utf8Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// `size` in bytes, in the largest binary unit in which it is at least 1.
+pub(crate) fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact() -> ElfArtifact {
+        ElfArtifact {
+            path: "/program/elf/fibonacci.elf".into(),
+            size: Some(3_250_586),
+            sha256: Some("ab12cd34ef56".to_string()),
+            cycles: None,
+        }
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(2048), "2.0 KiB");
+        assert_eq!(human_size(3_250_586), "3.1 MiB");
+        assert_eq!(human_size(5 << 30), "5.0 GiB");
+    }
+
+    #[test]
+    fn test_summary_line() {
+        let args = BuildArgs { features: vec!["bls".to_string()], ..Default::default() };
+        let elapsed = Some(Duration::from_millis(42_340));
+        assert_eq!(
+            line("fibonacci-program", &args, Status::Built, elapsed, &artifact()),
+            "[sp1] built fibonacci-program (bls) in 42.3s -> /program/elf/fibonacci.elf (3.1 MiB, \
+             sha256:ab12cd34…)"
+        );
+
+        let args = BuildArgs::default();
+        let executed = ElfArtifact { cycles: Some(9000), ..artifact() };
+        assert!(line("fib", &args, Status::Fresh, elapsed, &executed)
+            .starts_with("[sp1] reused the unchanged build of fib in 42.3s -> "));
+        assert!(line("fib", &args, Status::Fresh, elapsed, &executed).ends_with(", 9000 cycles)"));
+        assert!(line("fib", &args, Status::Skipped, elapsed, &artifact())
+            .starts_with("[sp1] SKIPPED building fib, reusing the ELF of a previous build -> "));
+        assert!(line("fib", &args, Status::Stale, elapsed, &artifact()).starts_with(
+            "[sp1] FAILED to build fib, reusing the STALE ELF of a previous build -> "
+        ));
+
+        // One of several ELFs of the program, whose time is in the total.
+        assert!(line("fib", &args, Status::Built, None, &artifact())
+            .starts_with("[sp1] built fib -> /program/elf/fibonacci.elf"));
+        let json = json("fib", Status::Built, None, &artifact(), "");
+        assert_eq!(json["elapsed_secs"], Value::Null);
+    }
+}
@@ -0,0 +1,111 @@
+//! The check that the ELFs are not copied into a target directory, see
+//! [`BuildArgs::allow_output_in_target`].
+//!
+//! `cargo clean` deletes the target directory, along with ELFs copied into it, such as with an
+//! output directory of `target/sp1`. The next build copies them again, so nothing fails, but
+//! anything relying on the ELFs in between, such as a comparison with the ELFs of a previous
+//! build, finds them missing.
+
+use std::{env, path::Path};
+
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Metadata,
+};
+
+use crate::{host_path, rerun, BuildArgs};
+
+/// The target directory containing `elf_dir`, of the program or of the host crate whose build
+/// script builds it, unless `args` allow it. The `OUT_DIR` of that build script, which is rebuilt
+/// along with the crate embedding the ELF, is not reported.
+pub(crate) fn containing_target_dir(
+    args: &BuildArgs,
+    program_metadata: &Metadata,
+    elf_dir: &Utf8Path,
+) -> Option<Utf8PathBuf> {
+    if args.allow_output_in_target {
+        return None;
+    }
+    if let Some(out_dir) = rerun::in_build_script().then(|| env::var("OUT_DIR").ok()).flatten() {
+        if containing(elf_dir, &[Utf8PathBuf::from(out_dir)]).is_some() {
+            return None;
+        }
+    }
+    let mut target_dirs = vec![program_metadata.target_directory.clone()];
+    target_dirs.extend(host_target_dir());
+    containing(elf_dir, &target_dirs)
+}
+
+/// The target directory of the host crate whose build script is running: the ancestor of its
+/// `OUT_DIR` which cargo marks with a `CACHEDIR.TAG`.
+fn host_target_dir() -> Option<Utf8PathBuf> {
+    if !rerun::in_build_script() {
+        return None;
+    }
+    let out_dir = Utf8PathBuf::from(env::var("OUT_DIR").ok()?);
+    out_dir.ancestors().find(|dir| dir.join("CACHEDIR.TAG").is_file()).map(Utf8Path::to_path_buf)
+}
+
+/// The first of `target_dirs` containing `dir`, comparing canonical paths so that a path through a
+/// symbolic link is recognized.
+fn containing(dir: &Utf8Path, target_dirs: &[Utf8PathBuf]) -> Option<Utf8PathBuf> {
+    let dir = canonical(dir.as_std_path());
+    target_dirs
+        .iter()
+        .find(|target_dir| dir.starts_with(canonical(target_dir.as_std_path())))
+        .cloned()
+}
+
+/// The canonical form of `path`, which may not exist yet: that of its closest existing ancestor,
+/// joined with the rest of it.
+fn canonical(path: &Path) -> std::path::PathBuf {
+    let mut missing = vec![];
+    for ancestor in path.ancestors() {
+        if let Ok(canonical) = host_path::canonicalize(ancestor) {
+            return missing.iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+        missing.extend(ancestor.file_name());
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_containing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let target_dir = root.join("program/target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let target_dirs = [root.join("host/target"), target_dir.clone()];
+
+        assert_eq!(containing(&root.join("program/target/sp1"), &target_dirs), Some(target_dir));
+        assert!(containing(&root.join("program/target/a/b/../c"), &target_dirs).is_some());
+        assert_eq!(containing(&root.join("program/elf"), &target_dirs), None);
+        assert_eq!(containing(&root.join("program/targets"), &target_dirs), None);
+        assert_eq!(containing(&root.join("program/target/../elf"), &target_dirs), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_containing_symlinked() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("program/target/elf")).unwrap();
+        std::os::unix::fs::symlink(root.join("program/target/elf"), root.join("program/elf"))
+            .unwrap();
+        std::os::unix::fs::symlink(root.join("program/target"), root.join("linked-target"))
+            .unwrap();
+
+        let target_dirs = [root.join("program/target")];
+        assert!(containing(&root.join("program/elf"), &target_dirs).is_some());
+        assert!(containing(&root.join("program/elf/nested"), &target_dirs).is_some());
+        let linked = [root.join("linked-target")];
+        assert!(containing(&root.join("program/target/sp1"), &linked).is_some());
+        assert!(containing(&root.join("program/src"), &linked).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! Utilities for the tests of crates building SP1 programs, enabled by the `testing` feature.
+//!
+//! Tests which build programs need the succinct toolchain, or nightly with `rust-src` for
+//! [`BuildArgs::toolchain_fallback`]. They are marked `#[ignore]`, run with `--ignored` by the CI
+//! jobs which install a toolchain, and check it with [`require_toolchain`] so that they fail with
+//! the reason rather than pass without building anything.
+//!
+//! ```no_run
+//! use sp1_build::{testing::FixtureProgram, BuildArgs};
+//!
+//! let program = FixtureProgram::minimal();
+//! let elf = program.build(&BuildArgs::default());
+//! assert!(elf.is_file());
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use cargo_metadata::camino::Utf8PathBuf;
+use tempfile::TempDir;
+
+use crate::{build_program_with_output, BuildArgs, BuildOutput, ValidationError};
+
+/// The package name of the programs written by [`FixtureProgram`].
+pub const FIXTURE_PACKAGE: &str = "fixture-program";
+
+/// The error [`FixtureProgram::with_compile_error`] makes the program fail with.
+pub const COMPILE_ERROR: &str = "deliberate compile error of the fixture program";
+
+/// The `main.rs` of [`FixtureProgram::minimal`].
+const MINIMAL_MAIN: &str = "#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+pub fn main() {}
+";
+
+/// The `main.rs` of [`FixtureProgram::bare`], which only needs `core`.
+const BARE_MAIN: &str = "#![no_std]
+#![no_main]
+
+#[no_mangle]
+pub extern \"C\" fn _start() -> ! {
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+";
+
+/// Check that `args` can build the program in `program_dir`, at the start of a test building it.
+///
+/// # Panics
+///
+/// If the arguments are invalid, such as when no toolchain is installed to build with.
+pub fn require_toolchain(args: &BuildArgs, program_dir: impl AsRef<Path>) {
+    if let Err(errors) = args.validate(program_dir.as_ref()) {
+        let missing = errors.contains(&ValidationError::ToolchainMissing);
+        let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+        if missing {
+            panic!(
+                "cannot build the program: {}. Install the succinct toolchain, or nightly with \
+                 `rust-src` for `toolchain_fallback`",
+                errors
+            );
+        }
+        panic!("cannot build the program: {}", errors);
+    }
+}
+
+/// Copy the fixture in `dir`, such as a workspace of `tests/fixtures`, into a temporary directory,
+/// so that building it leaves the sources as they are. `{sp1-build}` in its files is replaced with
+/// the directory of this crate, for the fixtures which depend on it. `target` directories and lock
+/// files are not copied.
+pub fn copy_fixture(dir: impl AsRef<Path>) -> TempDir {
+    fn copy(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let (path, name) = (entry.path(), entry.file_name());
+            if name == "target" || name == "Cargo.lock" {
+                continue;
+            }
+            if path.is_dir() {
+                copy(&path, &to.join(&name));
+            } else {
+                let contents = fs::read_to_string(&path).unwrap();
+                let contents = contents.replace("{sp1-build}", env!("CARGO_MANIFEST_DIR"));
+                fs::write(to.join(&name), contents).unwrap();
+            }
+        }
+    }
+    let copy_dir = tempfile::tempdir().expect("failed to create the fixture directory");
+    copy(dir.as_ref(), copy_dir.path());
+    copy_dir
+}
+
+/// A program written into a temporary directory, which is removed when it is dropped.
+///
+/// The program is a workspace of its own named [`FIXTURE_PACKAGE`], so that it is compiled into a
+/// target directory within the temporary directory, and its ELF is written there as well unless
+/// the arguments set another output directory.
+#[derive(Debug)]
+pub struct FixtureProgram {
+    dir: TempDir,
+}
+
+impl FixtureProgram {
+    /// A program with an empty `main`, using the entrypoint of `sp1-zkvm` of the same version as
+    /// this crate, which is fetched from crates.io.
+    pub fn minimal() -> Self {
+        Self::with_source(MINIMAL_MAIN)
+    }
+
+    /// A program with the given `main.rs`, depending on `sp1-zkvm` of the same version as this
+    /// crate.
+    pub fn with_source(main: &str) -> Self {
+        let dependency = format!("sp1-zkvm = \"{}\"\n", env!("CARGO_PKG_VERSION"));
+        Self::write(&dependency, main)
+    }
+
+    /// A `no_std` program without dependencies, which builds without network access.
+    pub fn bare() -> Self {
+        Self::write("", BARE_MAIN)
+    }
+
+    fn write(dependencies: &str, main: &str) -> Self {
+        let dir = tempfile::tempdir().expect("failed to create the fixture directory");
+        let manifest = format!(
+            "[workspace]\n\n[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\n{}",
+            FIXTURE_PACKAGE, dependencies
+        );
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        fs::write(dir.path().join("src/main.rs"), main).unwrap();
+        Self { dir }
+    }
+
+    /// Adds a `compile_error!` to the program, so that building it fails with [`COMPILE_ERROR`].
+    pub fn with_compile_error(self) -> Self {
+        let main = fs::read_to_string(self.main_path()).unwrap();
+        fs::write(self.main_path(), format!("{}\ncompile_error!(\"{}\");\n", main, COMPILE_ERROR))
+            .unwrap();
+        self
+    }
+
+    /// The directory of the program.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The `Cargo.toml` of the program.
+    pub fn manifest_path(&self) -> PathBuf {
+        self.dir().join("Cargo.toml")
+    }
+
+    /// The `src/main.rs` of the program.
+    pub fn main_path(&self) -> PathBuf {
+        self.dir().join("src/main.rs")
+    }
+
+    /// Builds the program with `args`, returning the [`BuildOutput`] or the error of the build.
+    pub fn try_build(&self, args: &BuildArgs) -> Result<BuildOutput> {
+        build_program_with_output(args, self.dir())
+    }
+
+    /// Builds the program with `args`, returning the path to its ELF.
+    ///
+    /// # Panics
+    ///
+    /// If the build fails or does not write the ELF.
+    pub fn build(&self, args: &BuildArgs) -> Utf8PathBuf {
+        let output = self
+            .try_build(args)
+            .unwrap_or_else(|err| panic!("failed to build the fixture program: {:#}", err));
+        let elf_path = output.elf_path().to_path_buf();
+        assert!(elf_path.is_file(), "the build did not write the ELF at {}", elf_path);
+        elf_path
+    }
+}
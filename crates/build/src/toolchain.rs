@@ -0,0 +1,522 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    semver::Version,
+};
+use dirs::home_dir;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{host_path, lock::BuildLock, output, CancellationToken};
+
+/// The name of the rustup toolchain programs are compiled with, set as `RUSTUP_TOOLCHAIN`.
+pub(crate) const TOOLCHAIN_NAME: &str = "succinct";
+
+/// The toolchain a build falls back to with [`crate::BuildArgs::toolchain_fallback`].
+pub(crate) const FALLBACK_TOOLCHAIN_NAME: &str = "nightly";
+
+/// The specification of the zkVM target for the fallback toolchain, which has no builtin target for
+/// it. This mirrors the target of the succinct toolchain.
+const FALLBACK_TARGET_SPEC: &str = r#"{
+  "arch": "riscv32",
+  "cpu": "generic-rv32",
+  "data-layout": "e-m:e-p:32:32-i64:64-n32-S128",
+  "eh-frame-header": false,
+  "emit-debug-gdb-scripts": false,
+  "features": "+m",
+  "linker": "rust-lld",
+  "linker-flavor": "gnu-lld",
+  "llvm-abiname": "ilp32",
+  "llvm-target": "riscv32",
+  "max-atomic-width": 64,
+  "os": "zkvm",
+  "panic-strategy": "abort",
+  "relocation-model": "static",
+  "singlethread": true,
+  "target-pointer-width": 32,
+  "vendor": "succinct"
+}
+"#;
+
+/// Skips checking that the toolchain is installed before building when set, for rustup layouts
+/// which the check does not recognize.
+pub(crate) const SKIP_CHECK_ENV: &str = "SP1_SKIP_TOOLCHAIN_CHECK";
+
+/// How to install the toolchain, following a description of the problem.
+pub(crate) const INSTALL_HELP: &str = "install it with `curl -L https://sp1.succinct.xyz | bash` \
+                                       followed by `sp1up`, or build in Docker with `--docker`";
+
+/// Whether the toolchain should be checked before building, see [`SKIP_CHECK_ENV`], failing if it
+/// is not set to a boolean.
+pub(crate) fn check_enabled() -> Result<bool> {
+    match env::var(SKIP_CHECK_ENV) {
+        Ok(value) if !value.trim().is_empty() => {
+            Ok(!crate::overrides::parse_bool(SKIP_CHECK_ENV, &value)?)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// The toolchain a local build compiles with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Toolchain {
+    /// The succinct toolchain.
+    Succinct,
+    /// A nightly toolchain building the standard library from source, used when the succinct
+    /// toolchain is missing with [`crate::BuildArgs::toolchain_fallback`].
+    NightlyFallback,
+}
+
+impl Toolchain {
+    /// The toolchain to build with `args`.
+    pub(crate) fn select(args: &crate::BuildArgs) -> Self {
+        if args.toolchain_fallback &&
+            !args.docker &&
+            // An invalid `SP1_SKIP_TOOLCHAIN_CHECK` is reported by the validation.
+            check_enabled().unwrap_or(true) &&
+            !is_installed(TOOLCHAIN_NAME)
+        {
+            Toolchain::NightlyFallback
+        } else {
+            Toolchain::Succinct
+        }
+    }
+
+    /// The name of the toolchain, set as `RUSTUP_TOOLCHAIN`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Toolchain::Succinct => TOOLCHAIN_NAME,
+            Toolchain::NightlyFallback => FALLBACK_TOOLCHAIN_NAME,
+        }
+    }
+}
+
+/// The path of the target specification the fallback toolchain compiles with, in `target_dir`.
+/// Cargo names the target after the file, so the ELFs end up in the same directory as with the
+/// succinct toolchain.
+pub(crate) fn fallback_target_spec(target_dir: &Utf8Path) -> Utf8PathBuf {
+    target_dir.join(format!("{}.json", crate::BUILD_TARGET))
+}
+
+/// The unstable flags cargo of the fallback toolchain builds with: building the standard library
+/// from source, and allowing the target specification if the version of cargo requires it.
+pub(crate) fn fallback_cargo_flags() -> Vec<String> {
+    let mut flags = vec!["-Z".to_string(), "build-std=core,alloc".to_string()];
+    let unstable_flags = Command::new("cargo")
+        .arg(format!("+{}", FALLBACK_TOOLCHAIN_NAME))
+        .args(["-Z", "help"])
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .output();
+    if unstable_flags.is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains("json-target-spec"))
+    {
+        flags.extend(["-Z".to_string(), "json-target-spec".to_string()]);
+    }
+    flags
+}
+
+/// Write the target specification of the fallback toolchain to `target_dir`.
+pub(crate) fn write_fallback_target_spec(target_dir: &Utf8Path) -> Result<()> {
+    let path = fallback_target_spec(target_dir);
+    fs::create_dir_all(target_dir)
+        .and_then(|()| fs::write(&path, FALLBACK_TARGET_SPEC))
+        .with_context(|| format!("failed to write the target specification {}", path))
+}
+
+/// The names of the files rustup reads the toolchain of a directory from.
+const TOOLCHAIN_FILES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+/// The rustup toolchain file which pins the toolchain of the program in `program_dir`, if any: the
+/// first found in the program directory or its ancestors, up to `workspace_root`.
+pub(crate) fn toolchain_file(
+    program_dir: &Utf8Path,
+    workspace_root: &Utf8Path,
+) -> Option<Utf8PathBuf> {
+    // The workspace root is canonical.
+    let program_dir = host_path::canonicalize(program_dir.as_std_path())
+        .ok()
+        .and_then(|dir| Utf8PathBuf::from_path_buf(dir).ok())
+        .unwrap_or_else(|| program_dir.to_path_buf());
+    for dir in program_dir.ancestors() {
+        let file = TOOLCHAIN_FILES.iter().map(|name| dir.join(name)).find(|file| file.is_file());
+        if file.is_some() || dir == workspace_root {
+            return file;
+        }
+    }
+    None
+}
+
+/// Whether the toolchain named `name` is installed with rustup: either in the `toolchains`
+/// directory of rustup, which is where `rustup toolchain link` puts it, or as listed by rustup. A
+/// channel such as `nightly` is also found under its full name, such as
+/// `nightly-x86_64-unknown-linux-gnu`.
+pub(crate) fn is_installed(name: &str) -> bool {
+    if rustup_home().is_some_and(|rustup_home| installed_in(&rustup_home, name)) {
+        return true;
+    }
+    let Ok(output) = Command::new("rustup").args(["toolchain", "list"]).output() else {
+        return false;
+    };
+    String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+        line.split_whitespace().next().is_some_and(|toolchain| is_named(toolchain, name))
+    })
+}
+
+fn is_named(toolchain: &str, name: &str) -> bool {
+    toolchain == name || toolchain.strip_prefix(name).is_some_and(|host| host.starts_with('-'))
+}
+
+/// The directory rustup is installed in: `RUSTUP_HOME`, or `~/.rustup`.
+fn rustup_home() -> Option<PathBuf> {
+    env::var_os("RUSTUP_HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".rustup")))
+}
+
+fn installed_in(rustup_home: &Path, name: &str) -> bool {
+    let Ok(entries) = fs::read_dir(rustup_home.join("toolchains")) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        entry.path().is_dir() && entry.file_name().to_str().is_some_and(|dir| is_named(dir, name))
+    })
+}
+
+/// The sysroot of the installed toolchain `name`, as printed by `rustc --print sysroot`.
+pub(crate) fn sysroot(name: &str) -> Option<PathBuf> {
+    let output = Command::new("rustc")
+        .arg(format!("+{}", name))
+        .args(["--print", "sysroot"])
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!sysroot.is_empty()).then(|| PathBuf::from(sysroot))
+}
+
+/// The version of an installed toolchain, as reported by `rustc --version --verbose`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ToolchainVersion {
+    /// The first line of the output, such as `rustc 1.79.0-dev (a0b1c2d3e 2024-06-01)`.
+    pub(crate) line: String,
+    /// The version of rustc, such as `1.79.0-dev`.
+    pub(crate) release: String,
+    /// The commit rustc was built from, if known.
+    pub(crate) commit_hash: Option<String>,
+}
+
+impl ToolchainVersion {
+    fn parse(output: &str) -> Option<Self> {
+        let line = output.lines().next()?.trim().to_string();
+        let field = |name: &str| {
+            output.lines().find_map(|l| l.strip_prefix(name).map(|value| value.trim().to_string()))
+        };
+        let release = field("release:")?;
+        let commit_hash = field("commit-hash:").filter(|hash| hash != "unknown");
+        Some(Self { line, release, commit_hash })
+    }
+
+    /// The semantic version of rustc, ignoring the pre-release and any other suffix the release
+    /// has, such as the `-dev` of the succinct toolchain. A missing patch version counts as 0.
+    pub(crate) fn version(&self) -> Option<Version> {
+        let end = self.release.find(|c: char| !c.is_ascii_digit() && c != '.');
+        let mut numbers = self.release[..end.unwrap_or(self.release.len())]
+            .split('.')
+            .filter(|n| !n.is_empty())
+            .map(str::parse::<u64>);
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next()?.ok()?;
+        let patch = numbers.next().transpose().ok()?.unwrap_or(0);
+        Some(Version::new(major, minor, patch))
+    }
+
+    /// Whether this is the version pinned as `pin`: the release, such as `1.79.0-dev`, the whole
+    /// first line of `rustc --version`, or a prefix of at least 7 characters of the commit hash.
+    pub(crate) fn matches(&self, pin: &str) -> bool {
+        let pin = pin.trim();
+        pin == self.release ||
+            pin == self.line ||
+            (pin.len() >= 7 && self.commit_hash.as_deref().is_some_and(|h| h.starts_with(pin)))
+    }
+}
+
+impl std::fmt::Display for ToolchainVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.line)
+    }
+}
+
+/// The versions of the toolchains read by this process, by name.
+fn versions() -> &'static Mutex<HashMap<String, ToolchainVersion>> {
+    static VERSIONS: OnceLock<Mutex<HashMap<String, ToolchainVersion>>> = OnceLock::new();
+    VERSIONS.get_or_init(Mutex::default)
+}
+
+/// Forget the versions of the toolchains read by this process, see
+/// [`crate::invalidate_metadata_cache`].
+pub(crate) fn invalidate_versions() {
+    versions().lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// The version of the installed toolchain named `name`. It is read once per process: a toolchain
+/// which is missing is looked up again, but one updated while the process runs is only seen after
+/// [`crate::invalidate_metadata_cache`].
+pub(crate) fn installed_version(name: &str) -> Result<ToolchainVersion> {
+    if let Some(version) = versions().lock().unwrap_or_else(|e| e.into_inner()).get(name) {
+        return Ok(version.clone());
+    }
+    let stdout = run(Command::new("rustc")
+        .arg(format!("+{}", name))
+        .args(["--version", "--verbose"])
+        .env_remove("RUSTUP_TOOLCHAIN"))?;
+    let version = ToolchainVersion::parse(&stdout)
+        .ok_or_else(|| anyhow!("failed to parse the version of the {} toolchain", name))?;
+    versions().lock().unwrap_or_else(|e| e.into_inner()).insert(name.into(), version.clone());
+    Ok(version)
+}
+
+/// The latest release of the prebuilt toolchains, as installed by `sp1up`.
+const RELEASES_URL: &str = "https://api.github.com/repos/succinctlabs/rust/releases/latest";
+
+/// A prebuilt toolchain for the host.
+#[derive(Debug, PartialEq, Eq)]
+struct Release {
+    tag: String,
+    url: String,
+    sha256: String,
+}
+
+/// Install the latest prebuilt toolchain for the host as `sp1up` does: download it to
+/// `~/.sp1/toolchains`, verify its checksum, unpack it and link it with rustup. Concurrent builds
+/// wait for the installation rather than installing it again.
+pub(crate) fn install(cancel: Option<&CancellationToken>) -> Result<()> {
+    let home = home_dir().ok_or_else(|| anyhow!("failed to find the home directory"))?;
+    let toolchains_dir = Utf8PathBuf::from_path_buf(home.join(".sp1").join("toolchains"))
+        .map_err(|dir| anyhow!("{} is not valid UTF-8", dir.display()))?;
+    let _lock = BuildLock::acquire(&toolchains_dir, cancel)?;
+    if is_installed(TOOLCHAIN_NAME) {
+        return Ok(());
+    }
+
+    let target = host_target()
+        .ok_or_else(|| anyhow!("no prebuilt toolchain is published for this platform"))?;
+    output::info(&format!(
+        "[sp1] the {} toolchain is not installed, installing the latest release for {}",
+        TOOLCHAIN_NAME, target
+    ));
+    let release = latest_release(&target)?;
+
+    let archive = toolchains_dir.join(format!("rust-toolchain-{}.tar.gz", target));
+    output::info(&format!("[sp1] downloading {}", release.url));
+    run(Command::new("curl").args(["-sSfL", "-o", archive.as_str(), &release.url]))?;
+    let digest = hex::encode(Sha256::digest(fs::read(&archive)?));
+    if digest != release.sha256 {
+        let _ = fs::remove_file(&archive);
+        bail!("the checksum of {} is {}, expected {}", release.url, digest, release.sha256);
+    }
+    output::info(&format!("[sp1] verified the sha256 checksum {}", digest));
+
+    let toolchain_dir = toolchains_dir.join(&release.tag);
+    let _ = fs::remove_dir_all(&toolchain_dir);
+    fs::create_dir_all(&toolchain_dir)?;
+    output::info(&format!("[sp1] unpacking the toolchain into {}", toolchain_dir));
+    run(Command::new("tar").args(["-xzf", archive.as_str(), "-C", toolchain_dir.as_str()]))?;
+    let _ = fs::remove_file(&archive);
+
+    #[cfg(unix)]
+    for dir in
+        [toolchain_dir.join("bin"), toolchain_dir.join("lib/rustlib").join(&target).join("bin")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            if entry.path().is_file() {
+                fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o755))?;
+            }
+        }
+    }
+
+    run(Command::new("rustup").args([
+        "toolchain",
+        "link",
+        TOOLCHAIN_NAME,
+        toolchain_dir.as_str(),
+    ]))?;
+    invalidate_versions();
+    output::info(&format!("[sp1] installed the {} toolchain {}", TOOLCHAIN_NAME, release.tag));
+    Ok(())
+}
+
+/// The target triple of the host, if prebuilt toolchains are published for it.
+pub(crate) fn host_target() -> Option<String> {
+    let os = match env::consts::OS {
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        _ => return None,
+    };
+    match env::consts::ARCH {
+        arch @ ("x86_64" | "aarch64") => Some(format!("{}-{}", arch, os)),
+        _ => None,
+    }
+}
+
+/// Fetch the latest release of the toolchain for `target`. `GITHUB_TOKEN` is used if set, to avoid
+/// GitHub's rate limits.
+fn latest_release(target: &str) -> Result<Release> {
+    let mut command = Command::new("curl");
+    command.args(["-sSfL", "-H", "User-Agent: sp1-build", RELEASES_URL]);
+    if let Ok(token) = env::var("GITHUB_TOKEN") {
+        command.args(["-H", &format!("Authorization: token {}", token)]);
+    }
+    let stdout = run(&mut command)?;
+    let release = serde_json::from_str(&stdout).context("failed to parse the latest release")?;
+    parse_release(&release, target)
+}
+
+/// Find the toolchain for `target` in a release as returned by the GitHub API, which publishes the
+/// checksum of each asset as its `digest`.
+fn parse_release(release: &Value, target: &str) -> Result<Release> {
+    let tag = release["tag_name"].as_str().ok_or_else(|| anyhow!("the release has no tag"))?;
+    let name = format!("rust-toolchain-{}.tar.gz", target);
+    let asset = release["assets"]
+        .as_array()
+        .and_then(|assets| assets.iter().find(|asset| asset["name"] == name.as_str()))
+        .ok_or_else(|| anyhow!("the release {} has no toolchain for {}", tag, target))?;
+    let url = asset["browser_download_url"]
+        .as_str()
+        .ok_or_else(|| anyhow!("the release {} has no download URL for {}", tag, name))?;
+    let sha256 = asset["digest"]
+        .as_str()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+        .ok_or_else(|| anyhow!("the release {} publishes no checksum for {}", tag, name))?;
+    Ok(Release { tag: tag.to_string(), url: url.to_string(), sha256: sha256.to_string() })
+}
+
+/// Run `command` to completion, returning its stdout, or an error with its stderr if it fails.
+fn run(command: &mut Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().with_context(|| format!("failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_installed_in_rustup_home() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!installed_in(dir.path(), TOOLCHAIN_NAME));
+        std::fs::create_dir_all(dir.path().join("toolchains").join(TOOLCHAIN_NAME)).unwrap();
+        assert!(installed_in(dir.path(), TOOLCHAIN_NAME));
+        assert!(!installed_in(dir.path(), "succinct-v2"));
+        assert!(!installed_in(dir.path(), FALLBACK_TOOLCHAIN_NAME));
+        std::fs::create_dir_all(dir.path().join("toolchains/nightly-x86_64-unknown-linux-gnu"))
+            .unwrap();
+        assert!(installed_in(dir.path(), FALLBACK_TOOLCHAIN_NAME));
+        assert!(!installed_in(dir.path(), "night"));
+    }
+
+    #[test]
+    fn test_fallback_target_spec() {
+        let spec: Value = serde_json::from_str(FALLBACK_TARGET_SPEC).unwrap();
+        assert_eq!(spec["os"], "zkvm");
+        assert_eq!(
+            fallback_target_spec(Utf8Path::new("target")).file_stem(),
+            Some(crate::BUILD_TARGET)
+        );
+    }
+
+    #[test]
+    fn test_toolchain_file() {
+        let root = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(root.path()).unwrap();
+        let workspace = root.join("workspace");
+        let program = workspace.join("program");
+        fs::create_dir_all(&program).unwrap();
+
+        // Files above the workspace root do not apply.
+        fs::write(root.join("rust-toolchain"), "stable").unwrap();
+        assert_eq!(toolchain_file(&program, &workspace), None);
+
+        fs::write(workspace.join("rust-toolchain.toml"), "[toolchain]\nchannel = \"stable\"")
+            .unwrap();
+        assert_eq!(
+            toolchain_file(&program, &workspace),
+            Some(workspace.join("rust-toolchain.toml"))
+        );
+
+        fs::write(program.join("rust-toolchain"), "stable").unwrap();
+        assert_eq!(toolchain_file(&program, &workspace), Some(program.join("rust-toolchain")));
+    }
+
+    #[test]
+    fn test_toolchain_version() {
+        let output = "rustc 1.79.0-dev (a0b1c2d3e 2024-06-01)\nbinary: rustc\n\
+                      commit-hash: a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9\n\
+                      host: x86_64-unknown-linux-gnu\nrelease: 1.79.0-dev\nLLVM version: 18.1.7\n";
+        let version = ToolchainVersion::parse(output).unwrap();
+        assert_eq!(version.release, "1.79.0-dev");
+        assert!(version.matches("1.79.0-dev"));
+        assert!(version.matches(" rustc 1.79.0-dev (a0b1c2d3e 2024-06-01)"));
+        assert!(version.matches("a0b1c2d3e4"));
+        assert!(!version.matches("a0b1"));
+        assert!(!version.matches("1.80.0-dev"));
+        assert!(ToolchainVersion::parse("rustc 1.79.0").is_none());
+
+        assert_eq!(version.version(), Some(Version::new(1, 79, 0)));
+        let release = |release: &str| ToolchainVersion {
+            line: String::new(),
+            release: release.to_string(),
+            commit_hash: None,
+        };
+        assert_eq!(release("1.81-succinct").version(), Some(Version::new(1, 81, 0)));
+        assert_eq!(release("1.82.0-nightly").version(), Some(Version::new(1, 82, 0)));
+        assert_eq!(release("dev").version(), None);
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let release = serde_json::json!({
+            "tag_name": "v1.80.0",
+            "assets": [
+                {
+                    "name": "rust-toolchain-x86_64-unknown-linux-gnu.tar.gz",
+                    "browser_download_url": "https://example.com/x86_64.tar.gz",
+                    "digest": "sha256:abc",
+                },
+                {
+                    "name": "rust-toolchain-aarch64-apple-darwin.tar.gz",
+                    "browser_download_url": "https://example.com/aarch64.tar.gz",
+                },
+            ],
+        });
+        assert_eq!(
+            parse_release(&release, "x86_64-unknown-linux-gnu").unwrap(),
+            Release {
+                tag: "v1.80.0".to_string(),
+                url: "https://example.com/x86_64.tar.gz".to_string(),
+                sha256: "abc".to_string(),
+            }
+        );
+        let err = parse_release(&release, "aarch64-apple-darwin").unwrap_err();
+        assert!(err.to_string().contains("no checksum"));
+        assert!(parse_release(&release, "riscv64gc-unknown-linux-gnu").is_err());
+    }
+}
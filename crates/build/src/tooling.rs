@@ -0,0 +1,85 @@
+//! Skipping the build of programs from build scripts run by tools which only check or document the
+//! crate building them, such as docs.rs, clippy and rust-analyzer, see
+//! [`crate::BuildArgs::build_in_tooling`].
+//!
+//! These tools run build scripts too, but cannot use the ELF, and docs.rs has neither the succinct
+//! toolchain nor network access. The ELF of a previous build is used instead, or, when the ELF is
+//! copied into the `OUT_DIR` of the build script, an empty placeholder, so that `SP1_ELF_<NAME>` is
+//! set and the crate still compiles.
+
+use std::fs;
+
+use cargo_metadata::camino::Utf8Path;
+
+use crate::{BuildArgs, BuildError};
+
+/// The variable rust-analyzer users may set, such as in `rust-analyzer.cargo.extraEnv`, to mark
+/// its build script runs, which it has no variable of its own for.
+pub(crate) const RUST_ANALYZER_ENV: &str = "SP1_BUILD_IGNORE_RUST_ANALYZER";
+
+/// The variables [`skipped_tool`] detects the tools by, so that the build script runs again, and
+/// replaces a placeholder, when the crate is built without them.
+pub(crate) const ENVS: [&str; 5] =
+    ["DOCS_RS", "CARGO_CFG_DOC", "CARGO_CFG_CLIPPY", "RUSTC_WORKSPACE_WRAPPER", RUST_ANALYZER_ENV];
+
+/// The tool running the build script, if the program is not built for it, described for messages.
+pub(crate) fn skipped_tool(
+    args: &BuildArgs,
+    in_build_script: bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<&'static str> {
+    if args.build_in_tooling || !in_build_script {
+        return None;
+    }
+    let set = |name: &str| lookup(name).is_some_and(|value| !value.is_empty() && value != "0");
+    let contains = |name: &str, tool: &str| lookup(name).is_some_and(|value| value.contains(tool));
+    if set("DOCS_RS") {
+        Some("docs.rs")
+    } else if set("CARGO_CFG_DOC") {
+        Some("rustdoc")
+    } else if set("CARGO_CFG_CLIPPY") || contains("RUSTC_WORKSPACE_WRAPPER", "clippy-driver") {
+        Some("clippy")
+    } else if set(RUST_ANALYZER_ENV) || contains("RUSTC_WRAPPER", "rust-analyzer") {
+        Some("rust-analyzer")
+    } else {
+        None
+    }
+}
+
+/// Write an empty placeholder ELF at `elf_path`.
+pub(crate) fn write_placeholder(elf_path: &Utf8Path) -> Result<(), BuildError> {
+    let artifact_error = |source| BuildError::Artifact { path: elf_path.to_path_buf(), source };
+    fs::create_dir_all(elf_path.parent().unwrap()).map_err(artifact_error)?;
+    fs::write(elf_path, b"").map_err(artifact_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_skipped_tool() {
+        let skipped = |args: &BuildArgs, vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> =
+                vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            skipped_tool(args, true, |name| vars.get(name).cloned())
+        };
+        let args = BuildArgs::default();
+        assert_eq!(skipped(&args, &[]), None);
+        assert_eq!(skipped(&args, &[("DOCS_RS", "1")]), Some("docs.rs"));
+        assert_eq!(skipped(&args, &[("CARGO_CFG_DOC", "")]), None);
+        let clippy = [("RUSTC_WORKSPACE_WRAPPER", "/home/.rustup/bin/clippy-driver")];
+        assert_eq!(skipped(&args, &clippy), Some("clippy"));
+        let rust_analyzer = [("RUSTC_WRAPPER", "/home/.vscode/rust-analyzer")];
+        assert_eq!(skipped(&args, &rust_analyzer), Some("rust-analyzer"));
+        assert_eq!(skipped(&args, &[(RUST_ANALYZER_ENV, "true")]), Some("rust-analyzer"));
+        assert_eq!(skipped(&args, &[("RUSTC_WRAPPER", "sccache")]), None);
+
+        // Built anyway when forced, or outside of a build script.
+        let forced = BuildArgs { build_in_tooling: true, ..Default::default() };
+        assert_eq!(skipped(&forced, &[("DOCS_RS", "1")]), None);
+        assert_eq!(skipped_tool(&args, false, |_| Some("1".to_string())), None);
+    }
+}
@@ -0,0 +1,990 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::{camino::Utf8PathBuf, Package};
+
+use crate::{
+    c_toolchain, compatibility, config, docker, generate_elf_paths, output, patches,
+    sp1_dependency, std_check, toolchain, BuildArgs, BuildWarning, ElfDestination, PatchedCrate,
+    WarningKind,
+};
+
+/// A problem with [`BuildArgs`] found before building, see [`BuildArgs::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The program's `Cargo.toml` is missing or could not be read.
+    Manifest(String),
+    /// The build configuration could not be resolved.
+    Config(String),
+    /// [`BuildArgs::binary`] is not a binary target of the program.
+    MissingBinary {
+        /// The requested binary.
+        name: String,
+        /// The binary targets of the program.
+        available: Vec<String>,
+    },
+    /// No binary is selected, and the program has no binary target named after its package, which
+    /// cargo would build by default.
+    NoDefaultBinary {
+        /// The package of the program.
+        package: String,
+        /// The binary targets of the program.
+        available: Vec<String>,
+    },
+    /// No binary is selected, and the program has no binary or example target, only a library or
+    /// tests, which compile to no ELF, see [`BuildArgs::allow_lib_build`].
+    LibraryOnly {
+        /// The package of the program.
+        package: String,
+        /// The targets of the program by kind, such as `lib sp1_program`.
+        targets: Vec<String>,
+    },
+    /// A requested feature is not declared by the program, or `dep/feature` by its dependency
+    /// `dep`.
+    UndeclaredFeature {
+        /// The requested feature.
+        feature: String,
+        /// The features declared by the program, or by the dependency.
+        available: Vec<String>,
+    },
+    /// [`BuildArgs::elf_name`] is not a plain file name.
+    InvalidElfName(String),
+    /// The output directory cannot be created.
+    OutputDirectory {
+        /// The output directory.
+        path: Utf8PathBuf,
+        /// The error creating it.
+        error: String,
+    },
+    /// [`BuildArgs::elf_destination`] is the `OUT_DIR`, but the build does not run in a build
+    /// script.
+    OutDirMissing,
+    /// Flags of [`BuildArgs::rustflags`] override those required by the zkVM, see
+    /// [`crate::ZkvmRustFlags::check`].
+    ConflictingRustflags(Vec<String>),
+    /// The cargo home of [`BuildArgs::cargo_home`] is not a directory, or cannot be created.
+    CargoHome {
+        /// The cargo home.
+        path: PathBuf,
+        /// The error creating it.
+        error: String,
+    },
+    /// The succinct toolchain is not installed. The check can be skipped by setting
+    /// `SP1_SKIP_TOOLCHAIN_CHECK`, e.g. for unusual rustup layouts.
+    ToolchainMissing,
+    /// Docker is not installed or not running.
+    DockerUnavailable,
+    /// The installed succinct toolchain is not [`BuildArgs::toolchain_version`].
+    ToolchainVersion {
+        /// The installed version, as printed by `rustc --version`.
+        installed: String,
+        /// The required version.
+        required: String,
+    },
+    /// The rustc of the installed succinct toolchain does not match [`BuildArgs::min_rustc`].
+    RustcVersion {
+        /// The version of rustc, such as `1.79.0-dev`.
+        installed: String,
+        /// The version requirement.
+        required: String,
+    },
+    /// An SP1 crate the program depends on is not compatible with the installed succinct
+    /// toolchain. This is only an error with [`BuildArgs::strict_version_check`], and a warning
+    /// otherwise.
+    IncompatibleToolchain {
+        /// The name of the crate.
+        crate_name: String,
+        /// The version of the crate the program resolved to.
+        crate_version: String,
+        /// The release of the installed toolchain, such as `1.79.0-dev`.
+        toolchain: String,
+        /// The toolchain versions compatible with the crate.
+        compatible: Vec<String>,
+    },
+    /// [`BuildArgs::c_compiler`] does not exist.
+    InvalidCCompiler(PathBuf),
+    /// The wrapper of rustc, see [`BuildArgs::rustc_wrapper`], does not exist.
+    InvalidRustcWrapper(PathBuf),
+    /// The program depends on crates which compile C code, but no C compiler for the zkVM target
+    /// is installed, or `CC_riscv32im_succinct_zkvm_elf` does not point to an existing
+    /// compiler.
+    CCompilerMissing {
+        /// The crates which compile C code.
+        crates: Vec<String>,
+        /// The value of `CC_riscv32im_succinct_zkvm_elf`, if set.
+        configured: Option<String>,
+    },
+    /// [`BuildArgs::locked`] is set, but the workspace of the program has no `Cargo.lock`, see
+    /// [`BuildArgs::generate_lockfile`].
+    LockfileMissing {
+        /// The directory of the workspace.
+        dir: Utf8PathBuf,
+        /// Whether [`BuildArgs::frozen`] is set, which never generates the lock file.
+        frozen: bool,
+    },
+    /// [`BuildArgs::locked`] is set, but cargo would update the lock file of the program.
+    LockfileStale {
+        /// The lock file.
+        path: Utf8PathBuf,
+        /// The packages cargo would add, remove or update, such as `added serde 1.0.204`.
+        changes: Vec<String>,
+    },
+    /// A dependency of the program needs an operating system, which the zkVM does not have. This
+    /// is only an error with [`BuildArgs::strict_no_std_check`], and a warning otherwise.
+    HostOnlyDependency {
+        /// The name of the dependency.
+        crate_name: String,
+        /// The names of the crates from the program to the dependency, each depending on the next.
+        chain: Vec<String>,
+    },
+    /// The binary built does not set `#![no_main]`, so that cargo links the `main` of `std` rather
+    /// than the entrypoint of `sp1-zkvm`. This is only an error with
+    /// [`BuildArgs::strict_no_std_check`], and a warning otherwise.
+    MissingNoMain {
+        /// The binary.
+        binary: String,
+        /// The source file of the binary.
+        path: Utf8PathBuf,
+    },
+    /// The built ELF has undefined symbols, usually of functions of an operating system, which
+    /// fail when they are called. This is only an error with [`BuildArgs::strict_no_std_check`],
+    /// and a warning otherwise.
+    UndefinedSymbols {
+        /// The ELF.
+        elf: Utf8PathBuf,
+        /// The undefined symbols.
+        symbols: Vec<String>,
+    },
+    /// A `[patch]` section replaces SP1 crates of the program, which changes its verification key.
+    /// This is only an error with [`BuildArgs::deny_patched_sp1`], and a warning otherwise.
+    PatchedSp1Crates(Vec<PatchedCrate>),
+    /// [`BuildArgs::incremental`] is enabled for a build with Docker, which is meant to be
+    /// reproducible, while incremental compilation is not deterministic.
+    IncrementalDocker,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Manifest(error) => write!(f, "invalid manifest: {}", error),
+            ValidationError::Config(error) => write!(f, "invalid build configuration: {}", error),
+            ValidationError::MissingBinary { name, available } if available.is_empty() => {
+                write!(f, "no binary target named `{}`, the program has no binaries", name)
+            }
+            ValidationError::MissingBinary { name, available } => {
+                write!(f, "no binary target named `{}`", name)?;
+                if let Some(closest) = closest_name(name, available) {
+                    write!(f, ", did you mean `{}`?", closest)?;
+                }
+                write!(f, " available binaries: {}", available.join(", "))
+            }
+            ValidationError::NoDefaultBinary { package, available } if available.is_empty() => {
+                write!(f, "the package `{}` has no binary targets", package)
+            }
+            ValidationError::NoDefaultBinary { package, available } => write!(
+                f,
+                "the package `{}` has no binary target named after it, select one of its \
+                 binaries with `binary`: {}",
+                package,
+                available.join(", ")
+            ),
+            ValidationError::LibraryOnly { package, targets } => write!(
+                f,
+                "the package `{}` has no binary target, but SP1 programs must be binaries: its \
+                 targets are {}. Add a `src/main.rs` with `#![no_main]` and \
+                 `sp1_zkvm::entrypoint!(main);`, or set `allow_lib_build` to compile the library \
+                 anyway",
+                package,
+                if targets.is_empty() { "none".to_string() } else { targets.join(", ") }
+            ),
+            ValidationError::UndeclaredFeature { feature, available } => {
+                let declarer = match feature.split_once('/') {
+                    Some((dep, _)) => format!("the dependency `{}`", dep.trim_end_matches('?')),
+                    None => "the program".to_string(),
+                };
+                write!(f, "the feature `{}` is not declared by {}", feature, declarer)?;
+                if available.is_empty() {
+                    write!(f, ", which declares no features")
+                } else {
+                    write!(f, ", available features: {}", available.join(", "))
+                }
+            }
+            ValidationError::InvalidElfName(name) => {
+                write!(f, "the ELF name `{}` must be a file name, not a path", name)
+            }
+            ValidationError::OutputDirectory { path, error } => {
+                write!(f, "cannot create the output directory {}: {}", path, error)
+            }
+            ValidationError::ConflictingRustflags(flags) => write!(
+                f,
+                "the rustflags `{}` override the flags required by the zkVM",
+                flags.join(" ")
+            ),
+            ValidationError::CargoHome { path, error } => {
+                write!(f, "invalid cargo home {}: {}", path.display(), error)
+            }
+            ValidationError::OutDirMissing => write!(
+                f,
+                "the ELF is copied to the `OUT_DIR` of a build script, but `OUT_DIR` is not set"
+            ),
+            ValidationError::ToolchainMissing => write!(
+                f,
+                "the {} toolchain is not installed, {}",
+                toolchain::TOOLCHAIN_NAME,
+                toolchain::INSTALL_HELP
+            ),
+            ValidationError::DockerUnavailable => write!(
+                f,
+                "docker is not installed or not running: https://docs.docker.com/get-docker/"
+            ),
+            ValidationError::ToolchainVersion { installed, required } => write!(
+                f,
+                "the installed {} toolchain is `{}`, but `{}` is required, install it with `sp1up` \
+                 or link it with `rustup toolchain link {} <path>`",
+                toolchain::TOOLCHAIN_NAME,
+                installed,
+                required,
+                toolchain::TOOLCHAIN_NAME
+            ),
+            ValidationError::RustcVersion { installed, required } => write!(
+                f,
+                "your {} toolchain is rustc {}, this program requires {}, run `sp1up` to update it",
+                toolchain::TOOLCHAIN_NAME,
+                installed,
+                required
+            ),
+            ValidationError::IncompatibleToolchain {
+                crate_name,
+                crate_version,
+                toolchain,
+                compatible,
+            } => write!(
+                f,
+                "{} {} requires the succinct toolchain {}, but {} is installed, update the \
+                 toolchain with `sp1up` or use a version of {} compatible with it. Compatible \
+                 versions: {}",
+                crate_name,
+                crate_version,
+                compatible.join(" or "),
+                toolchain,
+                crate_name,
+                compatibility::pairings()
+            ),
+            ValidationError::InvalidCCompiler(path) => {
+                write!(f, "the C compiler `{}` does not exist", path.display())
+            }
+            ValidationError::InvalidRustcWrapper(path) => {
+                write!(f, "the rustc wrapper `{}` does not exist", path.display())
+            }
+            ValidationError::CCompilerMissing { crates, configured: Some(configured) } => write!(
+                f,
+                "C code is compiled by {}, but the C compiler `{}` set as {} does not exist, {}",
+                crates.join(", "),
+                configured,
+                c_toolchain::CC_ENV,
+                c_toolchain::INSTALL_HELP
+            ),
+            ValidationError::CCompilerMissing { crates, configured: None } => write!(
+                f,
+                "C code is compiled by {}, but no C compiler for the zkVM target is installed, {}",
+                crates.join(", "),
+                c_toolchain::INSTALL_HELP
+            ),
+            ValidationError::LockfileMissing { dir, frozen: true } => write!(
+                f,
+                "Cargo.lock is missing, but `frozen` is set, which never generates it: run `cargo \
+                 generate-lockfile` in {}, or drop `frozen`",
+                dir
+            ),
+            ValidationError::LockfileMissing { dir, .. } => write!(
+                f,
+                "Cargo.lock is missing, but `locked` is set: run `cargo generate-lockfile` in {}, \
+                 set `generate_lockfile` to generate it before building, or drop `locked`",
+                dir
+            ),
+            ValidationError::LockfileStale { path, changes } if changes.is_empty() => write!(
+                f,
+                "the lock file {} would be rewritten, but `locked` is set: build once without \
+                 `locked` to update it",
+                path
+            ),
+            ValidationError::LockfileStale { path, changes } => write!(
+                f,
+                "the lock file {} would change, but `locked` is set: build once without `locked` \
+                 to update it. Changes: {}",
+                path,
+                changes.join(", ")
+            ),
+            ValidationError::HostOnlyDependency { crate_name, chain } => write!(
+                f,
+                "the program depends on `{}`, which needs an operating system the zkVM does not \
+                 have: {}. Disable the features of the dependencies which need it, or add `{}` \
+                 to `allowed_std_crates` if the program does not use them",
+                crate_name,
+                chain.join(" -> "),
+                crate_name
+            ),
+            ValidationError::MissingNoMain { binary, path } => write!(
+                f,
+                "the binary `{}` does not set `#![no_main]` in {}, so it is linked with the `main` \
+                 of `std` rather than the entrypoint of `sp1_zkvm::entrypoint!`",
+                binary, path
+            ),
+            ValidationError::UndefinedSymbols { elf, symbols } => write!(
+                f,
+                "the ELF {} has undefined symbols, which fail when called in the zkVM, usually \
+                 functions of an operating system used by a dependency: {}",
+                elf,
+                symbols.join(", ")
+            ),
+            ValidationError::PatchedSp1Crates(crates) => write!(
+                f,
+                "the program is built against patched SP1 crates, so its verification key differs \
+                 from the one of a build against their release: {}. Remove the `[patch]` entries \
+                 to build against the release",
+                crates
+                    .iter()
+                    .map(|c| format!("{} {} from {}", c.name, c.version, c.source))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ValidationError::IncrementalDocker => write!(
+                f,
+                "incremental compilation is not deterministic, and cannot be enabled for a \
+                 reproducible build with Docker"
+            ),
+        }
+    }
+}
+
+impl ValidationError {
+    /// The exit code of a process terminating because of this error, as in
+    /// [`crate::BuildError::exit_code`].
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ValidationError::ToolchainMissing |
+            ValidationError::ToolchainVersion { .. } |
+            ValidationError::RustcVersion { .. } |
+            ValidationError::IncompatibleToolchain { .. } |
+            ValidationError::CCompilerMissing { .. } => 10,
+            ValidationError::DockerUnavailable => 11,
+            _ => 2,
+        }
+    }
+
+    /// Emit this problem as a warning, as it is only an error with a strict option such as
+    /// [`BuildArgs::strict_no_std_check`]. Only the problems of such options have a
+    /// [`WarningKind`], so any other problem is a bug.
+    pub(crate) fn warn(self) {
+        let list = |names: &[String]| names.join(", ");
+        let warning = match &self {
+            ValidationError::PatchedSp1Crates(patched) => {
+                let names: Vec<_> = patched.iter().map(|patched| patched.name.clone()).collect();
+                BuildWarning::new(WarningKind::PatchedSp1Crates, self.to_string())
+                    .field("crates", list(&names))
+            }
+            ValidationError::HostOnlyDependency { crate_name, chain } => {
+                BuildWarning::new(WarningKind::HostOnlyDependency, self.to_string())
+                    .field("crate", crate_name)
+                    .field("chain", chain.join(" -> "))
+            }
+            ValidationError::MissingNoMain { binary, path } => {
+                BuildWarning::new(WarningKind::MissingNoMain, self.to_string())
+                    .field("binary", binary)
+                    .field("path", path)
+            }
+            ValidationError::UndefinedSymbols { elf, symbols } => {
+                BuildWarning::new(WarningKind::UndefinedSymbols, self.to_string())
+                    .field("elf", elf)
+                    .field("symbols", list(symbols))
+            }
+            ValidationError::IncompatibleToolchain {
+                crate_name, crate_version, toolchain, ..
+            } => BuildWarning::new(WarningKind::IncompatibleToolchain, self.to_string())
+                .field("crate", crate_name)
+                .field("version", crate_version)
+                .field("toolchain", toolchain),
+            _ => unreachable!("`{}` is never only a warning", self),
+        };
+        output::warning(warning);
+    }
+}
+
+impl BuildArgs {
+    /// Check everything about building the program in `program_dir` with these arguments that can
+    /// be checked without compiling it: the manifest, the binary target, the features, the ELF
+    /// name, the output directory, and the toolchain or Docker. The arguments are resolved from all
+    /// layers of configuration first, see [`crate::ResolvedBuildArgs`].
+    ///
+    /// Returns every problem found, rather than only the first one.
+    pub fn validate(&self, program_dir: &Path) -> Result<(), Vec<ValidationError>> {
+        if let Err(err) = crate::check_program_dir(program_dir) {
+            return Err(vec![ValidationError::Manifest(err.to_string())]);
+        }
+        let program_dir = crate::resolve_program_dir(program_dir)
+            .map_err(|err| vec![ValidationError::Manifest(format!("{:#}", err))])?;
+        let program_metadata =
+            crate::lockfile::metadata(self, &program_dir).map_err(|err| match err.downcast() {
+                Ok(crate::BuildError::Invalid(errors)) => errors,
+                Ok(err) => vec![ValidationError::Manifest(err.to_string())],
+                Err(err) => vec![ValidationError::Manifest(format!("{:#}", err))],
+            })?;
+        let resolved = config::resolve(self, &program_dir, &program_metadata)
+            .map_err(|err| vec![ValidationError::Config(format!("{:#}", err))])?;
+
+        let errors = validate_with_metadata(resolved.args(), &program_metadata);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Validate `args` for the program described by `program_metadata`. The toolchain, Docker and the
+/// output directory are not checked in a dry run, which does not need them.
+pub(crate) fn validate_with_metadata(
+    args: &BuildArgs,
+    program_metadata: &cargo_metadata::Metadata,
+) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    let Some(package) = program_metadata.root_package() else {
+        let error = "the program directory does not contain a package".to_string();
+        return vec![ValidationError::Manifest(error)];
+    };
+
+    let available: Vec<String> = package
+        .targets
+        .iter()
+        .filter(|target| target.is_bin())
+        .map(|target| target.name.clone())
+        .collect();
+    if !args.binary.is_empty() {
+        if !available.contains(&args.binary) {
+            errors.push(ValidationError::MissingBinary { name: args.binary.clone(), available });
+        }
+    } else if available.is_empty() && !package.targets.iter().any(|target| target.is_example()) {
+        if !args.allow_lib_build {
+            let targets = package
+                .targets
+                .iter()
+                .map(|target| format!("{} {}", target.kind.join("/"), target.name))
+                .collect();
+            errors.push(ValidationError::LibraryOnly { package: package.name.clone(), targets });
+        }
+    } else if !available.contains(&package.name) {
+        // Without a selection, the ELF of the binary named after the package is copied.
+        errors.push(ValidationError::NoDefaultBinary { package: package.name.clone(), available });
+    }
+
+    // Cargo also takes several features in one argument, separated by commas or spaces.
+    let mut features: Vec<&str> = vec![];
+    for feature in args.features.iter().chain(&args.linked_features) {
+        for feature in feature.split([',', ' ']).filter(|feature| !feature.is_empty()) {
+            if !features.contains(&feature) {
+                features.push(feature);
+            }
+        }
+    }
+    for feature in features {
+        if let Some(available) = undeclared_feature(package, program_metadata, feature) {
+            errors.push(ValidationError::UndeclaredFeature {
+                feature: feature.to_string(),
+                available,
+            });
+        }
+    }
+
+    let patched = patches::patched_sp1_crates(program_metadata);
+    if !patched.is_empty() {
+        let problem = ValidationError::PatchedSp1Crates(patched);
+        if args.deny_patched_sp1 {
+            errors.push(problem);
+        } else {
+            problem.warn();
+        }
+    }
+
+    for warning in sp1_dependency::warnings(args, program_metadata) {
+        output::warning(warning);
+    }
+
+    for problem in std_check::check(args, program_metadata) {
+        if args.strict_no_std_check {
+            errors.push(problem);
+        } else {
+            problem.warn();
+        }
+    }
+
+    if args.elf_name.contains(['/', '\\']) || args.elf_name == "." || args.elf_name == ".." {
+        errors.push(ValidationError::InvalidElfName(args.elf_name.clone()));
+    }
+    if args.elf_destination == ElfDestination::OutDir && std::env::var_os("OUT_DIR").is_none() {
+        errors.push(ValidationError::OutDirMissing);
+    }
+    if args.docker && args.incremental == Some(true) {
+        errors.push(ValidationError::IncrementalDocker);
+    }
+    let mut rustflags = crate::ZkvmRustFlags::default().flags(args.rustflags.iter().cloned());
+    if args.unchecked_rustflags {
+        rustflags = rustflags.unchecked();
+    }
+    if let Err(error) = rustflags.check() {
+        errors.push(error);
+    }
+
+    if args.dry_run {
+        return errors;
+    }
+
+    // A type-check copies nothing to the output directories.
+    let elf_paths = generate_elf_paths(args, program_metadata).ok().filter(|_| !args.check_only);
+    if let Some(elf_paths) = elf_paths {
+        for (_, elf_path) in elf_paths {
+            let output_dir = elf_path.parent().unwrap().to_path_buf();
+            if let Err(error) = fs::create_dir_all(&output_dir) {
+                errors.push(ValidationError::OutputDirectory {
+                    path: output_dir,
+                    error: error.to_string(),
+                });
+            }
+        }
+    }
+
+    let program_dir = package.manifest_path.parent().unwrap().as_std_path();
+    // The cargo home is created by the build, so that validating the arguments writes nothing.
+    if let Some(cargo_home) = crate::cargo_home::isolated(args, program_dir) {
+        if cargo_home.exists() && !cargo_home.is_dir() {
+            let error = "not a directory".to_string();
+            errors.push(ValidationError::CargoHome { path: cargo_home, error });
+        }
+    }
+
+    if args.docker {
+        if docker::check_docker_available().is_err() {
+            errors.push(ValidationError::DockerUnavailable);
+        }
+    } else if toolchain::Toolchain::select(args) == toolchain::Toolchain::NightlyFallback {
+        if !toolchain::is_installed(toolchain::FALLBACK_TOOLCHAIN_NAME) {
+            errors.push(ValidationError::ToolchainMissing);
+        }
+    } else if let Err(err) = toolchain::check_enabled() {
+        errors.push(ValidationError::Config(format!("{:#}", err)));
+    } else if toolchain::check_enabled().unwrap_or(true) &&
+        !toolchain::is_installed(toolchain::TOOLCHAIN_NAME)
+    {
+        errors.push(ValidationError::ToolchainMissing);
+    } else {
+        let installed = toolchain::installed_version(toolchain::TOOLCHAIN_NAME);
+        if let Some(required) = &args.toolchain_version {
+            match &installed {
+                Ok(installed) if installed.matches(required) => {}
+                Ok(installed) => errors.push(ValidationError::ToolchainVersion {
+                    installed: installed.to_string(),
+                    required: required.clone(),
+                }),
+                Err(err) => errors.push(ValidationError::ToolchainVersion {
+                    installed: format!("unknown ({:#})", err),
+                    required: required.clone(),
+                }),
+            }
+        }
+        if let Some(required) = &args.min_rustc {
+            match &installed {
+                Ok(installed) if installed.version().is_some_and(|v| required.matches(&v)) => {}
+                Ok(installed) => errors.push(ValidationError::RustcVersion {
+                    installed: installed.release.clone(),
+                    required: required.to_string(),
+                }),
+                Err(err) => errors.push(ValidationError::RustcVersion {
+                    installed: format!("unknown ({:#})", err),
+                    required: required.to_string(),
+                }),
+            }
+        }
+        if let Ok(installed) = &installed {
+            for incompatibility in compatibility::check(program_metadata, &installed.release) {
+                if args.strict_version_check {
+                    errors.push(incompatibility);
+                } else {
+                    incompatibility.warn();
+                }
+            }
+        }
+    }
+
+    // The compiler of a build with Docker is a path within the container, which is not checked.
+    if !args.docker {
+        if let Some(wrapper) = crate::rustc_wrapper(args) {
+            if c_toolchain::find_executable(&wrapper).is_none() {
+                errors.push(ValidationError::InvalidRustcWrapper(wrapper));
+            }
+        }
+        let compiler = c_toolchain::compiler(args);
+        if let (Some(path), Err(_)) = (&args.c_compiler, &compiler) {
+            errors.push(ValidationError::InvalidCCompiler(path.clone()));
+        }
+        let crates = c_toolchain::crates_with_c_code(program_metadata);
+        if !crates.is_empty() {
+            match compiler {
+                Ok(compiler) => output::info(&format!(
+                    "[sp1] using the C compiler {} ({}) for {}",
+                    compiler.path.display(),
+                    compiler.source,
+                    crates.join(", ")
+                )),
+                Err(_) if args.c_compiler.is_some() => {}
+                Err(configured) => {
+                    errors.push(ValidationError::CCompilerMissing { crates, configured })
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// The features declared by `package`, if `feature` is not one of them: those of its manifest, and
+/// those cargo declares implicitly for its optional dependencies not enabled with `dep:` by any of
+/// them. A feature `dep/feature`, or `dep?/feature`, is checked against the features of the
+/// dependency `dep` of `package`, by its name or the name it is renamed to, or of `package` itself
+/// if named `dep`.
+fn undeclared_feature(
+    package: &Package,
+    program_metadata: &cargo_metadata::Metadata,
+    feature: &str,
+) -> Option<Vec<String>> {
+    let (package, feature) = match feature.split_once('/') {
+        Some((dep_name, feature)) if dep_name == package.name => (package, feature),
+        Some((dep_name, feature)) => {
+            let dep_name = dep_name.trim_end_matches('?');
+            let dep = package
+                .dependencies
+                .iter()
+                .find(|dep| dep.rename.as_deref().unwrap_or(&dep.name) == dep_name);
+            // A dependency the program does not have declares no features.
+            let Some(dep) = dep else { return Some(vec![]) };
+            // A dependency which is not resolved, such as one of another target, is left to cargo.
+            let dep_package = program_metadata
+                .packages
+                .iter()
+                .find(|package| package.name == dep.name && dep.req.matches(&package.version))?;
+            (dep_package, feature)
+        }
+        None => (package, feature),
+    };
+    let available = declared_features(package);
+    (!available.iter().any(|declared| declared == feature)).then_some(available)
+}
+
+/// The features declared by `package`, sorted, see [`undeclared_feature`].
+fn declared_features(package: &Package) -> Vec<String> {
+    let enabled_with_dep: Vec<&str> = package
+        .features
+        .values()
+        .flatten()
+        .filter_map(|enabled| enabled.strip_prefix("dep:"))
+        .collect();
+    let mut features: Vec<String> = package.features.keys().cloned().collect();
+    for dep in package.dependencies.iter().filter(|dep| dep.optional) {
+        let name = dep.rename.as_deref().unwrap_or(&dep.name);
+        if !enabled_with_dep.contains(&name) && !features.iter().any(|feature| feature == name) {
+            features.push(name.to_string());
+        }
+    }
+    features.sort();
+    features
+}
+
+/// The name among `candidates` closest to the mistyped `name`: one containing it or contained in
+/// it, such as `fibonacci-program` for `fibonacci`, or within an edit distance of a third of its
+/// length.
+fn closest_name<'a>(name: &str, candidates: &'a [String]) -> Option<&'a String> {
+    candidates
+        .iter()
+        .filter_map(|candidate| {
+            let distance = edit_distance(name, candidate);
+            let related = candidate.contains(name) || name.contains(candidate.as_str());
+            (related || distance <= name.chars().count().max(3) / 3)
+                .then_some((distance, candidate))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between `a` and `b`: the number of characters to insert, remove or
+/// replace to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn test_warn_records_kinds() {
+        let problems = [
+            (
+                ValidationError::PatchedSp1Crates(vec![PatchedCrate {
+                    name: "sp1-zkvm".to_string(),
+                    version: "3.0.0".to_string(),
+                    source: "../sp1".to_string(),
+                }]),
+                WarningKind::PatchedSp1Crates,
+                ("crates", "sp1-zkvm"),
+            ),
+            (
+                ValidationError::HostOnlyDependency {
+                    crate_name: "tokio".to_string(),
+                    chain: vec!["program".to_string(), "tokio".to_string()],
+                },
+                WarningKind::HostOnlyDependency,
+                ("chain", "program -> tokio"),
+            ),
+            (
+                ValidationError::MissingNoMain {
+                    binary: "fib".to_string(),
+                    path: "main.rs".into(),
+                },
+                WarningKind::MissingNoMain,
+                ("binary", "fib"),
+            ),
+            (
+                ValidationError::UndefinedSymbols {
+                    elf: "elf".into(),
+                    symbols: vec!["open".to_string(), "read".to_string()],
+                },
+                WarningKind::UndefinedSymbols,
+                ("symbols", "open, read"),
+            ),
+            (
+                ValidationError::IncompatibleToolchain {
+                    crate_name: "sp1-zkvm".to_string(),
+                    crate_version: "1.0.0".to_string(),
+                    toolchain: "v3.0.0".to_string(),
+                    compatible: vec![],
+                },
+                WarningKind::IncompatibleToolchain,
+                ("crate", "sp1-zkvm"),
+            ),
+        ];
+        for (problem, kind, (field, value)) in problems {
+            let warnings = crate::warning::Collector::start();
+            let message = problem.to_string();
+            problem.warn();
+            let warnings = warnings.warnings();
+            assert_eq!(warnings.len(), 1);
+            assert_eq!((warnings[0].kind, &warnings[0].message), (kind, &message));
+            assert_eq!(warnings[0].fields[field], value);
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let args = BuildArgs {
+            binary: "missing".to_string(),
+            features: vec!["extra".to_string(), "undeclared".to_string(), "dep/feature".into()],
+            elf_name: "../elf".to_string(),
+            dry_run: true,
+            docker: true,
+            incremental: Some(true),
+            rustflags: vec!["-Cpanic=unwind".to_string(), "-Copt-level=3".to_string()],
+            ..Default::default()
+        }
+        .ignore_env();
+        let args = BuildArgs { linked_features: vec!["unlinked".to_string()], ..args };
+        let errors = args.validate(&fixture("metadata")).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::MissingBinary {
+                    name: "missing".to_string(),
+                    available: vec!["metadata-program".to_string()],
+                },
+                ValidationError::UndeclaredFeature {
+                    feature: "undeclared".to_string(),
+                    available: vec!["extra".to_string()],
+                },
+                ValidationError::UndeclaredFeature {
+                    feature: "dep/feature".to_string(),
+                    available: vec![],
+                },
+                ValidationError::UndeclaredFeature {
+                    feature: "unlinked".to_string(),
+                    available: vec!["extra".to_string()],
+                },
+                ValidationError::InvalidElfName("../elf".to_string()),
+                ValidationError::IncrementalDocker,
+                ValidationError::ConflictingRustflags(vec!["-Cpanic=unwind".to_string()]),
+            ]
+        );
+        assert!(errors[0].to_string().contains("available binaries: metadata-program"));
+
+        let unchecked = BuildArgs { unchecked_rustflags: true, ..args };
+        let errors = unchecked.validate(&fixture("metadata")).unwrap_err();
+        assert!(!errors
+            .iter()
+            .any(|error| matches!(error, ValidationError::ConflictingRustflags(_))));
+    }
+
+    #[test]
+    fn test_validate_binary_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = "[workspace]\n[package]\nname = \"multi\"\nversion = \"0.1.0\"\n";
+        fs::create_dir_all(dir.path().join("src/bin")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        for bin in ["fibonacci-program", "other"] {
+            fs::write(dir.path().join(format!("src/bin/{}.rs", bin)), "fn main() {}").unwrap();
+        }
+        let args = BuildArgs { dry_run: true, ..Default::default() }.ignore_env();
+        let available = vec!["fibonacci-program".to_string(), "other".to_string()];
+
+        let errors = args.validate(dir.path()).unwrap_err();
+        let expected = ValidationError::NoDefaultBinary {
+            package: "multi".to_string(),
+            available: available.clone(),
+        };
+        assert_eq!(errors, vec![expected]);
+        assert!(errors[0].to_string().ends_with("`binary`: fibonacci-program, other"));
+
+        let fibonacci = BuildArgs { binary: "fibonacci".to_string(), ..args.clone() };
+        let errors = fibonacci.validate(dir.path()).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ValidationError::MissingBinary { name: "fibonacci".to_string(), available }]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "no binary target named `fibonacci`, did you mean `fibonacci-program`? available \
+             binaries: fibonacci-program, other"
+        );
+
+        let other = BuildArgs { binary: "other".to_string(), ..args };
+        assert_eq!(other.validate(dir.path()), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_library_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = "[workspace]\n[package]\nname = \"guest\"\nversion = \"0.1.0\"\n";
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        let args = BuildArgs { dry_run: true, ..Default::default() }.ignore_env();
+
+        let errors = args.validate(dir.path()).unwrap_err();
+        let expected = ValidationError::LibraryOnly {
+            package: "guest".to_string(),
+            targets: vec!["lib guest".to_string()],
+        };
+        assert_eq!(errors, vec![expected]);
+        let message = errors[0].to_string();
+        assert!(message.starts_with("the package `guest` has no binary target"), "{}", message);
+        assert!(message.contains("its targets are lib guest"), "{}", message);
+        assert!(message.contains("sp1_zkvm::entrypoint!(main);"), "{}", message);
+
+        let allowed = BuildArgs { allow_lib_build: true, ..args };
+        assert_eq!(allowed.validate(dir.path()), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_features() {
+        // A workspace whose member depends on a renamed optional dependency, and on one which is
+        // only enabled with `dep:`.
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("Cargo.toml", "[workspace]\nmembers = [\"program\", \"bls\", \"hash\"]\n");
+        for lib in ["bls", "hash"] {
+            let manifest = format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[features]\nasm = []\n",
+                lib
+            );
+            write(&format!("{}/Cargo.toml", lib), &manifest);
+            write(&format!("{}/src/lib.rs", lib), "");
+        }
+        write(
+            "program/Cargo.toml",
+            "[package]\nname = \"program\"\nversion = \"0.1.0\"\n\n[dependencies]\n\
+             curve = { package = \"bls\", path = \"../bls\", optional = true }\n\
+             hash = { path = \"../hash\", optional = true }\n\n\
+             [features]\nfast = [\"dep:hash\", \"hash/asm\"]\n",
+        );
+        write("program/src/main.rs", "#![no_main]\nfn main() {}");
+        let program_dir = dir.path().join("program");
+        let validate = |features: &[&str]| {
+            let features = features.iter().map(|f| f.to_string()).collect();
+            let args = BuildArgs { features, dry_run: true, ..Default::default() }.ignore_env();
+            args.validate(&program_dir).err().unwrap_or_default()
+        };
+
+        assert_eq!(validate(&["curve", "fast,program/fast", "curve/asm", "hash?/asm"]), vec![]);
+        let available = vec!["curve".to_string(), "fast".to_string()];
+        assert_eq!(
+            validate(&["blss fast", "hash", "bls", "curve/blss", "missing/asm"]),
+            vec![
+                ValidationError::UndeclaredFeature {
+                    feature: "blss".to_string(),
+                    available: available.clone(),
+                },
+                ValidationError::UndeclaredFeature {
+                    feature: "hash".to_string(),
+                    available: available.clone(),
+                },
+                ValidationError::UndeclaredFeature { feature: "bls".to_string(), available },
+                ValidationError::UndeclaredFeature {
+                    feature: "curve/blss".to_string(),
+                    available: vec!["asm".to_string()],
+                },
+                ValidationError::UndeclaredFeature {
+                    feature: "missing/asm".to_string(),
+                    available: vec![],
+                },
+            ]
+        );
+        assert_eq!(
+            validate(&["blss"])[0].to_string(),
+            "the feature `blss` is not declared by the program, available features: curve, fast"
+        );
+    }
+
+    #[test]
+    fn test_closest_name() {
+        let candidates = vec!["fibonacci-program".to_string(), "is-prime".to_string()];
+        assert_eq!(closest_name("fibonacci", &candidates).unwrap(), "fibonacci-program");
+        assert_eq!(closest_name("is-prme", &candidates).unwrap(), "is-prime");
+        assert_eq!(closest_name("tendermint", &candidates), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_validate_manifest() {
+        let args = BuildArgs { dry_run: true, ..Default::default() }.ignore_env();
+        assert_eq!(args.validate(&fixture("metadata")), Ok(()));
+
+        let errors = args.validate(&fixture("missing")).unwrap_err();
+        assert!(matches!(errors[..], [ValidationError::Manifest(_)]));
+    }
+}
@@ -0,0 +1,227 @@
+//! The warnings of this crate, collected into [`crate::BuildOutput::warnings`] as they are emitted.
+//!
+//! Warnings are emitted with [`crate::output::warning`], which prints them as before and records
+//! them in the collector of the current thread, if a build started one. Warnings of rustc and cargo
+//! are not collected.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{BuildArgs, BuildError};
+
+/// The kind of a [`BuildWarning`], see [`BuildArgs::deny_warnings`].
+///
+/// Kinds are named in kebab-case in the configuration, the JSON output and on the command line,
+/// such as `patched-sp1-crates`.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A key of `[package.metadata.sp1]` or `sp1.toml` is not a build argument.
+    UnknownConfigKey,
+    /// Both `sp1.toml` and `.sp1/build.toml` exist, and the latter is ignored.
+    DuplicateConfigFile,
+    /// An `SP1_BUILD_*` variable sets a build argument, or is ignored, in a build script.
+    EnvOverride,
+    /// The ELF is copied to another directory than previous versions copied it to, see
+    /// [`BuildArgs::legacy_output_path`].
+    OutputPathChanged,
+    /// The ELF is copied into a target directory, see [`BuildArgs::allow_output_in_target`].
+    OutputInTargetDir,
+    /// The release profile sets a panic strategy, which is overridden with `abort`.
+    PanicStrategyOverridden,
+    /// The program is in the workspace of the crate building it.
+    SharedWorkspace,
+    /// Inherited rustflags conflicting with the flags of the zkVM are dropped.
+    DroppedRustflags,
+    /// Installing the succinct toolchain failed, see [`BuildArgs::auto_install_toolchain`].
+    ToolchainInstallFailed,
+    /// Installing the C toolchain failed, see [`BuildArgs::auto_install_c_toolchain`].
+    CToolchainInstallFailed,
+    /// The program is built with nightly, see [`BuildArgs::toolchain_fallback`].
+    NightlyFallback,
+    /// A `rust-toolchain` file of the program is overridden.
+    ToolchainFileOverridden,
+    /// The missing `Cargo.lock` of the program was generated, see
+    /// [`BuildArgs::generate_lockfile`].
+    LockfileGenerated,
+    /// A `[patch]` section replaces SP1 crates, see [`BuildArgs::deny_patched_sp1`].
+    PatchedSp1Crates,
+    /// The program does not depend on `sp1-zkvm`, see [`BuildArgs::allow_non_sp1_program`].
+    NotSp1Program,
+    /// The binary does not call `sp1_zkvm::entrypoint!`.
+    MissingEntrypoint,
+    /// The program depends on another release of SP1 than this crate.
+    Sp1ReleaseMismatch,
+    /// A dependency needs an operating system, see [`BuildArgs::strict_no_std_check`].
+    HostOnlyDependency,
+    /// The binary does not set `#![no_main]`, see [`BuildArgs::strict_no_std_check`].
+    MissingNoMain,
+    /// The ELF has undefined symbols, see [`BuildArgs::strict_no_std_check`].
+    UndefinedSymbols,
+    /// An SP1 crate is not compatible with the toolchain, see
+    /// [`BuildArgs::strict_version_check`].
+    IncompatibleToolchain,
+    /// The ELF is named after its package, as another program wrote its default name.
+    ElfRenamed,
+    /// A post-build hook failed, see [`BuildArgs::allow_hook_failure`].
+    HookFailed,
+    /// The program failed to compile and the ELF of a previous build is used, see
+    /// [`crate::FailPolicy::WarnAndUseStale`].
+    StaleElf,
+    /// The program failed to compile and is skipped, or there is no ELF of a previous build to
+    /// use, see [`crate::FailPolicy`].
+    CompileFailed,
+}
+
+/// A warning of this crate emitted during a build, see [`crate::BuildOutput::warnings`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildWarning {
+    /// What the warning is about.
+    pub kind: WarningKind,
+    /// The warning, as printed.
+    pub message: String,
+    /// The details of the warning by name, such as the `crates` of a
+    /// [`WarningKind::PatchedSp1Crates`] or the `path` of a [`WarningKind::ElfRenamed`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl BuildWarning {
+    pub(crate) fn new(kind: WarningKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), fields: BTreeMap::new() }
+    }
+
+    pub(crate) fn field(mut self, name: &str, value: impl ToString) -> Self {
+        self.fields.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+thread_local! {
+    static COLLECTED: RefCell<Option<Vec<BuildWarning>>> = const { RefCell::new(None) };
+}
+
+/// Collects the warnings emitted on the current thread until dropped. A collector started while
+/// another is collecting joins it, so that the warnings emitted while reading the metadata of a
+/// program, before the build starts its own, are collected too.
+pub(crate) struct Collector {
+    owner: bool,
+}
+
+impl Collector {
+    pub(crate) fn start() -> Self {
+        COLLECTED.with(|collected| {
+            let mut collected = collected.borrow_mut();
+            let owner = collected.is_none();
+            if owner {
+                *collected = Some(vec![]);
+            }
+            Self { owner }
+        })
+    }
+
+    /// The warnings collected so far.
+    pub(crate) fn warnings(&self) -> Vec<BuildWarning> {
+        COLLECTED.with(|collected| collected.borrow().clone().unwrap_or_default())
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        if self.owner {
+            COLLECTED.with(|collected| *collected.borrow_mut() = None);
+        }
+    }
+}
+
+/// Record `warning` in the collector of the current thread, if any.
+pub(crate) fn record(warning: BuildWarning) {
+    COLLECTED.with(|collected| {
+        if let Some(warnings) = collected.borrow_mut().as_mut() {
+            warnings.push(warning);
+        }
+    });
+}
+
+/// Fail with the first warning collected on the current thread whose kind `args` deny.
+pub(crate) fn check_denied(args: &BuildArgs) -> Result<(), BuildError> {
+    if args.deny_warnings.is_empty() {
+        return Ok(());
+    }
+    COLLECTED.with(|collected| {
+        let collected = collected.borrow();
+        let denied =
+            collected.iter().flatten().find(|warning| args.deny_warnings.contains(&warning.kind));
+        match denied {
+            Some(warning) => Err(BuildError::DeniedWarning(warning.clone())),
+            None => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collector() {
+        record(BuildWarning::new(WarningKind::NightlyFallback, "not collected"));
+        let outer = Collector::start();
+        record(BuildWarning::new(WarningKind::NightlyFallback, "outer"));
+        {
+            let inner = Collector::start();
+            record(BuildWarning::new(WarningKind::ElfRenamed, "inner").field("path", "elf"));
+            assert_eq!(inner.warnings().len(), 2);
+            assert_eq!(inner.warnings()[1].fields["path"], "elf");
+        }
+        let warnings = outer.warnings();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].message, "outer");
+
+        let args = BuildArgs { deny_warnings: vec![WarningKind::StaleElf], ..Default::default() };
+        assert!(check_denied(&args).is_ok());
+        let args = BuildArgs { deny_warnings: vec![WarningKind::NightlyFallback], ..args };
+        assert!(matches!(
+            check_denied(&args),
+            Err(BuildError::DeniedWarning(warning)) if warning.message == "outer"
+        ));
+        drop(outer);
+        record(BuildWarning::new(WarningKind::NightlyFallback, "not collected"));
+        assert!(Collector::start().warnings().is_empty());
+    }
+
+    #[test]
+    fn test_collect_and_deny_each_kind() {
+        for kind in WarningKind::value_variants() {
+            let collector = Collector::start();
+            record(BuildWarning::new(*kind, "warning"));
+            assert_eq!(collector.warnings().iter().map(|w| w.kind).collect::<Vec<_>>(), [*kind]);
+            let args = BuildArgs { deny_warnings: vec![*kind], ..Default::default() };
+            assert!(matches!(
+                check_denied(&args),
+                Err(BuildError::DeniedWarning(warning)) if warning.kind == *kind
+            ));
+            let others = WarningKind::value_variants().iter().filter(|other| *other != kind);
+            let args = BuildArgs { deny_warnings: others.copied().collect(), ..args };
+            assert!(check_denied(&args).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_kind_names() {
+        let kind: WarningKind = serde_json::from_str("\"patched-sp1-crates\"").unwrap();
+        assert_eq!(kind, WarningKind::PatchedSp1Crates);
+        assert_eq!(
+            WarningKind::from_str("c-toolchain-install-failed", false),
+            Ok(WarningKind::CToolchainInstallFailed)
+        );
+        for kind in WarningKind::value_variants() {
+            let name = kind.to_possible_value().unwrap().get_name().to_string();
+            assert_eq!(serde_json::to_string(kind).unwrap(), format!("\"{}\"", name));
+        }
+    }
+}
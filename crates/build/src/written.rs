@@ -0,0 +1,121 @@
+//! The ELFs written by the builds of this process, so that a program does not overwrite the ELF of
+//! another one.
+//!
+//! Programs built without [`BuildArgs::elf_name`] or [`BuildArgs::binary`] have the same default
+//! ELF name, so that two programs built into the same output directory, such as by one build
+//! script, would overwrite each other's ELF. When the default ELF path was written by another
+//! program, the ELF is named after the package of the program instead. Any other ELF path written
+//! by two programs is an error.
+//!
+//! Which program keeps the default name only depends on the order the programs are built in: each
+//! build claims its ELF paths, even when it is skipped, and [`crate::build_programs`] claims those
+//! of all its programs in the order they are listed before building any of them, so that it does
+//! not depend on which build finishes first.
+
+use std::sync::Mutex;
+
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Metadata,
+};
+
+use crate::{output, BuildArgs, BuildError, BuildWarning, WarningKind};
+
+/// The program which wrote an ELF.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Writer {
+    /// The manifest of the program, which identifies it.
+    manifest_path: Utf8PathBuf,
+    /// The name of its package, for messages.
+    name: String,
+}
+
+impl Writer {
+    fn of(metadata: &Metadata, bin_name: &str) -> Self {
+        match metadata.root_package() {
+            Some(package) => {
+                Writer { manifest_path: package.manifest_path.clone(), name: package.name.clone() }
+            }
+            None => Writer {
+                manifest_path: metadata.workspace_root.join("Cargo.toml"),
+                name: bin_name.to_string(),
+            },
+        }
+    }
+}
+
+static WRITTEN: Mutex<Vec<(Utf8PathBuf, Writer)>> = Mutex::new(vec![]);
+
+/// The program other than that of `metadata` which wrote the ELF at `path` in this process, if
+/// any.
+fn other_writer(path: &Utf8Path, metadata: &Metadata, bin_name: &str) -> Option<Writer> {
+    let writer = Writer::of(metadata, bin_name);
+    let written = WRITTEN.lock().unwrap_or_else(|err| err.into_inner());
+    written
+        .iter()
+        .find(|(written, other)| written == path && *other != writer)
+        .map(|(_, other)| other.clone())
+}
+
+/// The programs warned about being renamed, to warn only once for each.
+static WARNED: Mutex<Vec<Utf8PathBuf>> = Mutex::new(vec![]);
+
+/// Whether the ELF of the program of `metadata` is renamed after its package, because another
+/// program wrote the `default_path` it would be copied to with `args`. Warns about it once for
+/// each program if `warn` is set.
+pub(crate) fn renamed(
+    args: &BuildArgs,
+    default_path: &Utf8Path,
+    metadata: &Metadata,
+    bin_name: &str,
+    warn: bool,
+) -> bool {
+    if !args.elf_name.is_empty() || !args.binary.is_empty() || metadata.root_package().is_none() {
+        return false;
+    }
+    let Some(other) = other_writer(default_path, metadata, bin_name) else {
+        return false;
+    };
+    if !warn {
+        return true;
+    }
+    let writer = Writer::of(metadata, bin_name);
+    let mut warned = WARNED.lock().unwrap_or_else(|err| err.into_inner());
+    if !warned.contains(&writer.manifest_path) {
+        output::warning(
+            BuildWarning::new(
+                WarningKind::ElfRenamed,
+                format!(
+                    "the ELF of {} at {} was already written for {}, so it is named after its \
+                     package instead. Set `elf_name` to name it",
+                    writer.name, default_path, other.name
+                ),
+            )
+            .field("path", default_path)
+            .field("written_for", &other.name),
+        );
+        warned.push(writer.manifest_path);
+    }
+    true
+}
+
+/// Record that the program of `metadata` writes the ELF of `bin_name` to `path`, failing if another
+/// program wrote it.
+pub(crate) fn claim(
+    path: &Utf8Path,
+    metadata: &Metadata,
+    bin_name: &str,
+) -> Result<(), BuildError> {
+    if let Some(other) = other_writer(path, metadata, bin_name) {
+        return Err(BuildError::ElfConflict {
+            path: path.to_path_buf(),
+            first: other.name,
+            second: Writer::of(metadata, bin_name).name,
+        });
+    }
+    let mut written = WRITTEN.lock().unwrap_or_else(|err| err.into_inner());
+    if !written.iter().any(|(written, _)| written == path) {
+        written.push((path.to_path_buf(), Writer::of(metadata, bin_name)));
+    }
+    Ok(())
+}
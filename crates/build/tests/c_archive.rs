@@ -0,0 +1,22 @@
+//! Building a program whose dependency compiles a static C archive for the zkVM target.
+
+use std::{fs, path::PathBuf};
+
+use sp1_build::{build_program_with_output, BuildArgs};
+
+#[test]
+#[ignore = "requires the succinct toolchain and the C toolchain of `sp1up --c-toolchain`"]
+fn test_build_c_archive() {
+    let program_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/c-archive");
+    let output_dir = tempfile::tempdir().unwrap();
+    // The archiver and ranlib default to those installed alongside the C compiler.
+    let args = BuildArgs {
+        output_directory: vec![output_dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    }
+    .ignore_env();
+
+    let output = build_program_with_output(&args, program_dir).unwrap();
+    let elf = fs::read(output.elf_path()).unwrap();
+    assert!(elf.starts_with(b"\x7fELF"));
+}
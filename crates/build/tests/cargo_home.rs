@@ -0,0 +1,55 @@
+//! Building a program with its own cargo home, which the configuration in the cargo home of the
+//! user does not apply to. The builds run in a child process with the `CARGO_HOME` of the user, so
+//! that the variable is not set for the other tests.
+
+use std::process::Command;
+
+use sp1_build::{
+    testing::{require_toolchain, FixtureProgram},
+    BuildArgs, BuildError,
+};
+
+/// Set for the child process, which only then builds.
+const CHILD: &str = "SP1_TEST_CARGO_HOME_CHILD";
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_isolated_cargo_home() {
+    let program = FixtureProgram::bare();
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, program.dir());
+
+    // A configuration of the user which breaks builds for the zkVM, and only them.
+    let user_home = tempfile::tempdir().unwrap();
+    let config = "[target.'cfg(target_os = \"zkvm\")']\nlinker = \"/nonexistent/linker\"\n";
+    std::fs::write(user_home.path().join("config.toml"), config).unwrap();
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args(["build_with_user_cargo_home", "--exact", "--ignored", "--nocapture"])
+        .env("CARGO_HOME", user_home.path())
+        .env(CHILD, "1")
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+#[ignore = "run in a child process by `test_isolated_cargo_home`"]
+fn build_with_user_cargo_home() {
+    if std::env::var_os(CHILD).is_none() {
+        return;
+    }
+    let program = FixtureProgram::bare();
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    let err = program.try_build(&args).unwrap_err();
+    match err.downcast_ref() {
+        Some(BuildError::Compile { first_error: Some(first_error), .. }) => {
+            assert!(first_error.contains("/nonexistent/linker"), "{}", first_error)
+        }
+        _ => panic!("unexpected error: {:#}", err),
+    }
+
+    let isolated = BuildArgs { cargo_home: Some("cargo-home".into()), ..args };
+    let output = program.try_build(&isolated).unwrap();
+    assert!(std::fs::metadata(output.elf_path()).unwrap().len() > 0);
+    assert!(program.dir().join("cargo-home").is_dir());
+}
@@ -0,0 +1,63 @@
+//! Setting `SP1_ELF_<NAME>_SHA256` for the crate whose build script builds a program, which must
+//! change when the program does.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sp1_build::{
+    testing::{copy_fixture, require_toolchain},
+    BuildArgs,
+};
+
+/// The source of the program, with `marker` in its ELF.
+fn program_source(marker: &str) -> String {
+    format!(
+        "#![no_std]\n#![no_main]\n\nstatic MARKER: &[u8] = b\"{}\";\n\n#[no_mangle]\n\
+         pub extern \"C\" fn _start() -> ! {{\n    \
+         loop {{\n        unsafe {{ core::ptr::read_volatile(&MARKER[0]) }};\n    }}\n}}\n\n\
+         #[panic_handler]\nfn panic(_: &core::panic::PanicInfo) -> ! {{\n    loop {{}}\n}}\n",
+        marker
+    )
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_elf_sha256_changes_with_program() {
+    let fixture =
+        copy_fixture(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/elf-sha256"));
+    let workspace = fixture.path();
+    fs::create_dir_all(workspace.join("program/src")).unwrap();
+    fs::write(workspace.join("program/src/main.rs"), program_source("first")).unwrap();
+
+    // Resolve the dependencies of sp1-build to the versions of this repository, if it has a lock
+    // file, so that they build with its toolchain, and share a target directory between runs, in
+    // that of the tests, so that sp1-build is only compiled once.
+    let repository_lock_file = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../Cargo.lock");
+    if repository_lock_file.exists() {
+        fs::copy(repository_lock_file, workspace.join("Cargo.lock")).unwrap();
+    }
+
+    // The build script builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, workspace.join("program"));
+
+    let run = || {
+        let output = Command::new("cargo")
+            .args(["run", "-q"])
+            .current_dir(workspace.join("host"))
+            .env("CARGO_TARGET_DIR", Path::new(env!("CARGO_TARGET_TMPDIR")).join("elf-sha256"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let first = run();
+    assert!(first.trim().ends_with(" ok"), "{}", first);
+    fs::write(workspace.join("program/src/main.rs"), program_source("second")).unwrap();
+    let second = run();
+    assert!(second.trim().ends_with(" ok"), "{}", second);
+    assert_ne!(first, second);
+}
@@ -0,0 +1,265 @@
+//! Naming and copying the ELFs of programs built with `FixtureProgram`.
+
+use sp1_build::{
+    testing::{require_toolchain, FixtureProgram, COMPILE_ERROR, FIXTURE_PACKAGE},
+    BuildArgs, BuildError, SbomFormat, SmokeTest, WarningKind,
+};
+
+/// Arguments building with nightly if the succinct toolchain is missing.
+fn args(program: &FixtureProgram) -> BuildArgs {
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, program.dir());
+    args
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_elf_names_and_copies() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    // The default name, in the `elf` directory next to the target directory.
+    let elf = program.build(&args);
+    assert_eq!(elf.parent().unwrap(), program.dir().join("elf"));
+    assert!(elf.file_name().unwrap().starts_with(sp1_build::build_target()));
+    let compiled = program
+        .dir()
+        .join("target/elf-compilation")
+        .join(sp1_build::build_target())
+        .join("release")
+        .join(FIXTURE_PACKAGE);
+    assert_eq!(std::fs::read(&elf).unwrap(), std::fs::read(compiled).unwrap());
+
+    // An explicit name, in another output directory relative to the program.
+    let named = BuildArgs {
+        elf_name: "named".to_string(),
+        output_directory: vec!["out".into()],
+        ..args.clone()
+    };
+    let elf = program.build(&named);
+    assert_eq!(elf.parent().unwrap(), program.dir().join("out"));
+    assert!(elf.file_name().unwrap().starts_with("named"));
+
+    // Several output directories, each with the ELF and its sidecars.
+    let copies = BuildArgs {
+        output_directory: vec!["out".into(), "copies".into()],
+        manifest: true,
+        ..named
+    };
+    let output = program.try_build(&copies).unwrap();
+    let paths = output.elf_paths();
+    assert_eq!(paths[0], elf);
+    assert_eq!(paths[1], program.dir().join("copies").join(elf.file_name().unwrap()));
+    assert_eq!(output.manifests.len(), 2);
+    for path in output.elf_paths() {
+        assert_eq!(std::fs::read(path).unwrap(), std::fs::read(&elf).unwrap());
+    }
+
+    // The name of the binary, if there is no explicit name.
+    let binary = BuildArgs { binary: FIXTURE_PACKAGE.to_string(), ..args };
+    let elf = program.build(&binary);
+    assert!(elf.file_name().unwrap().starts_with(FIXTURE_PACKAGE));
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_post_build_hooks() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    // The log is outside of the program, whose sources would change otherwise.
+    let log_dir = tempfile::tempdir().unwrap();
+    let log = log_dir.path().join("hooks.log");
+    let hook = format!("echo \"$SP1_PACKAGE_NAME $SP1_ELF_SHA256\" >> {}", log.display());
+    let args = BuildArgs { post_build_hooks: vec![hook], ..args };
+    let output = program.try_build(&args).unwrap();
+    let line = format!("{} {}\n", FIXTURE_PACKAGE, output.artifacts[0].sha256.as_deref().unwrap());
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), line);
+
+    // An unchanged program runs no hook, unless `run_hooks_on_skip` is set.
+    assert!(program.try_build(&args).unwrap().fresh);
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), line);
+    let on_skip = BuildArgs { run_hooks_on_skip: true, ..args };
+    assert!(program.try_build(&on_skip).unwrap().fresh);
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), line.repeat(2));
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_post_build_hooks_parallel() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    // Programs built concurrently run their hooks, write their SBOMs, manifests and environment
+    // snapshots, and run their smoke tests as well.
+    let log_dir = tempfile::tempdir().unwrap();
+    let log = log_dir.path().join("hooks.log");
+    let hook = format!("echo \"$SP1_PACKAGE_NAME\" >> {}", log.display());
+    let args = BuildArgs {
+        post_build_hooks: vec![hook],
+        sbom: Some(SbomFormat::Spdx),
+        manifest: true,
+        capture_env: true,
+        ..args
+    };
+    let jobs = vec![(args.clone(), program.dir().to_path_buf())];
+    let elf = sp1_build::build_programs(jobs, 1).remove(0).unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), format!("{}\n", FIXTURE_PACKAGE));
+    assert!(std::path::Path::new(&format!("{}.spdx.json", elf)).is_file());
+    assert!(std::path::Path::new(&format!("{}.manifest.json", elf)).is_file());
+    assert!(std::path::Path::new(&format!("{}.env.json", elf)).is_file());
+
+    // They also warn before building, here about an output directory in the target directory.
+    let in_target = BuildArgs {
+        output_directory: vec!["target/out".into()],
+        deny_warnings: vec![WarningKind::OutputInTargetDir],
+        ..args.clone()
+    };
+    let jobs = vec![(in_target, program.dir().to_path_buf())];
+    let err = sp1_build::build_programs(jobs, 1).remove(0).unwrap_err();
+    match err.downcast_ref() {
+        Some(BuildError::DeniedWarning(warning)) => {
+            assert_eq!(warning.kind, WarningKind::OutputInTargetDir)
+        }
+        _ => panic!("unexpected error: {:#}", err),
+    }
+
+    let smoke_test = SmokeTest { max_cycles: Some(1), ..SmokeTest::default() };
+    let over_budget = BuildArgs { smoke_test: Some(smoke_test), ..args };
+    let jobs = vec![(over_budget, program.dir().to_path_buf())];
+    let err = sp1_build::build_programs(jobs, 1).remove(0).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::SmokeTest { .. })), "{:#}", err);
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_split_debuginfo() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    let args = BuildArgs { split_debuginfo: true, manifest: true, ..args };
+    let output = program.try_build(&args).unwrap();
+    let elf = output.elf_path();
+    let debug = sp1_build::locate_debug_file(elf).unwrap();
+    assert_eq!(debug, std::path::PathBuf::from(format!("{}.debug", elf)));
+    assert!(std::fs::metadata(elf).unwrap().len() < std::fs::metadata(&debug).unwrap().len());
+    let manifest = sp1_build::BuildManifest::read(&output.manifests[0]).unwrap();
+    assert_eq!(manifest.debug_sha256, Some(sp1_build::elf_sha256(&std::fs::read(&debug).unwrap())));
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_compile_error() {
+    let program = FixtureProgram::bare().with_compile_error();
+    let args = args(&program);
+
+    let err = program.try_build(&args).unwrap_err();
+    match err.downcast_ref() {
+        Some(BuildError::Compile { first_error: Some(first_error), .. }) => {
+            assert!(first_error.contains(COMPILE_ERROR), "{}", first_error)
+        }
+        _ => panic!("unexpected error: {:#}", err),
+    }
+    let elf = program.dir().join("elf").join(format!("{}-nightly", sp1_build::build_target()));
+    assert!(!elf.exists() && !elf.with_file_name(sp1_build::build_target()).exists());
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_check_only() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    // Nothing is copied, and the build which follows is not fresh.
+    sp1_build::check_program(&args, program.dir()).unwrap();
+    let copied = std::fs::read_dir(program.dir().join("elf")).map_or(0, |entries| entries.count());
+    assert_eq!(copied, 0);
+    assert!(!program.try_build(&args).unwrap().fresh);
+
+    // The functions returning the path of the ELF fail, as there is none.
+    let check_only = BuildArgs { check_only: true, ..args.clone() };
+    let err = sp1_build::build_program_in(&check_only, program.dir()).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::NoElf)), "{:#}", err);
+    let err = sp1_build::build_and_load(&check_only, program.dir()).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::NoElf)), "{:#}", err);
+    let err = sp1_build::build_and_load_all(&check_only, program.dir()).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::NoElf)), "{:#}", err);
+
+    let program = FixtureProgram::bare().with_compile_error();
+    let err = sp1_build::check_program(&args, program.dir()).unwrap_err();
+    match err.downcast_ref() {
+        Some(BuildError::Compile { first_error: Some(first_error), .. }) => {
+            assert!(first_error.contains(COMPILE_ERROR), "{}", first_error)
+        }
+        _ => panic!("unexpected error: {:#}", err),
+    }
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_build_and_load() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    let (elf, output) = sp1_build::build_and_load_with_output(&args, program.dir()).unwrap();
+    assert_eq!(elf, std::fs::read(output.elf_path()).unwrap());
+    assert!(sp1_build::verify_elf_sha256(&elf, output.artifacts[0].sha256.as_deref().unwrap()));
+    let elfs = sp1_build::build_and_load_all(&args, program.dir()).unwrap();
+    assert_eq!(elfs.into_values().collect::<Vec<_>>(), vec![elf]);
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_clean_program() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    let elf = program.build(&args);
+    let checksum = format!("{}.sha256", elf);
+    std::fs::write(&checksum, sp1_build::elf_sha256(&std::fs::read(&elf).unwrap())).unwrap();
+    let unrelated = program.dir().join("elf").join("README.md");
+    std::fs::write(&unrelated, "kept").unwrap();
+    let target_dir = program.dir().join("target").join("elf-compilation");
+
+    let dry_run = BuildArgs { dry_run: true, ..args.clone() };
+    let report = sp1_build::clean_program(&dry_run, program.dir()).unwrap();
+    assert!(report.removed.contains(&elf) && report.removed.iter().any(|p| p == &checksum));
+    assert!(report.bytes_freed > 0);
+    assert!(elf.exists() && target_dir.exists());
+
+    let report = sp1_build::clean_program(&args, program.dir()).unwrap();
+    assert_eq!(report.removed[0], target_dir);
+    assert!(!elf.exists() && !std::path::Path::new(&checksum).exists());
+    // Only the build lock of the target directory is kept.
+    let kept: Vec<_> =
+        std::fs::read_dir(&target_dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(kept, [".sp1-build.lock"]);
+    assert!(!std::path::Path::new(&format!("{}.fingerprint", elf)).exists());
+    assert!(unrelated.exists());
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_warnings() {
+    let program = FixtureProgram::bare();
+    let args = args(&program);
+
+    let output = program.try_build(&args).unwrap();
+    let kinds: Vec<_> = output.warnings.iter().map(|warning| warning.kind).collect();
+    assert!(kinds.contains(&WarningKind::NotSp1Program), "{:?}", kinds);
+    if output.elf_path().as_str().ends_with("-nightly") {
+        assert!(kinds.contains(&WarningKind::NightlyFallback), "{:?}", kinds);
+    }
+
+    // A denied warning fails the build before compiling.
+    let denied = BuildArgs { deny_warnings: vec![WarningKind::NotSp1Program], ..args };
+    let err = program.try_build(&denied).unwrap_err();
+    assert_eq!(BuildError::exit_code_of(&err), 50);
+    match err.downcast_ref() {
+        Some(BuildError::DeniedWarning(warning)) => {
+            assert_eq!(warning.kind, WarningKind::NotSp1Program);
+            assert!(warning.message.contains("does not depend on `sp1-zkvm`"));
+        }
+        _ => panic!("unexpected error: {:#}", err),
+    }
+}
@@ -0,0 +1,29 @@
+//! Compiles `add.c` into a static archive with the C toolchain configured by sp1-build.
+
+use std::{env, path::PathBuf, process::Command};
+
+fn tool(name: &str) -> String {
+    let target = env::var("TARGET").unwrap().replace('-', "_");
+    let var = format!("{}_{}", name, target);
+    println!("cargo:rerun-if-env-changed={}", var);
+    env::var(&var).unwrap_or_else(|_| panic!("{} is not set", var))
+}
+
+fn run(command: &mut Command) {
+    let status = command.status().unwrap();
+    assert!(status.success(), "{:?} failed", command);
+}
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let object = out_dir.join("add.o");
+    let archive = out_dir.join("libadd.a");
+    println!("cargo:rerun-if-changed=src/add.c");
+
+    run(Command::new(tool("CC")).args(["-c", "src/add.c", "-o"]).arg(&object));
+    run(Command::new(tool("AR")).arg("crs").arg(&archive).arg(&object));
+    run(Command::new(tool("RANLIB")).arg(&archive));
+
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    println!("cargo:rustc-link-lib=static=add");
+}
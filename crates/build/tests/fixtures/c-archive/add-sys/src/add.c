@@ -0,0 +1 @@
+unsigned int add(unsigned int a, unsigned int b) { return a + b; }
@@ -0,0 +1,3 @@
+extern "C" {
+    pub fn add(a: u32, b: u32) -> u32;
+}
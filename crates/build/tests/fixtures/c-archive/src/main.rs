@@ -0,0 +1,7 @@
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+pub fn main() {
+    let sum = unsafe { add_sys::add(1, 2) };
+    sp1_zkvm::io::commit(&sum);
+}
@@ -0,0 +1,7 @@
+use sp1_build::{build_program_in, BuildArgs};
+
+fn main() {
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() };
+    build_program_in(&args, "../program").unwrap();
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let elf = std::fs::read(env!("SP1_ELF_SHA256_PROGRAM")).unwrap();
+    let sha256 = env!("SP1_ELF_SHA256_PROGRAM_SHA256");
+    let verified = sp1_build::verify_elf_sha256(&elf, sha256);
+    println!("{} {}", sha256, if verified { "ok" } else { "mismatch" });
+}
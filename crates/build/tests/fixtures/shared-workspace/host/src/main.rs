@@ -0,0 +1,5 @@
+const ELF: &[u8] = include_bytes!(env!("SP1_ELF_SHARED_PROGRAM"));
+
+fn main() {
+    println!("{}", ELF.len());
+}
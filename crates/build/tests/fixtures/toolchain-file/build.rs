@@ -0,0 +1,12 @@
+//! Prints the version of the compiler the program is built with, which sp1-build must select
+//! despite the toolchain file.
+
+use std::{env, process::Command};
+
+fn main() {
+    let rustc = env::var("RUSTC").unwrap();
+    let output = Command::new(rustc).arg("-vV").output().unwrap();
+    for line in String::from_utf8(output.stdout).unwrap().lines() {
+        println!("cargo:warning=rustc -vV: {}", line);
+    }
+}
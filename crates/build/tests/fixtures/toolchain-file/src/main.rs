@@ -0,0 +1,3 @@
+#![no_main]
+
+fn main() {}
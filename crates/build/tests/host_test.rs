@@ -0,0 +1,55 @@
+//! Running the unit tests of a program on the host with `test_program`.
+
+use std::fs;
+
+use sp1_build::{test_program, BuildError, TestArgs};
+
+/// A program whose library has a passing and a failing test, compiling for the host only when the
+/// zkVM code is gated.
+const LIB: &str = "pub fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[cfg(target_os = \"zkvm\")]
+pub fn syscall() {}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn adds() {
+        assert_eq!(super::add(1, 2), 3);
+    }
+
+    #[test]
+    fn fails() {
+        assert_eq!(super::add(1, 2), 4);
+    }
+}
+";
+
+#[test]
+fn test_program_tests() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("Cargo.toml"),
+        "[workspace]\n[package]\nname = \"guest\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("src/lib.rs"), LIB).unwrap();
+
+    test_program(dir.path(), &TestArgs::default().filter("adds")).unwrap();
+    let err = test_program(dir.path(), &TestArgs::default()).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::Test { .. })), "{:#}", err);
+
+    // A configuration forcing the zkVM target fails before running cargo.
+    fs::create_dir_all(dir.path().join(".cargo")).unwrap();
+    fs::write(
+        dir.path().join(".cargo/config.toml"),
+        format!("[build]\ntarget = \"{}\"\n", sp1_build::build_target()),
+    )
+    .unwrap();
+    let err = test_program(dir.path(), &TestArgs::default().filter("adds")).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::Config(_))), "{:#}", err);
+    assert!(format!("{:#}", err).contains("[target.'cfg(target_os = \"zkvm\")']"));
+}
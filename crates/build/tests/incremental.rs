@@ -0,0 +1,30 @@
+//! Building a program incrementally, after changing one of its source files.
+
+use std::fs;
+
+use sp1_build::{
+    testing::{require_toolchain, FixtureProgram},
+    BuildArgs,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_incremental_build() {
+    let program = FixtureProgram::bare();
+    // Builds with nightly if the succinct toolchain is missing.
+    let args =
+        BuildArgs { toolchain_fallback: true, incremental: Some(true), ..Default::default() }
+            .ignore_env();
+    require_toolchain(&args, program.dir());
+
+    let output = program.try_build(&args).unwrap();
+    let incremental_dir =
+        output.target_directory.join(sp1_build::build_target()).join("release/incremental");
+    assert!(fs::read_dir(&incremental_dir).unwrap().next().is_some(), "{}", incremental_dir);
+
+    let main = fs::read_to_string(program.main_path()).unwrap();
+    fs::write(program.main_path(), main.replace("loop {}", "loop {\n        continue;\n    }"))
+        .unwrap();
+    let rebuilt = program.try_build(&args).unwrap();
+    assert!(!rebuilt.fresh);
+}
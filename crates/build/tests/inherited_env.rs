@@ -0,0 +1,49 @@
+//! Building a program within the environment of a host build script, whose variables must not leak
+//! into the build of the program. This runs in a process of its own, since it sets them.
+
+use std::{env, fs, path::PathBuf};
+
+use sp1_build::{
+    build_program_with_output,
+    testing::{copy_fixture, require_toolchain},
+    BuildArgs,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_build_ignores_inherited_env() {
+    let fixture =
+        copy_fixture(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/no-std"));
+    let program_dir = fixture.path();
+    let output_dir = tempfile::tempdir().unwrap();
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs {
+        toolchain_fallback: true,
+        output_directory: vec![output_dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    }
+    .ignore_env();
+    require_toolchain(&args, program_dir);
+
+    // `CARGO` is already set by `cargo test`, as it is for build scripts, to the cargo of the host
+    // toolchain rather than the rustup proxy.
+    for (key, value) in [
+        // Inherited, except for the flags which conflict with those required by the zkVM.
+        ("RUSTFLAGS", "--cfg inherited -C panic=unwind"),
+        ("CARGO_BUILD_RUSTFLAGS", "-C linker=/nonexistent/cc"),
+        ("CARGO_BUILD_TARGET", "x86_64-unknown-linux-gnu"),
+        ("CARGO_UNSTABLE_BUILD_STD", "std,panic_abort"),
+        ("RUSTC_WORKSPACE_WRAPPER", "/nonexistent/clippy-driver"),
+        ("CARGO_MAKEFLAGS", "-j --jobserver-fds=998,999 --jobserver-auth=998,999"),
+        ("CARGO_CFG_TARGET_OS", "linux"),
+        ("CARGO_FEATURE_STD", "1"),
+    ] {
+        env::set_var(key, value);
+    }
+
+    let output = build_program_with_output(&args, program_dir).unwrap();
+    let elf = fs::read(output.elf_path()).unwrap();
+    // A 32-bit ELF for RISC-V.
+    assert_eq!(&elf[..5], b"\x7fELF\x01");
+    assert_eq!(u16::from_le_bytes([elf[18], elf[19]]), 0xf3);
+}
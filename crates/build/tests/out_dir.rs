@@ -0,0 +1,36 @@
+//! Copying the ELF into the `OUT_DIR` of a build script. This runs in a process of its own, since
+//! it sets `OUT_DIR`.
+
+use std::{env, path::PathBuf};
+
+use cargo_metadata::{camino::Utf8PathBuf, MetadataCommand};
+use sp1_build::{generate_elf_paths, BuildArgs, ElfDestination, ValidationError};
+
+#[test]
+fn test_elf_destination() {
+    let program_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/metadata");
+    let metadata =
+        MetadataCommand::new().manifest_path(program_dir.join("Cargo.toml")).exec().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let out_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+    let elf_path = |args: BuildArgs| generate_elf_paths(&args, &metadata).unwrap()[0].1.clone();
+    let source_dir = metadata.root_package().unwrap().manifest_path.parent().unwrap();
+
+    env::set_var("OUT_DIR", &out_dir);
+    let args = BuildArgs { elf_name: "elf".to_string(), ..Default::default() };
+    assert_eq!(elf_path(args.clone()), out_dir.join("metadata-program/elf"));
+    let output_directory = BuildArgs { output_directory: vec!["out".to_string()], ..args.clone() };
+    assert_eq!(elf_path(output_directory.clone()), source_dir.join("out/elf"));
+    let elf_dir = BuildArgs { output_directory: vec!["elf".to_string()], ..args.clone() };
+    assert_eq!(elf_path(elf_dir), source_dir.join("elf/elf"));
+    let forced = BuildArgs { elf_destination: ElfDestination::OutDir, ..output_directory };
+    assert_eq!(elf_path(forced), out_dir.join("metadata-program/elf"));
+    let source = BuildArgs { elf_destination: ElfDestination::OutputDirectory, ..args.clone() };
+    assert_eq!(elf_path(source), source_dir.join("elf/elf"));
+
+    env::remove_var("OUT_DIR");
+    assert_eq!(elf_path(args.clone()), source_dir.join("elf/elf"));
+    let forced = BuildArgs { elf_destination: ElfDestination::OutDir, dry_run: true, ..args };
+    let errors = forced.ignore_env().validate(&program_dir).unwrap_err();
+    assert!(errors.contains(&ValidationError::OutDirMissing), "{:?}", errors);
+}
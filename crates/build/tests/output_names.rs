@@ -0,0 +1,58 @@
+//! Building two programs into the same output directory, whose default ELF names are the same.
+//! This runs in a process of its own, since which ELFs were written is kept for the process.
+
+use std::path::PathBuf;
+
+use sp1_build::{
+    build_program_with_output,
+    testing::{copy_fixture, require_toolchain},
+    BuildArgs, BuildError,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_programs_sharing_output_directory() {
+    let fixture =
+        copy_fixture(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/two-programs"));
+    let fixture = fixture.path();
+    let output_dir = tempfile::tempdir().unwrap();
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs {
+        toolchain_fallback: true,
+        output_directory: vec![output_dir.path().to_str().unwrap().to_string()],
+        ..Default::default()
+    }
+    .ignore_env();
+    require_toolchain(&args, fixture.join("first"));
+
+    let first = build_program_with_output(&args, fixture.join("first")).unwrap();
+    let second = build_program_with_output(&args, fixture.join("second")).unwrap();
+    assert!(first.elf_path().file_name().unwrap().starts_with(sp1_build::build_target()));
+    assert!(second.elf_path().file_name().unwrap().starts_with("second-program"));
+    assert_eq!(first.elf_path().parent(), second.elf_path().parent());
+    assert!(first.elf_path().is_file() && second.elf_path().is_file());
+
+    // Building the first program again keeps its name.
+    let again = build_program_with_output(&args, fixture.join("first")).unwrap();
+    assert_eq!(again.elf_path(), first.elf_path());
+
+    // Programs built together are named in the order they are listed, not in the order they finish.
+    let together = tempfile::tempdir().unwrap();
+    let together = BuildArgs {
+        output_directory: vec![together.path().to_str().unwrap().to_string()],
+        ..args.clone()
+    };
+    let jobs = vec![(together.clone(), fixture.join("second")), (together, fixture.join("first"))];
+    let built: Vec<_> =
+        sp1_build::build_programs(jobs, 2).into_iter().map(Result::unwrap).collect();
+    assert!(built[0].file_name().unwrap().starts_with(sp1_build::build_target()));
+    assert!(built[1].file_name().unwrap().starts_with("first-program"));
+
+    // An explicit name which the other program wrote is an error.
+    let elf_name = first.elf_path().file_name().unwrap().trim_end_matches("-nightly").to_string();
+    let conflicting = BuildArgs { elf_name, ..args };
+    let err = build_program_with_output(&conflicting, fixture.join("second")).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(BuildError::ElfConflict { .. })), "{:#}", err);
+    assert!(err.to_string().contains("first-program"), "{}", err);
+    assert_eq!(BuildError::exit_code_of(&err), 30);
+}
@@ -0,0 +1,52 @@
+//! Copying the ELF to the output directory of a program whose target directory is elsewhere. The
+//! build runs in a process of its own, since it sets `CARGO_TARGET_DIR`.
+
+use cargo_metadata::{camino::Utf8PathBuf, MetadataCommand};
+use sp1_build::{
+    generate_elf_paths,
+    testing::{require_toolchain, FixtureProgram},
+    BuildArgs,
+};
+
+#[test]
+fn test_output_directory_outside_target_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+
+    let program = FixtureProgram::bare();
+    let metadata = MetadataCommand::new()
+        .manifest_path(program.manifest_path())
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .exec()
+        .unwrap();
+    assert_eq!(metadata.target_directory, dir.join("target"));
+    let elf_path = |args: BuildArgs| generate_elf_paths(&args, &metadata).unwrap()[0].1.clone();
+    let program_dir = metadata.root_package().unwrap().manifest_path.parent().unwrap();
+
+    let args = BuildArgs { elf_name: "program".to_string(), ..Default::default() };
+    assert_eq!(elf_path(args.clone()), program_dir.join("elf/program"));
+    let legacy = BuildArgs { legacy_output_path: true, ..args };
+    assert_eq!(elf_path(legacy), dir.join("elf/program"));
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_build_outside_target_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+    std::env::set_var("CARGO_TARGET_DIR", dir.join("target"));
+
+    // Builds with nightly if the succinct toolchain is missing.
+    let program = FixtureProgram::bare();
+    let args = BuildArgs {
+        elf_name: "program".to_string(),
+        toolchain_fallback: true,
+        ..Default::default()
+    }
+    .ignore_env();
+    require_toolchain(&args, program.dir());
+    let output = program.try_build(&args).unwrap();
+    assert!(output.target_directory.starts_with(&dir));
+    assert!(output.elf_path().starts_with(program.dir()) && output.elf_path().is_file());
+    assert!(!dir.join("elf").exists());
+}
@@ -0,0 +1,84 @@
+//! Building the same program from two directories with `remap_paths`, which must write the same
+//! ELF although the path of a generated source file, in the target directory, is compiled into it,
+//! and locally and with Docker.
+
+use std::{fs, path::Path};
+
+use sp1_build::{build_program_with_output, testing::require_toolchain, BuildArgs};
+
+const BUILD_RS: &str = "fn main() {
+    let out_dir = std::env::var(\"OUT_DIR\").unwrap();
+    let check = \"pub fn check(x: u32) { if x == 0 { panic!(\\\"zero\\\") } }\";
+    std::fs::write(std::path::Path::new(&out_dir).join(\"check.rs\"), check).unwrap();
+}
+";
+
+const MAIN_RS: &str = "#![no_std]
+#![no_main]
+
+include!(concat!(env!(\"OUT_DIR\"), \"/check.rs\"));
+
+#[no_mangle]
+pub extern \"C\" fn _start() -> ! {
+    check(unsafe { core::ptr::read_volatile(0x1000 as *const u32) });
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let file = info.location().map_or(\"\", |location| location.file());
+    unsafe { core::ptr::write_volatile(0x1000 as *mut usize, file.len()) };
+    loop {}
+}
+";
+
+/// Write the program into `dir`.
+fn write_program(dir: &Path) {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[workspace]\n[package]\nname = \"remapped\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("build.rs"), BUILD_RS).unwrap();
+    fs::write(dir.join("src/main.rs"), MAIN_RS).unwrap();
+}
+
+/// The ELF of the program in `dir` built with `args`.
+fn elf(args: &BuildArgs, dir: &Path) -> Vec<u8> {
+    let output = build_program_with_output(args, dir).unwrap();
+    fs::read(output.elf_path()).unwrap()
+}
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_remap_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let (first, second) = (dir.path().join("first"), dir.path().join("second-program"));
+    write_program(&first);
+    write_program(&second);
+
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, &first);
+    assert_ne!(elf(&args, &first), elf(&args, &second));
+    let remapped = BuildArgs { remap_paths: true, ..args };
+    assert_eq!(elf(&remapped, &first), elf(&remapped, &second));
+}
+
+/// Needs Docker, and the succinct toolchain of the Docker image installed locally, as the
+/// remapping only removes the paths from the ELF.
+#[test]
+#[ignore = "builds a program with Docker and with the succinct toolchain of its image"]
+fn test_remap_paths_docker() {
+    let dir = tempfile::tempdir().unwrap();
+    let (local_dir, docker_dir) = (dir.path().join("local"), dir.path().join("docker"));
+    write_program(&local_dir);
+    write_program(&docker_dir);
+
+    let local = BuildArgs { remap_paths: true, ..Default::default() }.ignore_env();
+    require_toolchain(&local, &local_dir);
+    let docker = BuildArgs { docker: true, ..local.clone() };
+    require_toolchain(&docker, &docker_dir);
+    assert_eq!(elf(&local, &local_dir), elf(&docker, &docker_dir));
+}
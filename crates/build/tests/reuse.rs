@@ -0,0 +1,42 @@
+//! Reusing the ELF a build script compiled when the same program is built again outside of it, as
+//! by a release script. This runs in a process of its own, since it sets the variables of a build
+//! script.
+
+use std::env;
+
+use sp1_build::{
+    testing::{require_toolchain, FixtureProgram},
+    BuildArgs,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_reuse_elf_of_build_script() {
+    let program = FixtureProgram::bare();
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, program.dir());
+
+    // The build script of a host crate copies the ELF into its `OUT_DIR`.
+    let (host_dir, out_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    env::set_var("OUT_DIR", out_dir.path());
+    env::set_var("CARGO_MANIFEST_DIR", host_dir.path());
+    env::set_var("CARGO_PKG_NAME", "host");
+    let in_build_script = program.try_build(&args).unwrap();
+    assert!(in_build_script.elf_path().starts_with(out_dir.path()));
+    assert!(in_build_script.cargo_exit_code.is_some());
+
+    // The release script copies the ELF into the output directory, without running cargo.
+    for name in ["OUT_DIR", "CARGO_MANIFEST_DIR", "CARGO_PKG_NAME"] {
+        env::remove_var(name);
+    }
+    let reused = program.try_build(&args).unwrap();
+    assert!(reused.elf_path().starts_with(program.dir().join("elf")));
+    assert!(reused.fresh && reused.cargo_exit_code.is_none());
+    assert_eq!(reused.artifacts[0].sha256, in_build_script.artifacts[0].sha256);
+    assert!(program.try_build(&args).unwrap().fresh);
+
+    // Unless forced.
+    let forced = program.try_build(&BuildArgs { force: true, ..args }).unwrap();
+    assert!(!forced.fresh && forced.cargo_exit_code == Some(0));
+}
@@ -0,0 +1,58 @@
+//! Building two programs into the target directory they share. This runs in a process of its
+//! own, since it sets the directory of the shared target directories in the environment.
+
+use std::path::Path;
+
+use sp1_build::{
+    testing::{require_toolchain, FixtureProgram},
+    BuildArgs,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_programs_sharing_target_directory() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    std::env::set_var("SP1_BUILD_CACHE_DIR", cache_dir.path());
+
+    let (first, second) = (FixtureProgram::bare(), FixtureProgram::bare());
+    // Builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, shared_target: true, ..Default::default() }
+        .ignore_env();
+    require_toolchain(&args, first.dir());
+
+    let first_output = first.try_build(&args).unwrap();
+    let second_output = second.try_build(&args).unwrap();
+
+    assert_eq!(first_output.target_directory, second_output.target_directory);
+    assert!(first_output.target_directory.starts_with(cache_dir.path()));
+    assert!(!first.dir().join("target").exists());
+    assert!(first_output.elf_path().starts_with(first.dir()));
+    assert!(second_output.elf_path().starts_with(second.dir()));
+    assert!(first_output.elf_path().is_file() && second_output.elf_path().is_file());
+
+    // The second program reuses what the first compiled, such as the standard library the nightly
+    // fallback compiles from source, which is compiled once rather than for each program. The
+    // program itself is compiled again, as cargo tells the two apart by their paths.
+    assert!(!second_output.fresh);
+    let nightly = first_output.elf_path().as_str().ends_with("-nightly");
+    let compiled_core = count_files(first_output.target_directory.as_std_path(), "libcore-");
+    assert_eq!(compiled_core, usize::from(nightly));
+
+    // Other flags compile into another directory.
+    let flags = BuildArgs { rustflags: vec!["-Copt-level=s".to_string()], dry_run: true, ..args };
+    let output = first.try_build(&flags).unwrap();
+    assert_ne!(output.target_directory, first_output.target_directory);
+    assert!(output.target_directory.starts_with(cache_dir.path()));
+}
+
+/// The number of `.rlib` files below `dir` whose names start with `prefix`.
+fn count_files(dir: &Path, prefix: &str) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .map(|path| match path.file_name().unwrap().to_str().unwrap() {
+            _ if path.is_dir() => count_files(&path, prefix),
+            name => usize::from(name.starts_with(prefix) && name.ends_with(".rlib")),
+        })
+        .sum()
+}
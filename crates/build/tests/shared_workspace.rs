@@ -0,0 +1,44 @@
+//! Building a program from the build script of a crate in the same workspace, which must not
+//! invalidate the build of the crate and run its build script again.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use sp1_build::{
+    testing::{copy_fixture, require_toolchain},
+    BuildArgs,
+};
+
+#[test]
+#[ignore = "builds a program, see `sp1_build::testing`"]
+fn test_shared_workspace_is_not_rebuilt() {
+    let fixture = copy_fixture(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/shared-workspace"),
+    );
+    let workspace = fixture.path();
+    // Resolve the dependencies of sp1-build to the versions of this repository, if it has a lock
+    // file, so that they build with its toolchain.
+    let repository_lock_file = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../Cargo.lock");
+    if repository_lock_file.exists() {
+        fs::copy(repository_lock_file, workspace.join("Cargo.lock")).unwrap();
+    }
+
+    // The build script builds with nightly if the succinct toolchain is missing.
+    let args = BuildArgs { toolchain_fallback: true, ..Default::default() }.ignore_env();
+    require_toolchain(&args, workspace.join("program"));
+
+    let build = || {
+        let output = Command::new("cargo")
+            .args(["build", "-v"])
+            .current_dir(workspace.join("host"))
+            .env_remove("CARGO_TARGET_DIR")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{}", stderr);
+        stderr
+    };
+    build();
+    let stderr = build();
+    assert!(stderr.contains("Fresh shared-host"), "{}", stderr);
+    assert!(!workspace.join("target/elf-compilation").exists());
+}
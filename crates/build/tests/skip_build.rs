@@ -0,0 +1,45 @@
+//! Skipping the build of programs with `SP1_SKIP_PROGRAM_BUILD`. This runs in a process of its own,
+//! since it sets the variable.
+
+use std::{env, fs};
+
+use sp1_build::{build_program_with_output, build_target, BuildArgs, BuildError};
+
+#[test]
+fn test_skip_program_build() {
+    let dir = tempfile::tempdir().unwrap();
+    let program_dir = dir.path().join("program");
+    fs::create_dir_all(program_dir.join("src")).unwrap();
+    fs::write(
+        program_dir.join("Cargo.toml"),
+        "[workspace]\n[package]\nname = \"skipped\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(program_dir.join("src/main.rs"), "#![no_main]\n\nfn main() {}\n").unwrap();
+    let output_dir = dir.path().join("elf");
+
+    env::set_var("SP1_SKIP_PROGRAM_BUILD", "1");
+    for docker in [false, true] {
+        let args = BuildArgs {
+            docker,
+            output_directory: vec![output_dir.to_str().unwrap().to_string()],
+            ..Default::default()
+        }
+        .ignore_env();
+
+        // Nothing was built before.
+        let _ = fs::remove_dir_all(&output_dir);
+        let err = build_program_with_output(&args, &program_dir).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BuildError::Skipped)), "{:#}", err);
+        assert_eq!(BuildError::exit_code_of(&err), 3);
+
+        // The ELF of a previous build is returned.
+        fs::create_dir_all(&output_dir).unwrap();
+        fs::write(output_dir.join(build_target()), b"elf").unwrap();
+        let output = build_program_with_output(&args, &program_dir).unwrap();
+        assert!(output.skipped);
+        assert_eq!(output.elf_path(), output_dir.join(build_target()));
+        assert_eq!(output.artifacts[0].size, Some(3));
+        assert_eq!(output.cargo_exit_code, None);
+    }
+}
@@ -0,0 +1,34 @@
+//! Skipping the build of programs from build scripts run by docs.rs, clippy or rust-analyzer. This
+//! runs in a process of its own, since it sets the variables of a build script.
+
+use std::{env, fs};
+
+use sp1_build::{build_program_with_output, build_target, BuildArgs};
+
+#[test]
+fn test_skip_under_tooling() {
+    let dir = tempfile::tempdir().unwrap();
+    let program_dir = dir.path().join("program");
+    fs::create_dir_all(program_dir.join("src")).unwrap();
+    fs::write(
+        program_dir.join("Cargo.toml"),
+        "[workspace]\n[package]\nname = \"docs-rs\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(program_dir.join("src/main.rs"), "#![no_main]\n\nfn main() {}\n").unwrap();
+    let out_dir = dir.path().join("out");
+
+    // A build script on docs.rs, without a toolchain, gets an empty placeholder in its `OUT_DIR`.
+    env::set_var("OUT_DIR", &out_dir);
+    env::set_var("CARGO_MANIFEST_DIR", dir.path());
+    env::set_var("DOCS_RS", "1");
+    let args = BuildArgs::default().ignore_env();
+    let output = build_program_with_output(&args, &program_dir).unwrap();
+    assert!(output.skipped);
+    assert_eq!(output.elf_path(), out_dir.join("docs-rs").join(build_target()));
+    assert_eq!(output.artifacts[0].size, Some(0));
+
+    // Forcing the build builds it, here only as a dry run.
+    let forced = BuildArgs { build_in_tooling: true, dry_run: true, ..args };
+    assert!(!build_program_with_output(&forced, &program_dir).unwrap().skipped);
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use sp1_cli::{
     commands::{
         build::BuildCmd, build_toolchain::BuildToolchainCmd,
@@ -35,11 +35,18 @@ pub enum ProveCliCommands {
 }
 
 fn main() -> Result<()> {
-    let Cargo::Prove(args) = Cargo::parse();
+    let matches = Cargo::command().get_matches();
+    let Cargo::Prove(args) = Cargo::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
     let command = args.command.unwrap_or(ProveCliCommands::Prove(args.prove));
     match command {
         ProveCliCommands::New(cmd) => cmd.run(),
-        ProveCliCommands::Build(cmd) => cmd.run(),
+        ProveCliCommands::Build(cmd) => {
+            let build_matches = matches
+                .subcommand_matches("prove")
+                .and_then(|prove| prove.subcommand_matches("build"))
+                .expect("the build subcommand was matched");
+            cmd.with_matches(build_matches).run()
+        }
         ProveCliCommands::Prove(cmd) => cmd.run(),
         ProveCliCommands::BuildToolchain(cmd) => cmd.run(),
         ProveCliCommands::InstallToolchain(cmd) => cmd.run(),
@@ -1,17 +1,94 @@
+use std::{process, time::Duration};
+
 use anyhow::Result;
-use clap::Parser;
-use sp1_build::{build_program, BuildArgs};
+use clap::{ArgMatches, Parser};
+use sp1_build::{
+    build_program_in, clean_program, prune_build_caches, toolchain_info, BuildArgs, BuildError,
+    OutputFormat, PruneOptions,
+};
 
 #[derive(Parser)]
 #[command(name = "build", about = "Compile an SP1 program")]
 pub struct BuildCmd {
+    #[clap(
+        long,
+        action,
+        help = "Print the versions of the toolchain, the C compiler and Docker instead of building"
+    )]
+    version_info: bool,
+
+    #[clap(
+        long,
+        action,
+        conflicts_with = "prune_caches",
+        help = "Remove the target directories, ELFs and the files next to them the program's builds \
+                wrote instead of building, or list them with --dry-run"
+    )]
+    clean: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "Delete the target directories of the programs within the current directory and of \
+                the build cache which exceed --prune-max-age-days or --prune-max-size-gib instead \
+                of building, or list them with --dry-run"
+    )]
+    prune_caches: bool,
+
+    #[clap(long, requires = "prune_caches", help = "Delete target directories unused for this long")]
+    prune_max_age_days: Option<u64>,
+
+    #[clap(
+        long,
+        requires = "prune_caches",
+        help = "Delete the least recently used target directories until the rest fit in this size"
+    )]
+    prune_max_size_gib: Option<u64>,
+
     #[clap(flatten)]
     build_args: BuildArgs,
 }
 
 impl BuildCmd {
+    /// Count the build arguments given on the command line in `matches` as passed explicitly, so
+    /// that e.g. `--tag latest` overrides `SP1_BUILD_TAG`.
+    pub fn with_matches(mut self, matches: &ArgMatches) -> Self {
+        self.build_args = self.build_args.explicitly_set_from(matches);
+        self
+    }
+
     pub fn run(&self) -> Result<()> {
-        build_program(&self.build_args, None)?;
+        if self.version_info {
+            let info = toolchain_info();
+            if self.build_args.output_format == OutputFormat::Json {
+                println!("{}", serde_json::to_string(&info)?);
+            } else {
+                println!("{}", info);
+            }
+            return Ok(());
+        }
+
+        if self.clean {
+            clean_program(&self.build_args, ".")?;
+            return Ok(());
+        }
+
+        if self.prune_caches {
+            let options = PruneOptions {
+                max_age: self.prune_max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                max_total_size: self.prune_max_size_gib.map(|gib| gib << 30),
+                dry_run: self.build_args.dry_run,
+                ..PruneOptions::default()
+            };
+            prune_build_caches(&options)?;
+            return Ok(());
+        }
+
+        // Exit with the code of the failure category, so that wrappers can tell them apart.
+        if let Err(err) = build_program_in(&self.build_args, ".") {
+            eprintln!("Error: {:?}", err);
+            process::exit(BuildError::exit_code_of(&err));
+        }
 
         Ok(())
     }
@@ -1,13 +1,13 @@
 use anstyle::*;
 use anyhow::Result;
 use clap::Parser;
-use sp1_build::{build_program, BuildArgs};
+use sp1_build::{build_program_in, BuildArgs, BuildError};
 use sp1_core_machine::{
     io::SP1Stdin,
     utils::{setup_logger, setup_tracer},
 };
 use sp1_sdk::ProverClient;
-use std::{env, fs::File, io::Read, path::PathBuf, str::FromStr, time::Instant};
+use std::{env, fs::File, io::Read, path::PathBuf, process, str::FromStr, time::Instant};
 
 use crate::util::{elapsed, write_status};
 
@@ -70,7 +70,10 @@ pub struct ProveCmd {
 
 impl ProveCmd {
     pub fn run(&self) -> Result<()> {
-        let elf_path = build_program(&self.build_args, None)?;
+        let elf_path = build_program_in(&self.build_args, ".").unwrap_or_else(|err| {
+            eprintln!("Error: {:?}", err);
+            process::exit(BuildError::exit_code_of(&err));
+        });
 
         if !self.profile {
             match env::var("RUST_LOG") {
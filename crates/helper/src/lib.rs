@@ -1,67 +1,50 @@
-use cargo_metadata::Metadata;
 use chrono::Local;
-pub use sp1_build::BuildArgs;
+pub use sp1_build::{
+    build_programs_from_host_metadata, build_programs_matching, BuildArgs, ElfDestination,
+    FeatureMap,
+};
 use std::{path::Path, process::ExitStatus};
 
+mod version_check;
+
 fn current_datetime() -> String {
     let now = Local::now();
     now.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
-/// Re-run the cargo command if the Cargo.toml or Cargo.lock file changes.
-fn cargo_rerun_if_changed(metadata: &Metadata, program_dir: &Path) {
-    // Tell cargo to rerun the script only if program/{src, bin, build.rs, Cargo.toml} changes
-    // Ref: https://doc.rust-lang.org/nightly/cargo/reference/build-scripts.html#rerun-if-changed
-    let dirs = vec![
-        program_dir.join("src"),
-        program_dir.join("bin"),
-        program_dir.join("build.rs"),
-        program_dir.join("Cargo.toml"),
-    ];
-    for dir in dirs {
-        if dir.exists() {
-            println!("cargo::rerun-if-changed={}", dir.canonicalize().unwrap().display());
-        }
-    }
-
-    // Re-run the build script if the workspace root's Cargo.lock changes. If the program is its own
-    // workspace, this will be the program's Cargo.lock.
-    println!("cargo:rerun-if-changed={}", metadata.workspace_root.join("Cargo.lock").as_str());
-
-    // Re-run if any local dependency changes.
-    for package in &metadata.packages {
-        for dependency in &package.dependencies {
-            if let Some(path) = &dependency.path {
-                println!("cargo:rerun-if-changed={}", path.as_str());
-            }
-        }
-    }
-}
-
 /// Executes the `cargo prove build` command in the program directory. If there are any cargo prove
 /// build arguments, they are added to the command.
 fn execute_build_cmd(
     program_dir: &impl AsRef<std::path::Path>,
     args: Option<BuildArgs>,
+    program_name: &str,
 ) -> Result<std::process::ExitStatus, std::io::Error> {
-    // Check if RUSTC_WORKSPACE_WRAPPER is set to clippy-driver (i.e. if `cargo clippy` is the
-    // current compiler). If so, don't execute `cargo prove build` because it breaks
-    // rust-analyzer's `cargo clippy` feature.
-    let is_clippy_driver = std::env::var("RUSTC_WORKSPACE_WRAPPER")
-        .map(|val| val.contains("clippy-driver"))
-        .unwrap_or(false);
-    if is_clippy_driver {
-        println!("cargo:warning=Skipping build due to clippy invocation.");
-        return Ok(std::process::ExitStatus::default());
-    }
-
-    // Build the program with the given arguments.
-    let path_output = sp1_build::build_program(
-        &args.unwrap_or_default(),
-        Some(program_dir.as_ref().to_path_buf()),
-    );
-    if let Err(err) = path_output {
-        panic!("Failed to build SP1 program: {}.", err);
+    // Build the program with the given arguments, which is skipped under clippy, rust-analyzer and
+    // docs.rs unless `build_in_tooling` is set. A skipped build is not an error of the host crate,
+    // even if the program has never been built, nor is a failed build with
+    // `FailPolicy::WarnAndUseStale` or `FailPolicy::WarnAndSkip`.
+    let skipped = |reason: String| {
+        println!(
+            "cargo:warning=Build skipped for {} at {}{}",
+            program_name,
+            current_datetime(),
+            reason
+        );
+    };
+    match sp1_build::build_program_with_output(&args.unwrap_or_default(), program_dir) {
+        Ok(output) if output.skipped && output.artifacts.iter().all(|elf| elf.size == Some(0)) => {
+            skipped(format!(", using an empty placeholder ELF at {}", output.elf_path()))
+        }
+        Ok(output) if output.skipped => {
+            skipped(format!(", using the ELF of a previous build at {}", output.elf_path()))
+        }
+        // The build already warned that the ELF is stale.
+        Ok(output) if output.stale => {}
+        Ok(_) => println!("cargo:warning={} built at {}", program_name, current_datetime()),
+        Err(err) if matches!(err.downcast_ref(), Some(sp1_build::BuildError::Skipped)) => {
+            skipped(format!(": {}", err));
+        }
+        Err(err) => panic!("Failed to build SP1 program: {}.", err),
     }
 
     Ok(ExitStatus::default())
@@ -79,7 +62,13 @@ fn execute_build_cmd(
 /// This function is useful for automatically rebuilding the program during development
 /// when changes are made to the source code or its dependencies.
 ///
-/// Set the `SP1_SKIP_PROGRAM_BUILD` environment variable to `true` to skip building the program.
+/// Set the `SP1_SKIP_PROGRAM_BUILD` environment variable to `true` to skip building the program,
+/// keeping the ELF of the previous build if there is one, and `SP1_HELPER_VERBOSE` to `true` to log
+/// the versions of the toolchain and tools building it.
+///
+/// Warns when the `sp1-zkvm` of the program and the `sp1-sdk` or `sp1-prover` of the host crate
+/// are of incompatible releases, whose proofs would fail to verify, unless `SP1_SKIP_VERSION_CHECK`
+/// is set to `1`.
 pub fn build_program(path: &str) {
     build_program_internal(path, None)
 }
@@ -90,40 +79,105 @@ pub fn build_program(path: &str) {
 /// # Arguments
 ///
 /// * `path` - A string slice that holds the path to the program directory.
-/// * `args` - A [`BuildArgs`] struct that contains various build configuration options.
+/// * `args` - A [`BuildArgs`] struct that contains various build configuration options. A relative
+///   [`BuildArgs::output_directory`] is resolved against the program directory, see
+///   [`BuildArgs::legacy_output_path`].
+///
+/// Set the `SP1_SKIP_PROGRAM_BUILD` environment variable to `true` to skip building the program,
+/// keeping the ELF of the previous build if there is one, and `SP1_HELPER_VERBOSE` to `true` to log
+/// the versions of the toolchain and tools building it.
 ///
-/// Set the `SP1_SKIP_PROGRAM_BUILD` environment variable to `true` to skip building the program.
+/// Warns when the `sp1-zkvm` of the program and the `sp1-sdk` or `sp1-prover` of the host crate
+/// are of incompatible releases, whose proofs would fail to verify, unless `SP1_SKIP_VERSION_CHECK`
+/// is set to `1`.
 pub fn build_program_with_args(path: &str, args: BuildArgs) {
     build_program_internal(path, Some(args))
 }
 
+/// Builds several programs concurrently, each with its own arguments, if any of them, or one of
+/// their dependencies, changes.
+///
+/// # Arguments
+///
+/// * `programs` - The directory of each program, with the [`BuildArgs`] to build it with.
+///
+/// The output of each build is prefixed with the name of its program. Programs compiling into the
+/// same target directory, such as the members of one workspace, are built one at a time. The
+/// builds share the jobs of the cargo running the build script, and at most `SP1_HELPER_JOBS` of
+/// them run at once, or as many as there are CPUs if it is not set. Every program is built even if
+/// some fail, and the failures are reported together.
+pub fn build_programs_parallel<P: AsRef<Path>>(programs: &[(P, BuildArgs)]) {
+    let parallelism = std::env::var("SP1_HELPER_JOBS")
+        .ok()
+        .and_then(|jobs| jobs.trim().parse().ok())
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1);
+    println!("cargo:rerun-if-env-changed=SP1_HELPER_JOBS");
+    for (path, _) in programs {
+        version_check::check(path.as_ref());
+    }
+
+    let args_list =
+        programs.iter().map(|(path, args)| (args.clone(), path.as_ref().to_path_buf())).collect();
+    let results = sp1_build::build_programs(args_list, parallelism);
+    let mut failures = vec![];
+    for ((path, _), result) in programs.iter().zip(results) {
+        let path = path.as_ref().display();
+        match result {
+            Ok(elf_path) => {
+                println!("cargo:warning={} built at {} into {}", path, current_datetime(), elf_path)
+            }
+            Err(err) if matches!(err.downcast_ref(), Some(sp1_build::BuildError::Skipped)) => {
+                println!("cargo:warning=Build skipped for {}: {}", path, err);
+            }
+            Err(err) => {
+                println!("cargo:warning=Failed to build SP1 program {}: {:#}", path, err);
+                failures.push(format!("{}: {:#}", path, err));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        panic!(
+            "Failed to build {} of {} SP1 programs:\n  {}",
+            failures.len(),
+            programs.len(),
+            failures.join("\n  ")
+        );
+    }
+}
+
 /// Internal helper function to build the program with or without arguments.
 fn build_program_internal(path: &str, args: Option<BuildArgs>) {
-    // Get the root package name and metadata.
+    // A wrong path is the most common mistake with the helper, and is reported without the
+    // backtrace of a panic, which hides the message in the output of cargo.
     let program_dir = std::path::Path::new(path);
+    if let Err(err) = sp1_build::check_program_dir(program_dir) {
+        if std::env::var_os("OUT_DIR").is_none() {
+            panic!("Failed to build SP1 program: {}.", err);
+        }
+        println!("cargo:warning=Failed to build SP1 program: {}", err);
+        eprintln!("error: failed to build the SP1 program at {}: {}", path, err);
+        std::process::exit(1);
+    }
+
+    version_check::check(program_dir);
+
+    // Get the root package name and metadata.
     let metadata_file = program_dir.join("Cargo.toml");
     let mut metadata_cmd = cargo_metadata::MetadataCommand::new();
     let metadata = metadata_cmd.manifest_path(metadata_file).exec().unwrap();
     let root_package = metadata.root_package();
     let root_package_name = root_package.as_ref().map(|p| p.name.as_str()).unwrap_or("Program");
 
-    // Skip the program build if the SP1_SKIP_PROGRAM_BUILD environment variable is set to true.
-    let skip_program_build = std::env::var("SP1_SKIP_PROGRAM_BUILD")
+    // Log the versions of the tools building the program in verbose mode, for bug reports.
+    let verbose = std::env::var("SP1_HELPER_VERBOSE")
         .map(|v| v.eq_ignore_ascii_case("true"))
         .unwrap_or(false);
-    if skip_program_build {
-        println!(
-            "cargo:warning=Build skipped for {} at {} due to SP1_SKIP_PROGRAM_BUILD flag",
-            root_package_name,
-            current_datetime()
-        );
-        return;
+    if verbose {
+        println!("cargo:warning={}", sp1_build::toolchain_info().summary());
     }
 
-    // Activate the build command if the dependencies change.
-    cargo_rerun_if_changed(&metadata, program_dir);
-
-    let _ = execute_build_cmd(&program_dir, args);
-
-    println!("cargo:warning={} built at {}", root_package_name, current_datetime());
+    // The build prints the `cargo:rerun-if-changed` directives for the program and its local
+    // dependencies, so that it is built again when they change.
+    let _ = execute_build_cmd(&program_dir, args, root_package_name);
 }
@@ -0,0 +1,146 @@
+//! The check that the `sp1-zkvm` of a program and the `sp1-sdk` or `sp1-prover` of the host crate
+//! building it are of compatible releases, see [`check`].
+//!
+//! A program compiled against one release of `sp1-zkvm` and proven with a prover of another
+//! release builds and executes, but its proofs fail to verify, which is easily mistaken for a bug
+//! of the program. The versions are read from the lock files of both workspaces.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::semver::Version;
+
+/// The environment variable which disables the check.
+const SKIP_ENV: &str = "SP1_SKIP_VERSION_CHECK";
+
+/// The crates of the host which prove programs.
+const HOST_CRATES: &[&str] = &["sp1-sdk", "sp1-prover"];
+
+/// Warn when the `sp1-zkvm` of the program in `program_dir` and the `sp1-sdk` or `sp1-prover` of
+/// the host crate whose build script is running are of incompatible releases, unless
+/// `SP1_SKIP_VERSION_CHECK` is set. Outside of a build script, or without both lock files, there
+/// is nothing to check.
+pub(crate) fn check(program_dir: &Path) {
+    println!("cargo:rerun-if-env-changed={}", SKIP_ENV);
+    if let Some(value) = std::env::var(SKIP_ENV).ok().filter(|value| !value.is_empty()) {
+        match sp1_build::parse_bool(SKIP_ENV, &value) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => println!("cargo:warning={:#}", err),
+        }
+    }
+    let Some(host_dir) = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from) else {
+        return;
+    };
+    let lock_file = |dir: &Path| {
+        let dir = dir.canonicalize().ok()?;
+        let path = dir.ancestors().map(|dir| dir.join("Cargo.lock")).find(|path| path.is_file())?;
+        fs::read_to_string(path).ok()
+    };
+    let (Some(host_lock), Some(program_lock)) = (lock_file(&host_dir), lock_file(program_dir))
+    else {
+        return;
+    };
+    for warning in mismatches(&host_lock, &program_lock) {
+        println!("cargo:warning={}: {}", program_dir.display(), warning);
+    }
+}
+
+/// The warnings about each version of `sp1-zkvm` in the lock file `program_lock` which a version
+/// of the host crates in `host_lock` does not prove.
+fn mismatches(host_lock: &str, program_lock: &str) -> Vec<String> {
+    let host = locked_versions(host_lock, HOST_CRATES);
+    let mut warnings = vec![];
+    for (_, zkvm) in locked_versions(program_lock, &["sp1-zkvm"]) {
+        for (name, version) in host.iter().filter(|(_, version)| !compatible(version, &zkvm)) {
+            warnings.push(format!(
+                "the program uses sp1-zkvm {} while the host uses {} {}, which are from \
+                 incompatible release lines and whose proofs would fail to verify. Use the same \
+                 release of SP1 in both, or set {}=1 to skip this check",
+                zkvm, name, version, SKIP_ENV
+            ));
+        }
+    }
+    warnings
+}
+
+/// Whether the host crates of `host` prove programs compiled against `sp1-zkvm` of `zkvm`: each
+/// release line only proves its own.
+fn compatible(host: &Version, zkvm: &Version) -> bool {
+    release_line(host) == release_line(zkvm)
+}
+
+/// The release line of `version`, whose versions are compatible: the major version, or the major
+/// and minor ones before 1.0.
+fn release_line(version: &Version) -> String {
+    match version.major {
+        0 => format!("0.{}", version.minor),
+        major => major.to_string(),
+    }
+}
+
+/// The versions of the packages named `names` in the lock file `contents`, skipping a lock file
+/// or packages which cannot be parsed.
+fn locked_versions(contents: &str, names: &[&str]) -> Vec<(String, Version)> {
+    let Ok(lock) = contents.parse::<toml::Table>() else {
+        return vec![];
+    };
+    let packages = lock.get("package").and_then(|packages| packages.as_array());
+    packages
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?;
+            if !names.contains(&name) {
+                return None;
+            }
+            let version = Version::parse(package.get("version")?.as_str()?).ok()?;
+            Some((name.to_string(), version))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(packages: &[(&str, &str)]) -> String {
+        let mut lock = "version = 3\n".to_string();
+        for (name, version) in packages {
+            lock.push_str(&format!(
+                "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n",
+                name, version
+            ));
+        }
+        lock
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let program = lock(&[("fibonacci", "0.1.0"), ("sp1-zkvm", "3.4.0"), ("sp1-lib", "3.4.0")]);
+        let host = lock(&[("script", "0.1.0"), ("sp1-sdk", "4.0.0"), ("sp1-prover", "4.0.0")]);
+        let warnings = mismatches(&host, &program);
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].starts_with("the program uses sp1-zkvm 3.4.0 while the host uses"));
+        assert!(warnings[0].contains("sp1-sdk 4.0.0") && warnings[1].contains("sp1-prover 4.0.0"));
+
+        let host = lock(&[("sp1-sdk", "3.0.0-rc1"), ("sp1-prover", "3.1.0")]);
+        assert_eq!(mismatches(&host, &program), Vec::<String>::new());
+        // Without sp1-zkvm or the host crates, or with a lock file which cannot be parsed, there is
+        // nothing to compare.
+        assert_eq!(mismatches(&host, &lock(&[("sp1-lib", "4.0.0")])), Vec::<String>::new());
+        assert_eq!(mismatches(&lock(&[("serde", "1.0.0")]), &program), Vec::<String>::new());
+        assert_eq!(mismatches("[[package", &program), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_compatible() {
+        let version = |version: &str| Version::parse(version).unwrap();
+        assert!(compatible(&version("4.1.0"), &version("4.0.1")));
+        assert!(!compatible(&version("4.0.0"), &version("3.4.0")));
+        assert!(!compatible(&version("0.2.0"), &version("0.1.0")));
+        assert!(compatible(&version("9.0.0"), &version("9.2.0")));
+    }
+}
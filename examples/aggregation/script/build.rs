@@ -1,7 +1,12 @@
+use sp1_helper::{build_program_with_args, BuildArgs, ElfDestination};
+
 fn main() {
-    sp1_helper::build_program(&format!("{}/../program", env!("CARGO_MANIFEST_DIR")));
-    sp1_helper::build_program(&format!(
-        "{}/../../fibonacci/program",
-        env!("CARGO_MANIFEST_DIR")
-    ));
+    // Keep the committed ELF in the `program/elf` of each program up to date, since they are
+    // embedded from there rather than from the `OUT_DIR` of this build script.
+    let args = BuildArgs { elf_destination: ElfDestination::OutputDirectory, ..Default::default() };
+    build_program_with_args(&format!("{}/../program", env!("CARGO_MANIFEST_DIR")), args.clone());
+    build_program_with_args(
+        &format!("{}/../../fibonacci/program", env!("CARGO_MANIFEST_DIR")),
+        args,
+    );
 }
@@ -1,5 +1,8 @@
-use sp1_helper::build_program;
+use sp1_helper::{build_program_with_args, BuildArgs, ElfDestination};
 
 fn main() {
-    build_program("../program")
+    // Keep the committed ELF in `program/elf` up to date, since it is embedded from there rather
+    // than from the `OUT_DIR` of this build script.
+    let args = BuildArgs { elf_destination: ElfDestination::OutputDirectory, ..Default::default() };
+    build_program_with_args("../program", args)
 }